@@ -0,0 +1,49 @@
+//! Compares routing deallocations past a bump of many chunks, with and without caching its chunk
+//! ranges.
+
+use allocandrescu::{
+    alloc::{Bump, OwnedBump},
+    prelude::*,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::alloc::{Layout, System};
+
+/// Returns a bump grown to `chunks` chunks, as chunks double in size.
+fn grown_bump(chunks: usize) -> Bump {
+    let mut bump = Bump::new();
+    let mut size = 64;
+    while bump.iter_allocated_chunks().count() < chunks {
+        bump.alloc_layout(Layout::from_size_align(size, 8).unwrap());
+        size *= 2;
+    }
+    bump
+}
+
+fn spilled_deallocation(c: &mut Criterion) {
+    // Freeing a block the bump didn't allocate asks it about every chunk or none of them.
+    let layout = Layout::new::<[u8; 32]>();
+    let mut group = c.benchmark_group("spilled_deallocation");
+
+    let bump = grown_bump(16);
+    let alloc = (&bump).fallback(System);
+    group.bench_function("bump_ref", |b| {
+        b.iter(|| {
+            // Stands in for a block the bump would have refused, which the fallback spills.
+            let ptr = System.allocate(black_box(layout)).unwrap();
+            unsafe { alloc.deallocate(ptr.cast(), layout) };
+        })
+    });
+
+    let alloc = OwnedBump::new(grown_bump(16)).fallback(System);
+    group.bench_function("owned_bump", |b| {
+        b.iter(|| {
+            let ptr = System.allocate(black_box(layout)).unwrap();
+            unsafe { alloc.deallocate(ptr.cast(), layout) };
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, spilled_deallocation);
+criterion_main!(benches);
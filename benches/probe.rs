@@ -0,0 +1,40 @@
+//! Compares falling back from an exhausted primary with and without probing it first.
+
+use allocandrescu::{alloc::Stack, prelude::*};
+use allocator_api2::alloc::Global;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::alloc::Layout;
+
+fn exhausted_primary(c: &mut Criterion) {
+    let layout = Layout::new::<[u8; 32]>();
+    let mut group = c.benchmark_group("exhausted_primary");
+
+    // A deep primary makes every doomed attempt walk the whole chain.
+    let stack = Stack::<16>::new();
+    let primary = stack
+        .by_ref()
+        .cond(|layout| layout.size() <= 64)
+        .with_header()
+        .cond(|layout| layout.align() <= 16);
+
+    let alloc = primary.by_ref().fallback(Global);
+    group.bench_function("fallback", |b| {
+        b.iter(|| {
+            let ptr = alloc.allocate(black_box(layout)).unwrap();
+            unsafe { alloc.deallocate(ptr.cast(), layout) };
+        })
+    });
+
+    let alloc = primary.by_ref().probe().fallback(Global);
+    group.bench_function("probe_fallback", |b| {
+        b.iter(|| {
+            let ptr = alloc.allocate(black_box(layout)).unwrap();
+            unsafe { alloc.deallocate(ptr.cast(), layout) };
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, exhausted_primary);
+criterion_main!(benches);
@@ -0,0 +1,113 @@
+//! Measures the cost of creating a large `Stack`, compared to zeroing a buffer of the same size,
+//! of growing vectors in it, of large zeroed allocations and of allocating blocks in bulk.
+
+use allocandrescu::{alloc::Stack, BulkAllocator};
+use allocator_api2::{
+    alloc::{AllocError, Allocator},
+    vec::Vec,
+};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use std::{alloc::Layout, ptr::NonNull};
+
+const SIZE: usize = 1024 * 1024;
+
+fn construct(c: &mut Criterion) {
+    let mut group = c.benchmark_group("construct_1mib");
+    group.bench_function("stack", |b| {
+        b.iter(|| black_box(Box::new(Stack::<SIZE>::new())))
+    });
+    group.bench_function("zeroed_buffer", |b| {
+        b.iter(|| black_box(Box::new([0u8; SIZE])))
+    });
+    group.finish();
+}
+
+/// Forwards only `allocate` and `deallocate`, so that growing always copies.
+struct Plain<'a, const SIZE: usize>(&'a Stack<SIZE>);
+
+unsafe impl<const SIZE: usize> Allocator for Plain<'_, SIZE> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.0.deallocate(ptr, layout)
+    }
+}
+
+fn vec_push(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vec_push_4096");
+    let mut stack = Stack::<{ 64 * 1024 }>::new();
+    group.bench_function("grow_in_place", |b| {
+        b.iter(|| {
+            stack.scope(|stack| {
+                let mut v = Vec::new_in(stack);
+                v.extend(0..black_box(4096u32));
+                black_box(v.len())
+            })
+        })
+    });
+    group.bench_function("grow_by_copy", |b| {
+        b.iter(|| {
+            stack.scope(|stack| {
+                let mut v = Vec::new_in(Plain(stack));
+                v.extend(0..black_box(4096u32));
+                black_box(v.len())
+            })
+        })
+    });
+    group.finish();
+}
+
+fn allocate_zeroed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("allocate_zeroed_1mib");
+    let layout = Layout::array::<u8>(SIZE).unwrap();
+    group.bench_function("virgin", |b| {
+        b.iter_batched(
+            || Box::new(Stack::<SIZE>::zeroed()),
+            |stack| {
+                black_box(stack.allocate_zeroed(layout).unwrap());
+                stack
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("after_reset", |b| {
+        let stack = Box::new(Stack::<SIZE>::zeroed());
+        b.iter(|| {
+            black_box(stack.allocate_zeroed(layout).unwrap());
+            unsafe { stack.reset_unchecked() };
+        })
+    });
+    group.finish();
+}
+
+fn allocate_many(c: &mut Criterion) {
+    let mut group = c.benchmark_group("allocate_many_4096");
+    let mut stack = Stack::<{ 64 * 1024 }>::new();
+    let layout = Layout::new::<u64>();
+    group.bench_function("bulk", |b| {
+        b.iter(|| {
+            stack.scope(|stack| {
+                let mut last = None;
+                let n = stack.allocate_many(layout, black_box(4096), &mut |ptr| last = Some(ptr));
+                black_box((n.unwrap(), last))
+            })
+        })
+    });
+    group.bench_function("loop", |b| {
+        b.iter(|| {
+            stack.scope(|stack| {
+                let mut last = None;
+                for _ in 0..black_box(4096) {
+                    last = Some(stack.allocate(layout).unwrap());
+                }
+                black_box(last)
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, construct, vec_push, allocate_zeroed, allocate_many);
+criterion_main!(benches);
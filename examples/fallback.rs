@@ -0,0 +1,35 @@
+//! Allocates small vectors on a stack and falls back to the system allocator for larger ones.
+
+use allocandrescu::{alloc::Stack, prelude::*};
+use allocator_api2::vec::Vec;
+use std::{alloc::Layout, ptr::NonNull};
+
+/// Returns how many of the vectors ended up on the stack.
+pub fn run() -> usize {
+    let stack = Stack::<1024>::new();
+    let alloc = stack
+        .by_ref()
+        .cond(|layout| layout.size() <= 64)
+        .fallback(std::alloc::System);
+
+    let mut on_stack = 0;
+    for len in [8, 16, 128, 32, 1000] {
+        let mut v = Vec::with_capacity_in(len, &alloc);
+        v.extend((0..len).map(|i| i as u8));
+        let layout = Layout::array::<u8>(len).unwrap();
+        let ptr = NonNull::new(v.as_mut_ptr()).unwrap();
+        if stack.contains(ptr, layout) {
+            on_stack += 1;
+        }
+        println!(
+            "vector of {len} bytes on the stack: {}",
+            stack.contains(ptr, layout)
+        );
+    }
+    on_stack
+}
+
+#[allow(dead_code)]
+fn main() {
+    run();
+}
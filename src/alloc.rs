@@ -1,184 +1,2114 @@
 //! Basic allocators.
 
-use crate::ArenaAllocator;
+#[cfg(feature = "debug-tracking")]
+use crate::OwnsAllocations;
+use crate::{
+    dangling, util::align_up, AllocatorId, ArenaAllocator, BulkAllocator, GoodSizeAllocator,
+    InPlaceGrow, ProbeAllocator, ResetAllocator, SameAllocator, TrimAllocator,
+};
 use allocator_api2::alloc::{AllocError, Allocator};
 use core::{
     alloc::Layout,
     cell::{Cell, UnsafeCell},
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    ops::Range,
     ptr::{self, NonNull},
+    sync::atomic::{compiler_fence, Ordering},
 };
 
-/// Allocator that always fails allocation.
-///
-/// Deallocation is a no-op.
-#[derive(Debug)]
-pub struct Failing;
+#[cfg(feature = "alloc")]
+use alloc_crate::{boxed::Box, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::pin::Pin;
+
+mod builder;
+#[cfg(feature = "alloc")]
+mod cascade;
+mod global;
+#[cfg(feature = "linked_list_allocator")]
+mod linked_list_arena;
+#[cfg(all(unix, feature = "unix"))]
+mod mmap;
+#[cfg(feature = "bumpalo")]
+mod owned_bump;
+mod regions;
+mod sub_arena;
+#[cfg(feature = "talc")]
+mod talc_arena;
+#[cfg(feature = "debug-tracking")]
+mod tracking;
+
+pub use builder::StackBuilder;
+#[cfg(feature = "alloc")]
+pub use cascade::Cascade;
+pub use global::{FromGlobal, Global};
+#[cfg(feature = "linked_list_allocator")]
+pub use linked_list_arena::LinkedListArena;
+#[cfg(all(unix, feature = "unix"))]
+pub use mmap::MmapArena;
+#[cfg(feature = "bumpalo")]
+pub use owned_bump::OwnedBump;
+pub use regions::{Region, Regions};
+pub use sub_arena::SubArena;
+#[cfg(feature = "talc")]
+pub use talc_arena::TalcArena;
+
+/// Allocator that always fails allocation.
+///
+/// Deallocation is a no-op.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Failing;
+
+unsafe impl Allocator for Failing {
+    #[inline]
+    fn allocate(&self, _layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        Err(AllocError)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {}
+}
+
+impl ArenaAllocator for Failing {
+    #[inline]
+    fn contains(&self, _ptr: NonNull<u8>, _layout: Layout) -> bool {
+        false
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        Some(0)
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        Some(0)
+    }
+}
+
+impl TrimAllocator for Failing {}
+
+impl BulkAllocator for Failing {}
+
+impl GoodSizeAllocator for Failing {}
+
+impl InPlaceGrow for Failing {}
+
+impl SameAllocator for Failing {
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        AllocatorId::FAILING
+    }
+}
+
+impl ProbeAllocator for Failing {
+    #[inline]
+    fn can_allocate(&self, _layout: Layout) -> bool {
+        false
+    }
+}
+
+/// Stack-based bump allocator.
+///
+/// The buffer is left uninitialized, so creating even a large `Stack` is cheap.
+/// Memory returned by [`allocate`](Allocator::allocate) is uninitialized as well.
+///
+/// Blocks are exactly as large as requested, even when the next allocation would leave the bytes
+/// after a block as padding: the topmost block is reclaimed only if it ends at the cursor, so
+/// deallocating it with any smaller layout that [fits] it would leak it.
+///
+/// A stack created with [`zeroed`](Stack::zeroed) instead zeroes the buffer up front, which makes
+/// [`allocate_zeroed`](Allocator::allocate_zeroed) free for memory that was never allocated before.
+///
+/// `Stack` doesn't implement `Clone`: a copy of the buffer would contain copies of live
+/// allocations that nothing owns. Share it by reference instead, `&Stack` is also an allocator.
+/// ```compile_fail
+/// use allocandrescu::alloc::Stack;
+///
+/// let stack = Stack::<64>::new();
+/// let copy = stack.clone();
+/// ```
+///
+/// The buffer is stored inline, so moving a `Stack` moves every allocation in it and leaves the
+/// pointers to them dangling. Allocations borrow the stack, which prevents moving it while they
+/// are live, but raw pointers and leaked values aren't tracked. A stack that lives inside a value
+/// that gets moved around is best allocated with [`pinned`](Stack::pinned), which keeps it in
+/// place. In debug builds, using a block after the stack was moved panics.
+///
+/// `Stack` is `Send`, but not `Sync`: its cursor is updated through a `Cell` without any
+/// synchronization, so it must not be shared between threads. To use it from multiple threads,
+/// wrap it in a lock of your choice.
+///
+/// The buffer is aligned to `ALIGN` bytes, so allocations with an alignment that divides it
+/// never need padding when the stack is empty or its cursor is aligned as well.
+/// By default the buffer has no alignment requirement, see [`Align`] for the supported values.
+/// ```
+/// use allocandrescu::alloc::Stack;
+/// use allocator_api2::alloc::{Allocator, Layout};
+///
+/// let stack = Box::new(Stack::<8192, 4096>::new());
+/// let page = stack.allocate(Layout::from_size_align(8192, 4096).unwrap());
+/// assert!(page.is_ok());
+/// ```
+///
+/// [fits]: allocator_api2::alloc::Allocator#memory-fitting
+#[derive(Debug)]
+pub struct Stack<const SIZE: usize, const ALIGN: usize = 1>
+where
+    Align<ALIGN>: Alignment,
+{
+    stack: UnsafeCell<Buffer<SIZE, ALIGN>>,
+    idx: Cell<usize>,
+    /// Alignment padding in front of the topmost allocation, reclaimed when it is deallocated.
+    padding: Cell<usize>,
+    /// Sum of the alignment padding inserted since creation or the last reset.
+    padding_bytes: Cell<usize>,
+    /// Number of non-zero-sized allocations that weren't deallocated.
+    live_allocations: Cell<usize>,
+    /// Lowest position of the cursor since the last checkpoint. Blocks starting below it were
+    /// allocated before the checkpoint.
+    mark: Cell<usize>,
+    /// Number of live allocations starting below `mark`, the ones that survive rewinding to it.
+    live_below_mark: Cell<usize>,
+    /// Highest value of `idx` since creation or the last `reset_peak`.
+    peak: Cell<usize>,
+    /// End of the prefix of the buffer that may have been written to. The rest of it is zeroed.
+    dirty: Cell<usize>,
+    /// Address of the buffer at the last allocation, to detect moves while allocations are live.
+    #[cfg(debug_assertions)]
+    base: Cell<usize>,
+    #[cfg(feature = "debug-tracking")]
+    live: tracking::Tracker,
+    _pinned: PhantomPinned,
+}
+
+// SAFETY: `Stack` owns its buffer and has no thread affinity. Allocations borrow the stack,
+// so it can only be sent to another thread when none of them are live.
+unsafe impl<const SIZE: usize, const ALIGN: usize> Send for Stack<SIZE, ALIGN> where
+    Align<ALIGN>: Alignment
+{
+}
+
+/// The buffer of a [`Stack`], aligned to `ALIGN` by a zero-sized array at its start.
+#[repr(C)]
+struct Buffer<const SIZE: usize, const ALIGN: usize>
+where
+    Align<ALIGN>: Alignment,
+{
+    _align: [<Align<ALIGN> as Alignment>::Archetype; 0],
+    bytes: [MaybeUninit<u8>; SIZE],
+}
+
+impl<const SIZE: usize, const ALIGN: usize> Buffer<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    const UNINIT: Self = Self {
+        _align: [],
+        bytes: [MaybeUninit::uninit(); SIZE],
+    };
+
+    const ZEROED: Self = Self {
+        _align: [],
+        bytes: [MaybeUninit::zeroed(); SIZE],
+    };
+}
+
+/// Selects the alignment of a [`Stack`] buffer.
+///
+/// [`Alignment`] is implemented for powers of two up to 65536.
+/// ```compile_fail
+/// use allocandrescu::alloc::Stack;
+///
+/// let stack = Stack::<64, 3>::new();
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Align<const ALIGN: usize>;
+
+/// Alignments supported by [`Stack`]. This trait is sealed.
+pub trait Alignment: sealed::Sealed {
+    #[doc(hidden)]
+    type Archetype: Copy;
+}
+
+macro_rules! impl_alignment {
+    ($($align:literal => $archetype:ident),* $(,)?) => {
+        $(
+            impl sealed::Sealed for Align<$align> {}
+
+            impl Alignment for Align<$align> {
+                type Archetype = sealed::$archetype;
+            }
+        )*
+
+        mod sealed {
+            pub trait Sealed {}
+
+            $(
+                #[derive(Clone, Copy)]
+                #[repr(align($align))]
+                pub struct $archetype;
+            )*
+        }
+    };
+}
+
+impl_alignment! {
+    1 => Align1, 2 => Align2, 4 => Align4, 8 => Align8, 16 => Align16, 32 => Align32,
+    64 => Align64, 128 => Align128, 256 => Align256, 512 => Align512, 1024 => Align1024,
+    2048 => Align2048, 4096 => Align4096, 8192 => Align8192, 16384 => Align16384,
+    32768 => Align32768, 65536 => Align65536,
+}
+
+impl<const SIZE: usize, const ALIGN: usize> Default for Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            stack: UnsafeCell::new(Buffer::UNINIT),
+            idx: Cell::new(0),
+            padding: Cell::new(0),
+            padding_bytes: Cell::new(0),
+            live_allocations: Cell::new(0),
+            mark: Cell::new(0),
+            live_below_mark: Cell::new(0),
+            peak: Cell::new(0),
+            dirty: Cell::new(SIZE),
+            #[cfg(debug_assertions)]
+            base: Cell::new(0),
+            #[cfg(feature = "debug-tracking")]
+            live: tracking::Tracker::new(),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Creates a stack with a zeroed buffer.
+    ///
+    /// Zeroed allocations are not zeroed again, unless their memory was allocated before,
+    /// e.g. prior to a [`reset`](Stack::reset).
+    #[inline]
+    pub const fn zeroed() -> Self {
+        Self {
+            stack: UnsafeCell::new(Buffer::ZEROED),
+            idx: Cell::new(0),
+            padding: Cell::new(0),
+            padding_bytes: Cell::new(0),
+            live_allocations: Cell::new(0),
+            mark: Cell::new(0),
+            live_below_mark: Cell::new(0),
+            peak: Cell::new(0),
+            dirty: Cell::new(0),
+            #[cfg(debug_assertions)]
+            base: Cell::new(0),
+            #[cfg(feature = "debug-tracking")]
+            live: tracking::Tracker::new(),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Creates a stack on the heap that can't be moved out of its box.
+    ///
+    /// The stack is built on the call stack and then moved to the heap, so it can't be larger than
+    /// the call stack.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::alloc::Stack;
+    /// use allocator_api2::vec::Vec;
+    ///
+    /// struct Scratch {
+    ///     stack: core::pin::Pin<Box<Stack<1024>>>,
+    /// }
+    ///
+    /// let scratch = Scratch { stack: Stack::pinned() };
+    /// let mut v = Vec::new_in(&*scratch.stack);
+    /// v.extend(0..10u8);
+    /// ```
+    #[cfg(feature = "alloc")]
+    #[inline]
+    pub fn pinned() -> Pin<Box<Self>> {
+        Box::pin(Self::new())
+    }
+
+    /// Returns the size of the buffer in bytes.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        SIZE
+    }
+
+    /// Returns the number of bytes in use, including alignment padding.
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.idx.get()
+    }
+
+    /// Returns the number of bytes left in the buffer.
+    ///
+    /// It doesn't account for the padding that aligning a future allocation may need.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        SIZE - self.idx.get()
+    }
+
+    /// Returns the number of bytes inserted as alignment padding since the stack was created or reset.
+    ///
+    /// Padding reclaimed by deallocating the topmost allocation is still counted.
+    #[inline]
+    pub fn padding_bytes(&self) -> usize {
+        self.padding_bytes.get()
+    }
+
+    /// Returns the alignment padding in front of the topmost allocation.
+    ///
+    /// Right after an allocation it is the padding inserted for it, which lets
+    /// an [`inspect`](crate::Allocandrescu::inspect) observer report it:
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::boxed::Box;
+    ///
+    /// let stack = Stack::<64, 8>::new();
+    /// let alloc = stack.by_ref().inspect(|layout, result| {
+    ///     if result.is_ok() {
+    ///         println!("{layout:?} padded by {}", stack.top_padding());
+    ///     }
+    /// });
+    /// let byte = Box::new_in(1u8, &alloc);
+    /// let word = Box::new_in(2u64, &alloc);
+    /// assert_eq!(stack.top_padding(), 7);
+    /// ```
+    #[inline]
+    pub fn top_padding(&self) -> usize {
+        self.padding.get()
+    }
+
+    /// Returns the highest number of bytes that were in use at once, including alignment padding.
+    ///
+    /// The peak is kept across [`reset`](Stack::reset)s, so it tells how large the stack needs
+    /// to be for a workload.
+    #[inline]
+    pub fn peak_used(&self) -> usize {
+        self.peak.get()
+    }
+
+    /// Resets the peak to the number of bytes currently in use.
+    #[inline]
+    pub fn reset_peak(&self) {
+        self.peak.set(self.idx.get());
+    }
+
+    /// Returns the number of allocations that weren't deallocated yet, zero-sized ones excluded.
+    ///
+    /// Allocations discarded by a [`rewind`](Stack::rewind) or a reset are not counted.
+    #[inline]
+    pub fn live_allocations(&self) -> usize {
+        self.live_allocations.get()
+    }
+
+    /// Returns `true` if every allocation made in the stack was deallocated.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.live_allocations.get() == 0
+    }
+
+    /// Reset this stack allocator.
+    ///
+    /// Performs a mass deallocation on everything allocated in the stack by resetting the pointer.
+    /// Does not run any `Drop` implementations on deallocated objects.
+    ///
+    /// Allocations that weren't deallocated, e.g. values leaked with
+    /// [`alloc_value`](crate::ArenaExt::alloc_value), are discarded as well.
+    #[inline]
+    pub fn reset(&mut self) {
+        // Borrowing the stack mutably proves that no allocation is in use.
+        unsafe { self.reset_unchecked() }
+    }
+
+    /// Reset this stack allocator, expecting every allocation to be deallocated already.
+    ///
+    /// In debug builds, it panics if some allocations weren't deallocated, which catches resetting
+    /// too early, e.g. while collections that own blocks in the stack were leaked by mistake.
+    /// Otherwise it is the same as [`reset`](Stack::reset).
+    #[inline]
+    #[track_caller]
+    pub fn reset_checked(&mut self) {
+        debug_assert!(
+            self.is_empty(),
+            "resetting a stack with {} live allocations",
+            self.live_allocations()
+        );
+        self.reset()
+    }
+
+    /// Reset this stack allocator and zero its memory, so that stale data, e.g. secrets, can't be
+    /// observed through later allocations or in memory dumps.
+    ///
+    /// Everything ever allocated in the stack is wiped, that is the whole buffer unless it was
+    /// created [`zeroed`](Stack::zeroed). Copies of the data made outside of the stack are not
+    /// affected. Afterwards, zeroed allocations are free like in a freshly zeroed stack.
+    pub fn reset_zeroed(&mut self) {
+        let dirty = self.dirty.get();
+        unsafe { self.stack.get().cast::<u8>().write_bytes(0, dirty) };
+        // Keeps the writes from being optimized away, as nothing reads them.
+        compiler_fence(Ordering::SeqCst);
+        self.dirty.set(0);
+        self.reset();
+    }
+
+    /// Reset this stack allocator through a shared reference, e.g. when it is
+    /// behind a combinator chain.
+    ///
+    /// See [`reset`](Stack::reset).
+    ///
+    /// # Safety
+    /// None of the memory allocated in the stack may be used afterwards.
+    #[inline]
+    pub unsafe fn reset_unchecked(&self) {
+        self.idx.set(0);
+        self.padding.set(0);
+        self.padding_bytes.set(0);
+        self.live_allocations.set(0);
+        self.mark.set(0);
+        self.live_below_mark.set(0);
+        #[cfg(feature = "debug-tracking")]
+        self.live.clear();
+    }
+
+    /// Returns a marker of the current position in the stack, for use with [`rewind`](Stack::rewind).
+    ///
+    /// The marker borrows the stack, so it can't outlive a [`reset`](Stack::reset):
+    /// ```compile_fail
+    /// use allocandrescu::alloc::Stack;
+    ///
+    /// let mut stack = Stack::<64>::new();
+    /// let marker = stack.checkpoint();
+    /// stack.reset();
+    /// unsafe { stack.rewind(marker) };
+    /// ```
+    #[inline]
+    pub fn checkpoint(&self) -> Marker<'_> {
+        let idx = self.idx.get();
+        Marker {
+            idx,
+            outer_mark: self.mark.replace(idx),
+            outer_live: self.live_below_mark.replace(self.live_allocations.get()),
+            _stack: PhantomData,
+        }
+    }
+
+    /// Deallocates everything allocated in the stack since `marker` was taken.
+    ///
+    /// Does not run any `Drop` implementations on deallocated objects.
+    ///
+    /// The count of [`live_allocations`](Stack::live_allocations) stays exact as long as nested
+    /// markers are rewound innermost first, as [`scope`](Stack::scope) and
+    /// [`Regions`] do, and blocks allocated before the outer ones aren't
+    /// deallocated while an inner one is in use.
+    ///
+    /// # Safety
+    /// `marker` must come from this stack and none of the memory allocated after it was taken may
+    /// be used afterwards.
+    #[inline]
+    pub unsafe fn rewind(&self, marker: Marker<'_>) {
+        debug_assert!(
+            marker.idx <= self.idx.get(),
+            "rewinding to a marker beyond the current position"
+        );
+        // Blocks allocated since the checkpoint start at or above the mark, even those that
+        // reused memory freed below the marker.
+        let mark = self.mark.get();
+        self.idx.set(marker.idx);
+        self.padding.set(0);
+        self.live_allocations.set(self.live_below_mark.get());
+        self.mark.set(marker.outer_mark.min(mark));
+        self.live_below_mark.set(marker.outer_live);
+        #[cfg(feature = "debug-tracking")]
+        self.live.truncate(mark);
+    }
+
+    /// Runs `f` with the stack and deallocates everything allocated in it afterwards,
+    /// also when `f` panics.
+    ///
+    /// Does not run any `Drop` implementations on deallocated objects.
+    ///
+    /// The stack is borrowed mutably, so `f` can only allocate through the reference it receives.
+    /// Values allocated with it can't escape the closure:
+    /// ```compile_fail
+    /// use allocandrescu::alloc::Stack;
+    /// use allocator_api2::boxed::Box;
+    ///
+    /// let mut stack = Stack::<64>::new();
+    /// let escaped = stack.scope(|stack| Box::new_in(1u32, stack));
+    /// ```
+    /// Raw pointers are not tracked, so they must not be used after `f` returns.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::alloc::Stack;
+    /// use allocator_api2::vec::Vec;
+    ///
+    /// let mut stack = Stack::<1024>::new();
+    /// for i in 0..10 {
+    ///     let sum = stack.scope(|stack| {
+    ///         let mut scratch = Vec::with_capacity_in(100, stack);
+    ///         scratch.extend(0..i);
+    ///         scratch.iter().sum::<u32>()
+    ///     });
+    ///     assert_eq!(sum, (0..i).sum());
+    ///     assert_eq!(stack.used(), 0);
+    /// }
+    /// ```
+    pub fn scope<R>(&mut self, f: impl FnOnce(&Self) -> R) -> R {
+        struct Rewind<'a, const SIZE: usize, const ALIGN: usize>(
+            &'a Stack<SIZE, ALIGN>,
+            Marker<'a>,
+        )
+        where
+            Align<ALIGN>: Alignment;
+
+        impl<const SIZE: usize, const ALIGN: usize> Drop for Rewind<'_, SIZE, ALIGN>
+        where
+            Align<ALIGN>: Alignment,
+        {
+            fn drop(&mut self) {
+                // Nothing allocated in `f` outlives it, as it had to borrow the stack.
+                unsafe { self.0.rewind(self.1) }
+            }
+        }
+
+        let stack = &*self;
+        let _rewind = Rewind(stack, stack.checkpoint());
+        f(stack)
+    }
+
+    /// Returns a builder of a byte buffer of unknown length at the top of the stack.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::alloc::Stack;
+    /// use core::fmt::Write;
+    ///
+    /// let stack = Stack::<64>::new();
+    /// let mut builder = stack.builder();
+    /// write!(builder, "{} + {} = {}", 2, 2, 4).unwrap();
+    /// let text = builder.finish();
+    /// assert_eq!(text, b"2 + 2 = 4");
+    /// assert_eq!(stack.used(), 9);
+    /// ```
+    #[inline]
+    pub fn builder(&self) -> StackBuilder<'_, SIZE, ALIGN> {
+        StackBuilder::new(self)
+    }
+
+    /// Allocates a region of `bytes` bytes and returns a [`SubArena`] over it.
+    ///
+    /// The stack treats the region as a single allocation until the sub-arena is dropped.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::alloc::Stack;
+    /// use allocator_api2::vec::Vec;
+    ///
+    /// let stack = Stack::<1024>::new();
+    /// let subsystem = stack.carve(256).unwrap();
+    /// let mut v = Vec::new_in(&subsystem);
+    /// v.extend(0..10u32);
+    /// // The subsystem can't use more than its share.
+    /// assert!(v.try_reserve(64).is_err());
+    /// ```
+    pub fn carve(&self, bytes: usize) -> Result<SubArena<'_>, AllocError> {
+        let layout = Layout::from_size_align(bytes, 1).map_err(|_| AllocError)?;
+        let region = self.allocate(layout)?;
+        Ok(SubArena::new(self, region))
+    }
+
+    /// Copies the allocated part of the stack and its state to the heap, for use with
+    /// [`restore`](Stack::restore).
+    ///
+    /// Unlike a [`checkpoint`](Stack::checkpoint), a snapshot also preserves the contents of the
+    /// allocations that existed when it was taken, even if they are modified afterwards.
+    #[cfg(feature = "alloc")]
+    pub fn snapshot(&self) -> StackSnapshot<SIZE, ALIGN> {
+        let idx = self.idx.get();
+        let mut bytes = Vec::with_capacity(idx);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.stack.get().cast::<MaybeUninit<u8>>(),
+                bytes.as_mut_ptr(),
+                idx,
+            );
+            bytes.set_len(idx);
+        }
+        StackSnapshot {
+            bytes: bytes.into_boxed_slice(),
+            padding: self.padding.get(),
+            padding_bytes: self.padding_bytes.get(),
+            live_allocations: self.live_allocations.get(),
+            mark: self.mark.get(),
+            live_below_mark: self.live_below_mark.get(),
+            #[cfg(feature = "debug-tracking")]
+            live: self.live.clone(),
+        }
+    }
+
+    /// Rolls the stack back to `snapshot`, both its contents and its position.
+    ///
+    /// Allocations that existed when the snapshot was taken are valid again and hold the data
+    /// they held back then. Everything allocated afterwards is deallocated.
+    ///
+    /// The snapshot has the size and alignment of the stack in its type, so it can't be restored
+    /// into a stack with a differently aligned buffer:
+    /// ```compile_fail
+    /// use allocandrescu::alloc::Stack;
+    ///
+    /// let snapshot = Stack::<64>::new().snapshot();
+    /// let mut stack = Stack::<64, 16>::new();
+    /// unsafe { stack.restore(&snapshot) };
+    /// ```
+    ///
+    /// # Safety
+    /// `snapshot` must come from this stack, possibly before it was moved, and neither pointers to memory allocated after it was
+    /// taken nor pointers to memory deallocated before it was taken may be used afterwards.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::alloc::Stack;
+    /// use allocator_api2::alloc::{Allocator, Layout};
+    ///
+    /// let mut stack = Stack::<64>::new();
+    /// let ptr = stack.allocate(Layout::new::<u32>()).unwrap().cast::<u32>();
+    /// unsafe { ptr.write(1) };
+    ///
+    /// let snapshot = stack.snapshot();
+    /// unsafe { ptr.write(2) };
+    /// stack.allocate(Layout::new::<u32>()).unwrap();
+    ///
+    /// unsafe {
+    ///     stack.restore(&snapshot);
+    ///     assert_eq!(ptr.read(), 1);
+    /// }
+    /// assert_eq!(stack.used(), 4);
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub unsafe fn restore(&mut self, snapshot: &StackSnapshot<SIZE, ALIGN>) {
+        let idx = snapshot.bytes.len();
+        ptr::copy_nonoverlapping(
+            snapshot.bytes.as_ptr(),
+            self.stack.get().cast::<MaybeUninit<u8>>(),
+            idx,
+        );
+        self.idx.set(idx);
+        // The copied bytes may be stale data that a zeroing reset wiped.
+        self.peak.set(self.peak.get().max(idx));
+        self.dirty.set(self.dirty.get().max(idx));
+        self.padding.set(snapshot.padding);
+        self.padding_bytes.set(snapshot.padding_bytes);
+        self.live_allocations.set(snapshot.live_allocations);
+        self.mark.set(snapshot.mark);
+        self.live_below_mark.set(snapshot.live_below_mark);
+        #[cfg(feature = "debug-tracking")]
+        {
+            self.live = snapshot.live.clone();
+        }
+    }
+
+    /// Panics in debug builds if the stack was moved since the last allocation, while blocks are live.
+    #[inline]
+    #[track_caller]
+    fn check_base(&self) {
+        #[cfg(debug_assertions)]
+        assert!(
+            self.live_allocations.get() == 0 || self.base.get() == self.stack.get() as usize,
+            "stack was moved while allocations were live"
+        );
+    }
+
+    /// Returns the offset of a block within the buffer.
+    #[cfg(feature = "debug-tracking")]
+    #[inline]
+    fn offset_of(&self, ptr: NonNull<u8>) -> usize {
+        as_usize(ptr).wrapping_sub(self.stack.get() as usize)
+    }
+
+    /// Writes a table of the live allocations followed by the total, padding and free bytes.
+    ///
+    /// Bytes of the used region that don't belong to a live allocation are reported as padding.
+    /// Besides alignment padding, they include allocations that were deallocated out of order.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::alloc::Stack;
+    /// use allocator_api2::boxed::Box;
+    ///
+    /// let stack = Stack::<64, 8>::new();
+    /// let byte = Box::new_in(1u8, &stack);
+    /// let word = Box::new_in(2u64, &stack);
+    ///
+    /// let mut dump = String::new();
+    /// stack.dump(&mut dump).unwrap();
+    /// assert_eq!(
+    ///     dump,
+    ///     "    offset       size      align
+    ///          0          1          1
+    ///          8          8          8
+    /// blocks: 2, live: 9, padding: 7, free: 48, total: 64
+    /// "
+    /// );
+    /// ```
+    #[cfg(feature = "debug-tracking")]
+    pub fn dump(&self, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+        self.live.dump(out, SIZE, self.idx.get())
+    }
+
+    /// Remembers the address of the buffer in debug builds, for [`check_base`](Stack::check_base).
+    #[inline]
+    fn record_base(&self) {
+        #[cfg(debug_assertions)]
+        self.base.set(self.stack.get() as usize);
+    }
+
+    /// Returns the offset of the block if it is the topmost allocation in the stack.
+    #[inline]
+    fn top_offset(&self, ptr: NonNull<u8>, layout: Layout) -> Option<usize> {
+        self.check_base();
+        let base = self.stack.get() as usize;
+        let offset = as_usize(ptr).checked_sub(base)?;
+        (layout.size() != 0 && offset.checked_add(layout.size())? == self.idx.get())
+            .then_some(offset)
+    }
+
+    /// Moves the cursor forward to `end`.
+    #[inline]
+    fn bump(&self, end: usize) {
+        self.idx.set(end);
+        self.peak.set(self.peak.get().max(end));
+        self.dirty.set(self.dirty.get().max(end));
+    }
+
+    /// Moves the cursor back to `end`, keeping blocks allocated from there apart from the ones
+    /// allocated before the last checkpoint.
+    #[inline]
+    fn retreat(&self, end: usize) {
+        self.idx.set(end);
+        self.mark.set(self.mark.get().min(end));
+    }
+
+    /// Zeroes the part of `len` bytes at `offset` that lies within the first `dirty` bytes.
+    #[inline]
+    unsafe fn zero_dirty(&self, offset: usize, len: usize, dirty: usize) {
+        let len = dirty.saturating_sub(offset).min(len);
+        self.stack
+            .get()
+            .cast::<u8>()
+            .add(offset)
+            .write_bytes(0, len);
+    }
+
+    /// Extends the block by bumping the cursor, if it is the topmost allocation.
+    #[inline]
+    fn grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Option<NonNull<[u8]>> {
+        let offset = self.top_offset(ptr, old_layout)?;
+        let end = offset.checked_add(new_layout.size())?;
+        if as_usize(ptr) % new_layout.align() != 0 || end > SIZE {
+            return None;
+        }
+        self.bump(end);
+        #[cfg(feature = "debug-tracking")]
+        self.live.insert(offset, new_layout);
+        Some(NonNull::slice_from_raw_parts(ptr, new_layout.size()))
+    }
+
+    /// Returns the range of the buffer that an allocation with `layout` would occupy.
+    #[inline]
+    fn bounds(&self, layout: Layout) -> Option<(usize, usize)> {
+        let base = self.stack.get() as usize;
+        let aligned_start = align_up(base + self.idx.get(), layout.align())? - base;
+        let aligned_end = aligned_start.checked_add(layout.size())?;
+        (aligned_end <= SIZE).then_some((aligned_start, aligned_end))
+    }
+}
+
+/// A copy of the allocated part of a [`Stack`], created by [`snapshot`](Stack::snapshot).
+///
+/// It records offsets rather than addresses, so it can be restored after the stack was moved.
+#[cfg(feature = "alloc")]
+pub struct StackSnapshot<const SIZE: usize, const ALIGN: usize = 1> {
+    bytes: Box<[MaybeUninit<u8>]>,
+    padding: usize,
+    padding_bytes: usize,
+    live_allocations: usize,
+    mark: usize,
+    live_below_mark: usize,
+    #[cfg(feature = "debug-tracking")]
+    live: tracking::Tracker,
+}
+
+#[cfg(feature = "alloc")]
+impl<const SIZE: usize, const ALIGN: usize> StackSnapshot<SIZE, ALIGN> {
+    /// Returns the number of bytes that were in use when the snapshot was taken.
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<const SIZE: usize, const ALIGN: usize> core::fmt::Debug for StackSnapshot<SIZE, ALIGN> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("StackSnapshot")
+            .field("used", &self.used())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A position in a [`Stack`], created by [`checkpoint`](Stack::checkpoint).
+///
+/// Markers taken later compare greater, unless the stack was rewound in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Marker<'a> {
+    idx: usize,
+    /// State of the enclosing checkpoint, restored on rewind.
+    outer_mark: usize,
+    outer_live: usize,
+    _stack: PhantomData<&'a ()>,
+}
+
+unsafe impl<const SIZE: usize, const ALIGN: usize> Allocator for Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        self.check_base();
+        let unaligned_start = self.idx.get();
+        let (aligned_start, aligned_end) = self.bounds(layout).ok_or(AllocError)?;
+        // `bounds` guarantees that the block is within the buffer.
+        let ptr = unsafe {
+            let ptr = self.stack.get().cast::<u8>().add(aligned_start);
+            NonNull::new_unchecked(ptr)
+        };
+        self.bump(aligned_end);
+        self.padding.set(aligned_start - unaligned_start);
+        self.padding_bytes
+            .set(self.padding_bytes.get() + aligned_start - unaligned_start);
+        self.record_base();
+        self.live_allocations.set(self.live_allocations.get() + 1);
+        #[cfg(feature = "debug-tracking")]
+        self.live.insert(aligned_start, layout);
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        self.check_base();
+        debug_assert!(
+            self.contains(ptr, layout),
+            "deallocated block {ptr:p} is not within the stack"
+        );
+        self.live_allocations.set(self.live_allocations.get() - 1);
+        if as_usize(ptr) - (self.stack.get() as usize) < self.mark.get() {
+            self.live_below_mark.set(self.live_below_mark.get() - 1);
+        }
+        #[cfg(feature = "debug-tracking")]
+        self.live.remove(self.offset_of(ptr));
+        if let Some(offset) = self.top_offset(ptr, layout) {
+            // The padding in front of the blocks below is unknown, so it stays allocated.
+            self.retreat(offset - self.padding.replace(0))
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if let Some(block) = self.grow_in_place(ptr, old_layout, new_layout) {
+            return Ok(block);
+        }
+        let new_ptr = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.cast().as_ptr(), old_layout.size());
+        self.deallocate(ptr, old_layout);
+        Ok(new_ptr)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let dirty = self.dirty.get();
+        if let Some(block) = self.grow_in_place(ptr, old_layout, new_layout) {
+            let old_end = as_usize(ptr) - self.stack.get() as usize + old_layout.size();
+            self.zero_dirty(old_end, new_layout.size() - old_layout.size(), dirty);
+            return Ok(block);
+        }
+        let new_ptr = self.allocate_zeroed(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.cast().as_ptr(), old_layout.size());
+        self.deallocate(ptr, old_layout);
+        Ok(new_ptr)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if new_layout.size() == 0 {
+            self.deallocate(ptr, old_layout);
+            return Ok(dangling(new_layout));
+        }
+        if as_usize(ptr) % new_layout.align() != 0 {
+            let new_ptr = self.allocate(new_layout)?;
+            ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.cast().as_ptr(), new_layout.size());
+            self.deallocate(ptr, old_layout);
+            return Ok(new_ptr);
+        }
+        if let Some(offset) = self.top_offset(ptr, old_layout) {
+            self.retreat(offset + new_layout.size());
+        }
+        #[cfg(feature = "debug-tracking")]
+        self.live.insert(self.offset_of(ptr), new_layout);
+        Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()))
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let dirty = self.dirty.get();
+        let block = self.allocate(layout)?;
+        if layout.size() != 0 {
+            // The block is the topmost allocation now.
+            let offset = self.idx.get() - layout.size();
+            unsafe { self.zero_dirty(offset, layout.size(), dirty) };
+        }
+        Ok(block)
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> ArenaAllocator for Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    /// Only the allocated prefix of the buffer is considered, so pointers into the unused tail
+    /// are not contained.
+    #[inline]
+    fn arena_range(&self) -> Option<Range<usize>> {
+        let stack_start = self.stack.get() as usize;
+        Some(stack_start..stack_start + self.idx.get())
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        Some(self.remaining())
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        Some(self.used())
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> ProbeAllocator for Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        layout.size() == 0 || self.bounds(layout).is_some()
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> TrimAllocator for Stack<SIZE, ALIGN> where
+    Align<ALIGN>: Alignment
+{
+}
+
+/// Blocks are exactly as large as requested, as the bytes after them are where the next
+/// allocation starts.
+impl<const SIZE: usize, const ALIGN: usize> GoodSizeAllocator for Stack<SIZE, ALIGN> where
+    Align<ALIGN>: Alignment
+{
+}
+
+impl<const SIZE: usize, const ALIGN: usize> InPlaceGrow for Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    /// Succeeds for the topmost allocation if the buffer has room for the new size and `ptr` is
+    /// aligned to the new alignment.
+    #[inline]
+    unsafe fn try_grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.grow_in_place(ptr, old_layout, new_layout)
+            .ok_or(AllocError)
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> SameAllocator for Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        AllocatorId::from_ptr(self.stack.get())
+    }
+}
+
+/// Zero-sized blocks are not tracked.
+#[cfg(feature = "debug-tracking")]
+impl<const SIZE: usize, const ALIGN: usize> OwnsAllocations for Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        self.live.contains(self.offset_of(ptr))
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.live.len()
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> BulkAllocator for Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    /// Bumps the cursor once for all the blocks, which are laid out contiguously,
+    /// `layout.pad_to_align().size()` bytes apart.
+    fn allocate_many(
+        &self,
+        layout: Layout,
+        n: usize,
+        out: &mut dyn FnMut(NonNull<[u8]>),
+    ) -> Result<usize, AllocError> {
+        if n == 0 {
+            return Ok(0);
+        }
+        if layout.size() == 0 {
+            (0..n).for_each(|_| out(dangling(layout)));
+            return Ok(n);
+        }
+        self.check_base();
+        let unaligned_start = self.idx.get();
+        let (aligned_start, _) = self.bounds(layout).ok_or(AllocError)?;
+        let stride = layout.pad_to_align().size();
+        let count = ((SIZE - aligned_start - layout.size()) / stride + 1).min(n);
+        let last_start = aligned_start + (count - 1) * stride;
+        self.bump(last_start + layout.size());
+        // The blocks after the first are padded to their stride.
+        let gap = stride - layout.size();
+        let padding = aligned_start - unaligned_start;
+        self.padding.set(if count == 1 { padding } else { gap });
+        self.padding_bytes
+            .set(self.padding_bytes.get() + padding + (count - 1) * gap);
+        self.record_base();
+        self.live_allocations
+            .set(self.live_allocations.get() + count);
+        let base = self.stack.get().cast::<u8>();
+        for offset in (aligned_start..=last_start).step_by(stride) {
+            #[cfg(feature = "debug-tracking")]
+            self.live.insert(offset, layout);
+            // The blocks are within the buffer.
+            let ptr = unsafe { NonNull::new_unchecked(base.add(offset)) };
+            out(NonNull::slice_from_raw_parts(ptr, layout.size()));
+        }
+        Ok(count)
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> ResetAllocator for Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    /// See [`Stack::reset`].
+    #[inline]
+    fn reset(&mut self) {
+        Stack::reset(self)
+    }
+}
+
+/// Re-rexport of [`bumpalo::Bump`](https://docs.rs/bumpalo/latest/bumpalo/struct.Bump.html).
+#[cfg(feature = "bumpalo")]
+pub use bumpalo::Bump;
+
+#[cfg(feature = "bumpalo")]
+impl ArenaAllocator for &Bump {
+    /// Walks the chunks from the current one, so a block that isn't in the bump visits all of
+    /// them. [`OwnedBump`] caches their ranges instead.
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        let chunks = unsafe { self.iter_allocated_chunks_raw() };
+        let ranges = chunks.map(|(chunk_ptr, chunk_size)| {
+            let chunk_start = chunk_ptr as usize;
+            chunk_start..chunk_start + chunk_size
+        });
+        crate::ranges_contain(ranges, ptr, layout)
+    }
+
+    /// Known only if the bump has an allocation limit, as new chunks are allocated otherwise.
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        let limit = self.allocation_limit()?;
+        Some(self.chunk_capacity() + limit.saturating_sub(Bump::allocated_bytes(self)))
+    }
+
+    /// Includes the space left unused at the end of the chunks before the current one.
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        Some(Bump::allocated_bytes(self) - self.chunk_capacity())
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl ProbeAllocator for &Bump {
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        // Once the limit is reached no new chunks are allocated, so the current one must fit it.
+        let limit_reached = self
+            .allocation_limit()
+            .is_some_and(|limit| Bump::allocated_bytes(self) >= limit);
+        !limit_reached || self.chunk_capacity() >= layout.size()
+    }
+}
+
+/// Chunks are released only by [`Bump::reset`], which needs exclusive access.
+#[cfg(feature = "bumpalo")]
+impl TrimAllocator for &Bump {}
+
+#[cfg(feature = "bumpalo")]
+impl BulkAllocator for &Bump {}
+
+#[cfg(feature = "bumpalo")]
+impl GoodSizeAllocator for &Bump {}
+
+/// `Bump` allocates downwards, so even its last allocation grows towards lower addresses and
+/// can't stay in place.
+#[cfg(feature = "bumpalo")]
+impl InPlaceGrow for &Bump {}
+
+/// Identified by the address of the `Bump`, which owns all of its chunks.
+#[cfg(feature = "bumpalo")]
+impl SameAllocator for &Bump {
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        AllocatorId::from_ptr(*self)
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl ResetAllocator for Bump {
+    #[inline]
+    fn reset(&mut self) {
+        Bump::reset(self)
+    }
+}
+
+#[inline]
+fn as_usize<T>(ptr: NonNull<T>) -> usize {
+    ptr.as_ptr() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Allocates, resizes and frees blocks of various layouts, checking their contents.
+    ///
+    /// Shared by the tests of the allocators in the submodules.
+    pub(crate) fn exercise<A: Allocator>(alloc: &A) {
+        for align in [1, 8, 64, 4096] {
+            for size in [0, 1, 24, 1000] {
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let block = alloc.allocate_zeroed(layout).unwrap();
+                let ptr = block.cast::<u8>();
+                assert_eq!(ptr.as_ptr() as usize % align, 0);
+                assert!(block.len() >= size);
+                unsafe {
+                    assert!((0..size).all(|i| *ptr.as_ptr().add(i) == 0));
+                    ptr.as_ptr().write_bytes(0xa5, size);
+
+                    let grown_layout = Layout::from_size_align(2 * size + 8, 16).unwrap();
+                    let grown = alloc.grow_zeroed(ptr, layout, grown_layout).unwrap();
+                    let grown = grown.cast::<u8>().as_ptr();
+                    assert_eq!(grown as usize % 16, 0);
+                    assert!((0..size).all(|i| *grown.add(i) == 0xa5));
+                    assert!((size..2 * size + 8).all(|i| *grown.add(i) == 0));
+
+                    let shrunk_layout = Layout::from_size_align(size / 2, align).unwrap();
+                    let shrunk = alloc
+                        .shrink(NonNull::new_unchecked(grown), grown_layout, shrunk_layout)
+                        .unwrap();
+                    let shrunk = shrunk.cast::<u8>();
+                    assert_eq!(shrunk.as_ptr() as usize % align, 0);
+                    assert!((0..size / 2).all(|i| *shrunk.as_ptr().add(i) == 0xa5));
+                    alloc.deallocate(shrunk, shrunk_layout);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stack_allocator_aligns_memory() {
+        let alloc = Stack::<16>::new();
+        let stack_addr = alloc.stack.get() as usize;
+
+        let layout = Layout::new::<u8>();
+        let ptr1 = alloc.allocate(layout).unwrap().cast::<u8>();
+        assert_eq!(alloc.used(), 1);
+        assert_eq!(as_usize(ptr1), stack_addr);
+
+        let layout = Layout::new::<u32>();
+        let ptr2_align_offset = unsafe { ptr1.add(1) }.align_offset(layout.align());
+        let ptr2 = alloc.allocate(layout).unwrap().cast::<u8>();
+        let ptr3 = alloc.allocate(layout).unwrap().cast::<u8>();
+        assert_eq!(alloc.used(), 1 + ptr2_align_offset + 4 + 4);
+        assert_eq!(as_usize(ptr2), as_usize(ptr1) + 1 + ptr2_align_offset);
+        assert_eq!(as_usize(ptr3), as_usize(ptr2) + 4);
+    }
+
+    #[test]
+    fn stack_allocator_aligns_from_cursor() {
+        #[repr(align(32))]
+        struct Aligned([u8; 32]);
+
+        let alloc = Stack::<256>::new();
+        let _ = alloc.allocate(Layout::new::<u8>()).unwrap();
+        let ptr = alloc.allocate(Layout::new::<u64>()).unwrap();
+        assert_eq!(as_usize(ptr.cast::<u8>()) % 8, 0);
+        let _ = alloc.allocate(Layout::new::<u8>()).unwrap();
+        let aligned = allocator_api2::boxed::Box::new_in(Aligned([1; 32]), &alloc);
+        assert_eq!(core::ptr::addr_of!(*aligned) as usize % 32, 0);
+        assert_eq!(aligned.0, [1; 32]);
+    }
+
+    #[test]
+    fn stack_allocator_allocates_zst() {
+        let alloc = Stack::<16>::new();
+        let _ = alloc.allocate(Layout::new::<[u8; 16]>()).unwrap();
+
+        let layout = Layout::new::<[u64; 0]>();
+        let ptr = alloc.allocate(layout).unwrap().cast::<u8>();
+        assert_eq!(alloc.used(), 16);
+        assert_eq!(as_usize(ptr) % layout.align(), 0);
+        unsafe { alloc.deallocate(ptr, layout) };
+        assert_eq!(alloc.used(), 16);
+    }
+
+    #[test]
+    fn stack_contains_half_open_range() {
+        let alloc = Stack::<16>::new();
+        let start = alloc.stack.get().cast::<u8>();
+        let ptr = |offset| unsafe { NonNull::new_unchecked(start.add(offset)) };
+        let zst = Layout::new::<()>();
+        let block = Layout::new::<[u8; 8]>();
+
+        alloc.allocate(block).unwrap();
+        alloc.allocate(block).unwrap();
+        assert!(alloc.contains(ptr(0), zst));
+        assert!(alloc.contains(ptr(15), zst));
+        assert!(!alloc.contains(ptr(16), zst));
+        assert!(alloc.contains(ptr(8), block));
+        assert!(!alloc.contains(ptr(9), block));
+    }
+
+    #[test]
+    fn stack_contains_blocks_at_range_boundaries() {
+        let alloc = Stack::<16>::new();
+        let base = alloc
+            .allocate(Layout::new::<[u8; 12]>())
+            .unwrap()
+            .cast::<u8>();
+        let range = alloc.arena_range().unwrap();
+        assert_eq!(range.len(), 12);
+        let ptr = |addr: usize| {
+            NonNull::new(base.as_ptr().wrapping_add(addr.wrapping_sub(range.start))).unwrap()
+        };
+        let block = Layout::new::<[u8; 4]>();
+
+        assert!(alloc.contains(ptr(range.start), block));
+        assert!(alloc.contains(ptr(range.end - 4), block));
+        assert!(!alloc.contains(ptr(range.end - 3), block));
+        assert!(!alloc.contains(ptr(range.end), block));
+        assert!(!alloc.contains(ptr(range.end), Layout::new::<()>()));
+        assert!(!alloc.contains(ptr(range.start - 1), block));
+        assert!(!alloc.contains(ptr(range.start), Layout::new::<[u8; 13]>()));
+        assert!(!alloc.contains(ptr(usize::MAX), block));
+    }
+
+    #[test]
+    fn stack_does_not_contain_unused_region() {
+        let alloc = Stack::<16>::new();
+        let start = alloc.stack.get().cast::<u8>();
+        let ptr = |offset| unsafe { NonNull::new_unchecked(start.add(offset)) };
+        let block = Layout::new::<[u8; 8]>();
+        assert!(!alloc.contains(ptr(0), block));
+        assert!(!alloc.contains(ptr(0), Layout::new::<()>()));
+
+        let first = alloc.allocate(block).unwrap().cast();
+        assert!(alloc.contains(first, block));
+        assert!(!alloc.contains(ptr(8), block));
+        assert!(!alloc.contains(ptr(4), block));
+        assert!(!alloc.contains(ptr(8), Layout::new::<()>()));
+
+        let second = alloc.allocate(block).unwrap().cast();
+        assert!(alloc.contains(second, block));
+
+        unsafe { alloc.deallocate(second, block) };
+        assert!(!alloc.contains(second, block));
+        assert!(alloc.contains(first, block));
+    }
+
+    #[test]
+    fn large_stack_on_heap() {
+        const SIZE: usize = 512 * 1024;
+
+        let alloc = std::boxed::Box::new(Stack::<SIZE>::new());
+        let v = allocator_api2::vec![in &*alloc; 7u8; SIZE / 2];
+        assert!(v.iter().all(|&b| b == 7));
+        let zeroed = alloc.allocate_zeroed(Layout::array::<u8>(SIZE / 2).unwrap());
+        let zeroed = unsafe { zeroed.unwrap().as_ref() };
+        assert!(zeroed.iter().all(|&b| b == 0));
+        assert!(alloc.allocate(Layout::new::<u8>()).is_err());
+    }
+
+    #[test]
+    fn aligned_stack_does_not_pad() {
+        let alloc = Stack::<256, 16>::new();
+        assert_eq!(alloc.stack.get() as usize % 16, 0);
+        for align in [1, 2, 4, 8, 16] {
+            let layout = Layout::from_size_align(16, align).unwrap();
+            let used = alloc.used();
+            let ptr = alloc.allocate(layout).unwrap().cast::<u8>();
+            assert_eq!(as_usize(ptr) % align, 0);
+            assert_eq!(alloc.used(), used + 16);
+        }
+    }
+
+    #[test]
+    fn aligned_stack_pads_above_its_alignment() {
+        let alloc = Stack::<256, 8>::new();
+        alloc.allocate(Layout::new::<u64>()).unwrap();
+        let layout = Layout::from_size_align(16, 64).unwrap();
+        let ptr = alloc.allocate(layout).unwrap().cast::<u8>();
+        assert_eq!(as_usize(ptr) % 64, 0);
+        let padding = alloc.used() - 8 - 16;
+        assert!(padding < 64 && padding % 8 == 0);
+    }
+
+    #[test]
+    fn page_aligned_stack_fits_pages_exactly() {
+        let alloc = std::boxed::Box::new(Stack::<8192, 4096>::new());
+        let page = Layout::from_size_align(4096, 4096).unwrap();
+        assert!(alloc.allocate(page).is_ok());
+        assert!(alloc.allocate(page).is_ok());
+        assert_eq!(alloc.remaining(), 0);
+    }
+
+    #[test]
+    fn stack_counts_padding_bytes() {
+        let alloc = Stack::<256, 8>::new();
+        for _ in 0..10 {
+            alloc.allocate(Layout::new::<u8>()).unwrap();
+            alloc.allocate(Layout::new::<u64>()).unwrap();
+            assert_eq!(alloc.top_padding(), 7);
+        }
+        assert_eq!(alloc.padding_bytes(), 10 * 7);
+        assert_eq!(alloc.used(), 10 * 16);
+
+        unsafe { alloc.reset_unchecked() };
+        assert_eq!(alloc.padding_bytes(), 0);
+        alloc.allocate(Layout::new::<u64>()).unwrap();
+        assert_eq!(alloc.padding_bytes(), 0);
+    }
+
+    #[test]
+    fn stack_reset_zeroed_wipes_old_data() {
+        for mut alloc in [Stack::<64>::new(), Stack::<64>::zeroed()] {
+            let layout = Layout::new::<[u8; 32]>();
+            let a = alloc.allocate(layout).unwrap();
+            let b = alloc.allocate(layout).unwrap();
+            unsafe {
+                a.cast::<u8>().write_bytes(0x5a, 32);
+                b.cast::<u8>().write_bytes(0xa5, 32);
+                // The data of deallocated blocks is wiped as well.
+                alloc.deallocate(b.cast(), layout);
+                alloc.deallocate(a.cast(), layout);
+            }
+            alloc.reset_zeroed();
+            assert_eq!(alloc.used(), 0);
+
+            let block = alloc.allocate(Layout::new::<[u8; 64]>()).unwrap();
+            assert_eq!(block.cast(), a.cast::<u8>());
+            assert!(unsafe { block.as_ref() }.iter().all(|&b| b == 0));
+        }
+    }
 
-unsafe impl Allocator for Failing {
-    #[inline]
-    fn allocate(&self, _layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        Err(AllocError)
+    #[test]
+    #[cfg(feature = "debug-tracking")]
+    fn stack_is_live_differs_from_contains() {
+        let stack = Stack::<64>::new();
+        let layout = Layout::new::<u32>();
+        let freed = stack.allocate(layout).unwrap().cast();
+        let live = stack.allocate(layout).unwrap().cast();
+        assert_eq!(stack.live_count(), 2);
+        unsafe { stack.deallocate(freed, layout) };
+
+        assert!(stack.contains(freed, layout) && !stack.is_live(freed));
+        assert!(stack.contains(live, layout) && stack.is_live(live));
+        assert_eq!(stack.live_count(), 1);
+        let scratch = [0u8; 4];
+        assert!(!stack.is_live(NonNull::from(&scratch).cast()));
+        assert!(!stack.is_live(stack.allocate(Layout::new::<()>()).unwrap().cast()));
     }
 
-    #[inline]
-    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {}
-}
+    #[test]
+    #[cfg(feature = "debug-tracking")]
+    fn stack_dumps_live_blocks() {
+        use std::string::String;
 
-impl ArenaAllocator for Failing {
-    #[inline]
-    fn contains(&self, _ptr: NonNull<u8>, _layout: Layout) -> bool {
-        false
+        fn dump<const SIZE: usize>(alloc: &Stack<SIZE, 8>) -> String {
+            let mut out = String::new();
+            alloc.dump(&mut out).unwrap();
+            out
+        }
+
+        let alloc = Stack::<128, 8>::new();
+        let marker = alloc.checkpoint();
+        let a = alloc.allocate(Layout::new::<[u8; 4]>()).unwrap().cast();
+        let b = alloc.allocate(Layout::new::<u64>()).unwrap().cast();
+        let c = alloc.allocate(Layout::new::<[u8; 8]>()).unwrap().cast();
+        unsafe {
+            alloc.deallocate(b, Layout::new::<u64>());
+            alloc
+                .grow(c, Layout::new::<[u8; 8]>(), Layout::new::<[u8; 24]>())
+                .unwrap();
+            alloc
+                .shrink(a, Layout::new::<[u8; 4]>(), Layout::new::<[u8; 2]>())
+                .unwrap();
+        }
+        assert_eq!(
+            dump(&alloc),
+            "    offset       size      align
+         0          2          1
+        16         24          1
+blocks: 2, live: 26, padding: 14, free: 88, total: 128
+"
+        );
+
+        unsafe { alloc.rewind(marker) };
+        assert_eq!(
+            dump(&alloc).lines().last().unwrap(),
+            "blocks: 0, live: 0, padding: 0, free: 128, total: 128"
+        );
     }
-}
 
-/// Stack-based bump allocator.
-#[derive(Debug)]
-pub struct Stack<const SIZE: usize> {
-    stack: UnsafeCell<[u8; SIZE]>,
-    idx: Cell<usize>,
-}
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn stack_restores_snapshot() {
+        let mut alloc = Stack::<128, 8>::new();
+        let small = Layout::new::<[u8; 2]>();
+        let grown = Layout::new::<[u8; 16]>();
+        let a = alloc.allocate(Layout::new::<u64>()).unwrap().cast::<u64>();
+        let b = alloc.allocate(small).unwrap().cast::<u8>();
+        unsafe {
+            a.write(1);
+            b.write_bytes(2, 2);
+        }
+        let snapshot = alloc.snapshot();
+        assert_eq!(snapshot.used(), 10);
 
-impl<const SIZE: usize> Default for Stack<SIZE> {
-    #[inline]
-    fn default() -> Self {
-        Self::new()
+        unsafe {
+            // Growing in place overwrites `b` and the bytes past its end.
+            let b = alloc.grow(b, small, grown).unwrap().cast::<u8>();
+            b.write_bytes(3, 16);
+            a.write(4);
+            alloc.allocate(Layout::new::<u64>()).unwrap();
+            assert_eq!(alloc.used(), 32);
+
+            alloc.restore(&snapshot);
+            assert_eq!(a.read(), 1);
+            assert_eq!(core::slice::from_raw_parts(b.as_ptr(), 2), [2, 2]);
+        }
+        assert_eq!(alloc.used(), 10);
+
+        // `b` is the topmost allocation again, so it can be deallocated.
+        unsafe { alloc.deallocate(b, small) };
+        assert_eq!(alloc.used(), 8);
     }
-}
 
-impl<const SIZE: usize> Stack<SIZE> {
-    #[inline]
-    pub const fn new() -> Self {
-        Self {
-            stack: UnsafeCell::new([0; SIZE]),
-            idx: Cell::new(0),
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn stack_restores_snapshot_after_moving() {
+        let alloc = Stack::<64>::new();
+        let layout = Layout::new::<u32>();
+        let ptr = alloc.allocate(layout).unwrap();
+        let snapshot = alloc.snapshot();
+        unsafe { alloc.deallocate(ptr.cast(), layout) };
+
+        let mut moved = std::boxed::Box::new(alloc);
+        unsafe { moved.restore(&snapshot) };
+        assert_eq!((moved.used(), moved.live_allocations()), (4, 1));
+    }
+
+    #[test]
+    fn stack_counts_live_allocations() {
+        let alloc = Stack::<128>::new();
+        let small = Layout::new::<[u8; 8]>();
+        let large = Layout::new::<[u8; 32]>();
+        assert!(alloc.is_empty());
+
+        let a = alloc.allocate(small).unwrap().cast();
+        let b = alloc.allocate(small).unwrap().cast();
+        alloc.allocate(Layout::new::<()>()).unwrap();
+        assert_eq!(alloc.live_allocations(), 2);
+
+        unsafe {
+            // In place and by moving.
+            let b = alloc.grow(b, small, large).unwrap().cast();
+            let a = alloc.grow(a, small, large).unwrap().cast();
+            assert_eq!(alloc.live_allocations(), 2);
+            let a = alloc.shrink(a, large, small).unwrap().cast();
+            alloc.shrink(b, large, Layout::new::<()>()).unwrap();
+            assert_eq!(alloc.live_allocations(), 1);
+            alloc.deallocate(a, small);
         }
+        assert!(alloc.is_empty());
+
+        let marker = alloc.checkpoint();
+        alloc.allocate(small).unwrap();
+        unsafe { alloc.rewind(marker) };
+        assert!(alloc.is_empty());
     }
 
-    /// Reset this stack allocator.
-    ///
-    /// Performs a mass deallocation on everything allocated in the stack by resetting the pointer.
-    /// Does not run any `Drop` implementations on deallocated objects.
-    #[inline]
-    pub fn reset(&mut self) {
-        self.idx.set(0)
+    #[test]
+    fn stack_rewind_counts_blocks_freed_after_the_marker() {
+        let alloc = Stack::<128>::new();
+        let layout = Layout::new::<u64>();
+        let a = alloc.allocate(layout).unwrap().cast();
+        let marker = alloc.checkpoint();
+        unsafe { alloc.deallocate(a, layout) };
+        // Reuses the memory of `a`, below the marker.
+        let b = alloc.allocate(layout).unwrap().cast::<u8>();
+        assert_eq!(b, a);
+        unsafe { alloc.rewind(marker) };
+        assert_eq!(alloc.live_allocations(), 0);
+        assert!(alloc.is_empty());
+
+        // Nested markers restore the count of the enclosing one.
+        let a = alloc.allocate(layout).unwrap().cast();
+        let outer = alloc.checkpoint();
+        let b = alloc.allocate(layout).unwrap().cast();
+        let inner = alloc.checkpoint();
+        unsafe { alloc.deallocate(b, layout) };
+        alloc.allocate(layout).unwrap();
+        unsafe { alloc.rewind(inner) };
+        assert_eq!(alloc.live_allocations(), 1);
+        alloc.allocate(layout).unwrap();
+        unsafe { alloc.rewind(outer) };
+        assert_eq!(alloc.live_allocations(), 1);
+        unsafe { alloc.deallocate(a, layout) };
+        assert!(alloc.is_empty());
     }
-}
 
-unsafe impl<const SIZE: usize> Allocator for Stack<SIZE> {
-    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        let stack = self.stack.get();
-        let unaligned_start = self.idx.get();
-        let align_offset = stack.align_offset(layout.align());
-        let aligned_start = unaligned_start
-            .checked_add(align_offset)
-            .ok_or(AllocError)?;
-        let aligned_end = aligned_start.checked_add(layout.size()).ok_or(AllocError)?;
-        if aligned_end > SIZE {
-            return Err(AllocError);
-        }
-        let slice = unsafe {
-            let slice = (*stack)
-                .get_mut(aligned_start..aligned_end)
-                .unwrap_unchecked();
-            NonNull::new_unchecked(ptr::addr_of_mut!(*slice))
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "resetting a stack with 1 live allocations"]
+    fn stack_reset_checked_with_live_allocations_panics_in_debug() {
+        let mut alloc = Stack::<64>::new();
+        alloc.allocate(Layout::new::<u64>()).unwrap();
+        alloc.reset_checked();
+    }
+
+    #[test]
+    fn stack_reset_discards_leaked_values() {
+        use crate::ArenaExt;
+
+        let mut alloc = Stack::<64>::new();
+        alloc.alloc_value(1u32).unwrap();
+        alloc.alloc_str("leaked").unwrap();
+        alloc.reset();
+        assert!(alloc.is_empty());
+        assert_eq!(alloc.used(), 0);
+
+        alloc.alloc_value(2u64).unwrap();
+        ResetAllocator::reset(&mut alloc);
+        assert_eq!(alloc.used(), 0);
+        alloc.reset_checked();
+    }
+
+    #[test]
+    fn stack_reset_unchecked_discards_live_allocations() {
+        let mut alloc = Stack::<64>::new();
+        alloc.allocate(Layout::new::<u64>()).unwrap();
+        unsafe { alloc.reset_unchecked() };
+        assert!(alloc.is_empty());
+        assert_eq!(alloc.used(), 0);
+        alloc.reset();
+    }
+
+    #[test]
+    fn stack_tracks_peak_usage() {
+        let alloc = Stack::<128>::new();
+        let small = Layout::new::<[u8; 3]>();
+        let aligned = Layout::new::<[u64; 4]>();
+
+        let a = alloc.allocate(small).unwrap().cast();
+        let b = alloc.allocate(aligned).unwrap().cast();
+        // The padding in front of `b` counts too.
+        let peak = alloc.used();
+        assert!(peak >= 35);
+        assert_eq!(alloc.peak_used(), peak);
+
+        unsafe {
+            alloc.deallocate(b, aligned);
+            alloc.deallocate(a, small);
+        }
+        assert_eq!(alloc.used(), 0);
+        let large = Layout::new::<[u8; 32]>();
+        let c = alloc.allocate(large).unwrap().cast();
+        assert_eq!(alloc.used(), 32);
+        assert_eq!(alloc.peak_used(), peak);
+
+        let grown = Layout::new::<[u8; 64]>();
+        unsafe { alloc.grow(c, large, grown).unwrap() };
+        assert_eq!(alloc.peak_used(), 64);
+
+        unsafe { alloc.reset_unchecked() };
+        assert_eq!(alloc.peak_used(), 64);
+        alloc.allocate(small).unwrap();
+        alloc.reset_peak();
+        assert_eq!(alloc.peak_used(), 3);
+    }
+
+    #[test]
+    fn zeroed_stack_skips_zeroing_virgin_memory() {
+        let alloc = Stack::<64>::zeroed();
+        let layout = Layout::new::<[u8; 16]>();
+        let first = alloc.allocate_zeroed(layout).unwrap();
+        assert!(unsafe { first.as_ref() }.iter().all(|&b| b == 0));
+        assert_eq!(alloc.dirty.get(), 16);
+
+        let second = alloc.allocate(layout).unwrap();
+        unsafe { second.cast::<u8>().write_bytes(0xff, 16) };
+        assert_eq!(alloc.dirty.get(), 32);
+    }
+
+    #[test]
+    fn zeroed_allocation_after_reset_has_no_stale_data() {
+        for alloc in [Stack::<64>::new(), Stack::<64>::zeroed()] {
+            let layout = Layout::new::<[u8; 48]>();
+            let block = alloc.allocate(layout).unwrap();
+            unsafe { block.cast::<u8>().write_bytes(0xaa, 48) };
+            unsafe { alloc.reset_unchecked() };
+
+            // Partly over the stale data, partly over memory that was never allocated.
+            let zeroed = alloc.allocate_zeroed(Layout::new::<[u8; 64]>()).unwrap();
+            assert!(unsafe { zeroed.as_ref() }.iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn zeroed_growth_after_reset_has_no_stale_data() {
+        let alloc = Stack::<64>::zeroed();
+        let block = alloc.allocate(Layout::new::<[u8; 32]>()).unwrap();
+        unsafe { block.cast::<u8>().write_bytes(0xaa, 32) };
+        unsafe { alloc.reset_unchecked() };
+
+        let small = Layout::new::<[u8; 8]>();
+        let ptr = alloc.allocate_zeroed(small).unwrap().cast();
+        let grown = unsafe { alloc.grow_zeroed(ptr, small, Layout::new::<[u8; 48]>()) };
+        assert_eq!(grown.unwrap().cast(), ptr);
+        assert!(unsafe { grown.unwrap().as_ref() }.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn zeroed_growth_after_restore_has_no_stale_data() {
+        let mut alloc = Stack::<64>::zeroed();
+        let layout = Layout::new::<[u8; 16]>();
+        let ptr = alloc.allocate(layout).unwrap().cast::<u8>();
+        unsafe { ptr.write_bytes(0xaa, 16) };
+        let snapshot = alloc.snapshot();
+        unsafe { alloc.deallocate(ptr, layout) };
+        alloc.reset_zeroed();
+        alloc.reset_peak();
+
+        unsafe {
+            // The snapshot writes the stale data back into the wiped buffer.
+            alloc.restore(&snapshot);
+            let empty = Layout::new::<()>();
+            let ptr = alloc.shrink(ptr, layout, empty).unwrap().cast();
+            let grown = alloc.grow_zeroed(ptr, empty, layout).unwrap();
+            assert_eq!(grown.as_ref(), [0; 16]);
+        }
+        assert_eq!(alloc.peak_used(), 16);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn pinned_stack_stays_in_place() {
+        struct Scratch {
+            stack: Pin<Box<Stack<256>>>,
+        }
+
+        let scratch = Scratch {
+            stack: Stack::pinned(),
         };
-        self.idx.set(aligned_end);
-        Ok(slice)
+        let layout = Layout::new::<u64>();
+        let ptr = scratch.stack.allocate(layout).unwrap().cast::<u64>();
+        unsafe { ptr.write(7) };
+
+        // Moving the box doesn't move the stack.
+        let moved = std::vec![scratch];
+        let stack = &*moved[0].stack;
+        unsafe {
+            assert_eq!(ptr.read(), 7);
+            stack.deallocate(ptr.cast(), layout);
+        }
+        assert_eq!(stack.used(), 0);
     }
 
-    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
-        let idx = self.idx.get();
-        let alloc_start = as_usize(ptr);
-        let alloc_end = alloc_start.saturating_add(layout.size());
-        if alloc_end == idx {
-            self.idx.set(alloc_start)
+    #[test]
+    fn empty_stack_can_be_moved() {
+        let mut stack = Stack::<64>::new();
+        let layout = Layout::new::<u64>();
+        let ptr = stack.allocate(layout).unwrap();
+        unsafe { stack.deallocate(ptr.cast(), layout) };
+        stack.reset();
+
+        let moved = std::boxed::Box::new(stack);
+        let ptr = moved.allocate(layout).unwrap();
+        unsafe { moved.deallocate(ptr.cast(), layout) };
+        assert_eq!(moved.used(), 0);
+    }
+
+    #[test]
+    fn stack_without_live_blocks_can_be_moved() {
+        let stack = Stack::<64>::new();
+        let layout = Layout::new::<u64>();
+        let a = stack.allocate(layout).unwrap();
+        let b = stack.allocate(layout).unwrap();
+        // Freeing out of order leaves the first block as unreclaimed padding.
+        unsafe {
+            stack.deallocate(a.cast(), layout);
+            stack.deallocate(b.cast(), layout);
         }
+        assert_eq!((stack.live_allocations(), stack.used()), (0, 8));
+
+        let moved = std::boxed::Box::new(stack);
+        let ptr = moved.allocate(layout).unwrap();
+        unsafe { moved.deallocate(ptr.cast(), layout) };
+        assert_eq!(moved.live_allocations(), 0);
     }
 
-    // TODO: optimize default implementations where applicable
-}
+    #[test]
+    fn rewound_stack_can_be_moved() {
+        let stack = Stack::<64>::new();
+        let layout = Layout::new::<u64>();
+        let a = stack.allocate(layout).unwrap();
+        let marker = stack.checkpoint();
+        unsafe { stack.deallocate(a.cast(), layout) };
+        stack.allocate(layout).unwrap();
+        unsafe { stack.rewind(marker) };
 
-// TODO: test owns
-impl<const SIZE: usize> ArenaAllocator for Stack<SIZE> {
-    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
-        let stack_start = self.stack.get() as usize;
-        let stack_end = stack_start.saturating_add(SIZE);
-        let alloc_start = as_usize(ptr);
-        let alloc_end = alloc_start.saturating_add(layout.size());
-        stack_start <= alloc_start && stack_end >= alloc_end
+        let moved = std::boxed::Box::new(stack);
+        let ptr = moved.allocate(layout).unwrap();
+        unsafe { moved.deallocate(ptr.cast(), layout) };
+        assert!(moved.is_empty());
     }
-}
 
-/// Re-rexport of [`bumpalo::Bump`](https://docs.rs/bumpalo/latest/bumpalo/struct.Bump.html).
-#[cfg(feature = "bumpalo")]
-pub use bumpalo::Bump;
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "stack was moved while allocations were live"]
+    fn moved_stack_panics_in_debug() {
+        let stack = Stack::<64>::new();
+        let layout = Layout::new::<u64>();
+        let ptr = stack.allocate(layout).unwrap();
+        let moved = std::boxed::Box::new(stack);
+        unsafe { moved.deallocate(ptr.cast(), layout) };
+    }
 
-#[cfg(feature = "bumpalo")]
-impl ArenaAllocator for &Bump {
-    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+    #[test]
+    fn stack_rewinds_loop_scratch() {
+        let alloc = Stack::<64>::new();
+        let kept = alloc.allocate(Layout::new::<[u8; 8]>()).unwrap();
+        let marker = alloc.checkpoint();
+
+        let mut scratch = None;
+        for i in 0..100 {
+            let ptr = alloc.allocate(Layout::new::<[u8; 32]>()).unwrap();
+            unsafe { ptr.cast::<u8>().write_bytes(i, 32) };
+            assert_eq!(*scratch.get_or_insert(ptr), ptr);
+            assert!(marker < alloc.checkpoint());
+            unsafe { alloc.rewind(marker) };
+        }
+        assert_eq!(alloc.checkpoint(), marker);
+        assert_eq!(alloc.used(), 8);
+        assert!(alloc.contains(kept.cast(), Layout::new::<[u8; 8]>()));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "rewinding to a marker beyond the current position"]
+    fn stack_rewind_past_cursor_panics() {
+        let alloc = Stack::<64>::new();
+        let start = alloc.checkpoint();
+        let _ = alloc.allocate(Layout::new::<u64>()).unwrap();
+        let end = alloc.checkpoint();
         unsafe {
-            self.iter_allocated_chunks_raw()
-                .any(|(chunk_ptr, chunk_size)| {
-                    let chunk_start = chunk_ptr as usize;
-                    let chunk_end = chunk_start.saturating_add(chunk_size);
-                    let alloc_start = as_usize(ptr);
-                    let alloc_end = alloc_start.saturating_add(layout.size());
-                    chunk_start <= alloc_start && chunk_end >= alloc_end
-                })
+            alloc.rewind(start);
+            alloc.rewind(end);
         }
     }
-}
 
-#[inline]
-fn as_usize<T>(ptr: NonNull<T>) -> usize {
-    ptr.as_ptr() as usize
-}
+    #[test]
+    fn stack_scopes_reuse_memory() {
+        let mut alloc = Stack::<64>::new();
+        let layout = Layout::new::<[u8; 48]>();
+        let first = alloc.scope(|alloc| alloc.allocate(layout).unwrap().cast::<u8>());
+        for _ in 0..10 {
+            let ptr = alloc.scope(|alloc| alloc.allocate(layout).unwrap().cast::<u8>());
+            assert_eq!(ptr, first);
+        }
+        assert_eq!(alloc.used(), 0);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn stack_scope_rewinds_on_panic() {
+        let mut alloc = Stack::<64>::new();
+        let _ = alloc.allocate(Layout::new::<[u8; 8]>()).unwrap();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            alloc.scope(|alloc| {
+                let _ = alloc.allocate(Layout::new::<[u8; 32]>()).unwrap();
+                panic!("scratch failure");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(alloc.used(), 8);
+    }
 
     #[test]
-    fn stack_allocator_aligns_memory() {
-        let alloc = Stack::<16>::new();
-        let stack_addr = alloc.stack.get() as usize;
+    fn stack_grows_topmost_allocation_in_place() {
+        use allocator_api2::vec::Vec;
 
-        let layout = Layout::new::<u8>();
-        let ptr1 = alloc.allocate(layout).unwrap().cast::<u8>();
-        assert_eq!(alloc.idx.get(), 1);
-        assert_eq!(as_usize(ptr1), stack_addr);
+        let alloc = Stack::<4096>::new();
+        let mut v = Vec::with_capacity_in(1, &alloc);
+        let ptr = v.as_ptr();
+        v.extend(0..1000u16);
+        assert_eq!(v.as_ptr(), ptr);
+        assert!(v.iter().copied().eq(0..1000));
+        assert_eq!(alloc.used(), v.capacity() * 2);
 
-        let layout = Layout::new::<u32>();
-        let ptr1_align_offset = ptr1.align_offset(layout.align());
-        let ptr2 = alloc.allocate(layout).unwrap().cast::<u8>();
-        let ptr3 = alloc.allocate(layout).unwrap().cast::<u8>();
-        assert_eq!(alloc.idx.get(), 4 + 4 + ptr1_align_offset + 1);
-        assert_eq!(as_usize(ptr2), 1 + ptr1_align_offset + as_usize(ptr1));
-        assert_eq!(as_usize(ptr3), 4 + as_usize(ptr2));
+        let grown = unsafe {
+            let layout = Layout::array::<u16>(v.capacity()).unwrap();
+            let extended = Layout::from_size_align(alloc.capacity() + 2, 2).unwrap();
+            alloc.grow_zeroed(
+                NonNull::new_unchecked(v.as_mut_ptr().cast()),
+                layout,
+                extended,
+            )
+        };
+        assert!(grown.is_err());
+        drop(v);
+
+        let ptr = alloc
+            .allocate(Layout::new::<[u8; 4]>())
+            .unwrap()
+            .cast::<u8>();
+        let bytes = unsafe {
+            ptr.write_bytes(0xff, 4);
+            let layout = Layout::new::<[u8; 8]>();
+            let grown = alloc
+                .grow_zeroed(ptr, Layout::new::<[u8; 4]>(), layout)
+                .unwrap();
+            assert_eq!(grown.cast(), ptr);
+            grown.as_ref()
+        };
+        assert_eq!(bytes, [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]);
+
+        // Blocks below the top are moved.
+        let mut below = Vec::with_capacity_in(1, &alloc);
+        let _top = alloc.allocate(Layout::new::<u8>()).unwrap();
+        let ptr = below.as_ptr();
+        below.push(1u8);
+        below.push(2);
+        assert_ne!(below.as_ptr(), ptr);
+        assert_eq!(below, [1, 2]);
     }
 
     #[test]
-    fn stack_allocator_allocates_zst() {
-        let alloc = Stack::<16>::new();
-        let stack_addr = alloc.stack.get() as usize;
+    fn stack_shrinks_in_place() {
+        use allocator_api2::vec::Vec;
 
-        let layout = Layout::new::<()>();
-        let ptr = alloc.allocate(layout).unwrap().cast::<u8>();
-        assert_eq!(alloc.idx.get(), 0);
-        assert_eq!(as_usize(ptr), stack_addr);
+        let alloc = Stack::<256>::new();
+        let mut v = Vec::with_capacity_in(64, &alloc);
+        v.extend(0..16u8);
+        let ptr = v.as_ptr();
+        v.shrink_to_fit();
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(alloc.used(), 16);
+
+        let _top = alloc.allocate(Layout::new::<[u8; 32]>()).unwrap();
+        v.truncate(4);
+        v.shrink_to_fit();
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(v, [0, 1, 2, 3]);
+        assert_eq!(alloc.used(), 48);
+
+        // A stricter alignment moves the block.
+        if (alloc.stack.get() as usize + alloc.used()) % 8 == 0 {
+            let _ = alloc.allocate(Layout::new::<u8>()).unwrap();
+        }
+        let misaligned = alloc
+            .allocate(Layout::new::<[u8; 32]>())
+            .unwrap()
+            .cast::<u8>();
+        let layout = Layout::new::<[u64; 2]>();
+        let shrunk = unsafe { alloc.shrink(misaligned, Layout::new::<[u8; 32]>(), layout) };
+        let shrunk = shrunk.unwrap().cast::<u8>();
+        assert_ne!(shrunk, misaligned);
+        assert_eq!(as_usize(shrunk) % 8, 0);
+    }
+
+    #[test]
+    fn stack_deallocate_reclaims_padding() {
+        let alloc = Stack::<64>::new();
+        let _ = alloc.allocate(Layout::new::<u8>()).unwrap();
+        for _ in 0..100 {
+            let ptr = alloc.allocate(Layout::new::<u64>()).unwrap();
+            assert_eq!(as_usize(ptr.cast::<u8>()) % 8, 0);
+            unsafe { alloc.deallocate(ptr.cast(), Layout::new::<u64>()) };
+            assert_eq!(alloc.used(), 1);
+        }
+
+        let ptr = alloc.allocate(Layout::new::<u64>()).unwrap().cast::<u8>();
+        let ptr = unsafe { alloc.shrink(ptr, Layout::new::<u64>(), Layout::new::<()>()) };
+        assert_eq!(ptr.unwrap().len(), 0);
+        assert_eq!(alloc.used(), 1);
+    }
+
+    #[test]
+    fn stack_resets_through_fallback() {
+        use crate::Allocandrescu as _;
+        use allocator_api2::vec::Vec;
+
+        let stack = Stack::<64>::new();
+        let alloc = stack
+            .by_ref()
+            .cond(|layout| layout.size() <= 32)
+            .fallback(std::alloc::System);
+        let owned = Stack::<64>::new().fallback(Failing);
+
+        let mut first = None;
+        for round in 0..10 {
+            let mut v = Vec::with_capacity_in(32, &alloc);
+            v.extend(0..32u8);
+            let mut w = Vec::with_capacity_in(64, &owned);
+            w.extend(0..64u8);
+            assert_eq!(
+                *first.get_or_insert(v.as_ptr()),
+                v.as_ptr(),
+                "round {round}"
+            );
+            assert!(stack.contains(NonNull::from(&v[0]), Layout::new::<[u8; 32]>()));
+            assert!(owned
+                .primary()
+                .contains(NonNull::from(&w[0]), Layout::new::<[u8; 64]>()));
+            drop((v, w));
+
+            unsafe {
+                alloc.primary().inner().reset_unchecked();
+                owned.primary().reset_unchecked();
+            }
+            assert_eq!(stack.used(), 0);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "is not within the stack"]
+    fn stack_rejects_foreign_pointer_in_debug() {
+        use std::alloc::System;
+
+        let alloc = Stack::<64>::new();
+        let layout = Layout::new::<u64>();
+        struct Free(NonNull<[u8]>, Layout);
+        impl Drop for Free {
+            fn drop(&mut self) {
+                unsafe { System.deallocate(self.0.cast(), self.1) }
+            }
+        }
+
+        let foreign = Free(System.allocate(layout).unwrap(), layout);
+        unsafe { alloc.deallocate(foreign.0.cast(), layout) };
+    }
+
+    #[test]
+    fn stack_keeps_older_allocations_writable() {
+        let alloc = Stack::<64>::new();
+        let older = alloc
+            .allocate(Layout::new::<[u8; 16]>())
+            .unwrap()
+            .cast::<u8>();
+        unsafe { older.write_bytes(1, 16) };
+        let newer = alloc
+            .allocate(Layout::new::<[u8; 16]>())
+            .unwrap()
+            .cast::<u8>();
+        unsafe {
+            newer.write_bytes(2, 16);
+            older.write_bytes(3, 16);
+            assert_eq!(older.as_ptr().read(), 3);
+            assert_eq!(newer.as_ptr().read(), 2);
+        }
+        let newest =
+            unsafe { alloc.grow(newer, Layout::new::<[u8; 16]>(), Layout::new::<[u8; 32]>()) };
+        unsafe {
+            older.as_ptr().add(15).write(4);
+            assert_eq!(newest.unwrap().cast::<u8>().as_ptr().read(), 2);
+        }
     }
 
     #[test]
     fn stack_allocator_handles_out_of_memory() {
         let alloc = Stack::<4>::new();
+        assert_eq!(alloc.capacity(), 4);
 
         let layout = Layout::new::<u8>();
         let _ = alloc.allocate(layout).unwrap();
-        assert_eq!(alloc.idx.get(), 1);
+        assert_eq!(alloc.used(), 1);
+        assert_eq!(alloc.remaining(), 3);
 
         let layout = Layout::new::<u32>();
         let _ptr = alloc.allocate(layout).unwrap_err();
-        assert_eq!(alloc.idx.get(), 1);
+        assert_eq!(alloc.used(), 1);
     }
 
     #[test]
@@ -240,4 +2170,180 @@ mod tests {
         assert!(!bump.contains(NonNull::new(addr_of!(v3[0]).cast_mut()).unwrap(), layout));
         assert!(!bump.contains(NonNull::new(addr_of!(v3[8]).cast_mut()).unwrap(), layout));
     }
+
+    #[cfg(feature = "bumpalo")]
+    #[test]
+    fn bumpalo_contains_blocks_at_chunk_boundaries() {
+        let bump = Bump::with_capacity(64);
+        bump.alloc([0u8; 16]);
+        let bump = &bump;
+        let (chunk_ptr, chunk_size) = unsafe { bump.iter_allocated_chunks_raw() }.next().unwrap();
+        let (start, end) = (chunk_ptr as usize, chunk_ptr as usize + chunk_size);
+        let ptr =
+            |addr: usize| NonNull::new(chunk_ptr.wrapping_add(addr.wrapping_sub(start))).unwrap();
+        let block = Layout::new::<[u8; 4]>();
+
+        assert!(bump.contains(ptr(start), block));
+        assert!(bump.contains(ptr(end - 4), block));
+        assert!(!bump.contains(ptr(end - 3), block));
+        assert!(!bump.contains(ptr(end), Layout::new::<()>()));
+        assert!(!bump.contains(ptr(start - 1), block));
+        assert!(bump.arena_range().is_none());
+    }
+
+    fn allocate_many<A: BulkAllocator>(
+        alloc: &A,
+        layout: Layout,
+        n: usize,
+    ) -> std::vec::Vec<NonNull<u8>> {
+        let mut blocks = std::vec::Vec::new();
+        let count = alloc
+            .allocate_many(layout, n, &mut |block| {
+                assert_eq!(block.len(), layout.size());
+                blocks.push(block.cast())
+            })
+            .unwrap_or(0);
+        assert_eq!(count, blocks.len());
+        blocks
+    }
+
+    #[test]
+    fn stack_allocate_many_is_contiguous() {
+        let stack = Stack::<256>::new();
+        let _byte = stack.allocate(Layout::new::<u8>()).unwrap();
+        let layout = Layout::from_size_align(12, 8).unwrap();
+        let blocks = allocate_many(&stack, layout, 4);
+        assert_eq!(blocks.len(), 4);
+        let addrs = blocks.iter().map(|ptr| ptr.as_ptr() as usize);
+        assert!(addrs.clone().zip(addrs.skip(1)).all(|(a, b)| b - a == 16));
+        assert_eq!(blocks[0].as_ptr() as usize % 8, 0);
+        assert_eq!(stack.used(), 8 + 3 * 16 + 12);
+        assert_eq!(stack.live_allocations(), 5);
+
+        // Only the blocks that fit are allocated.
+        let rest = allocate_many(&stack, Layout::new::<[u8; 64]>(), 10);
+        assert_eq!(rest.len(), 2);
+        assert!(allocate_many(&stack, Layout::new::<[u8; 64]>(), 1).is_empty());
+        assert_eq!(allocate_many(&stack, Layout::new::<()>(), 3).len(), 3);
+        assert_eq!(allocate_many(&stack, layout, 0).len(), 0);
+    }
+
+    #[test]
+    fn stack_deallocate_many_reclaims_batch() {
+        let stack = Stack::<256>::new();
+        let layout = Layout::new::<[u32; 4]>();
+        let blocks = allocate_many(&stack, layout, 8);
+        let mut ptrs = blocks.into_iter().rev();
+        unsafe { stack.deallocate_many(layout, &mut ptrs) };
+        assert_eq!(stack.used(), 0);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn bulk_allocator_loops_by_default() {
+        let stack = Stack::<64>::new();
+        let sub = stack.carve(40).unwrap();
+        let blocks = allocate_many(&&sub, Layout::new::<u64>(), 8);
+        assert_eq!(blocks.len(), 5);
+        assert!(allocate_many(&Failing, Layout::new::<u64>(), 8).is_empty());
+    }
+
+    #[test]
+    fn stack_try_grow_in_place_never_moves() {
+        let stack = Stack::<64>::new();
+        let layout = |size| Layout::from_size_align(size, 1).unwrap();
+        let block = stack.allocate(layout(8)).unwrap().cast::<u8>();
+        unsafe { block.as_ptr().write_bytes(7, 8) };
+        let intact = || unsafe { core::slice::from_raw_parts(block.as_ptr(), 8) } == [7; 8];
+
+        let grown = unsafe { stack.try_grow_in_place(block, layout(8), layout(24)) }.unwrap();
+        assert_eq!((grown.cast::<u8>(), grown.len()), (block, 24));
+        assert!(intact());
+        assert!(unsafe { stack.try_grow_in_place(block, layout(24), layout(65)) }.is_err());
+
+        let top = stack.allocate(layout(8)).unwrap();
+        assert!(unsafe { stack.try_grow_in_place(block, layout(24), layout(32)) }.is_err());
+        assert_eq!(stack.used(), 32);
+        assert!(intact());
+
+        unsafe { stack.deallocate(top.cast(), layout(8)) };
+        let grown = unsafe { stack.try_grow_in_place(block, layout(24), layout(32)) }.unwrap();
+        assert_eq!(grown.cast::<u8>(), block);
+        unsafe { stack.deallocate(block, layout(32)) };
+        assert!(stack.is_empty());
+    }
+
+    #[cfg(feature = "bumpalo")]
+    #[test]
+    fn bump_never_grows_in_place() {
+        let bump = Bump::new();
+        let alloc = &bump;
+        let (old_layout, new_layout) = (Layout::new::<u32>(), Layout::new::<[u32; 2]>());
+        let block = alloc.allocate(old_layout).unwrap().cast::<u32>();
+        unsafe { block.write(42) };
+        assert!(unsafe { alloc.try_grow_in_place(block.cast(), old_layout, new_layout) }.is_err());
+        assert_eq!(unsafe { block.read() }, 42);
+        let grown = unsafe { alloc.grow(block.cast(), old_layout, new_layout) }.unwrap();
+        assert_eq!(unsafe { grown.cast::<u32>().read() }, 42);
+    }
+
+    #[cfg(feature = "bumpalo")]
+    #[test]
+    fn reset_allocator_recycles_stack_and_bump() {
+        fn recycle<A: ResetAllocator>(arena: &mut A) {
+            arena.reset();
+        }
+        let layout = Layout::new::<u64>();
+
+        let mut stack = Stack::<64>::new();
+        let a = stack.allocate(layout).unwrap().cast();
+        let b = stack.allocate(layout).unwrap().cast();
+        unsafe {
+            stack.deallocate(a, layout);
+            stack.deallocate(b, layout);
+        }
+        assert_eq!(stack.used(), 8);
+        recycle(&mut stack);
+        assert_eq!(stack.used(), 0);
+        assert_eq!(stack.allocate(layout).unwrap().cast(), a);
+
+        let mut bump = Bump::new();
+        let first = NonNull::from(bump.alloc(1u64));
+        bump.alloc(2u64);
+        recycle(&mut bump);
+        assert_eq!(NonNull::from(bump.alloc(3u64)), first);
+    }
+
+    #[cfg(feature = "bumpalo")]
+    #[test]
+    fn bumpalo_probe_respects_allocation_limit() {
+        let bump = Bump::with_capacity(64);
+        bump.set_allocation_limit(Some(bump.allocated_bytes()));
+        let bump = &bump;
+
+        let layout = Layout::new::<[u8; 8]>();
+        while bump.can_allocate(layout) {
+            bump.allocate(layout).unwrap();
+        }
+        assert!(bump.allocate(layout).is_err());
+        assert!(bump.can_allocate(Layout::new::<()>()));
+    }
+
+    #[cfg(feature = "bumpalo")]
+    #[test]
+    fn bumpalo_reports_capacity_only_with_limit() {
+        let unlimited = Bump::with_capacity(64);
+        assert_eq!((&unlimited).remaining_capacity(), None);
+
+        let bump = Bump::with_capacity(64);
+        bump.set_allocation_limit(Some(bump.allocated_bytes()));
+        let bump = &bump;
+        let capacity = bump.remaining_capacity().unwrap();
+        assert!(capacity >= 64);
+        assert_eq!(ArenaAllocator::allocated_bytes(&bump), Some(0));
+
+        bump.allocate(Layout::new::<[u8; 16]>()).unwrap();
+        assert_eq!(bump.remaining_capacity(), Some(capacity - 16));
+        assert_eq!(ArenaAllocator::allocated_bytes(&bump), Some(16));
+    }
 }
@@ -0,0 +1,210 @@
+use super::{Align, Alignment, Stack};
+use crate::{Error, Operation};
+use core::{alloc::Layout, fmt, mem::ManuallyDrop, ptr, slice};
+
+/// Builds a byte buffer of unknown length in place at the top of a [`Stack`].
+///
+/// The builder claims all the remaining space of the stack, so that the buffer can grow without
+/// being copied. While the builder is open, allocations in the stack fail.
+/// [`finish`](StackBuilder::finish) releases the space that the buffer didn't use.
+/// Dropping the builder without finishing it releases the buffer as well.
+///
+/// This `struct` is created by [`builder`](Stack::builder) method on [`Stack`].
+/// See its documentation for more details.
+pub struct StackBuilder<'a, const SIZE: usize, const ALIGN: usize = 1>
+where
+    Align<ALIGN>: Alignment,
+{
+    stack: &'a Stack<SIZE, ALIGN>,
+    start: usize,
+    len: usize,
+}
+
+impl<'a, const SIZE: usize, const ALIGN: usize> StackBuilder<'a, SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    #[inline]
+    pub(crate) fn new(stack: &'a Stack<SIZE, ALIGN>) -> Self {
+        let start = stack.idx.get();
+        // Claiming the rest of the buffer makes other allocations fail.
+        stack.idx.set(SIZE);
+        stack.record_base();
+        Self {
+            stack,
+            start,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of bytes in the buffer.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the buffer is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes the buffer can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        SIZE - self.start
+    }
+
+    /// Returns the bytes in the buffer.
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr(), self.len) }
+    }
+
+    /// Appends a byte to the buffer. Fails if the stack is full.
+    #[inline]
+    pub fn push(&mut self, byte: u8) -> Result<(), Error> {
+        self.extend_from_slice(&[byte])
+    }
+
+    /// Appends bytes to the buffer. Fails without appending anything if they don't fit in the stack.
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if bytes.len() > self.capacity() - self.len {
+            // The requested length fits in `usize`, as both the buffer and `bytes` do.
+            let layout = Layout::array::<u8>(self.len + bytes.len()).ok();
+            return Err(Error::new(Operation::Grow, layout));
+        }
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), self.ptr().add(self.len), bytes.len());
+        }
+        self.len += bytes.len();
+        // The bytes outlive the builder if it is dropped, so a zeroed stack must wipe them.
+        let dirty = &self.stack.dirty;
+        dirty.set(dirty.get().max(self.start + self.len));
+        Ok(())
+    }
+
+    /// Returns the buffer and releases the rest of the stack.
+    ///
+    /// The buffer stays allocated until the stack is reset or rewound. It must not be deallocated.
+    pub fn finish(self) -> &'a mut [u8] {
+        let this = ManuallyDrop::new(self);
+        let end = this.start + this.len;
+        this.stack.idx.set(this.start);
+        this.stack.bump(end);
+        this.stack.padding.set(0);
+        unsafe { slice::from_raw_parts_mut(this.ptr(), this.len) }
+    }
+
+    #[inline]
+    fn ptr(&self) -> *mut u8 {
+        // `start` is within the buffer, or one past its end.
+        unsafe { self.stack.stack.get().cast::<u8>().add(self.start) }
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> Drop for StackBuilder<'_, SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    fn drop(&mut self) {
+        self.stack.idx.set(self.start);
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> fmt::Write for StackBuilder<'_, SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.extend_from_slice(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> fmt::Debug for StackBuilder<'_, SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StackBuilder")
+            .field("start", &self.start)
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use allocator_api2::alloc::Allocator;
+    use core::{alloc::Layout, fmt::Write};
+
+    #[test]
+    fn builder_consumes_only_final_length() {
+        let stack = Stack::<1024>::new();
+        stack.allocate(Layout::new::<[u8; 10]>()).unwrap();
+
+        let mut builder = stack.builder();
+        for i in 0..50 {
+            write!(builder, "{i},").unwrap();
+        }
+        builder.push(b'!').unwrap();
+        let len = builder.len();
+        assert!(len > 64);
+        let bytes = builder.finish();
+        assert_eq!(bytes.len(), len);
+        assert!(bytes.starts_with(b"0,1,2,"));
+        assert!(bytes.ends_with(b"48,49,!"));
+        assert_eq!(stack.used(), 10 + len);
+
+        let after = stack.allocate(Layout::new::<u8>()).unwrap();
+        assert!(unsafe { bytes.as_ptr().add(len) } <= after.cast::<u8>().as_ptr());
+    }
+
+    #[test]
+    fn builder_fails_concurrent_allocations() {
+        let stack = Stack::<64>::new();
+        let mut builder = stack.builder();
+        builder.extend_from_slice(b"abc").unwrap();
+        assert!(stack.allocate(Layout::new::<u8>()).is_err());
+        assert_eq!(builder.finish(), b"abc");
+        assert!(stack.allocate(Layout::new::<u8>()).is_ok());
+    }
+
+    #[test]
+    fn builder_stops_at_end_of_stack() {
+        let stack = Stack::<8>::new();
+        let mut builder = stack.builder();
+        assert_eq!(builder.capacity(), 8);
+        assert!(builder.write_str("too long!").is_err());
+        assert!(builder.is_empty());
+        builder.extend_from_slice(b"12345678").unwrap();
+        let error = builder.push(b'9').unwrap_err();
+        assert_eq!(
+            std::format!("{error}"),
+            "failed to grow a block of size 9 and align 1"
+        );
+        assert_eq!(builder.as_slice(), b"12345678");
+    }
+
+    #[test]
+    fn dropped_builder_releases_stack() {
+        let stack = Stack::<64>::new();
+        let mut builder = stack.builder();
+        builder.extend_from_slice(&[1; 32]).unwrap();
+        drop(builder);
+        assert_eq!(stack.used(), 0);
+    }
+
+    #[test]
+    fn dropped_builder_leaves_no_stale_data_in_zeroed_stack() {
+        let stack = Stack::<64>::zeroed();
+        let mut builder = stack.builder();
+        builder.write_str("secret").unwrap();
+        drop(builder);
+
+        let block = stack.allocate_zeroed(Layout::new::<[u8; 6]>()).unwrap();
+        assert_eq!(unsafe { block.as_ref() }, [0; 6]);
+    }
+}
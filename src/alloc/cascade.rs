@@ -0,0 +1,355 @@
+use crate::{dangling, ArenaAllocator, ResetAllocator};
+use alloc_crate::{boxed::Box, vec::Vec};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::RefCell, fmt, ptr, ptr::NonNull};
+
+/// An allocator over a list of arenas, creating a new one with `factory` whenever the existing
+/// ones can't serve an allocation.
+///
+/// Allocations are tried against the most recent arena first, falling back through the older
+/// ones. Deallocations are routed to the arena that [contains](ArenaAllocator::contains) the
+/// block. Each arena is boxed, so arenas storing their memory inline, like [`Stack`](super::Stack),
+/// stay in place as the list grows. All arenas are dropped with the cascade.
+///
+/// The first arena is created on the first allocation. A new arena that can't serve the allocation
+/// either is dropped right away, so an allocation larger than a fresh arena just fails.
+/// Zero-sized allocations are served by `Cascade` itself, see
+/// [zero-sized allocations](crate#zero-sized-allocations).
+///
+/// # Example
+/// ```
+/// use allocandrescu::alloc::{Cascade, Stack};
+/// use allocator_api2::vec::Vec;
+///
+/// let cascade = Cascade::new(Stack::<256>::new);
+/// let a = Vec::<u8, _>::with_capacity_in(200, &cascade);
+/// let b = Vec::<u8, _>::with_capacity_in(200, &cascade);
+/// assert_eq!(cascade.arenas(), 2);
+/// # drop((a, b));
+/// ```
+pub struct Cascade<F, A> {
+    factory: F,
+    arenas: RefCell<Vec<Box<A>>>,
+    max_arenas: usize,
+}
+
+impl<F, A> Cascade<F, A> {
+    /// Creates a cascade that creates as many arenas as needed.
+    #[inline]
+    pub const fn new(factory: F) -> Self {
+        Self::with_max_arenas(factory, usize::MAX)
+    }
+
+    /// Creates a cascade that creates at most `max_arenas` arenas, failing allocations that none
+    /// of them can serve afterwards.
+    #[inline]
+    pub const fn with_max_arenas(factory: F, max_arenas: usize) -> Self {
+        Self {
+            factory,
+            arenas: RefCell::new(Vec::new()),
+            max_arenas,
+        }
+    }
+
+    /// Returns the number of arenas created so far.
+    #[inline]
+    pub fn arenas(&self) -> usize {
+        self.arenas.borrow().len()
+    }
+
+    /// Returns the maximum number of arenas.
+    #[inline]
+    pub fn max_arenas(&self) -> usize {
+        self.max_arenas
+    }
+
+    /// Returns the index of the arena that contains the block, if any.
+    #[inline]
+    fn owner(&self, ptr: NonNull<u8>, layout: Layout) -> Option<usize>
+    where
+        A: ArenaAllocator,
+    {
+        let arenas = self.arenas.borrow();
+        arenas.iter().rposition(|arena| arena.contains(ptr, layout))
+    }
+
+    /// Returns the arena at `idx`.
+    ///
+    /// Arenas are boxed and only dropped by `reset` and `drop`, which take `self` mutably, so the
+    /// reference stays valid while `self` is borrowed.
+    #[inline]
+    fn arena(&self, idx: usize) -> &A {
+        let arenas = self.arenas.borrow();
+        let arena: *const A = &*arenas[idx];
+        unsafe { &*arena }
+    }
+}
+
+impl<F, A> Cascade<F, A>
+where
+    F: Fn() -> A,
+    A: ArenaAllocator,
+{
+    fn alloc(&self, layout: Layout, zeroed: bool) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        let alloc = |arena: &A| {
+            if zeroed {
+                arena.allocate_zeroed(layout)
+            } else {
+                arena.allocate(layout)
+            }
+        };
+        for idx in (0..self.arenas()).rev() {
+            if let Ok(block) = alloc(self.arena(idx)) {
+                return Ok(block);
+            }
+        }
+        if self.arenas() >= self.max_arenas {
+            return Err(AllocError);
+        }
+        // Boxing the arena first keeps the block in place when it is pushed.
+        let arena = Box::new((self.factory)());
+        let block = alloc(&arena)?;
+        self.arenas.borrow_mut().push(arena);
+        Ok(block)
+    }
+
+    /// Moves the block to memory allocated anywhere in the cascade.
+    unsafe fn relocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc(new_layout, zeroed)?;
+        let len = old_layout.size().min(new_layout.size());
+        ptr::copy_nonoverlapping(ptr.as_ptr(), block.cast().as_ptr(), len);
+        self.deallocate(ptr, old_layout);
+        Ok(block)
+    }
+}
+
+impl<F, A> fmt::Debug for Cascade<F, A>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cascade")
+            .field("arenas", &self.arenas.borrow())
+            .field("max_arenas", &self.max_arenas)
+            .finish_non_exhaustive()
+    }
+}
+
+unsafe impl<F, A> Allocator for Cascade<F, A>
+where
+    F: Fn() -> A,
+    A: ArenaAllocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, false)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, true)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        let owner = self.owner(ptr, layout);
+        debug_assert!(
+            owner.is_some(),
+            "deallocated block {ptr:p} is not within the cascade"
+        );
+        if let Some(idx) = owner {
+            self.arena(idx).deallocate(ptr, layout)
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() == 0 {
+            return self.alloc(new_layout, false);
+        }
+        let owner = self.owner(ptr, old_layout).ok_or(AllocError)?;
+        self.arena(owner)
+            .grow(ptr, old_layout, new_layout)
+            .or_else(|_| self.relocate(ptr, old_layout, new_layout, false))
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() == 0 {
+            return self.alloc(new_layout, true);
+        }
+        let owner = self.owner(ptr, old_layout).ok_or(AllocError)?;
+        self.arena(owner)
+            .grow_zeroed(ptr, old_layout, new_layout)
+            .or_else(|_| self.relocate(ptr, old_layout, new_layout, true))
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if new_layout.size() == 0 {
+            self.deallocate(ptr, old_layout);
+            return Ok(dangling(new_layout));
+        }
+        let owner = self.owner(ptr, old_layout).ok_or(AllocError)?;
+        self.arena(owner)
+            .shrink(ptr, old_layout, new_layout)
+            .or_else(|_| self.relocate(ptr, old_layout, new_layout, false))
+    }
+}
+
+impl<F, A> ArenaAllocator for Cascade<F, A>
+where
+    F: Fn() -> A,
+    A: ArenaAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.owner(ptr, layout).is_some()
+    }
+
+    /// Returns the total remaining capacity of the arenas once no more of them can be created.
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        let arenas = self.arenas.borrow();
+        if arenas.len() < self.max_arenas {
+            return None;
+        }
+        arenas.iter().map(|arena| arena.remaining_capacity()).sum()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        let arenas = self.arenas.borrow();
+        arenas.iter().map(|arena| arena.allocated_bytes()).sum()
+    }
+}
+
+impl<F, A> ResetAllocator for Cascade<F, A>
+where
+    A: ResetAllocator,
+{
+    /// Drops every arena but the first one and resets the first one.
+    #[inline]
+    fn reset(&mut self) {
+        let arenas = self.arenas.get_mut();
+        arenas.truncate(1);
+        if let Some(first) = arenas.first_mut() {
+            first.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::Stack;
+
+    #[test]
+    fn cascade_creates_arena_when_full() {
+        let cascade = Cascade::new(Stack::<64>::new);
+        assert_eq!(cascade.arenas(), 0);
+        let layout = Layout::new::<[u8; 32]>();
+        let a = cascade.allocate(layout).unwrap().cast::<u8>();
+        let b = cascade.allocate(layout).unwrap().cast::<u8>();
+        assert_eq!(cascade.arenas(), 1);
+        let c = cascade.allocate(layout).unwrap().cast::<u8>();
+        assert_eq!(cascade.arenas(), 2);
+        assert_eq!(cascade.allocated_bytes(), Some(96));
+        assert!([a, b, c].iter().all(|&ptr| cascade.contains(ptr, layout)));
+
+        // Freeing the top of the first arena makes room there again.
+        unsafe { cascade.deallocate(b, layout) };
+        assert_eq!(cascade.arena(0).used(), 32);
+        assert_eq!(cascade.arena(1).used(), 32);
+        let d = cascade.allocate(Layout::new::<[u8; 24]>()).unwrap();
+        assert_eq!(cascade.arena(1).used(), 56);
+        assert_eq!(cascade.arenas(), 2);
+
+        let e = cascade.allocate(layout).unwrap().cast::<u8>();
+        assert_eq!((cascade.arenas(), e), (2, b));
+        unsafe {
+            cascade.deallocate(e, layout);
+            cascade.deallocate(d.cast(), Layout::new::<[u8; 24]>());
+            cascade.deallocate(c, layout);
+            cascade.deallocate(a, layout);
+        }
+        assert_eq!(cascade.allocated_bytes(), Some(0));
+    }
+
+    #[test]
+    fn cascade_respects_max_arenas_and_reset() {
+        let mut cascade = Cascade::with_max_arenas(Stack::<16>::new, 2);
+        let layout = Layout::new::<[u8; 16]>();
+        let a = cascade.allocate(layout).unwrap().cast();
+        assert_eq!(cascade.remaining_capacity(), None);
+        let b = cascade.allocate(layout).unwrap().cast();
+        assert!(cascade.allocate(layout).is_err());
+        assert_eq!(
+            (cascade.arenas(), cascade.remaining_capacity()),
+            (2, Some(0))
+        );
+
+        unsafe {
+            cascade.deallocate(b, layout);
+            cascade.deallocate(a, layout);
+        }
+        cascade.reset();
+        assert_eq!((cascade.arenas(), cascade.allocated_bytes()), (1, Some(0)));
+        let _c = cascade.allocate(layout).unwrap();
+        assert_eq!(cascade.arenas(), 1);
+    }
+
+    #[test]
+    fn cascade_drops_arenas_too_small_for_the_allocation() {
+        let cascade = Cascade::new(Stack::<64>::new);
+        let oversized = Layout::new::<[u8; 128]>();
+        for _ in 0..1000 {
+            assert!(cascade.allocate(oversized).is_err());
+        }
+        assert_eq!(cascade.arenas(), 0);
+
+        let _a = cascade.allocate(Layout::new::<[u8; 32]>()).unwrap();
+        assert!(cascade.allocate(oversized).is_err());
+        assert_eq!(cascade.arenas(), 1);
+    }
+
+    #[test]
+    fn cascade_grows_across_arenas() {
+        let cascade = Cascade::new(Stack::<128>::new);
+        let mut v = allocator_api2::vec::Vec::new_in(&cascade);
+        v.extend_from_slice(&[7u8; 48]);
+        let w = allocator_api2::vec![in &cascade; 1u8; 8];
+        // The block can't grow in place behind `w`, so it moves to a new arena.
+        v.extend_from_slice(&[7u8; 16]);
+        assert_eq!(cascade.arenas(), 2);
+        assert!(v.iter().all(|&b| b == 7) && v.len() == 64);
+        assert!(cascade
+            .arena(1)
+            .contains(NonNull::from(&v[0]), Layout::new::<u8>()));
+        assert_eq!(cascade.arena(0).used(), 56);
+        drop(w);
+    }
+}
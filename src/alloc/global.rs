@@ -0,0 +1,362 @@
+use crate::dangling;
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{self, NonNull},
+};
+
+/// An adapter implementing [`GlobalAlloc`] for an [`Allocator`], so that it can be registered
+/// with `#[global_allocator]`.
+///
+/// The constructor is `const`, so any allocator that can be built in const context works, which
+/// rules out closures: use [`Predicate`](crate::combinator::Predicate)s like
+/// [`SizeAtMost`](crate::combinator::SizeAtMost) instead. Failed allocations return null, as
+/// [`GlobalAlloc`] requires. Callers of [`GlobalAlloc`] never request zero-sized blocks, so no
+/// special handling is needed for them.
+///
+/// The global allocator is used from every thread, so the allocator must be `Sync`. Allocators
+/// with interior mutability, like [`Stack`](super::Stack), have to be wrapped in a lock first.
+/// Neither the lock nor the allocator may allocate from the global heap themselves, which
+/// rules out a [`Stack`](super::Stack) with the `debug-tracking` feature, as it records its
+/// allocations there.
+///
+/// # Example
+/// ```
+/// use allocandrescu::alloc::Global;
+/// use std::alloc::System;
+///
+/// #[global_allocator]
+/// static GLOBAL: Global<System> = Global::new(System);
+///
+/// let v: Vec<u32> = (0..100).collect();
+/// assert_eq!(v.iter().sum::<u32>(), 4950);
+/// ```
+#[derive(Debug, Default)]
+pub struct Global<A> {
+    alloc: A,
+}
+
+impl<A> Global<A> {
+    #[inline]
+    pub const fn new(alloc: A) -> Self {
+        Self { alloc }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub const fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the adapter, returning the underlying allocator.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+}
+
+unsafe impl<A> GlobalAlloc for Global<A>
+where
+    A: Allocator + Sync,
+{
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc
+            .allocate(layout)
+            .map_or(ptr::null_mut(), |block| block.cast().as_ptr())
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.alloc
+            .allocate_zeroed(layout)
+            .map_or(ptr::null_mut(), |block| block.cast().as_ptr())
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.alloc.deallocate(NonNull::new_unchecked(ptr), layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let ptr = NonNull::new_unchecked(ptr);
+        // The caller guarantees that the new size doesn't overflow when rounded up to the alignment.
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let result = if new_size >= layout.size() {
+            self.alloc.grow(ptr, layout, new_layout)
+        } else {
+            self.alloc.shrink(ptr, layout, new_layout)
+        };
+        result.map_or(ptr::null_mut(), |block| block.cast().as_ptr())
+    }
+}
+
+/// An adapter implementing [`Allocator`] for a [`GlobalAlloc`], like the ones of mimalloc or
+/// jemallocator, so that it can be composed with the combinators.
+///
+/// Zero-sized blocks are dangling pointers that never reach the global allocator. Growing and
+/// shrinking go through [`realloc`](GlobalAlloc::realloc) when the alignment stays the same, as it
+/// can't change the alignment. Otherwise, the block is moved to a new one.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{
+///     alloc::{FromGlobal, Stack},
+///     prelude::*,
+/// };
+/// use allocator_api2::vec::Vec;
+/// use core::{alloc::Layout, ptr::NonNull};
+/// use std::alloc::System;
+///
+/// let stack = Stack::<64>::new();
+/// let alloc = stack.by_ref().fallback(FromGlobal::new(System));
+/// let mut v = Vec::with_capacity_in(16, &alloc);
+/// v.extend(0..100u32);
+/// assert!(!stack.contains(NonNull::from(&v[..]).cast(), Layout::for_value(&v[..])));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FromGlobal<G> {
+    global: G,
+}
+
+#[cfg(feature = "std")]
+impl FromGlobal<std::alloc::System> {
+    /// The system allocator, which allocator-api2 also implements [`Allocator`] for directly.
+    pub const SYSTEM: Self = Self::new(std::alloc::System);
+}
+
+impl<G> FromGlobal<G> {
+    #[inline]
+    pub const fn new(global: G) -> Self {
+        Self { global }
+    }
+
+    /// Returns a reference to the underlying global allocator.
+    #[inline]
+    pub const fn inner(&self) -> &G {
+        &self.global
+    }
+
+    /// Consumes the adapter, returning the underlying global allocator.
+    #[inline]
+    pub fn into_inner(self) -> G {
+        self.global
+    }
+}
+
+impl<G> FromGlobal<G>
+where
+    G: GlobalAlloc,
+{
+    #[inline]
+    fn block(ptr: *mut u8, size: usize) -> Result<NonNull<[u8]>, AllocError> {
+        NonNull::new(ptr)
+            .map(|ptr| NonNull::slice_from_raw_parts(ptr, size))
+            .ok_or(AllocError)
+    }
+
+    /// Resizes the block in place or moves it, leaving the bytes past the old size uninitialized.
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() == 0 {
+            return self.allocate(new_layout);
+        }
+        if new_layout.size() == 0 {
+            self.deallocate(ptr, old_layout);
+            return Ok(dangling(new_layout));
+        }
+        if old_layout.align() == new_layout.align() {
+            let new_ptr = self
+                .global
+                .realloc(ptr.as_ptr(), old_layout, new_layout.size());
+            return Self::block(new_ptr, new_layout.size());
+        }
+        let block = self.allocate(new_layout)?;
+        let size = old_layout.size().min(new_layout.size());
+        ptr::copy_nonoverlapping(ptr.as_ptr(), block.cast().as_ptr(), size);
+        self.deallocate(ptr, old_layout);
+        Ok(block)
+    }
+}
+
+unsafe impl<G> Allocator for FromGlobal<G>
+where
+    G: GlobalAlloc,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        Self::block(unsafe { self.global.alloc(layout) }, layout.size())
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        Self::block(unsafe { self.global.alloc_zeroed(layout) }, layout.size())
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            self.global.dealloc(ptr.as_ptr(), layout)
+        }
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() == 0 {
+            return self.allocate_zeroed(new_layout);
+        }
+        let block = self.realloc(ptr, old_layout, new_layout)?;
+        let tail = block.cast::<u8>().as_ptr().add(old_layout.size());
+        tail.write_bytes(0, new_layout.size() - old_layout.size());
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::{tests::exercise, Failing, Stack};
+    use allocator_api2::alloc::AllocError;
+    use std::{alloc::System, sync::Mutex};
+
+    /// A stack behind a lock, so it can be shared.
+    struct Locked(Mutex<Stack<256>>);
+
+    unsafe impl Allocator for Locked {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.lock().unwrap().allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.lock().unwrap().deallocate(ptr, layout)
+        }
+
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.lock().unwrap().grow(ptr, old_layout, new_layout)
+        }
+
+        unsafe fn shrink(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.lock().unwrap().shrink(ptr, old_layout, new_layout)
+        }
+    }
+
+    #[test]
+    fn global_translates_calls() {
+        let global = Global::new(Locked(Mutex::new(Stack::new())));
+        let layout = Layout::new::<[u8; 16]>();
+        unsafe {
+            let ptr = global.alloc_zeroed(layout);
+            assert!(std::slice::from_raw_parts(ptr, 16).iter().all(|&b| b == 0));
+            ptr.write_bytes(7, 16);
+            // The top of the stack grows in place.
+            let grown = global.realloc(ptr, layout, 64);
+            assert_eq!(grown, ptr);
+            assert_eq!(*grown.add(15), 7);
+            let shrunk = global.realloc(grown, Layout::new::<[u8; 64]>(), 8);
+            assert_eq!(global.inner().0.lock().unwrap().used(), 8);
+            assert!(global
+                .realloc(shrunk, Layout::new::<[u8; 8]>(), 512)
+                .is_null());
+            global.dealloc(shrunk, Layout::new::<[u8; 8]>());
+        }
+        assert_eq!(global.inner().0.lock().unwrap().used(), 0);
+    }
+
+    #[test]
+    fn from_global_passes_battery() {
+        exercise(&FromGlobal::new(System));
+        exercise(&FromGlobal::new(Global::new(FromGlobal::new(System))));
+    }
+
+    #[test]
+    fn from_global_backs_stack_fallback() {
+        use crate::{Allocandrescu as _, ArenaAllocator};
+        use allocator_api2::vec::Vec;
+
+        let stack = Stack::<256>::new();
+        let alloc = stack.by_ref().fallback(FromGlobal::new(System));
+        let small = Vec::<u8, _>::with_capacity_in(64, &alloc);
+        let mut large = Vec::with_capacity_in(8, &alloc);
+        large.extend(0..1000u32);
+        let layout = Layout::for_value(&large[..]);
+        assert!(!stack.contains(NonNull::from(&large[..]).cast(), layout));
+        assert!(large.iter().copied().eq(0..1000));
+        // It grew in place on top of the stack until it didn't fit, then moved out.
+        assert_eq!(stack.used(), 64);
+        drop(large);
+        assert_eq!(small.capacity(), 64);
+    }
+
+    #[test]
+    fn from_global_reports_failure() {
+        let alloc = FromGlobal::new(Global::new(Failing));
+        assert!(alloc.allocate(Layout::new::<u64>()).is_err());
+        assert!(alloc.allocate_zeroed(Layout::new::<u64>()).is_err());
+        // Zero-sized blocks never reach it.
+        let block = alloc.allocate(Layout::new::<()>()).unwrap();
+        unsafe {
+            let grown = alloc.grow(block.cast(), Layout::new::<()>(), Layout::new::<u8>());
+            assert!(grown.is_err());
+            alloc.deallocate(block.cast(), Layout::new::<()>());
+        }
+    }
+
+    #[test]
+    fn global_returns_null_on_failure() {
+        let global = Global::new(Failing);
+        unsafe {
+            assert!(global.alloc(Layout::new::<u64>()).is_null());
+            assert!(global.alloc_zeroed(Layout::new::<u64>()).is_null());
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{
+    dangling, AllocatorId, ArenaAllocator, BulkAllocator, GoodSizeAllocator, InPlaceGrow,
+    SameAllocator, TrimAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, mem::MaybeUninit, ops::Range, ptr::NonNull};
+use linked_list_allocator::Heap;
+use lock_api::{Mutex, RawMutex};
+
+/// General-purpose allocator over a [`Heap`] of
+/// [linked_list_allocator](https://crates.io/crates/linked_list_allocator).
+///
+/// The heap keeps its free blocks in a list sorted by address, so freed memory is reused, but
+/// allocation and deallocation take time linear in the number of free blocks. It is guarded by
+/// the lock `R`, e.g. `spin::Mutex<()>`, so the arena is `Sync` if `R` is.
+/// [`contains`](ArenaAllocator::contains) checks the range between the bottom and the top of
+/// the heap, which follows [`Heap::extend`].
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::LinkedListArena, prelude::*};
+/// use allocator_api2::vec::Vec;
+/// use std::{alloc::System, mem::MaybeUninit};
+///
+/// static mut HEAP: [MaybeUninit<u8>; 4096] = [MaybeUninit::uninit(); 4096];
+///
+/// // The arena is the only user of the memory.
+/// let arena = unsafe {
+///     LinkedListArena::<spin::Mutex<()>>::new(std::ptr::addr_of_mut!(HEAP).cast(), 4096)
+/// };
+/// let alloc = arena
+///     .by_ref()
+///     .cond(|layout| layout.size() <= 256)
+///     .fallback(System);
+///
+/// let small: Vec<u32, _> = (0..16).collect_in(&alloc);
+/// let large: Vec<u32, _> = (0..1024).collect_in(&alloc);
+/// assert!(arena.contains(NonNull::from(&small[..]).cast(), Layout::for_value(&small[..])));
+/// assert!(!arena.contains(NonNull::from(&large[..]).cast(), Layout::for_value(&large[..])));
+/// # use std::{alloc::Layout, ptr::NonNull};
+/// ```
+pub struct LinkedListArena<R: RawMutex> {
+    heap: Mutex<R, Heap>,
+}
+
+impl<R: RawMutex> LinkedListArena<R> {
+    /// Creates a new arena managing `heap_size` bytes starting at `heap_bottom`.
+    ///
+    /// The bottom is aligned up to a word and the size is truncated to whole words.
+    ///
+    /// # Safety
+    /// The memory in `[heap_bottom, heap_bottom + heap_size)` must be valid for reads and writes
+    /// and must not be used for anything else for as long as the arena is in use, like for
+    /// [`Heap::new`].
+    ///
+    /// # Panics
+    /// Panics if `heap_size` is too small for the metadata of the heap, which is two or three
+    /// words depending on the alignment of `heap_bottom`.
+    #[inline]
+    pub unsafe fn new(heap_bottom: *mut u8, heap_size: usize) -> Self {
+        Self::from_heap(Heap::new(heap_bottom, heap_size))
+    }
+
+    /// Creates a new arena managing `mem`.
+    ///
+    /// # Panics
+    /// Panics if `mem` is too small for the metadata of the heap, see [`LinkedListArena::new`].
+    #[inline]
+    pub fn from_slice(mem: &'static mut [MaybeUninit<u8>]) -> Self {
+        Self::from_heap(Heap::from_slice(mem))
+    }
+
+    /// Creates a new arena managing `heap`, which may already have blocks allocated.
+    #[inline]
+    pub const fn from_heap(heap: Heap) -> Self {
+        Self {
+            heap: Mutex::new(heap),
+        }
+    }
+
+    /// Returns the number of bytes allocated, including the padding of each block to whole words.
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.heap.lock().used()
+    }
+
+    /// Returns the number of bytes free for allocation, which may be fragmented.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.heap.lock().free()
+    }
+
+    /// Returns the number of bytes the heap uses for allocations.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.heap.lock().size()
+    }
+
+    /// Returns a reference to the locked heap, e.g. to [extend](Heap::extend) it.
+    #[inline]
+    pub fn inner(&self) -> &Mutex<R, Heap> {
+        &self.heap
+    }
+
+    /// Returns a mutable reference to the heap.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut Heap {
+        self.heap.get_mut()
+    }
+
+    /// Returns the heap.
+    #[inline]
+    pub fn into_inner(self) -> Heap {
+        self.heap.into_inner()
+    }
+}
+
+unsafe impl<R: RawMutex> Allocator for LinkedListArena<R> {
+    /// Takes the first free block that fits, with the size rounded up to whole words.
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        let ptr = self
+            .heap
+            .lock()
+            .allocate_first_fit(layout)
+            .map_err(|()| AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            self.heap.lock().deallocate(ptr, layout)
+        }
+    }
+}
+
+impl<R: RawMutex> ArenaAllocator for LinkedListArena<R> {
+    /// The whole heap is considered, including its free blocks.
+    #[inline]
+    fn arena_range(&self) -> Option<Range<usize>> {
+        let heap = self.heap.lock();
+        Some(heap.bottom() as usize..heap.top() as usize)
+    }
+
+    /// Free blocks are fragmented, so a single allocation may not get all of it.
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        Some(self.remaining())
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        Some(self.used())
+    }
+}
+
+/// The heap can only grow, so nothing is released.
+impl<R: RawMutex> TrimAllocator for LinkedListArena<R> {}
+
+impl<R: RawMutex> BulkAllocator for LinkedListArena<R> {}
+
+impl<R: RawMutex> GoodSizeAllocator for LinkedListArena<R> {}
+
+/// The heap has no way to resize a block, so growing always moves it.
+impl<R: RawMutex> InPlaceGrow for LinkedListArena<R> {}
+
+/// Identified by the bottom of the heap, which stays put when it is extended.
+impl<R: RawMutex> SameAllocator for LinkedListArena<R> {
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        AllocatorId::from_ptr(self.heap.lock().bottom())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::tests::exercise, combinator::Fallback};
+    use std::{alloc::System, boxed::Box};
+
+    type Arena = LinkedListArena<spin::Mutex<()>>;
+
+    /// Runs `f` on an arena over a leaked buffer of `len` bytes, then frees the buffer.
+    fn with_arena(len: usize, f: impl FnOnce(Arena)) {
+        let buf = Box::into_raw(std::vec![MaybeUninit::<u8>::uninit(); len].into_boxed_slice());
+        // The buffer is only reclaimed after the arena is dropped by `f`.
+        f(unsafe { Arena::new(buf.cast(), len) });
+        drop(unsafe { Box::from_raw(buf) });
+    }
+
+    #[test]
+    fn linked_list_arena_passes_battery() {
+        with_arena(64 * 1024, |arena| {
+            let remaining = arena.remaining();
+            exercise(&arena);
+            assert_eq!((arena.used(), arena.remaining()), (0, remaining));
+        });
+    }
+
+    #[test]
+    fn linked_list_arena_reuses_freed_blocks() {
+        with_arena(4096, |arena| {
+            let layout = Layout::new::<[u64; 8]>();
+            let first = arena.allocate(layout).unwrap();
+            let second = arena.allocate(layout).unwrap();
+            assert_eq!(arena.used(), 128);
+            unsafe { arena.deallocate(first.cast(), layout) };
+            assert_eq!(arena.used(), 64);
+            assert_eq!(arena.allocate(layout).unwrap(), first);
+
+            // The top also counts the bytes too few to be used yet.
+            let heap = arena.arena_range().unwrap();
+            assert!(heap.len() >= arena.capacity());
+            assert!(arena.contains(second.cast(), layout));
+            assert_eq!(arena.remaining_capacity(), Some(arena.capacity() - 128));
+        });
+    }
+
+    #[test]
+    fn linked_list_arena_routes_fallback_deallocations() {
+        with_arena(4096, |arena| {
+            let alloc = Fallback::new(arena, System);
+            let (small, large) = (Layout::new::<[u8; 512]>(), Layout::new::<[u8; 8192]>());
+
+            let in_arena = alloc.allocate(small).unwrap().cast();
+            let spilled = alloc.allocate(large).unwrap().cast();
+            assert!(alloc.primary().contains(in_arena, small));
+            assert!(!alloc.primary().contains(spilled, large));
+
+            // Freeing the spilled block must reach the system allocator and leave the arena alone.
+            unsafe { alloc.deallocate(spilled, large) };
+            assert_eq!(alloc.primary().used(), 512);
+            unsafe { alloc.deallocate(in_arena, small) };
+            assert_eq!(alloc.primary().used(), 0);
+        });
+    }
+
+    #[test]
+    #[should_panic]
+    fn linked_list_arena_rejects_region_too_small_for_metadata() {
+        let mut buf = [MaybeUninit::<u8>::uninit(); 8];
+        unsafe { Arena::new(buf.as_mut_ptr().cast(), buf.len()) };
+    }
+}
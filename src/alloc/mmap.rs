@@ -0,0 +1,380 @@
+use crate::{
+    dangling, util::align_up, AllocatorId, ArenaAllocator, GoodSizeAllocator, InPlaceGrow,
+    ResetAllocator, SameAllocator, TrimAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{
+    alloc::Layout,
+    cell::Cell,
+    ops::Range,
+    ptr::{self, NonNull},
+    sync::atomic::{compiler_fence, Ordering},
+};
+
+/// Bump allocator over anonymous memory pages mapped with `mmap`.
+///
+/// The mapping is always page-aligned and spans a whole number of pages, which lets the arena be
+/// [frozen](MmapArena::freeze) — made read-only — once the data in it is built.
+#[derive(Debug)]
+pub struct MmapArena {
+    base: NonNull<u8>,
+    len: usize,
+    idx: Cell<usize>,
+    frozen: Cell<bool>,
+    #[cfg(feature = "debug-tracking")]
+    live: super::tracking::Tracker,
+}
+
+impl MmapArena {
+    /// Maps a new arena of at least `size` bytes. The size is rounded up to a multiple of the page size.
+    pub fn new(size: usize) -> Result<Self, AllocError> {
+        let len = size
+            .max(1)
+            .checked_next_multiple_of(page_size())
+            .ok_or(AllocError)?;
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(AllocError);
+        }
+        Ok(Self {
+            base: NonNull::new(base.cast()).ok_or(AllocError)?,
+            len,
+            idx: Cell::new(0),
+            frozen: Cell::new(false),
+            #[cfg(feature = "debug-tracking")]
+            live: super::tracking::Tracker::new(),
+        })
+    }
+
+    /// Returns the size of the mapping in bytes.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of bytes in use, including alignment padding.
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.idx.get()
+    }
+
+    /// Returns the number of bytes left in the mapping.
+    ///
+    /// It doesn't account for the padding that aligning a future allocation may need.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.len - self.idx.get()
+    }
+
+    /// Makes the pages of the arena read-only.
+    ///
+    /// Previously allocated data can still be read, but any write to it faults and terminates the
+    /// process. While the arena is frozen, allocation fails and deallocation is a no-op.
+    /// Zero-sized allocations still succeed, as they don't touch the arena.
+    ///
+    /// Fails if the protection of the pages could not be changed.
+    pub fn freeze(&self) -> Result<(), AllocError> {
+        self.protect(libc::PROT_READ)?;
+        self.frozen.set(true);
+        Ok(())
+    }
+
+    /// Makes the pages of the arena writable again after [`freeze`](MmapArena::freeze).
+    pub fn thaw(&self) -> Result<(), AllocError> {
+        self.protect(libc::PROT_READ | libc::PROT_WRITE)?;
+        self.frozen.set(false);
+        Ok(())
+    }
+
+    /// Returns `true` if the arena is frozen.
+    #[inline]
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.get()
+    }
+
+    /// Reset this arena.
+    ///
+    /// Performs a mass deallocation on everything allocated in the arena by resetting the pointer.
+    /// Does not run any `Drop` implementations on deallocated objects.
+    /// Thaws the arena if it is frozen.
+    pub fn reset(&mut self) -> Result<(), AllocError> {
+        // Borrowing the arena mutably proves that no allocation is in use.
+        unsafe { self.reset_unchecked() }
+    }
+
+    /// Reset this arena and zero the whole mapping, so that stale data, e.g. secrets, can't be
+    /// observed through later allocations or in memory dumps.
+    ///
+    /// Copies of the data made outside of the arena are not affected. Thaws the arena if it is frozen.
+    pub fn reset_zeroed(&mut self) -> Result<(), AllocError> {
+        self.reset()?;
+        unsafe { self.base.as_ptr().write_bytes(0, self.len) };
+        // Keeps the writes from being optimized away, as nothing reads them.
+        compiler_fence(Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Reset this arena through a shared reference, e.g. when it is behind a combinator chain.
+    ///
+    /// See [`reset`](MmapArena::reset).
+    ///
+    /// # Safety
+    /// None of the memory allocated in the arena may be used afterwards.
+    pub unsafe fn reset_unchecked(&self) -> Result<(), AllocError> {
+        if self.is_frozen() {
+            self.thaw()?;
+        }
+        self.idx.set(0);
+        #[cfg(feature = "debug-tracking")]
+        self.live.clear();
+        Ok(())
+    }
+
+    /// Writes a table of the live allocations followed by the total, padding and free bytes.
+    ///
+    /// See [`Stack::dump`](super::Stack::dump).
+    #[cfg(feature = "debug-tracking")]
+    pub fn dump(&self, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+        self.live.dump(out, self.len, self.idx.get())
+    }
+
+    fn protect(&self, prot: libc::c_int) -> Result<(), AllocError> {
+        // `base` and `len` come from `mmap`, so they are page-aligned.
+        let result = unsafe { libc::mprotect(self.base.as_ptr().cast(), self.len, prot) };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(AllocError)
+        }
+    }
+}
+
+impl Drop for MmapArena {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.base.as_ptr().cast(), self.len) };
+    }
+}
+
+unsafe impl Allocator for MmapArena {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        if self.is_frozen() {
+            return Err(AllocError);
+        }
+        let base = self.base.as_ptr() as usize;
+        let aligned_start =
+            align_up(base + self.idx.get(), layout.align()).ok_or(AllocError)? - base;
+        let aligned_end = aligned_start.checked_add(layout.size()).ok_or(AllocError)?;
+        if aligned_end > self.len {
+            return Err(AllocError);
+        }
+        self.idx.set(aligned_end);
+        #[cfg(feature = "debug-tracking")]
+        self.live.insert(aligned_start, layout);
+        let ptr = unsafe { self.base.add(aligned_start) };
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 || self.is_frozen() {
+            return;
+        }
+        debug_assert!(
+            self.contains(ptr, layout),
+            "deallocated block {ptr:p} is not within the arena"
+        );
+        let alloc_start = (ptr.as_ptr() as usize).wrapping_sub(self.base.as_ptr() as usize);
+        #[cfg(feature = "debug-tracking")]
+        self.live.remove(alloc_start);
+        if alloc_start.wrapping_add(layout.size()) == self.idx.get() {
+            self.idx.set(alloc_start)
+        }
+    }
+}
+
+impl ArenaAllocator for MmapArena {
+    /// Only the allocated prefix of the mapping is considered, like for [`Stack`](super::Stack).
+    #[inline]
+    fn arena_range(&self) -> Option<Range<usize>> {
+        let base = self.base.as_ptr() as usize;
+        Some(base..base + self.idx.get())
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        Some(self.remaining())
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        Some(self.used())
+    }
+}
+
+impl TrimAllocator for MmapArena {}
+
+/// Blocks are bumped like in [`Stack`](super::Stack), only the mapping spans whole pages.
+impl GoodSizeAllocator for MmapArena {}
+
+impl InPlaceGrow for MmapArena {}
+
+/// Zero-sized blocks are not tracked.
+#[cfg(feature = "debug-tracking")]
+impl crate::OwnsAllocations for MmapArena {
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        self.live
+            .contains((ptr.as_ptr() as usize).wrapping_sub(self.base.as_ptr() as usize))
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.live.len()
+    }
+}
+
+impl SameAllocator for MmapArena {
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        AllocatorId::from_ptr(self.base.as_ptr())
+    }
+}
+
+impl ResetAllocator for MmapArena {
+    /// See [`MmapArena::reset`].
+    ///
+    /// # Panics
+    /// Panics if the arena is frozen and can't be thawed.
+    fn reset(&mut self) {
+        MmapArena::reset(self).expect("failed to thaw the arena")
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use allocator_api2::boxed::Box;
+
+    #[test]
+    fn mmap_arena_is_page_granular() {
+        let arena = MmapArena::new(1).unwrap();
+        assert_eq!(arena.capacity(), page_size());
+        assert_eq!(arena.base.as_ptr() as usize % page_size(), 0);
+    }
+
+    #[test]
+    fn frozen_mmap_arena_is_readable_and_fails_allocation() {
+        let arena = MmapArena::new(4096).unwrap();
+        let value = Box::new_in(42u64, &arena);
+        arena.freeze().unwrap();
+        assert!(arena.is_frozen());
+
+        assert_eq!(*value, 42);
+        assert!(arena.allocate(Layout::new::<u64>()).is_err());
+
+        arena.thaw().unwrap();
+        let other = Box::new_in(7u64, &arena);
+        assert_eq!(*value + *other, 49);
+        assert_eq!(arena.used(), 16);
+        assert_eq!(arena.remaining(), arena.capacity() - 16);
+    }
+
+    #[test]
+    fn frozen_mmap_arena_reset_zeroed_wipes_old_data() {
+        let mut arena = MmapArena::new(4096).unwrap();
+        let layout = Layout::new::<[u8; 64]>();
+        let block = arena.allocate(layout).unwrap();
+        unsafe { block.cast::<u8>().write_bytes(0x5a, 64) };
+        arena.freeze().unwrap();
+
+        arena.reset_zeroed().unwrap();
+        assert!(!arena.is_frozen());
+        let again = arena.allocate(layout).unwrap();
+        assert_eq!(again.cast::<u8>(), block.cast::<u8>());
+        assert!(unsafe { again.as_ref() }.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    #[cfg(feature = "debug-tracking")]
+    fn mmap_arena_dumps_live_blocks() {
+        let arena = MmapArena::new(4096).unwrap();
+        let a = arena.allocate(Layout::new::<u32>()).unwrap();
+        let b = arena.allocate(Layout::new::<u64>()).unwrap();
+        unsafe { arena.deallocate(a.cast(), Layout::new::<u32>()) };
+
+        let mut dump = std::string::String::new();
+        arena.dump(&mut dump).unwrap();
+        let mut lines = dump.lines().skip(1);
+        assert_eq!(
+            lines
+                .next()
+                .unwrap()
+                .split_whitespace()
+                .collect::<std::vec::Vec<_>>(),
+            ["8", "8", "8"]
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            std::format!(
+                "blocks: 1, live: 8, padding: 8, free: {}, total: {}",
+                arena.capacity() - 16,
+                arena.capacity()
+            )
+        );
+        unsafe { arena.deallocate(b.cast(), Layout::new::<u64>()) };
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "is not within the arena"]
+    fn mmap_arena_rejects_foreign_pointer_in_debug() {
+        let arena = MmapArena::new(4096).unwrap();
+        let other = MmapArena::new(4096).unwrap();
+        let layout = Layout::new::<u64>();
+        let foreign = other.allocate(layout).unwrap();
+        unsafe { arena.deallocate(foreign.cast(), layout) };
+    }
+
+    #[test]
+    fn write_to_frozen_mmap_arena_faults() {
+        use std::os::unix::process::ExitStatusExt;
+
+        const CHILD: &str = "ALLOCANDRESCU_FROZEN_WRITE_CHILD";
+        if std::env::var_os(CHILD).is_some() {
+            let arena = MmapArena::new(4096).unwrap();
+            let ptr = arena.allocate(Layout::new::<u64>()).unwrap().cast::<u64>();
+            arena.freeze().unwrap();
+            unsafe { ptr::write_volatile(ptr.as_ptr(), 1) };
+            std::process::exit(0);
+        }
+
+        let test_name = concat!(module_path!(), "::write_to_frozen_mmap_arena_faults");
+        let test_name = test_name.split_once("::").unwrap().1;
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", test_name, "--test-threads=1"])
+            .env(CHILD, "1")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(matches!(
+            status.signal(),
+            Some(libc::SIGSEGV) | Some(libc::SIGBUS)
+        ));
+    }
+}
@@ -0,0 +1,323 @@
+use super::Bump;
+use crate::{
+    util, AllocatorId, ArenaAllocator, BulkAllocator, GoodSizeAllocator, InPlaceGrow,
+    ProbeAllocator, ResetAllocator, SameAllocator, TrimAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::Cell, fmt, iter, ptr::NonNull};
+
+/// Number of chunks before the current one whose ranges are cached.
+///
+/// Chunks double in size, so it takes a bump of gigabytes to have more of them.
+const CACHED_CHUNKS: usize = 32;
+
+/// An owned [`Bump`] that is an allocator by value.
+///
+/// allocator-api2 implements `Allocator` only for `&Bump`, and the orphan rule keeps this crate
+/// from implementing it for `Bump` itself, so a bump stored by value, e.g. as the primary of a
+/// [`Fallback`](crate::combinator::Fallback), is wrapped in this instead. It forwards everything
+/// to `&Bump`, and the chunks live on the heap, so moving it doesn't move the blocks.
+///
+/// Unlike `&Bump`, it caches the ranges of the chunks before the current one, which stay the same
+/// until the bump is reset. [`contains`](ArenaAllocator::contains) checks the current chunk and
+/// then searches the cache, which is refilled only after the bump grows a new chunk, instead of
+/// walking every chunk. This matters to [`Fallback`](crate::combinator::Fallback), which asks the
+/// primary on every deallocation.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{
+///     alloc::{Bump, OwnedBump},
+///     combinator::Fallback,
+///     prelude::*,
+/// };
+/// use allocator_api2::vec::Vec;
+/// use std::alloc::System;
+///
+/// fn scratch() -> Fallback<OwnedBump, System> {
+///     Fallback::new(OwnedBump::new(Bump::with_capacity(64)), System)
+/// }
+///
+/// let alloc = scratch();
+/// let v: Vec<u8, _> = (0..32).collect_in(&alloc);
+/// assert!(alloc.primary().contains(NonNull::from(&v[..]).cast(), Layout::for_value(&v[..])));
+/// # use std::{alloc::Layout, ptr::NonNull};
+/// ```
+pub struct OwnedBump {
+    bump: Bump,
+    /// End of the chunk that was current when the cache was filled, or 0 if it is stale.
+    current_end: Cell<usize>,
+    /// Ranges of the chunks before it, sorted by start.
+    chunks: [Cell<(usize, usize)>; CACHED_CHUNKS],
+    /// Number of cached ranges, or more than [`CACHED_CHUNKS`] if they didn't fit.
+    len: Cell<usize>,
+}
+
+impl OwnedBump {
+    /// Creates a new `OwnedBump` owning `bump`.
+    #[inline]
+    pub const fn new(bump: Bump) -> Self {
+        Self {
+            bump,
+            current_end: Cell::new(0),
+            chunks: [const { Cell::new((0, 0)) }; CACHED_CHUNKS],
+            len: Cell::new(0),
+        }
+    }
+
+    /// Returns a reference to the bump.
+    #[inline]
+    pub fn inner(&self) -> &Bump {
+        &self.bump
+    }
+
+    /// Returns a mutable reference to the bump.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut Bump {
+        // The bump may be reset through it, releasing cached chunks.
+        self.current_end.set(0);
+        &mut self.bump
+    }
+
+    /// Returns the bump.
+    #[inline]
+    pub fn into_inner(self) -> Bump {
+        self.bump
+    }
+
+    /// Refills the cache with the ranges of the chunks before the one ending at `current_end`.
+    #[cold]
+    fn refill(&self, chunks: impl Iterator<Item = (*mut u8, usize)>, current_end: usize) {
+        let mut len = 0;
+        for (ptr, size) in chunks {
+            len += 1;
+            if len > CACHED_CHUNKS {
+                break;
+            }
+            // Insertion sort, as the chunks are few and this runs once per new chunk.
+            let range = (ptr as usize, ptr as usize + size);
+            let mut idx = len - 1;
+            while idx > 0 && self.chunks[idx - 1].get().0 > range.0 {
+                self.chunks[idx].set(self.chunks[idx - 1].get());
+                idx -= 1;
+            }
+            self.chunks[idx].set(range);
+        }
+        self.len.set(len);
+        self.current_end.set(current_end);
+    }
+}
+
+impl Default for OwnedBump {
+    #[inline]
+    fn default() -> Self {
+        Self::new(Bump::new())
+    }
+}
+
+impl From<Bump> for OwnedBump {
+    #[inline]
+    fn from(bump: Bump) -> Self {
+        Self::new(bump)
+    }
+}
+
+impl fmt::Debug for OwnedBump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OwnedBump").field(&self.bump).finish()
+    }
+}
+
+unsafe impl Allocator for OwnedBump {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        (&self.bump).allocate(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (&self.bump).deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        (&self.bump).grow(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        (&self.bump).shrink(ptr, old_layout, new_layout)
+    }
+}
+
+impl ArenaAllocator for OwnedBump {
+    /// Same as for `&Bump`, but searches the cached ranges of the chunks before the current one.
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        let Some((start, _)) = util::checked_range(ptr, layout) else {
+            return false;
+        };
+        let mut chunks = unsafe { self.bump.iter_allocated_chunks_raw() };
+        let Some((current_ptr, current_size)) = chunks.next() else {
+            return false;
+        };
+        let current_start = current_ptr as usize;
+        let current_end = current_start + current_size;
+        if crate::ranges_contain(iter::once(current_start..current_end), ptr, layout) {
+            return true;
+        }
+
+        // Chunks are only added in front, or released by a reset, which goes through `&mut self`.
+        if self.current_end.get() != current_end {
+            self.refill(chunks, current_end);
+        }
+        let len = self.len.get();
+        if len > CACHED_CHUNKS {
+            return (&self.bump).contains(ptr, layout);
+        }
+        let chunks = &self.chunks[..len];
+        // The last chunk starting at or below the block is the only one that can hold it.
+        let idx = chunks.partition_point(|chunk| chunk.get().0 <= start);
+        idx.checked_sub(1).is_some_and(|idx| {
+            let (start, end) = chunks[idx].get();
+            crate::ranges_contain(iter::once(start..end), ptr, layout)
+        })
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        (&self.bump).remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        ArenaAllocator::allocated_bytes(&&self.bump)
+    }
+}
+
+impl ProbeAllocator for OwnedBump {
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        (&self.bump).can_allocate(layout)
+    }
+}
+
+impl TrimAllocator for OwnedBump {}
+
+impl BulkAllocator for OwnedBump {}
+
+impl GoodSizeAllocator for OwnedBump {}
+
+impl InPlaceGrow for OwnedBump {}
+
+/// Has the ID of the `&Bump` it wraps, so the two can deallocate each other's blocks.
+impl SameAllocator for OwnedBump {
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        (&self.bump).allocator_id()
+    }
+}
+
+impl ResetAllocator for OwnedBump {
+    #[inline]
+    fn reset(&mut self) {
+        self.inner_mut().reset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combinator::Fallback;
+    use std::{alloc::System, vec::Vec};
+
+    #[test]
+    fn owned_bump_routes_fallback_by_value() {
+        let bump = Bump::with_capacity(64);
+        bump.set_allocation_limit(Some(bump.allocated_bytes()));
+        let alloc = Fallback::new(OwnedBump::new(bump), System);
+        let capacity = alloc.primary().remaining_capacity();
+        let in_bump = |v: &[u64]| {
+            let block = NonNull::from(v).cast();
+            alloc.primary().contains(block, Layout::for_value(v))
+        };
+
+        let small = allocator_api2::vec![in &alloc; 1u64; 4];
+        assert!(in_bump(&small));
+        // The limit keeps the bump from growing, so this one spills.
+        let large = allocator_api2::vec![in &alloc; 2u64; 64];
+        assert!(!in_bump(&large));
+        assert!(!alloc.primary().can_allocate(Layout::for_value(&large[..])));
+        assert_eq!(alloc.primary().allocated_bytes(), Some(32));
+
+        // The spilled block goes back to the system allocator, so it isn't leaked under Miri.
+        drop(large);
+        drop(small);
+        let mut bump = alloc.into_parts().0;
+        bump.reset();
+        assert_eq!(bump.remaining_capacity(), capacity);
+    }
+
+    #[test]
+    fn owned_bump_is_the_same_as_its_reference() {
+        let owned = OwnedBump::new(Bump::new());
+        assert!(crate::same_allocator(&owned, &owned.inner()));
+        let block = owned.allocate(Layout::new::<u32>()).unwrap().cast();
+        assert!(owned.inner().contains(block, Layout::new::<u32>()));
+        unsafe { owned.inner().deallocate(block, Layout::new::<u32>()) };
+    }
+
+    #[test]
+    fn owned_bump_contains_blocks_right_after_growing() {
+        let owned = OwnedBump::new(Bump::with_capacity(64));
+        let chunk_count = || unsafe { owned.inner().iter_allocated_chunks_raw() }.count();
+        let mut blocks = Vec::new();
+        let mut layout = Layout::new::<[u64; 4]>();
+        while chunk_count() < 6 {
+            let chunks = chunk_count();
+            let block = owned.allocate(layout).unwrap().cast::<u8>();
+            blocks.push((block, layout));
+            if chunk_count() > chunks {
+                // The first query after growing refills the cache.
+                assert!(owned.contains(block, layout));
+                layout = Layout::from_size_align(layout.size() * 2, 8).unwrap();
+            }
+            for &(block, layout) in &blocks {
+                assert!(owned.contains(block, layout));
+                assert_eq!(
+                    owned.contains(block, layout),
+                    owned.inner().contains(block, layout)
+                );
+            }
+        }
+
+        let outside = System.allocate(layout).unwrap().cast::<u8>();
+        assert!(!owned.contains(outside, layout));
+        unsafe { System.deallocate(outside, layout) };
+    }
+
+    #[test]
+    fn owned_bump_forgets_chunks_released_by_reset() {
+        let mut owned = OwnedBump::new(Bump::with_capacity(64));
+        let layout = Layout::new::<[u8; 48]>();
+        let blocks: Vec<_> = (0..64)
+            .map(|_| owned.allocate(layout).unwrap().cast::<u8>())
+            .collect();
+        assert!(blocks.iter().all(|&block| owned.contains(block, layout)));
+
+        owned.reset();
+        // Only the addresses are compared, the chunks themselves are gone.
+        assert!(blocks.iter().all(|&block| !owned.contains(block, layout)));
+        let block = owned.allocate(layout).unwrap().cast();
+        assert!(owned.contains(block, layout));
+    }
+}
@@ -0,0 +1,377 @@
+use super::{Align, Alignment, Marker, Stack};
+use crate::ArenaAllocator;
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::Cell, fmt, ops::Range, ptr::NonNull};
+
+/// A [`Stack`] whose memory is handed out through nested [`Region`]s.
+///
+/// A region is an allocator. Dropping it deallocates everything allocated in it and in the regions
+/// nested in it. Regions borrow the region they were opened in, so they are closed in the reverse
+/// order of opening. Only the innermost open region can allocate, and each region can have only
+/// one region open in it at a time.
+///
+/// Unlike [`checkpoint`](Stack::checkpoint), a region is a value, so it can be passed to code
+/// generic over [`Allocator`].
+///
+/// # Example
+/// ```
+/// use allocandrescu::alloc::Regions;
+/// use allocator_api2::vec::Vec;
+///
+/// let regions = Regions::<1024>::new();
+/// let frame = regions.region();
+/// let mut results = Vec::new_in(&frame);
+/// for i in 0..3 {
+///     let scratch = frame.region();
+///     let squares = (0..10).map(|j| j * i).collect::<Vec<_>>();
+///     let mut temp = Vec::with_capacity_in(squares.len(), &scratch);
+///     temp.extend(squares);
+///     let sum: i32 = temp.iter().sum();
+///     drop(temp);
+///     drop(scratch);
+///     results.push(sum);
+/// }
+/// assert_eq!(results, [0, 45, 90]);
+/// ```
+pub struct Regions<const SIZE: usize, const ALIGN: usize = 1>
+where
+    Align<ALIGN>: Alignment,
+{
+    stack: Stack<SIZE, ALIGN>,
+    /// Number of open regions.
+    open: Cell<usize>,
+}
+
+impl<const SIZE: usize, const ALIGN: usize> Regions<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            stack: Stack::new(),
+            open: Cell::new(0),
+        }
+    }
+
+    /// Returns the size of the stack in bytes.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        SIZE
+    }
+
+    /// Returns the number of bytes in use by the open regions, including alignment padding.
+    ///
+    /// The stack itself isn't exposed: memory allocated from it directly would be freed by the
+    /// region it was allocated in and handed out again by the next one.
+    /// ```compile_fail
+    /// use allocandrescu::alloc::Regions;
+    /// use allocator_api2::boxed::Box;
+    ///
+    /// let regions = Regions::<64>::new();
+    /// let region = regions.region();
+    /// let b = Box::new_in(1u64, regions.stack());
+    /// drop(region);
+    /// ```
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.stack.used()
+    }
+
+    /// Opens the outermost region, which can use the whole stack.
+    ///
+    /// # Panics
+    /// Panics if a region is already open.
+    #[inline]
+    #[track_caller]
+    pub fn region(&self) -> Region<'_> {
+        assert_eq!(self.open.get(), 0, "a region is already open");
+        Region::open(&self.stack, &self.open, 1)
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> Default for Regions<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize, const ALIGN: usize> fmt::Debug for Regions<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Regions")
+            .field("stack", &self.stack)
+            .field("open", &self.open.get())
+            .finish()
+    }
+}
+
+/// The operations of a [`Stack`] that regions need, independent of its size and alignment.
+trait RegionArena: ArenaAllocator {
+    fn checkpoint(&self) -> Marker<'_>;
+
+    unsafe fn rewind(&self, marker: Marker<'_>);
+}
+
+impl<const SIZE: usize, const ALIGN: usize> RegionArena for Stack<SIZE, ALIGN>
+where
+    Align<ALIGN>: Alignment,
+{
+    #[inline]
+    fn checkpoint(&self) -> Marker<'_> {
+        Stack::checkpoint(self)
+    }
+
+    #[inline]
+    unsafe fn rewind(&self, marker: Marker<'_>) {
+        Stack::rewind(self, marker)
+    }
+}
+
+/// An allocator over the part of a [`Regions`] stack that was free when it was opened.
+///
+/// It only allocates while it is the innermost open region. Deallocating in a region that has
+/// another one open in it is deferred until the region is closed.
+///
+/// This `struct` is created by [`region`](Regions::region) methods on [`Regions`] and [`Region`].
+pub struct Region<'a> {
+    stack: &'a dyn RegionArena,
+    open: &'a Cell<usize>,
+    /// Position of the stack when the region was opened.
+    mark: Marker<'a>,
+    /// Number of regions open up to and including this one.
+    depth: usize,
+}
+
+impl<'a> Region<'a> {
+    #[inline]
+    fn open(stack: &'a dyn RegionArena, open: &'a Cell<usize>, depth: usize) -> Self {
+        open.set(depth);
+        Self {
+            stack,
+            open,
+            mark: stack.checkpoint(),
+            depth,
+        }
+    }
+
+    /// Opens a region nested in this one, which allocates from the memory this one hasn't used
+    /// yet. This region can't allocate until the returned one is dropped.
+    ///
+    /// # Panics
+    /// Panics if a region is already open in this one.
+    #[inline]
+    #[track_caller]
+    pub fn region(&self) -> Region<'_> {
+        assert!(self.is_innermost(), "a region is already open in this one");
+        Region::open(self.stack, self.open, self.depth + 1)
+    }
+
+    /// Returns `true` if no region is open in this one.
+    #[inline]
+    pub fn is_innermost(&self) -> bool {
+        self.open.get() == self.depth
+    }
+
+    /// Returns the nesting depth of the region, starting at 1 for the outermost one.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+impl Drop for Region<'_> {
+    fn drop(&mut self) {
+        // Regions opened in this one borrowed it, so they are closed or leaked.
+        unsafe { self.stack.rewind(self.mark) };
+        self.open.set(self.depth - 1);
+    }
+}
+
+impl fmt::Debug for Region<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Region")
+            .field("depth", &self.depth)
+            .field("innermost", &self.is_innermost())
+            .field("allocated_bytes", &self.allocated_bytes())
+            .finish()
+    }
+}
+
+unsafe impl Allocator for Region<'_> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if !self.is_innermost() {
+            return Err(AllocError);
+        }
+        self.stack.allocate(layout)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if !self.is_innermost() {
+            return Err(AllocError);
+        }
+        self.stack.allocate_zeroed(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        // Reclaiming the topmost block now could move the stack below the inner region's mark.
+        if self.is_innermost() {
+            self.stack.deallocate(ptr, layout)
+        }
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if !self.is_innermost() {
+            return Err(AllocError);
+        }
+        self.stack.grow(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if !self.is_innermost() {
+            return Err(AllocError);
+        }
+        self.stack.grow_zeroed(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.is_innermost() {
+            return self.stack.shrink(ptr, old_layout, new_layout);
+        }
+        // Shrinking in place leaves the stack alone, the rest is reclaimed on close.
+        if ptr.as_ptr() as usize % new_layout.align() != 0 {
+            return Err(AllocError);
+        }
+        Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()))
+    }
+}
+
+impl ArenaAllocator for Region<'_> {
+    /// Only the part of the stack allocated since the region was opened is considered, so blocks
+    /// of the regions it is nested in are not contained.
+    #[inline]
+    fn arena_range(&self) -> Option<Range<usize>> {
+        let range = self.stack.arena_range()?;
+        Some(range.start + self.mark.idx.min(range.len())..range.end)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.stack.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        let range = self.stack.arena_range()?;
+        Some(range.len().saturating_sub(self.mark.idx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use allocator_api2::vec::Vec;
+
+    #[test]
+    fn nested_regions_reuse_memory() {
+        let regions = Regions::<1024, 8>::new();
+        let outer = regions.region();
+        let a = allocator_api2::vec![in &outer; 1u64; 4];
+        assert_eq!(outer.allocated_bytes(), Some(32));
+
+        let mut previous = None;
+        for round in 0..3u64 {
+            let middle = outer.region();
+            assert!(!outer.is_innermost());
+            assert!(outer.allocate(Layout::new::<u8>()).is_err());
+            let b = allocator_api2::vec![in &middle; round; 8];
+            {
+                let inner = middle.region();
+                let c = allocator_api2::vec![in &inner; 3u64; 16];
+                assert_eq!((inner.depth(), inner.allocated_bytes()), (3, Some(128)));
+                assert!(inner.contains(NonNull::from(&c[0]).cast(), Layout::new::<u64>()));
+                assert!(!inner.contains(NonNull::from(&b[0]).cast(), Layout::new::<u64>()));
+                assert_eq!(regions.used(), 32 + 64 + 128);
+            }
+            let again = Vec::<u64, _>::with_capacity_in(16, &middle);
+            // The inner region's memory was reclaimed when it was closed.
+            assert_eq!(regions.used(), 32 + 64 + 128);
+            assert_eq!(b, [round; 8]);
+            let first = b.as_ptr();
+            assert!(previous.map_or(true, |previous| previous == first));
+            previous = Some(first);
+            drop(again);
+        }
+        assert!(outer.is_innermost());
+        assert_eq!(regions.used(), 32);
+        assert_eq!(a, [1; 4]);
+        drop(a);
+        drop(outer);
+        assert_eq!(regions.used(), 0);
+        let _reopened = regions.region();
+    }
+
+    #[test]
+    fn outer_region_defers_deallocation() {
+        let regions = Regions::<256>::new();
+        let outer = regions.region();
+        let mut a = allocator_api2::vec![in &outer; 0u8; 16];
+        let inner = outer.region();
+        // The outer block is topmost, but freeing it now would move the stack below the
+        // inner region.
+        a.truncate(4);
+        a.shrink_to_fit();
+        drop(a);
+        assert_eq!(regions.used(), 16);
+        let b = allocator_api2::vec![in &inner; 1u8; 8];
+        drop(b);
+        drop(inner);
+        assert_eq!(regions.used(), 16);
+        drop(outer);
+        assert_eq!(regions.used(), 0);
+    }
+
+    #[test]
+    #[should_panic = "already open in this one"]
+    fn region_rejects_sibling() {
+        let regions = Regions::<64>::new();
+        let outer = regions.region();
+        let _first = outer.region();
+        let _second = outer.region();
+    }
+
+    #[test]
+    #[should_panic = "already open"]
+    fn regions_reject_second_outermost_region() {
+        let regions = Regions::<64>::new();
+        let _first = regions.region();
+        let _second = regions.region();
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    dangling, id::tag, util::align_up, AllocatorId, ArenaAllocator, BulkAllocator,
+    GoodSizeAllocator, InPlaceGrow, ProbeAllocator, ResetAllocator, SameAllocator, TrimAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::Cell, fmt, ops::Range, ptr::NonNull};
+
+/// Bump allocator over a region carved out of another allocator.
+///
+/// The parent treats the whole region as a single allocation, which is deallocated when the
+/// sub-arena is dropped. Usage of the sub-arena is bounded by the size of the region and it can
+/// be [reset](SubArena::reset) independently of the parent.
+///
+/// This `struct` is created by [`carve`](super::Stack::carve) method on [`Stack`](super::Stack).
+/// See its documentation for more details.
+pub struct SubArena<'a> {
+    parent: &'a dyn Allocator,
+    base: NonNull<u8>,
+    len: usize,
+    idx: Cell<usize>,
+}
+
+impl<'a> SubArena<'a> {
+    /// Takes ownership of `region`, allocated by `parent` with an alignment of 1.
+    #[inline]
+    pub(crate) fn new(parent: &'a dyn Allocator, region: NonNull<[u8]>) -> Self {
+        Self {
+            parent,
+            base: region.cast(),
+            len: region.len(),
+            idx: Cell::new(0),
+        }
+    }
+
+    /// Returns the size of the region in bytes.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of bytes in use, including alignment padding.
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.idx.get()
+    }
+
+    /// Returns the number of bytes left in the region.
+    ///
+    /// It doesn't account for the padding that aligning a future allocation may need.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.len - self.idx.get()
+    }
+
+    /// Reset this sub-arena.
+    ///
+    /// Performs a mass deallocation on everything allocated in the sub-arena by resetting the pointer.
+    /// Does not run any `Drop` implementations on deallocated objects.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.idx.set(0);
+    }
+
+    /// Returns the range of the region that an allocation with `layout` would occupy.
+    #[inline]
+    fn bounds(&self, layout: Layout) -> Option<(usize, usize)> {
+        let base = self.base.as_ptr() as usize;
+        let aligned_start = align_up(base + self.idx.get(), layout.align())? - base;
+        let aligned_end = aligned_start.checked_add(layout.size())?;
+        (aligned_end <= self.len).then_some((aligned_start, aligned_end))
+    }
+}
+
+impl Drop for SubArena<'_> {
+    fn drop(&mut self) {
+        let layout = unsafe { Layout::from_size_align_unchecked(self.len, 1) };
+        unsafe { self.parent.deallocate(self.base, layout) }
+    }
+}
+
+impl fmt::Debug for SubArena<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SubArena")
+            .field("base", &self.base)
+            .field("len", &self.len)
+            .field("idx", &self.idx)
+            .finish_non_exhaustive()
+    }
+}
+
+unsafe impl Allocator for SubArena<'_> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        let (aligned_start, aligned_end) = self.bounds(layout).ok_or(AllocError)?;
+        self.idx.set(aligned_end);
+        let ptr = unsafe { self.base.add(aligned_start) };
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        debug_assert!(
+            self.contains(ptr, layout),
+            "deallocated block {ptr:p} is not within the sub-arena"
+        );
+        let alloc_start = (ptr.as_ptr() as usize).wrapping_sub(self.base.as_ptr() as usize);
+        if alloc_start.wrapping_add(layout.size()) == self.idx.get() {
+            self.idx.set(alloc_start)
+        }
+    }
+}
+
+impl TrimAllocator for SubArena<'_> {}
+
+impl BulkAllocator for SubArena<'_> {}
+
+impl GoodSizeAllocator for SubArena<'_> {}
+
+impl InPlaceGrow for SubArena<'_> {}
+
+/// Derived from the region, so that it differs from the ID of the parent even if the region starts
+/// at the beginning of the parent's memory.
+impl SameAllocator for SubArena<'_> {
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        AllocatorId::composite(tag::SUB_ARENA, &[AllocatorId::from_ptr(self.base.as_ptr())])
+    }
+}
+
+impl ResetAllocator for SubArena<'_> {
+    #[inline]
+    fn reset(&mut self) {
+        SubArena::reset(self)
+    }
+}
+
+impl ArenaAllocator for SubArena<'_> {
+    /// Only the allocated prefix of the region is considered, like for [`Stack`](super::Stack).
+    #[inline]
+    fn arena_range(&self) -> Option<Range<usize>> {
+        let base = self.base.as_ptr() as usize;
+        Some(base..base + self.idx.get())
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        Some(self.remaining())
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        Some(self.used())
+    }
+}
+
+impl ProbeAllocator for SubArena<'_> {
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        layout.size() == 0 || self.bounds(layout).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::Stack;
+
+    #[test]
+    fn carved_sub_arenas_are_independent() {
+        let stack = Stack::<256>::new();
+        let mut first = stack.carve(64).unwrap();
+        let second = stack.carve(64).unwrap();
+        assert_eq!(stack.used(), 128);
+
+        let chunk = Layout::new::<[u8; 32]>();
+        let a = first.allocate(chunk).unwrap().cast();
+        first.allocate(chunk).unwrap();
+        assert!(first.allocate(Layout::new::<u8>()).is_err());
+        assert!(!first.can_allocate(Layout::new::<u8>()));
+
+        let b = second.allocate(chunk).unwrap().cast();
+        assert_eq!(second.remaining(), 32);
+
+        assert!(first.contains(a, chunk));
+        assert!(!second.contains(a, chunk));
+        assert!(second.contains(b, chunk));
+        assert!(!first.contains(b, chunk));
+        // The carved regions are live allocations of the parent.
+        assert!(stack.contains(a, chunk));
+        assert!(stack.contains(b, chunk));
+
+        first.reset();
+        assert_eq!(first.used(), 0);
+        assert_eq!(second.used(), 32);
+        assert!(first.allocate(Layout::new::<[u8; 64]>()).is_ok());
+    }
+
+    #[test]
+    fn dropping_sub_arena_releases_its_region() {
+        let stack = Stack::<128>::new();
+        let kept = stack.carve(16).unwrap();
+        let sub = stack.carve(64).unwrap();
+        assert!(stack.carve(64).is_err());
+
+        drop(sub);
+        assert_eq!(stack.used(), 16);
+        let again = stack.carve(64).unwrap();
+        assert_eq!(again.capacity(), 64);
+        drop(kept);
+    }
+
+    #[test]
+    fn vec_in_sub_arena() {
+        use allocator_api2::vec::Vec;
+
+        let stack = Stack::<1024>::new();
+        let sub = stack.carve(256).unwrap();
+        let mut v = Vec::new_in(&sub);
+        v.extend(0..32u32);
+        assert!(v.iter().copied().eq(0..32));
+        assert!(v.try_reserve(64).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    dangling, AllocatorId, ArenaAllocator, BulkAllocator, GoodSizeAllocator, InPlaceGrow,
+    SameAllocator, TrimAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, marker::PhantomData, mem::MaybeUninit, ops::Range, ptr::NonNull};
+use lock_api::RawMutex;
+use talc::{ErrOnOom, Span, Talc, Talck};
+
+/// General-purpose allocator over a borrowed buffer, managed by [talc](https://crates.io/crates/talc).
+///
+/// Unlike the bump allocators of this crate, it reuses freed blocks, at the cost of some metadata
+/// kept at the start of the buffer, so it suits long-lived data with a bounded footprint. Talc is
+/// guarded by the lock `R`, e.g. `spin::Mutex<()>`, so the arena is `Sync` if `R` is. It never
+/// claims memory beyond the buffer, so [`contains`](ArenaAllocator::contains) checks the heap
+/// that talc established in it.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::TalcArena, prelude::*};
+/// use allocator_api2::vec::Vec;
+/// use std::{alloc::System, mem::MaybeUninit};
+///
+/// let mut buf = [MaybeUninit::uninit(); 4096];
+/// let talc_arena = TalcArena::<spin::Mutex<()>>::new(&mut buf).unwrap();
+/// let alloc = talc_arena
+///     .by_ref()
+///     .cond(|layout| layout.size() <= 256)
+///     .fallback(System);
+///
+/// let small: Vec<u32, _> = (0..16).collect_in(&alloc);
+/// let large: Vec<u32, _> = (0..1024).collect_in(&alloc);
+/// assert!(talc_arena.contains(NonNull::from(&small[..]).cast(), Layout::for_value(&small[..])));
+/// assert!(!talc_arena.contains(NonNull::from(&large[..]).cast(), Layout::for_value(&large[..])));
+/// # use std::{alloc::Layout, ptr::NonNull};
+/// ```
+pub struct TalcArena<'a, R: RawMutex> {
+    talck: Talck<R, ErrOnOom>,
+    heap: Range<usize>,
+    _marker: PhantomData<&'a mut [MaybeUninit<u8>]>,
+}
+
+impl<'a, R: RawMutex> TalcArena<'a, R> {
+    /// Hands `buf` over to talc, or fails if it is too small to hold talc's metadata.
+    pub fn new(buf: &'a mut [MaybeUninit<u8>]) -> Result<Self, AllocError> {
+        let mut talc = Talc::new(ErrOnOom);
+        let memory = Span::from_base_size(buf.as_mut_ptr().cast(), buf.len());
+        // The buffer is borrowed for as long as talc lives, so nothing else touches it.
+        let heap = unsafe { talc.claim(memory) }.map_err(|()| AllocError)?;
+        let (base, acme) = heap.get_base_acme().ok_or(AllocError)?;
+        Ok(Self {
+            talck: talc.lock(),
+            heap: base as usize..acme as usize,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns the number of bytes allocated, without the padding and metadata.
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.talck.lock().get_counters().allocated_bytes
+    }
+
+    /// Returns the number of bytes free for allocation, which may be fragmented.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.talck.lock().get_counters().available_bytes
+    }
+
+    /// Returns a reference to the locked talc.
+    #[inline]
+    pub fn inner(&self) -> &Talck<R, ErrOnOom> {
+        &self.talck
+    }
+}
+
+unsafe impl<R: RawMutex> Allocator for TalcArena<'_, R> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        let ptr = unsafe { self.talck.lock().malloc(layout) }.map_err(|()| AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            self.talck.lock().free(ptr, layout)
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if let Ok(block) = self.try_grow_in_place(ptr, old_layout, new_layout) {
+            return Ok(block);
+        }
+        let block = self.allocate(new_layout)?;
+        block
+            .cast::<u8>()
+            .as_ptr()
+            .copy_from_nonoverlapping(ptr.as_ptr(), old_layout.size());
+        self.deallocate(ptr, old_layout);
+        Ok(block)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if new_layout.size() != 0 && ptr.as_ptr() as usize % new_layout.align() == 0 {
+            self.talck.lock().shrink(ptr, old_layout, new_layout.size());
+            return Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()));
+        }
+        let block = self.allocate(new_layout)?;
+        block
+            .cast::<u8>()
+            .as_ptr()
+            .copy_from_nonoverlapping(ptr.as_ptr(), new_layout.size());
+        self.deallocate(ptr, old_layout);
+        Ok(block)
+    }
+}
+
+impl<R: RawMutex> ArenaAllocator for TalcArena<'_, R> {
+    /// The whole heap is considered, including its free blocks and talc's metadata.
+    #[inline]
+    fn arena_range(&self) -> Option<Range<usize>> {
+        Some(self.heap.clone())
+    }
+
+    /// Free blocks are fragmented, so a single allocation may not get all of it.
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        Some(self.remaining())
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        Some(self.used())
+    }
+}
+
+impl<R: RawMutex> InPlaceGrow for TalcArena<'_, R> {
+    /// Succeeds if the free space after the block fits the new size.
+    unsafe fn try_grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() == 0 || ptr.as_ptr() as usize % new_layout.align() != 0 {
+            return Err(AllocError);
+        }
+        let ptr = self
+            .talck
+            .lock()
+            .grow_in_place(ptr, old_layout, new_layout.size())
+            .map_err(|()| AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()))
+    }
+}
+
+/// Talc doesn't release memory, the buffer is returned when the arena is dropped.
+impl<R: RawMutex> TrimAllocator for TalcArena<'_, R> {}
+
+impl<R: RawMutex> BulkAllocator for TalcArena<'_, R> {}
+
+impl<R: RawMutex> GoodSizeAllocator for TalcArena<'_, R> {}
+
+impl<R: RawMutex> SameAllocator for TalcArena<'_, R> {
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        AllocatorId::from_ptr(self.heap.start as *const u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::tests::exercise, combinator::Fallback};
+    use std::alloc::System;
+
+    type Arena<'a> = TalcArena<'a, spin::Mutex<()>>;
+
+    #[test]
+    fn talc_arena_passes_battery() {
+        let mut buf = std::vec![MaybeUninit::uninit(); 64 * 1024];
+        let arena = Arena::new(&mut buf).unwrap();
+        let remaining = arena.remaining();
+        exercise(&arena);
+        assert_eq!((arena.used(), arena.remaining()), (0, remaining));
+    }
+
+    #[test]
+    fn talc_arena_reuses_freed_blocks() {
+        let mut buf = [MaybeUninit::uninit(); 4096];
+        let bounds = buf.as_ptr_range();
+        let arena = Arena::new(&mut buf).unwrap();
+        let heap = arena.arena_range().unwrap();
+        assert!(bounds.start as usize <= heap.start && heap.end <= bounds.end as usize);
+
+        let layout = Layout::new::<[u64; 8]>();
+        let first = arena.allocate(layout).unwrap();
+        let _second = arena.allocate(layout).unwrap();
+        assert_eq!(arena.used(), 128);
+        unsafe { arena.deallocate(first.cast(), layout) };
+        assert_eq!(arena.allocate(layout).unwrap(), first);
+    }
+
+    #[test]
+    fn talc_arena_routes_fallback_deallocations() {
+        let mut buf = [MaybeUninit::uninit(); 4096];
+        let alloc = Fallback::new(Arena::new(&mut buf).unwrap(), System);
+        let (small, large) = (Layout::new::<[u8; 512]>(), Layout::new::<[u8; 8192]>());
+
+        let in_arena = alloc.allocate(small).unwrap().cast();
+        let spilled = alloc.allocate(large).unwrap().cast();
+        assert!(alloc.primary().contains(in_arena, small));
+        assert!(!alloc.primary().contains(spilled, large));
+
+        let used = alloc.primary().used();
+        // Freeing the spilled block must reach the system allocator and leave the arena alone.
+        unsafe { alloc.deallocate(spilled, large) };
+        assert_eq!(alloc.primary().used(), used);
+        unsafe { alloc.deallocate(in_arena, small) };
+        assert_eq!(alloc.primary().used(), 0);
+    }
+
+    #[test]
+    fn talc_arena_rejects_buffer_too_small_for_metadata() {
+        let mut buf = [MaybeUninit::uninit(); 64];
+        assert!(Arena::new(&mut buf).is_err());
+    }
+}
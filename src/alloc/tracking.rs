@@ -0,0 +1,79 @@
+use alloc_crate::collections::BTreeMap;
+use core::{alloc::Layout, cell::RefCell, fmt};
+
+/// Side table of the live allocations of an arena, by their offset.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Tracker {
+    blocks: RefCell<BTreeMap<usize, Layout>>,
+}
+
+impl Tracker {
+    #[inline]
+    pub(crate) const fn new() -> Self {
+        Self {
+            blocks: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records a block, replacing the previous one at the same offset, e.g. after a reallocation.
+    #[inline]
+    pub(crate) fn insert(&self, offset: usize, layout: Layout) {
+        self.blocks.borrow_mut().insert(offset, layout);
+    }
+
+    #[inline]
+    pub(crate) fn remove(&self, offset: usize) {
+        self.blocks.borrow_mut().remove(&offset);
+    }
+
+    /// Forgets the blocks at `offset` and above.
+    #[inline]
+    pub(crate) fn truncate(&self, offset: usize) {
+        self.blocks.borrow_mut().split_off(&offset);
+    }
+
+    #[inline]
+    pub(crate) fn contains(&self, offset: usize) -> bool {
+        self.blocks.borrow().contains_key(&offset)
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.blocks.borrow().len()
+    }
+
+    #[inline]
+    pub(crate) fn clear(&self) {
+        self.blocks.borrow_mut().clear();
+    }
+
+    /// Writes a table of the live blocks, followed by a summary of the arena.
+    ///
+    /// Bytes of the used region that don't belong to a live block are reported as padding.
+    /// Besides alignment padding, it includes blocks that were deallocated out of order.
+    pub(crate) fn dump(
+        &self,
+        out: &mut dyn fmt::Write,
+        capacity: usize,
+        used: usize,
+    ) -> fmt::Result {
+        let blocks = self.blocks.borrow();
+        writeln!(out, "{:>10} {:>10} {:>10}", "offset", "size", "align")?;
+        for (offset, layout) in blocks.iter() {
+            writeln!(
+                out,
+                "{offset:>10} {:>10} {:>10}",
+                layout.size(),
+                layout.align()
+            )?;
+        }
+        let live: usize = blocks.values().map(Layout::size).sum();
+        writeln!(
+            out,
+            "blocks: {}, live: {live}, padding: {}, free: {}, total: {capacity}",
+            blocks.len(),
+            used - live,
+            capacity - used,
+        )
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{ArenaExt, Error};
+use allocator_api2::alloc::Allocator;
+use core::{
+    fmt,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+/// An owning pointer to a value in an arena, without the allocator.
+///
+/// Unlike [`Box`](allocator_api2::boxed::Box), it doesn't store the allocator, so it is exactly
+/// one pointer wide however large the allocator handle is. In exchange, dropping it runs the
+/// destructor of the value but never deallocates the memory: it is reclaimed when the arena is
+/// reset or dropped. With an allocator that frees blocks individually, the memory leaks.
+///
+/// The box borrows the arena, so it can't outlive it.
+/// ```compile_fail
+/// use allocandrescu::{alloc::Stack, ArenaBox};
+///
+/// let boxed = {
+///     let stack = Stack::<64>::new();
+///     ArenaBox::new_in(1u64, &stack)
+/// };
+/// ```
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, ArenaBox};
+/// use core::mem::size_of;
+///
+/// let stack = Stack::<64>::new();
+/// let mut name = ArenaBox::new_in(String::from("arena"), &stack);
+/// name.push_str("-box");
+/// assert_eq!(*name, "arena-box");
+/// assert_eq!(size_of::<ArenaBox<String>>(), size_of::<usize>());
+/// ```
+pub struct ArenaBox<'a, T: ?Sized> {
+    ptr: NonNull<T>,
+    /// Borrows the arena and owns the value, like `Box`.
+    _marker: PhantomData<(&'a (), T)>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for ArenaBox<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for ArenaBox<'_, T> {}
+
+impl<'a, T> ArenaBox<'a, T> {
+    /// Moves `value` into `alloc`.
+    ///
+    /// # Panics
+    /// Panics if the allocation fails.
+    #[inline]
+    pub fn new_in<A>(value: T, alloc: &'a A) -> Self
+    where
+        A: Allocator + ?Sized,
+    {
+        Self::try_new_in(value, alloc).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Moves `value` into `alloc`, or returns an error if the allocation fails.
+    #[inline]
+    pub fn try_new_in<A>(value: T, alloc: &'a A) -> Result<Self, Error>
+    where
+        A: Allocator + ?Sized,
+    {
+        alloc.alloc_value(value).map(|value| Self {
+            ptr: NonNull::from(value),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Moves the value out of the box.
+    #[inline]
+    pub fn into_inner(this: Self) -> T {
+        let this = ManuallyDrop::new(this);
+        unsafe { this.ptr.as_ptr().read() }
+    }
+}
+
+impl<'a, T: ?Sized> ArenaBox<'a, T> {
+    /// Consumes the box without dropping the value, returning a reference to it.
+    #[inline]
+    pub fn leak(this: Self) -> &'a mut T {
+        let this = ManuallyDrop::new(this);
+        unsafe { &mut *this.ptr.as_ptr() }
+    }
+}
+
+impl<T: ?Sized> Deref for ArenaBox<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for ArenaBox<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: ?Sized> Drop for ArenaBox<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.ptr.as_ptr()) }
+    }
+}
+
+impl<T> fmt::Debug for ArenaBox<'_, T>
+where
+    T: ?Sized + fmt::Debug,
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _};
+    use core::{
+        cell::Cell,
+        mem::{size_of, size_of_val},
+    };
+
+    struct Noisy<'a>(&'a Cell<usize>);
+
+    impl Drop for Noisy<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn arena_box_is_one_pointer_wide() {
+        assert_eq!(size_of::<ArenaBox<u64>>(), size_of::<usize>());
+        assert_eq!(size_of::<Option<ArenaBox<u64>>>(), size_of::<usize>());
+        // The allocator handle isn't stored, however large it is.
+        let stack = Stack::<64>::new();
+        let alloc = stack.by_ref().cond(|_| true).fallback(&stack);
+        let boxed = ArenaBox::new_in(7u64, &alloc);
+        assert!(size_of_val(&alloc) > size_of_val(&boxed));
+        assert_eq!(*boxed, 7);
+    }
+
+    #[test]
+    fn arena_box_drops_value_but_keeps_memory() {
+        let drops = Cell::new(0);
+        let stack = Stack::<64>::new();
+        let mut boxed = ArenaBox::new_in(Noisy(&drops), &stack);
+        *boxed = Noisy(&drops);
+        assert_eq!(drops.get(), 1);
+        drop(boxed);
+        assert_eq!(drops.get(), 2);
+        assert_eq!(stack.used(), size_of::<Noisy>());
+
+        let value = ArenaBox::into_inner(ArenaBox::new_in(Noisy(&drops), &stack));
+        assert_eq!(drops.get(), 2);
+        drop(value);
+        let leaked = ArenaBox::leak(ArenaBox::new_in(Noisy(&drops), &stack));
+        assert_eq!(drops.get(), 3);
+        assert!(core::ptr::eq(leaked.0, &drops));
+    }
+
+    #[test]
+    fn arena_box_reports_failure() {
+        let stack = Stack::<8>::new();
+        let error = ArenaBox::try_new_in([0u64; 2], &stack).unwrap_err();
+        assert_eq!(error.layout(), Some(core::alloc::Layout::new::<[u64; 2]>()));
+        assert_eq!(std::format!("{:?}", ArenaBox::new_in(1u8, &stack)), "1");
+    }
+}
@@ -0,0 +1,304 @@
+use crate::{ArenaExt, Error};
+use allocator_api2::alloc::Allocator;
+use core::{
+    cell::Cell,
+    fmt,
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ops::Deref,
+    ptr::{self, NonNull},
+};
+
+/// A reference-counted pointer to a value in an arena, with the count stored in front of it.
+///
+/// Like [`ArenaBox`](crate::ArenaBox), it borrows the arena instead of storing the allocator, so
+/// it is one pointer wide and can't outlive the arena. Cloning it increments the count, and
+/// dropping the last clone runs the destructor of the value. The memory is never deallocated: it
+/// is reclaimed when the arena is reset or dropped, and with an allocator that frees blocks
+/// individually, it leaks.
+///
+/// Clones that refer to each other in a cycle keep their counts above zero, so their destructors
+/// never run. The memory is still reclaimed with the arena, but whatever the values own outside
+/// of it leaks, like with `Rc`.
+///
+/// Weak references are kept without a count: the memory outlives them anyway, so
+/// [`ArenaWeak::upgrade`] only checks that the value wasn't dropped.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, ArenaRc};
+///
+/// let stack = Stack::<256>::new();
+/// let shared = ArenaRc::new_in([1, 2, 3], &stack);
+/// let left = (ArenaRc::new_in('l', &stack), shared.clone());
+/// let right = (ArenaRc::new_in('r', &stack), shared.clone());
+/// assert_eq!(ArenaRc::strong_count(&shared), 3);
+/// assert!(ArenaRc::ptr_eq(&left.1, &right.1));
+/// drop((shared, left));
+/// assert_eq!(*right.1, [1, 2, 3]);
+/// ```
+pub struct ArenaRc<'a, T> {
+    ptr: NonNull<RcBox<T>>,
+    _marker: PhantomData<(&'a (), RcBox<T>)>,
+}
+
+struct RcBox<T> {
+    strong: Cell<usize>,
+    value: ManuallyDrop<T>,
+}
+
+impl<'a, T> ArenaRc<'a, T> {
+    /// Moves `value` into `alloc` with a count of one.
+    ///
+    /// # Panics
+    /// Panics if the allocation fails.
+    #[inline]
+    pub fn new_in<A>(value: T, alloc: &'a A) -> Self
+    where
+        A: Allocator + ?Sized,
+    {
+        Self::try_new_in(value, alloc).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Moves `value` into `alloc` with a count of one, or returns an error if the allocation
+    /// fails.
+    #[inline]
+    pub fn try_new_in<A>(value: T, alloc: &'a A) -> Result<Self, Error>
+    where
+        A: Allocator + ?Sized,
+    {
+        let rc_box = RcBox {
+            strong: Cell::new(1),
+            value: ManuallyDrop::new(value),
+        };
+        alloc.alloc_value(rc_box).map(|rc_box| Self {
+            ptr: NonNull::from(rc_box),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns the number of clones referring to the value.
+    #[inline]
+    pub fn strong_count(this: &Self) -> usize {
+        this.rc_box().strong.get()
+    }
+
+    /// Returns `true` if both refer to the same value.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Returns a weak reference to the value.
+    #[inline]
+    pub fn downgrade(this: &Self) -> ArenaWeak<'a, T> {
+        ArenaWeak {
+            ptr: this.ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Moves the value out if this is the last clone, or returns `None` and drops this clone.
+    #[inline]
+    pub fn into_inner(this: Self) -> Option<T> {
+        let this = ManuallyDrop::new(this);
+        let rc_box = this.rc_box();
+        let strong = rc_box.strong.get() - 1;
+        rc_box.strong.set(strong);
+        if strong != 0 {
+            return None;
+        }
+        // Weak references see the value as dropped once the count is zero, so it can be moved out.
+        let value = unsafe { ptr::addr_of!((*this.ptr.as_ptr()).value).read() };
+        Some(ManuallyDrop::into_inner(value))
+    }
+
+    #[inline]
+    fn rc_box(&self) -> &RcBox<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> Clone for ArenaRc<'_, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        let strong = &self.rc_box().strong;
+        strong.set(
+            strong
+                .get()
+                .checked_add(1)
+                .expect("reference count overflow"),
+        );
+        Self {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for ArenaRc<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.rc_box().value
+    }
+}
+
+impl<T> Drop for ArenaRc<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        let strong = &self.rc_box().strong;
+        strong.set(strong.get() - 1);
+        if strong.get() == 0 {
+            // Upgrading from the destructor fails, as the count is already zero.
+            unsafe { ManuallyDrop::drop(&mut (*self.ptr.as_ptr()).value) }
+        }
+    }
+}
+
+impl<T> fmt::Debug for ArenaRc<'_, T>
+where
+    T: fmt::Debug,
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+/// A weak reference to a value of an [`ArenaRc`], which doesn't keep it from being dropped.
+///
+/// It doesn't count, so it is `Copy`.
+///
+/// This `struct` is created by [`ArenaRc::downgrade`].
+pub struct ArenaWeak<'a, T> {
+    ptr: NonNull<RcBox<T>>,
+    _marker: PhantomData<(&'a (), RcBox<T>)>,
+}
+
+impl<'a, T> ArenaWeak<'a, T> {
+    /// Returns a new clone of the value, or `None` if it was dropped.
+    #[inline]
+    pub fn upgrade(&self) -> Option<ArenaRc<'a, T>> {
+        // The memory lives as long as the arena, even after the value is dropped.
+        let strong = unsafe { &self.ptr.as_ref().strong };
+        (strong.get() != 0).then(|| {
+            let rc = ArenaRc {
+                ptr: self.ptr,
+                _marker: PhantomData,
+            };
+            // Counts the returned clone.
+            mem::forget(rc.clone());
+            rc
+        })
+    }
+}
+
+impl<T> Clone for ArenaWeak<'_, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ArenaWeak<'_, T> {}
+
+impl<T> fmt::Debug for ArenaWeak<'_, T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(ArenaWeak)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::Stack;
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    /// Logs its name when dropped.
+    struct Named<'l>(&'static str, &'l RefCell<Vec<&'static str>>);
+
+    impl Drop for Named<'_> {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    #[test]
+    fn arena_rc_drops_value_with_last_clone() {
+        let log = RefCell::new(Vec::new());
+        let stack = Stack::<256>::new();
+        let a = ArenaRc::new_in(Named("a", &log), &stack);
+        let b = ArenaRc::new_in(Named("b", &log), &stack);
+        let (a2, b2, b3) = (a.clone(), b.clone(), b.clone());
+        assert_eq!(
+            (ArenaRc::strong_count(&a), ArenaRc::strong_count(&b)),
+            (2, 3)
+        );
+        let used = stack.used();
+
+        drop((a, b, b2));
+        assert!(log.borrow().is_empty());
+        drop(b3);
+        assert_eq!(*log.borrow(), ["b"]);
+        drop(a2);
+        assert_eq!(*log.borrow(), ["b", "a"]);
+        // The memory goes back with the arena only.
+        assert_eq!(stack.used(), used);
+    }
+
+    #[test]
+    fn arena_rc_weak_upgrades_until_dropped() {
+        let log = RefCell::new(Vec::new());
+        let stack = Stack::<256>::new();
+        let rc = ArenaRc::new_in(Named("value", &log), &stack);
+        let weak = ArenaRc::downgrade(&rc);
+        let upgraded = weak.upgrade().unwrap();
+        assert_eq!(ArenaRc::strong_count(&rc), 2);
+        assert!(ArenaRc::into_inner(upgraded).is_none());
+
+        let value = ArenaRc::into_inner(rc).unwrap();
+        assert!(weak.upgrade().is_none());
+        assert!(log.borrow().is_empty());
+        drop(value);
+        assert_eq!(*log.borrow(), ["value"]);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn arena_rc_cycles_leak() {
+        struct Node<'a, 'l> {
+            _name: Named<'l>,
+            next: RefCell<Option<ArenaRc<'a, Node<'a, 'l>>>>,
+        }
+
+        let log = RefCell::new(Vec::new());
+        let stack = Stack::<256>::new();
+        let node = |name| {
+            let next = RefCell::new(None);
+            ArenaRc::new_in(
+                Node {
+                    _name: Named(name, &log),
+                    next,
+                },
+                &stack,
+            )
+        };
+        let (a, b) = (node("a"), node("b"));
+        *a.next.borrow_mut() = Some(b.clone());
+        *b.next.borrow_mut() = Some(a.clone());
+        drop((a, b));
+        assert!(log.borrow().is_empty());
+
+        // Breaking the cycle before dropping lets both go.
+        let (c, d) = (node("c"), node("d"));
+        *c.next.borrow_mut() = Some(d.clone());
+        *d.next.borrow_mut() = Some(c.clone());
+        c.next.borrow_mut().take();
+        drop((c, d));
+        assert_eq!(*log.borrow(), ["d", "c"]);
+    }
+}
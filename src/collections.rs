@@ -0,0 +1,360 @@
+//! Collections and collection helpers built on allocators.
+
+use crate::{Error, Operation};
+use allocator_api2::{alloc::Allocator, boxed::Box, vec::Vec};
+use core::alloc::Layout;
+
+mod clone_in;
+mod fixed_vec;
+mod interner;
+mod seg_vec;
+
+pub use clone_in::TryCloneIn;
+pub use fixed_vec::{FixedVec, FixedVecDrain};
+pub use interner::{Interner, InternerIter};
+pub use seg_vec::{SegVec, SegVecIter};
+
+/// Conversion from an [`Iterator`] into a collection allocated in an allocator.
+///
+/// It is the counterpart of [`FromIterator`] used by [`CollectIn`].
+pub trait FromIteratorIn<T, A: Allocator>: Sized {
+    /// Creates a collection in `alloc` from the values of `iter`.
+    ///
+    /// # Panics
+    /// Panics if the allocation fails.
+    fn from_iter_in<I>(iter: I, alloc: A) -> Self
+    where
+        I: IntoIterator<Item = T>;
+
+    /// Creates a collection in `alloc` from the values of `iter`, or returns an error if the
+    /// allocation fails.
+    fn try_from_iter_in<I>(iter: I, alloc: A) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = T>;
+}
+
+impl<T, A> FromIteratorIn<T, A> for Vec<T, A>
+where
+    A: Allocator,
+{
+    fn from_iter_in<I>(iter: I, alloc: A) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let iter = iter.into_iter();
+        let mut vec = Vec::with_capacity_in(iter.size_hint().0, alloc);
+        vec.extend(iter);
+        vec
+    }
+
+    fn try_from_iter_in<I>(iter: I, alloc: A) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let iter = iter.into_iter();
+        let mut vec = Vec::new_in(alloc);
+        try_reserve_exact(&mut vec, iter.size_hint().0)?;
+        for value in iter {
+            if vec.len() == vec.capacity() {
+                let additional = vec.capacity().max(4);
+                try_reserve_exact(&mut vec, additional)?;
+            }
+            vec.push(value);
+        }
+        Ok(vec)
+    }
+}
+
+impl<T, A> FromIteratorIn<T, A> for Box<[T], A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn from_iter_in<I>(iter: I, alloc: A) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        Vec::from_iter_in(iter, alloc).into_boxed_slice()
+    }
+
+    #[inline]
+    fn try_from_iter_in<I>(iter: I, alloc: A) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        Vec::try_from_iter_in(iter, alloc).map(Vec::into_boxed_slice)
+    }
+}
+
+/// Creates an empty vector with room for exactly `capacity` values in `alloc`, or returns an
+/// error if the allocation fails.
+///
+/// Unlike [`Vec::with_capacity_in`], it never aborts.
+#[inline]
+pub fn try_vec_with_capacity_in<T, A>(capacity: usize, alloc: A) -> Result<Vec<T, A>, Error>
+where
+    A: Allocator,
+{
+    let mut vec = Vec::new_in(alloc);
+    try_reserve_exact(&mut vec, capacity)?;
+    Ok(vec)
+}
+
+/// Creates a vector of `n` clones of `elem` in `alloc`, or returns an error if the allocation
+/// fails. See [`try_vec_in!`](crate::try_vec_in).
+pub fn try_vec_from_elem_in<T, A>(elem: T, n: usize, alloc: A) -> Result<Vec<T, A>, Error>
+where
+    T: Clone,
+    A: Allocator,
+{
+    let mut vec = try_vec_with_capacity_in(n, alloc)?;
+    // The capacity is reserved, so resizing doesn't allocate.
+    vec.resize(n, elem);
+    Ok(vec)
+}
+
+/// Moves `value` into a box in `alloc`, or returns an error if the allocation fails.
+///
+/// Unlike [`Box::new_in`], it never aborts. See [`try_box_in!`](crate::try_box_in).
+#[inline]
+pub fn try_box_new_in<T, A>(value: T, alloc: A) -> Result<Box<T, A>, Error>
+where
+    A: Allocator,
+{
+    Box::try_new_in(value, alloc).map_err(Error::map(Operation::Allocate, Layout::new::<T>()))
+}
+
+/// Creates a [`Vec`] in an allocator like `vec![in alloc; ...]`, returning `Result<Vec<T, A>, Error>`
+/// instead of aborting if the allocation fails.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, try_vec_in};
+///
+/// let stack = Stack::<64>::new();
+/// let zeros = try_vec_in!(&stack; 0u32; 8).unwrap();
+/// let digits = try_vec_in!(&stack; 1u8, 2, 3).unwrap();
+/// assert_eq!((zeros.len(), &digits[..]), (8, &[1, 2, 3][..]));
+/// assert!(try_vec_in!(&stack; 0u64; 8).is_err());
+/// ```
+#[macro_export]
+macro_rules! try_vec_in {
+    ($alloc:expr $(;)?) => {
+        $crate::collections::try_vec_with_capacity_in(0, $alloc)
+    };
+    ($alloc:expr; $elem:expr; $n:expr) => {
+        $crate::collections::try_vec_from_elem_in($elem, $n, $alloc)
+    };
+    ($alloc:expr; $($elem:expr),+ $(,)?) => {
+        <$crate::__private::Vec<_, _> as $crate::collections::FromIteratorIn<_, _>>::try_from_iter_in(
+            [$($elem),+],
+            $alloc,
+        )
+    };
+}
+
+/// Moves a value into a [`Box`] in an allocator, returning `Result<Box<T, A>, Error>` instead of
+/// aborting if the allocation fails.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, try_box_in};
+///
+/// let stack = Stack::<16>::new();
+/// let boxed = try_box_in!(&stack, 7u64).unwrap();
+/// assert_eq!(*boxed, 7);
+/// assert!(try_box_in!(&stack, [0u64; 2]).is_err());
+/// ```
+#[macro_export]
+macro_rules! try_box_in {
+    ($alloc:expr, $value:expr $(,)?) => {
+        $crate::collections::try_box_new_in($value, $alloc)
+    };
+}
+
+/// Reserves space for exactly `additional` more values, reporting the requested layout,
+/// which allocator-api2 doesn't expose.
+fn try_reserve_exact<T, A>(vec: &mut Vec<T, A>, additional: usize) -> Result<(), Error>
+where
+    A: Allocator,
+{
+    let operation = if vec.capacity() == 0 {
+        Operation::Allocate
+    } else {
+        Operation::Grow
+    };
+    let layout = vec
+        .len()
+        .checked_add(additional)
+        .and_then(|capacity| Layout::array::<T>(capacity).ok())
+        .ok_or(Error::new(operation, None))?;
+    vec.try_reserve_exact(additional)
+        .map_err(|_| Error::new(operation, Some(layout)))
+}
+
+/// Extension trait for [`Iterator`] trait that provides methods for collecting into an allocator.
+pub trait CollectIn: Iterator + Sized {
+    /// Collects the values into a collection allocated in `alloc`.
+    ///
+    /// The collection is preallocated for the lower bound of the iterator's size hint.
+    ///
+    /// # Panics
+    /// Panics if the allocation fails.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::vec::Vec;
+    ///
+    /// let stack = Stack::<256>::new();
+    /// let squares: Vec<u32, _> = (0..16).map(|i| i * i).collect_in(&stack);
+    /// assert_eq!(squares[15], 225);
+    /// ```
+    #[inline]
+    fn collect_in<C, A>(self, alloc: A) -> C
+    where
+        C: FromIteratorIn<Self::Item, A>,
+        A: Allocator,
+    {
+        C::from_iter_in(self, alloc)
+    }
+
+    /// Collects the values into a collection allocated in `alloc`, or returns an error if the
+    /// allocation fails.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::boxed::Box;
+    ///
+    /// let stack = Stack::<16>::new();
+    /// let result = (0..16u32).try_collect_in::<Box<[_], _>, _>(&stack);
+    /// assert!(result.is_err());
+    /// ```
+    #[inline]
+    fn try_collect_in<C, A>(self, alloc: A) -> Result<C, Error>
+    where
+        C: FromIteratorIn<Self::Item, A>,
+        A: Allocator,
+    {
+        C::try_from_iter_in(self, alloc)
+    }
+}
+
+impl<I: Iterator> CollectIn for I {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, prelude::*};
+    use core::ptr::NonNull;
+
+    /// Yields `len` values while claiming a lower bound of `hint`.
+    struct Hinted {
+        len: usize,
+        hint: usize,
+    }
+
+    impl Iterator for Hinted {
+        type Item = u64;
+
+        fn next(&mut self) -> Option<u64> {
+            self.len = self.len.checked_sub(1)?;
+            Some(self.len as u64)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.hint, None)
+        }
+    }
+
+    #[test]
+    fn try_vec_in_fails_gracefully() {
+        let stack = Stack::<8>::new();
+        let error = crate::try_vec_in!(&stack; 0u32; 4).unwrap_err();
+        assert_eq!(error.layout(), Some(Layout::new::<[u32; 4]>()));
+        let error = crate::try_vec_in!(&stack; 1u16, 2, 3, 4, 5).unwrap_err();
+        assert_eq!(error.layout(), Some(Layout::new::<[u16; 5]>()));
+        let error = crate::try_box_in!(&stack, [0u8; 9]).unwrap_err();
+        assert_eq!(error.layout(), Some(Layout::new::<[u8; 9]>()));
+        assert!(try_vec_with_capacity_in::<u8, _>(usize::MAX, &stack).is_err());
+        assert_eq!(stack.used(), 0);
+
+        let units = crate::try_vec_in!(&stack; (); 1000).unwrap();
+        let empty = crate::try_vec_in!(&stack; 0u64; 0).unwrap();
+        let unit = crate::try_box_in!(&stack, ()).unwrap();
+        let none: Vec<u64, _> = crate::try_vec_in!(&stack).unwrap();
+        assert_eq!(
+            (units.len(), empty.len(), *unit, none.capacity()),
+            (1000, 0, (), 0)
+        );
+        assert_eq!(stack.used(), 0);
+    }
+
+    #[test]
+    fn try_vec_in_succeeds_in_chain() {
+        let (primary, secondary) = (Stack::<8>::new(), Stack::<256>::new());
+        let alloc = primary.by_ref().fallback(secondary.by_ref());
+        let small = crate::try_vec_in!(&alloc; 7u8; 8).unwrap();
+        let large = crate::try_vec_in!(&alloc; String::from("x"); 3).unwrap();
+        let boxed = crate::try_box_in!(&alloc, [1u64; 4]).unwrap();
+        assert_eq!((&small[..], primary.used()), (&[7; 8][..], 8));
+        assert!(large.iter().all(|s| s == "x"));
+        assert_eq!(*boxed, [1; 4]);
+        let ptr = NonNull::from(&*boxed).cast();
+        assert!(secondary.contains(ptr, Layout::new::<[u64; 4]>()));
+    }
+
+    #[test]
+    fn collect_in_places_vec_in_chain() {
+        let (primary, secondary) = (Stack::<64>::new(), Stack::<1024>::new());
+        let alloc = primary.by_ref().fallback(secondary.by_ref());
+
+        let small: Vec<u8, _> = (0..32).collect_in(&alloc);
+        let large: Vec<u32, _> = (0..64).collect_in(&alloc);
+        assert_eq!(small.capacity(), 32);
+        assert!(small.iter().copied().eq(0..32));
+        assert!(large.iter().copied().eq(0..64));
+        let layout = |len| Layout::array::<u32>(len).unwrap();
+        assert!(primary.contains(NonNull::from(&small[0]), Layout::array::<u8>(32).unwrap()));
+        assert!(secondary.contains(NonNull::from(&large[0]).cast(), layout(64)));
+        assert!(!primary.contains(NonNull::from(&large[0]).cast(), layout(64)));
+    }
+
+    #[test]
+    fn collect_in_boxes_slices() {
+        let stack = Stack::<256>::new();
+        let boxed: Box<[u64], _> = Hinted { len: 10, hint: 1 }.collect_in(&stack);
+        assert!(boxed.iter().copied().eq((0..10).rev()));
+        assert!(stack.contains(NonNull::from(&boxed[0]).cast(), Layout::for_value(&*boxed)));
+
+        let boxed = Hinted { len: 3, hint: 0 }
+            .try_collect_in::<Box<[_], _>, _>(&stack)
+            .unwrap();
+        assert_eq!(&*boxed, [2, 1, 0]);
+    }
+
+    #[test]
+    fn try_collect_in_reports_failures() {
+        let stack = Stack::<64>::new();
+        let error = Hinted {
+            len: 0,
+            hint: usize::MAX,
+        }
+        .try_collect_in::<Vec<_, _>, _>(&stack)
+        .unwrap_err();
+        assert_eq!(error.operation(), Operation::Allocate);
+        assert_eq!(error.layout(), None);
+
+        let error = Hinted { len: 10, hint: 4 }
+            .try_collect_in::<Vec<_, _>, _>(&stack)
+            .unwrap_err();
+        let layout = Layout::array::<u64>(16).unwrap();
+        assert_eq!(error, Error::new(Operation::Grow, Some(layout)));
+        assert_eq!(
+            std::format!("{error}"),
+            "failed to grow a block of size 128 and align 8"
+        );
+        assert_eq!(stack.used(), 0);
+    }
+}
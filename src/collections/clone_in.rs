@@ -0,0 +1,268 @@
+use super::try_reserve_exact;
+use crate::{Error, Operation};
+use allocator_api2::{alloc::Allocator, boxed::Box, vec::Vec};
+use core::alloc::Layout;
+
+/// Deep cloning of values into an allocator.
+///
+/// Every allocation of the clone, at every level of nesting, is made in the given allocator, so
+/// e.g. a `Vec<Vec<u8>>` on the global heap clones into a `Vec<Vec<u8, A>, A>`. The allocator is
+/// cloned for each allocation, which is cheap for references like `&Stack`.
+///
+/// Unsized slices and strings clone into boxes, e.g. `str` into `Box<str, A>`.
+/// References clone the value they point to.
+///
+/// # Implementing for your types
+/// Make the allocator a type parameter of the type and clone its fields one by one:
+/// ```
+/// use allocandrescu::{alloc::Stack, collections::TryCloneIn, Error};
+/// use allocator_api2::{
+///     alloc::{Allocator, Global},
+///     boxed::Box,
+///     vec::Vec,
+/// };
+///
+/// struct Mesh<A: Allocator = Global> {
+///     name: Box<str, A>,
+///     indices: Vec<u32, A>,
+/// }
+///
+/// impl<B: Allocator> TryCloneIn for Mesh<B> {
+///     type Cloned<A: Allocator> = Mesh<A>;
+///
+///     fn try_clone_in<A: Allocator + Clone>(&self, alloc: A) -> Result<Mesh<A>, Error> {
+///         Ok(Mesh {
+///             name: self.name.try_clone_in(alloc.clone())?,
+///             indices: self.indices.try_clone_in(alloc)?,
+///         })
+///     }
+/// }
+///
+/// let mesh = Mesh {
+///     name: Box::from("quad"),
+///     indices: Vec::from([0, 1, 2, 2, 3, 0]),
+/// };
+/// let stack = Stack::<256>::new();
+/// let cloned = mesh.try_clone_in(&stack).unwrap();
+/// assert_eq!((&*cloned.name, cloned.indices.len()), ("quad", 6));
+/// ```
+pub trait TryCloneIn {
+    /// The type of the clone allocated in `A`.
+    type Cloned<A: Allocator>;
+
+    /// Clones the value into `alloc`, or returns an error if an allocation fails.
+    fn try_clone_in<A: Allocator + Clone>(&self, alloc: A) -> Result<Self::Cloned<A>, Error>;
+
+    /// Clones the value into `alloc`.
+    ///
+    /// # Panics
+    /// Panics if an allocation fails.
+    #[inline]
+    fn clone_in<A: Allocator + Clone>(&self, alloc: A) -> Self::Cloned<A> {
+        self.try_clone_in(alloc)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+}
+
+macro_rules! impl_try_clone_in_by_copy {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl TryCloneIn for $ty {
+                type Cloned<A: Allocator> = $ty;
+
+                #[inline]
+                fn try_clone_in<A: Allocator + Clone>(&self, _: A) -> Result<$ty, Error> {
+                    Ok(*self)
+                }
+            }
+        )*
+    };
+}
+
+impl_try_clone_in_by_copy!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+);
+
+impl<T> TryCloneIn for &T
+where
+    T: TryCloneIn + ?Sized,
+{
+    type Cloned<A: Allocator> = T::Cloned<A>;
+
+    #[inline]
+    fn try_clone_in<A: Allocator + Clone>(&self, alloc: A) -> Result<Self::Cloned<A>, Error> {
+        (**self).try_clone_in(alloc)
+    }
+}
+
+impl<T> TryCloneIn for Option<T>
+where
+    T: TryCloneIn,
+{
+    type Cloned<A: Allocator> = Option<T::Cloned<A>>;
+
+    #[inline]
+    fn try_clone_in<A: Allocator + Clone>(&self, alloc: A) -> Result<Self::Cloned<A>, Error> {
+        self.as_ref()
+            .map(|value| value.try_clone_in(alloc))
+            .transpose()
+    }
+}
+
+impl<T> TryCloneIn for [T]
+where
+    T: TryCloneIn,
+{
+    type Cloned<A: Allocator> = Box<[T::Cloned<A>], A>;
+
+    #[inline]
+    fn try_clone_in<A: Allocator + Clone>(&self, alloc: A) -> Result<Self::Cloned<A>, Error> {
+        clone_slice(self, alloc).map(Vec::into_boxed_slice)
+    }
+}
+
+impl TryCloneIn for str {
+    type Cloned<A: Allocator> = Box<str, A>;
+
+    fn try_clone_in<A: Allocator + Clone>(&self, alloc: A) -> Result<Box<str, A>, Error> {
+        let mut bytes = Vec::new_in(alloc);
+        try_reserve_exact(&mut bytes, self.len())?;
+        bytes.extend_from_slice(self.as_bytes());
+        let (ptr, alloc) = Box::into_raw_with_allocator(bytes.into_boxed_slice());
+        // The bytes were copied from a `str`.
+        Ok(unsafe { Box::from_raw_in(ptr as *mut str, alloc) })
+    }
+}
+
+impl<T, B> TryCloneIn for Vec<T, B>
+where
+    T: TryCloneIn,
+    B: Allocator,
+{
+    type Cloned<A: Allocator> = Vec<T::Cloned<A>, A>;
+
+    #[inline]
+    fn try_clone_in<A: Allocator + Clone>(&self, alloc: A) -> Result<Self::Cloned<A>, Error> {
+        clone_slice(self, alloc)
+    }
+}
+
+impl<T, B> TryCloneIn for Box<T, B>
+where
+    T: TryCloneIn,
+    B: Allocator,
+{
+    type Cloned<A: Allocator> = Box<T::Cloned<A>, A>;
+
+    fn try_clone_in<A: Allocator + Clone>(&self, alloc: A) -> Result<Self::Cloned<A>, Error> {
+        let value = (**self).try_clone_in(alloc.clone())?;
+        Box::try_new_in(value, alloc).map_err(Error::map(
+            Operation::Allocate,
+            Layout::new::<T::Cloned<A>>(),
+        ))
+    }
+}
+
+impl<T, B> TryCloneIn for Box<[T], B>
+where
+    T: TryCloneIn,
+    B: Allocator,
+{
+    type Cloned<A: Allocator> = Box<[T::Cloned<A>], A>;
+
+    #[inline]
+    fn try_clone_in<A: Allocator + Clone>(&self, alloc: A) -> Result<Self::Cloned<A>, Error> {
+        (**self).try_clone_in(alloc)
+    }
+}
+
+impl<B> TryCloneIn for Box<str, B>
+where
+    B: Allocator,
+{
+    type Cloned<A: Allocator> = Box<str, A>;
+
+    #[inline]
+    fn try_clone_in<A: Allocator + Clone>(&self, alloc: A) -> Result<Box<str, A>, Error> {
+        (**self).try_clone_in(alloc)
+    }
+}
+
+/// Clones the values of `slice` one by one into a vector of the exact length.
+fn clone_slice<T, A>(slice: &[T], alloc: A) -> Result<Vec<T::Cloned<A>, A>, Error>
+where
+    T: TryCloneIn,
+    A: Allocator + Clone,
+{
+    let mut vec = Vec::new_in(alloc.clone());
+    try_reserve_exact(&mut vec, slice.len())?;
+    for value in slice {
+        vec.push(value.try_clone_in(alloc.clone())?);
+    }
+    Ok(vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, ArenaAllocator};
+    use core::ptr::NonNull;
+
+    fn slice_in<T>(alloc: &impl ArenaAllocator, slice: &[T]) -> bool {
+        alloc.contains(NonNull::from(slice).cast(), Layout::for_value(slice))
+    }
+
+    #[test]
+    fn try_clone_in_clones_every_level() {
+        let nested: Vec<Vec<u8>> = Vec::from([Vec::from([1, 2]), Vec::new(), Vec::from([3])]);
+        let boxed: Box<[Option<Box<str>>]> = Box::from([Some(Box::from("a")), None]);
+
+        let stack = Stack::<512>::new();
+        let cloned = nested.try_clone_in(&stack).unwrap();
+        let cloned_boxed = boxed.try_clone_in(&stack).unwrap();
+        assert_eq!(cloned, nested);
+        assert!(slice_in(&stack, &cloned));
+        assert!(cloned
+            .iter()
+            .filter(|inner| !inner.is_empty())
+            .all(|inner| slice_in(&stack, inner)));
+        assert!(slice_in(&stack, &cloned_boxed));
+        let inner = cloned_boxed[0].as_deref().unwrap();
+        assert_eq!(inner, "a");
+        assert!(slice_in(&stack, inner.as_bytes()));
+        assert!(cloned_boxed[1].is_none());
+
+        let value = Box::new(7u64).try_clone_in(&stack).unwrap();
+        assert!(stack.contains(NonNull::from(&*value).cast(), Layout::new::<u64>()));
+        assert_eq!((&"str").try_clone_in(&stack).as_deref(), Ok("str"));
+    }
+
+    #[test]
+    fn try_clone_in_reports_failure() {
+        let stack = Stack::<16>::new();
+        let error = [1u32, 2, 3, 4, 5].try_clone_in(&stack).unwrap_err();
+        assert_eq!(error.layout(), Some(Layout::new::<[u32; 5]>()));
+        // The outer vector fits, but not both of the inner ones.
+        let stack = Stack::<64>::new();
+        let error = Vec::from([[0u8; 12], [0; 12]].map(Vec::from))
+            .try_clone_in(&stack)
+            .unwrap_err();
+        assert_eq!(error.layout(), Some(Layout::new::<[u8; 12]>()));
+    }
+}
@@ -0,0 +1,416 @@
+use crate::{Error, Operation};
+use allocator_api2::alloc::Allocator;
+use core::{
+    alloc::Layout,
+    fmt,
+    iter::FusedIterator,
+    marker::PhantomData,
+    mem,
+    ops::{Bound, Deref, DerefMut, Range, RangeBounds},
+    ptr::{self, NonNull},
+    slice,
+};
+
+/// A contiguous vector whose capacity is allocated once, upfront.
+///
+/// [`push`](FixedVec::push) never allocates: once the vector is full, it hands the value back.
+/// Unlike with `Vec`, the elements never move to another block, so they stay in the allocator they
+/// were first placed in, even behind a [`fallback`](crate::Allocandrescu::fallback) chain. Unlike
+/// with [`SegVec`](super::SegVec), they are contiguous and can be viewed as a slice.
+///
+/// The block is allocated even for zero-sized elements or capacity, and deallocated on drop.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, collections::FixedVec};
+///
+/// let stack = Stack::<64>::new();
+/// let mut vec = FixedVec::with_capacity_in(3, &stack).unwrap();
+/// for i in 0..3u32 {
+///     vec.push(i).unwrap();
+/// }
+/// assert_eq!(vec.push(3), Err(3));
+/// assert_eq!(vec.as_slice(), [0, 1, 2]);
+/// assert_eq!(stack.used(), 12);
+/// ```
+pub struct FixedVec<T, A: Allocator> {
+    alloc: A,
+    ptr: NonNull<T>,
+    cap: usize,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+// The elements are owned like in `Vec`.
+unsafe impl<T: Send, A: Allocator + Send> Send for FixedVec<T, A> {}
+unsafe impl<T: Sync, A: Allocator + Sync> Sync for FixedVec<T, A> {}
+
+impl<T, A> FixedVec<T, A>
+where
+    A: Allocator,
+{
+    /// Creates an empty vector with room for exactly `capacity` elements, or returns an error if
+    /// the allocation fails.
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Result<Self, Error> {
+        let layout =
+            Layout::array::<T>(capacity).map_err(|_| Error::new(Operation::Allocate, None))?;
+        let ptr = alloc
+            .allocate(layout)
+            .map_err(Error::map(Operation::Allocate, layout))?
+            .cast();
+        Ok(Self {
+            alloc,
+            ptr,
+            cap: capacity,
+            len: 0,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns the number of elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the vector has no elements.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of elements the vector can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Returns `true` if no more elements fit.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.len == self.cap
+    }
+
+    /// Appends `value`, or returns it if the vector is full.
+    #[inline]
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+        unsafe { self.ptr.as_ptr().add(self.len).write(value) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the last element and returns it, or `None` if the vector is empty.
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        self.len = self.len.checked_sub(1)?;
+        Some(unsafe { self.ptr.as_ptr().add(self.len).read() })
+    }
+
+    /// Drops the elements past the first `len`, keeping the capacity.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        let tail =
+            ptr::slice_from_raw_parts_mut(unsafe { self.ptr.as_ptr().add(len) }, self.len - len);
+        // The length is set first, so a panicking destructor leaks the rest instead of dropping twice.
+        self.len = len;
+        unsafe { ptr::drop_in_place(tail) };
+    }
+
+    /// Drops all the elements, keeping the capacity.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Returns the elements as a slice.
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Returns the elements as a mutable slice.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Removes the elements in `range` and returns an iterator over them.
+    ///
+    /// The elements after the range are moved back when the iterator is dropped. The elements
+    /// that weren't iterated over are dropped then too.
+    ///
+    /// # Panics
+    /// Panics if the range is decreasing or out of bounds.
+    #[track_caller]
+    pub fn drain<R>(&mut self, range: R) -> FixedVecDrain<'_, T, A>
+    where
+        R: RangeBounds<usize>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.checked_add(1).expect("range start overflows"),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.checked_add(1).expect("range end overflows"),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len,
+        };
+        assert!(start <= end, "range starts at {start} but ends at {end}");
+        assert!(
+            end <= self.len,
+            "range end {end} is out of bounds of length {}",
+            self.len
+        );
+        let tail = self.len - end;
+        // Until the iterator is dropped, the vector only owns the elements before the range.
+        self.len = start;
+        FixedVecDrain {
+            vec: self,
+            range: start..end,
+            tail_start: end,
+            tail,
+        }
+    }
+}
+
+impl<T, A> Drop for FixedVec<T, A>
+where
+    A: Allocator,
+{
+    fn drop(&mut self) {
+        self.clear();
+        // The layout was checked when the block was allocated.
+        let layout = unsafe { Layout::array::<T>(self.cap).unwrap_unchecked() };
+        unsafe { self.alloc.deallocate(self.ptr.cast(), layout) };
+    }
+}
+
+impl<T, A> Deref for FixedVec<T, A>
+where
+    A: Allocator,
+{
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, A> DerefMut for FixedVec<T, A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T, A> fmt::Debug for FixedVec<T, A>
+where
+    T: fmt::Debug,
+    A: Allocator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+/// An iterator that removes a range of elements from a [`FixedVec`].
+///
+/// This `struct` is created by [`FixedVec::drain`].
+pub struct FixedVecDrain<'v, T, A: Allocator> {
+    vec: &'v mut FixedVec<T, A>,
+    /// The elements left to yield.
+    range: Range<usize>,
+    /// The index of the first element after the drained range.
+    tail_start: usize,
+    /// The number of elements after the drained range.
+    tail: usize,
+}
+
+impl<T, A> FixedVecDrain<'_, T, A>
+where
+    A: Allocator,
+{
+    /// Returns the elements left to yield as a slice.
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        let ptr = unsafe { self.vec.ptr.as_ptr().add(self.range.start) };
+        unsafe { slice::from_raw_parts(ptr, self.range.len()) }
+    }
+}
+
+impl<T, A> Iterator for FixedVecDrain<'_, T, A>
+where
+    A: Allocator,
+{
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        let index = self.range.next()?;
+        Some(unsafe { self.vec.ptr.as_ptr().add(index).read() })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<T, A> DoubleEndedIterator for FixedVecDrain<'_, T, A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        let index = self.range.next_back()?;
+        Some(unsafe { self.vec.ptr.as_ptr().add(index).read() })
+    }
+}
+
+impl<T, A> ExactSizeIterator for FixedVecDrain<'_, T, A> where A: Allocator {}
+
+impl<T, A> FusedIterator for FixedVecDrain<'_, T, A> where A: Allocator {}
+
+impl<T, A> Drop for FixedVecDrain<'_, T, A>
+where
+    A: Allocator,
+{
+    fn drop(&mut self) {
+        /// Moves the tail back even if dropping the rest of the range panics.
+        struct MoveTail<'a, T, A: Allocator> {
+            vec: &'a mut FixedVec<T, A>,
+            tail_start: usize,
+            tail: usize,
+        }
+
+        impl<T, A: Allocator> Drop for MoveTail<'_, T, A> {
+            fn drop(&mut self) {
+                let base = self.vec.ptr.as_ptr();
+                unsafe { ptr::copy(base.add(self.tail_start), base.add(self.vec.len), self.tail) };
+                self.vec.len += self.tail;
+            }
+        }
+
+        let rest = mem::replace(&mut self.range, 0..0);
+        let guard = MoveTail {
+            vec: &mut *self.vec,
+            tail_start: self.tail_start,
+            tail: self.tail,
+        };
+        let first = unsafe { guard.vec.ptr.as_ptr().add(rest.start) };
+        unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(first, rest.len())) };
+    }
+}
+
+impl<T, A> fmt::Debug for FixedVecDrain<'_, T, A>
+where
+    T: fmt::Debug,
+    A: Allocator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FixedVecDrain")
+            .field(&self.as_slice())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _, ArenaAllocator};
+    use std::rc::Rc;
+
+    #[test]
+    fn fixed_vec_rejects_pushes_when_full() {
+        let stack = Stack::<64>::new();
+        let mut vec = FixedVec::with_capacity_in(4, &stack).unwrap();
+        for i in 0..4u64 {
+            vec.push(i).unwrap();
+            assert_eq!(stack.used(), 32);
+        }
+        assert!(vec.is_full());
+        assert_eq!(vec.push(4), Err(4));
+        assert_eq!(stack.used(), 32);
+        assert_eq!(vec.pop(), Some(3));
+        vec.push(5).unwrap();
+        vec[0] = 9;
+        assert_eq!((vec.as_slice(), vec.len()), (&[9, 1, 2, 5][..], 4));
+
+        let error = FixedVec::<u64, _>::with_capacity_in(5, &stack).unwrap_err();
+        assert_eq!(error.layout(), Some(Layout::new::<[u64; 5]>()));
+        assert!(FixedVec::<u64, _>::with_capacity_in(usize::MAX, &stack).is_err());
+        drop(vec);
+        assert_eq!(stack.used(), 0);
+    }
+
+    #[test]
+    fn fixed_vec_holds_zsts() {
+        let stack = Stack::<64>::new();
+        let mut vec = FixedVec::with_capacity_in(1000, &stack).unwrap();
+        while vec.push(()).is_ok() {}
+        assert_eq!((vec.len(), stack.used()), (1000, 0));
+        assert_eq!(vec.drain(10..).count(), 990);
+        assert_eq!(vec.len(), 10);
+    }
+
+    #[test]
+    fn fixed_vec_never_spills() {
+        let (primary, secondary) = (Stack::<64>::new(), Stack::<1024>::new());
+        let alloc = primary.by_ref().fallback(secondary.by_ref());
+        let mut vec = FixedVec::with_capacity_in(16, &alloc).unwrap();
+        while vec.push(0u32).is_ok() {}
+        // A full vector rejects values instead of moving to the secondary like `Vec` would.
+        assert_eq!(vec.push(1), Err(1));
+        let other = FixedVec::<u8, _>::with_capacity_in(1000, &alloc).unwrap();
+        assert_eq!(other.len(), 0);
+        let layout = Layout::for_value(vec.as_slice());
+        assert!(primary.contains(NonNull::from(vec.as_slice()).cast(), layout));
+        assert!(secondary.contains(
+            NonNull::from(other.as_slice()).cast(),
+            Layout::new::<[u8; 1000]>()
+        ));
+    }
+
+    #[test]
+    fn fixed_vec_drain_drops_and_moves_tail() {
+        let stack = Stack::<256>::new();
+        let mut vec = FixedVec::with_capacity_in(8, &stack).unwrap();
+        let values: std::vec::Vec<_> = (0..8).map(Rc::new).collect();
+        for value in &values {
+            vec.push(value.clone()).unwrap();
+        }
+        let mut drain = vec.drain(2..6);
+        assert_eq!(*drain.next().unwrap(), 2);
+        assert_eq!(*drain.next_back().unwrap(), 5);
+        assert_eq!(drain.len(), 2);
+        drop(drain);
+        assert!(vec.iter().map(|v| **v).eq([0, 1, 6, 7]));
+        assert!((2..6).all(|i| Rc::strong_count(&values[i]) == 1));
+        // Room is made again.
+        vec.push(values[3].clone()).unwrap();
+        assert_eq!(vec.len(), 5);
+
+        let all: std::vec::Vec<_> = vec.drain(..).map(|v| *v).collect();
+        assert_eq!(all, [0, 1, 6, 7, 3]);
+        vec.push(values[0].clone()).unwrap();
+        vec.clear();
+        assert!(vec.is_empty());
+        assert!(values.iter().all(|v| Rc::strong_count(v) == 1));
+    }
+}
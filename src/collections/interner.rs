@@ -0,0 +1,324 @@
+use super::try_reserve_exact;
+use crate::{ArenaExt, Error};
+use allocator_api2::{alloc::Allocator, vec::Vec};
+use core::{fmt, iter::FusedIterator, slice};
+
+/// A set of strings whose bytes live in an allocator, handing out one shared reference per
+/// distinct string.
+///
+/// The returned references borrow the allocator, not the interner, so they stay valid while more
+/// strings are interned and after the interner is dropped. The bytes are never deallocated: they
+/// are reclaimed when the arena is reset or dropped.
+///
+/// The lookup table and the list of strings are allocated in the same allocator and grow
+/// as needed. Arenas like [`Stack`](crate::alloc::Stack) can't reuse the space of the outgrown
+/// ones, so reserving it upfront with [`with_capacity`](Interner::with_capacity) wastes less.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, collections::Interner};
+///
+/// let stack = Stack::<1024>::new();
+/// let mut interner = Interner::new(&stack);
+/// let a = interner.intern("vertex");
+/// let b = interner.intern("fragment");
+/// let c = interner.intern(&String::from("vertex"));
+/// drop(interner);
+/// assert!(std::ptr::eq(a, c));
+/// assert_eq!((a, b), ("vertex", "fragment"));
+/// ```
+///
+/// The strings can't outlive the allocator:
+/// ```compile_fail
+/// use allocandrescu::{alloc::Stack, collections::Interner};
+///
+/// let name;
+/// {
+///     let stack = Stack::<64>::new();
+///     name = Interner::new(&stack).intern("dangling");
+/// }
+/// println!("{name}");
+/// ```
+pub struct Interner<'a, A: Allocator> {
+    alloc: &'a A,
+    /// The strings in the order they were interned.
+    strings: Vec<&'a str, &'a A>,
+    /// Open-addressing table of indices into `strings`, offset by one so that zero marks an empty slot.
+    /// Its length is zero or a power of two.
+    table: Vec<usize, &'a A>,
+}
+
+impl<'a, A> Interner<'a, A>
+where
+    A: Allocator,
+{
+    #[inline]
+    pub fn new(alloc: &'a A) -> Self {
+        Self {
+            alloc,
+            strings: Vec::new_in(alloc),
+            table: Vec::new_in(alloc),
+        }
+    }
+
+    /// Creates an interner that can hold `capacity` strings without growing its table.
+    ///
+    /// # Panics
+    /// Panics if the allocation fails.
+    pub fn with_capacity(capacity: usize, alloc: &'a A) -> Self {
+        let mut interner = Self::new(alloc);
+        interner
+            .reserve(capacity)
+            .unwrap_or_else(|err| panic!("{err}"));
+        interner
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn allocator(&self) -> &'a A {
+        self.alloc
+    }
+
+    /// Returns the number of distinct strings.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns `true` if no string was interned.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Returns the interned copy of `s`, copying it into the allocator if it is new.
+    ///
+    /// # Panics
+    /// Panics if the allocation fails.
+    #[inline]
+    pub fn intern(&mut self, s: &str) -> &'a str {
+        self.try_intern(s).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Returns the interned copy of `s`, copying it into the allocator if it is new, or returns an
+    /// error if the allocation fails.
+    ///
+    /// The interner is left unchanged on failure.
+    pub fn try_intern(&mut self, s: &str) -> Result<&'a str, Error> {
+        let hash = hash(s);
+        if let Some(interned) = self.get_hashed(s, hash) {
+            return Ok(interned);
+        }
+        self.reserve(1)?;
+        let alloc = self.alloc;
+        let interned = &*alloc.alloc_str(s)?;
+        let slot = self.find(s, hash);
+        self.strings.push(interned);
+        self.table[slot] = self.strings.len();
+        Ok(interned)
+    }
+
+    /// Returns the interned copy of `s`, if any.
+    #[inline]
+    pub fn get(&self, s: &str) -> Option<&'a str> {
+        self.get_hashed(s, hash(s))
+    }
+
+    /// Returns an iterator over the strings in the order they were interned.
+    #[inline]
+    pub fn iter(&self) -> InternerIter<'_, 'a> {
+        InternerIter {
+            iter: self.strings.iter(),
+        }
+    }
+
+    fn get_hashed(&self, s: &str, hash: usize) -> Option<&'a str> {
+        if self.table.is_empty() {
+            return None;
+        }
+        let index = self.table[self.find(s, hash)].checked_sub(1)?;
+        Some(self.strings[index])
+    }
+
+    /// Returns the slot of `s` in the table, or the empty slot where it belongs.
+    ///
+    /// The table must not be full.
+    fn find(&self, s: &str, hash: usize) -> usize {
+        let mask = self.table.len() - 1;
+        let mut slot = hash & mask;
+        loop {
+            match self.table[slot] {
+                0 => return slot,
+                index if self.strings[index - 1] == s => return slot,
+                _ => slot = (slot + 1) & mask,
+            }
+        }
+    }
+
+    /// Makes room for `additional` more strings, keeping the table at most three quarters full.
+    fn reserve(&mut self, additional: usize) -> Result<(), Error> {
+        let len = self.strings.len();
+        let required = len.saturating_add(additional);
+        if self.strings.capacity() < required {
+            try_reserve_exact(&mut self.strings, additional.max(len).max(4))?;
+        }
+        if required.saturating_mul(4) <= self.table.len() * 3 {
+            return Ok(());
+        }
+        let slots = required
+            .saturating_mul(4)
+            .div_ceil(3)
+            .checked_next_power_of_two()
+            .unwrap_or(usize::MAX)
+            .max(8);
+        let mut table = Vec::new_in(self.alloc);
+        try_reserve_exact(&mut table, slots)?;
+        table.resize(slots, 0);
+        self.table = table;
+        for index in 0..len {
+            let s = self.strings[index];
+            let slot = self.find(s, hash(s));
+            self.table[slot] = index + 1;
+        }
+        Ok(())
+    }
+}
+
+impl<A> fmt::Debug for Interner<'_, A>
+where
+    A: Allocator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<'i, 'a, A> IntoIterator for &'i Interner<'a, A>
+where
+    A: Allocator,
+{
+    type Item = &'a str;
+    type IntoIter = InternerIter<'i, 'a>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the strings of an [`Interner`].
+///
+/// This `struct` is created by [`iter`](Interner::iter) method on [`Interner`].
+/// See its documentation for more details.
+#[derive(Debug, Clone)]
+pub struct InternerIter<'i, 'a> {
+    iter: slice::Iter<'i, &'a str>,
+}
+
+impl<'a> Iterator for InternerIter<'_, 'a> {
+    type Item = &'a str;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a str> {
+        self.iter.next().copied()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for InternerIter<'_, '_> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().copied()
+    }
+}
+
+impl ExactSizeIterator for InternerIter<'_, '_> {}
+
+impl FusedIterator for InternerIter<'_, '_> {}
+
+/// FNV-1a, which is short and good enough for the short keys interners usually see.
+fn hash(s: &str) -> usize {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &byte in s.as_bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, ArenaAllocator};
+    use core::{alloc::Layout, ptr::NonNull};
+    use std::{format, string::String};
+
+    /// Empty strings have no bytes and a dangling pointer, so they are not in any arena.
+    fn contains(alloc: &impl ArenaAllocator, s: &str) -> bool {
+        s.is_empty() || alloc.contains(NonNull::from(s.as_bytes()).cast(), Layout::for_value(s))
+    }
+
+    #[test]
+    fn interner_deduplicates_strings() {
+        let stack = Stack::<4096>::new();
+        let mut interner = Interner::new(&stack);
+        let names = ["a", "b", "", "a", "ab", "b", ""];
+        let interned = names.map(|name| interner.intern(name));
+
+        assert_eq!(interned, names);
+        assert_eq!(interner.len(), 4);
+        assert!(interner.iter().eq(["a", "b", "", "ab"]));
+        assert!(core::ptr::eq(interned[0], interned[3]));
+        assert!(core::ptr::eq(interned[1], interned[5]));
+        assert_eq!(
+            interner.get("ab").map(str::as_ptr),
+            Some(interned[4].as_ptr())
+        );
+        assert_eq!(interner.get("abc"), None);
+        assert!(interned.iter().all(|s| contains(&stack, s)));
+        assert_eq!(format!("{interner:?}"), r#"{"a", "b", "", "ab"}"#);
+    }
+
+    #[test]
+    fn interned_strings_outlive_interner() {
+        let stack = Stack::<{ 1 << 16 }>::new();
+        let mut interner = Interner::new(&stack);
+        let first = interner.intern("first");
+        let name = String::from("name");
+        // Interning many more strings makes the table and the list of strings grow.
+        let interned: std::vec::Vec<_> = (0..200)
+            .map(|i| interner.intern(&format!("{name}{i}")))
+            .collect();
+        drop(name);
+
+        assert_eq!(interner.len(), 201);
+        assert!(interner.table.len().is_power_of_two());
+        assert!((0..200).all(|i| interner.get(&format!("name{i}")) == Some(interned[i])));
+        drop(interner);
+        assert_eq!(first, "first");
+        assert!(interned.iter().all(|s| contains(&stack, s)));
+        assert!(interned
+            .iter()
+            .enumerate()
+            .all(|(i, s)| *s == format!("name{i}")));
+    }
+
+    #[test]
+    fn failed_intern_leaves_interner_unchanged() {
+        let stack = Stack::<512>::new();
+        let mut interner = Interner::with_capacity(4, &stack);
+        interner.intern("short");
+        let long = "x".repeat(512);
+        assert!(interner.try_intern(&long).is_err());
+        assert_eq!(interner.get(&long), None);
+        assert!(interner.iter().eq(["short"]));
+        assert_eq!(
+            interner.intern("short").as_ptr(),
+            interner.get("short").unwrap().as_ptr()
+        );
+    }
+}
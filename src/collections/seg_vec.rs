@@ -0,0 +1,461 @@
+use crate::{Error, Operation};
+use allocator_api2::alloc::Allocator;
+use core::{
+    alloc::Layout,
+    cell::Cell,
+    fmt,
+    iter::FusedIterator,
+    ops::{Index, IndexMut, Range},
+    ptr::{self, NonNull},
+};
+
+/// The number of segments needed to hold `usize::MAX` elements.
+const SEGMENTS: usize = usize::BITS as usize;
+
+/// A vector made of segments that double in size, so that its elements never move.
+///
+/// When a segment is full, the next one is allocated, twice as large, instead of reallocating
+/// the elements into a larger block. Unlike with `Vec`, growing in an arena like
+/// [`Stack`](crate::alloc::Stack) doesn't strand the outgrown blocks, and elements can be pushed
+/// through a shared reference: [`push`](SegVec::push) takes `&self`, so references to earlier
+/// elements stay valid across pushes. The elements are not contiguous, so the vector can't be
+/// viewed as a slice.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, collections::SegVec};
+///
+/// let stack = Stack::<4096>::new();
+/// let names = SegVec::new_in(&stack);
+/// let first = names.push("first");
+/// for i in 0..100 {
+///     names.push(if i % 2 == 0 { "even" } else { "odd" });
+/// }
+/// assert_eq!((*first, names[100]), ("first", "odd"));
+/// ```
+pub struct SegVec<T, A: Allocator> {
+    alloc: A,
+    len: Cell<usize>,
+    /// Base-2 logarithm of the capacity of the first segment.
+    first_log2: u32,
+    /// Segment `k` holds `1 << (first_log2 + k)` elements.
+    segments: [Cell<Option<NonNull<T>>>; SEGMENTS],
+}
+
+// The elements are owned like in `Vec`. No `Sync`, as pushing through `&self` isn't synchronized.
+unsafe impl<T: Send, A: Allocator + Send> Send for SegVec<T, A> {}
+
+impl<T, A> SegVec<T, A>
+where
+    A: Allocator,
+{
+    #[inline]
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_first_log2(2, alloc)
+    }
+
+    /// Creates a vector whose first segment holds at least `capacity` elements.
+    ///
+    /// The capacity is rounded up to a power of two.
+    ///
+    /// # Panics
+    /// Panics if the allocation fails.
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        let first_log2 = capacity
+            .checked_next_power_of_two()
+            .map_or(usize::BITS - 1, usize::trailing_zeros)
+            .max(2);
+        let vec = Self::with_first_log2(first_log2, alloc);
+        if capacity > 0 {
+            vec.segment(0).unwrap_or_else(|err| panic!("{err}"));
+        }
+        vec
+    }
+
+    #[inline]
+    fn with_first_log2(first_log2: u32, alloc: A) -> Self {
+        Self {
+            alloc,
+            len: Cell::new(0),
+            first_log2,
+            segments: [const { Cell::new(None) }; SEGMENTS],
+        }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns the number of elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    /// Returns `true` if the vector has no elements.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len.get() == 0
+    }
+
+    /// Returns the number of elements the allocated segments can hold.
+    pub fn capacity(&self) -> usize {
+        (0..SEGMENTS)
+            .take_while(|&k| self.segments[k].get().is_some())
+            .map(|k| self.segment_capacity(k))
+            .sum()
+    }
+
+    /// Appends `value` and returns a reference to it.
+    ///
+    /// # Panics
+    /// Panics if the allocation fails.
+    #[inline]
+    pub fn push(&self, value: T) -> &T {
+        self.try_push(value).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Appends `value` and returns a reference to it, or returns an error if the allocation fails.
+    ///
+    /// The value is dropped on failure.
+    pub fn try_push(&self, value: T) -> Result<&T, Error> {
+        loop {
+            let index = self.len.get();
+            let (k, offset) = self
+                .locate(index)
+                .ok_or(Error::new(Operation::Allocate, None))?;
+            match self.segments[k].get() {
+                Some(segment) => unsafe {
+                    let ptr = segment.as_ptr().add(offset);
+                    ptr.write(value);
+                    self.len.set(index + 1);
+                    return Ok(&*ptr);
+                },
+                // The allocator may push into the vector itself, hence the loop.
+                None => self.segment(k)?,
+            };
+        }
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if it is out of bounds.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.slot(index).map(|ptr| unsafe { &*ptr.as_ptr() })
+    }
+
+    /// Returns a mutable reference to the element at `index`, or `None` if it is out of bounds.
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.slot(index).map(|ptr| unsafe { &mut *ptr.as_ptr() })
+    }
+
+    /// Returns an iterator over the elements.
+    ///
+    /// Elements pushed while iterating are not visited.
+    #[inline]
+    pub fn iter(&self) -> SegVecIter<'_, T, A> {
+        SegVecIter {
+            vec: self,
+            range: 0..self.len(),
+        }
+    }
+
+    #[inline]
+    fn segment_capacity(&self, k: usize) -> usize {
+        1 << (self.first_log2 as usize + k)
+    }
+
+    /// Returns the segment and the offset in it of the element at `index`,
+    /// or `None` if the index is beyond the largest capacity.
+    #[inline]
+    fn locate(&self, index: usize) -> Option<(usize, usize)> {
+        // Segments up to `k` hold `(2 << k) - 1` times as many elements as the first one.
+        let shifted = index.checked_add(1 << self.first_log2)?;
+        let k = (usize::BITS - 1 - shifted.leading_zeros() - self.first_log2) as usize;
+        Some((k, shifted - self.segment_capacity(k)))
+    }
+
+    #[inline]
+    fn slot(&self, index: usize) -> Option<NonNull<T>> {
+        if index >= self.len.get() {
+            return None;
+        }
+        let (k, offset) = self.locate(index)?;
+        // The segments of all the elements are allocated.
+        let segment = unsafe { self.segments[k].get().unwrap_unchecked() };
+        Some(unsafe { segment.add(offset) })
+    }
+
+    /// Returns the layout of segment `k`, or `None` if it overflows.
+    #[inline]
+    fn segment_layout(&self, k: usize) -> Option<Layout> {
+        Layout::array::<T>(self.segment_capacity(k)).ok()
+    }
+
+    /// Allocates segment `k`, unless it is allocated.
+    fn segment(&self, k: usize) -> Result<(), Error> {
+        if self.segments[k].get().is_some() {
+            return Ok(());
+        }
+        let layout = self
+            .segment_layout(k)
+            .ok_or(Error::new(Operation::Allocate, None))?;
+        let segment = self
+            .alloc
+            .allocate(layout)
+            .map_err(Error::map(Operation::Allocate, layout))?
+            .cast();
+        if self.segments[k].get().is_some() {
+            // The allocator allocated it meanwhile.
+            unsafe { self.alloc.deallocate(segment.cast(), layout) };
+        } else {
+            self.segments[k].set(Some(segment));
+        }
+        Ok(())
+    }
+}
+
+impl<T, A> Drop for SegVec<T, A>
+where
+    A: Allocator,
+{
+    fn drop(&mut self) {
+        let mut len = self.len.get();
+        for k in 0..SEGMENTS {
+            let Some(segment) = self.segments[k].get() else {
+                break;
+            };
+            let count = len.min(self.segment_capacity(k));
+            len -= count;
+            unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(segment.as_ptr(), count)) };
+        }
+        // The latest segments are the likeliest to be on top of an arena, so they go first.
+        for k in (0..SEGMENTS).rev() {
+            if let Some(segment) = self.segments[k].get() {
+                // The layout was checked when the segment was allocated.
+                let layout = unsafe { self.segment_layout(k).unwrap_unchecked() };
+                unsafe { self.alloc.deallocate(segment.cast(), layout) };
+            }
+        }
+    }
+}
+
+impl<T, A> Index<usize> for SegVec<T, A>
+where
+    A: Allocator,
+{
+    type Output = T;
+
+    #[inline]
+    #[track_caller]
+    fn index(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(value) => value,
+            None => panic!(
+                "index out of bounds: the len is {} but the index is {index}",
+                self.len()
+            ),
+        }
+    }
+}
+
+impl<T, A> IndexMut<usize> for SegVec<T, A>
+where
+    A: Allocator,
+{
+    #[inline]
+    #[track_caller]
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len();
+        match self.get_mut(index) {
+            Some(value) => value,
+            None => panic!("index out of bounds: the len is {len} but the index is {index}"),
+        }
+    }
+}
+
+impl<T, A> fmt::Debug for SegVec<T, A>
+where
+    T: fmt::Debug,
+    A: Allocator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'v, T, A> IntoIterator for &'v SegVec<T, A>
+where
+    A: Allocator,
+{
+    type Item = &'v T;
+    type IntoIter = SegVecIter<'v, T, A>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the elements of a [`SegVec`].
+///
+/// This `struct` is created by [`iter`](SegVec::iter) method on [`SegVec`].
+/// See its documentation for more details.
+pub struct SegVecIter<'v, T, A: Allocator> {
+    vec: &'v SegVec<T, A>,
+    range: Range<usize>,
+}
+
+impl<T, A> Clone for SegVecIter<'_, T, A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            vec: self.vec,
+            range: self.range.clone(),
+        }
+    }
+}
+
+impl<T, A> fmt::Debug for SegVecIter<'_, T, A>
+where
+    T: fmt::Debug,
+    A: Allocator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'v, T, A> Iterator for SegVecIter<'v, T, A>
+where
+    A: Allocator,
+{
+    type Item = &'v T;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'v T> {
+        self.range.next().and_then(|index| self.vec.get(index))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<T, A> DoubleEndedIterator for SegVecIter<'_, T, A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back().and_then(|index| self.vec.get(index))
+    }
+}
+
+impl<T, A> ExactSizeIterator for SegVecIter<'_, T, A> where A: Allocator {}
+
+impl<T, A> FusedIterator for SegVecIter<'_, T, A> where A: Allocator {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, ArenaAllocator};
+    use allocator_api2::vec::Vec;
+    use core::mem::size_of;
+    use std::rc::Rc;
+
+    #[test]
+    fn seg_vec_indexes_across_segments() {
+        let stack = Stack::<4096>::new();
+        let mut vec = SegVec::new_in(&stack);
+        for i in 0..100u32 {
+            vec.push(i);
+        }
+        assert_eq!(vec.len(), 100);
+        assert_eq!(vec.capacity(), 4 + 8 + 16 + 32 + 64);
+        assert!(vec.iter().copied().eq(0..100));
+        assert!(vec.iter().rev().copied().eq((0..100).rev()));
+        assert_eq!(
+            (vec[3], vec[4], vec[11], vec[12], vec[99]),
+            (3, 4, 11, 12, 99)
+        );
+        assert_eq!(vec.get(100), None);
+        vec[50] = 0;
+        *vec.get_mut(51).unwrap() += 1;
+        assert_eq!((vec[50], vec[51]), (0, 52));
+    }
+
+    #[test]
+    fn seg_vec_references_survive_pushes() {
+        let stack = Stack::<4096>::new();
+        let vec = SegVec::new_in(&stack);
+        let first = vec.push(Rc::new(0));
+        let refs: std::vec::Vec<&Rc<i32>> = (1..200).map(|i| vec.push(Rc::new(i))).collect();
+        assert_eq!(**first, 0);
+        assert!(refs.iter().zip(1..).all(|(r, i)| ***r == i));
+        assert!(refs
+            .iter()
+            .zip(vec.iter().skip(1))
+            .all(|(&r, e)| ptr::eq(r, e)));
+
+        let shared = Rc::new(-1);
+        vec.push(shared.clone());
+        assert_eq!(Rc::strong_count(&shared), 2);
+        drop(vec);
+        assert_eq!(Rc::strong_count(&shared), 1);
+        assert_eq!(stack.used(), 0);
+    }
+
+    #[test]
+    fn seg_vec_strands_no_blocks_unlike_vec() {
+        const BYTES: usize = (4 + 8 + 16 + 32 + 64) * size_of::<u32>();
+
+        // Interleaving two vectors keeps each from growing in place.
+        let stack = Stack::<4096>::new();
+        let (a, b) = (SegVec::new_in(&stack), SegVec::new_in(&stack));
+        for i in 0..100u32 {
+            a.push(i);
+            b.push(i);
+        }
+        assert_eq!(a.capacity() * size_of::<u32>(), BYTES);
+        assert_eq!(stack.used(), 2 * BYTES);
+        assert!([&a, &b].iter().all(|vec| vec
+            .iter()
+            .all(|v| stack.contains(NonNull::from(v).cast(), Layout::new::<u32>()))));
+
+        let stack = Stack::<4096>::new();
+        let (mut a, mut b) = (Vec::new_in(&stack), Vec::new_in(&stack));
+        for i in 0..100u32 {
+            a.push(i);
+            b.push(i);
+        }
+        let live = (a.capacity() + b.capacity()) * size_of::<u32>();
+        assert_eq!(stack.used(), live + 2 * BYTES);
+    }
+
+    #[test]
+    fn seg_vec_with_capacity_reports_failure() {
+        let stack = Stack::<256>::new();
+        let vec = SegVec::with_capacity_in(10, &stack);
+        assert_eq!(vec.capacity(), 16);
+        assert_eq!(stack.used(), 16 * size_of::<u64>());
+        for i in 0..16u64 {
+            vec.push(i);
+        }
+        let error = vec.try_push(16).unwrap_err();
+        assert_eq!(error.layout(), Some(Layout::new::<[u64; 32]>()));
+        assert_eq!(vec.len(), 16);
+
+        let zsts = SegVec::new_in(&stack);
+        for _ in 0..1000 {
+            zsts.push(());
+        }
+        assert_eq!(zsts.len(), 1000);
+    }
+}
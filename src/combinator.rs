@@ -2,75 +2,420 @@
 //!
 //! See the [`Allocandrescu`](`crate::Allocandrescu`) extension trait for an ergonomic way of combining allocators.
 
-use crate::ArenaAllocator;
+use crate::{
+    dangling, id::tag, AllocatorId, ArenaAllocator, GoodSizeAllocator, InPlaceGrow,
+    OwnsAllocations, ProbeAllocator, ResetAllocator, SameAllocator, TrimAllocator,
+};
 use allocator_api2::alloc::{AllocError, Allocator};
-use core::{alloc::Layout, ptr::NonNull};
+use core::{alloc::Layout, cell::Cell, fmt, ptr::NonNull};
+
+#[cfg(feature = "std")]
+mod aging;
+mod balance;
+#[cfg(feature = "alloc")]
+mod boxed;
+mod branded;
+mod bucketizer;
+#[cfg(feature = "alloc")]
+mod budgets;
+mod by_deref;
+mod color;
+#[cfg(feature = "alloc")]
+mod drop_arena;
+#[cfg(feature = "std")]
+mod false_sharing;
+mod intercept;
+#[cfg(feature = "std")]
+mod layout_table;
+mod min_align;
+#[cfg(feature = "alloc")]
+mod mirror;
+mod priority;
+mod probe;
+#[cfg(feature = "std")]
+mod profiler;
+mod sample;
+#[cfg(feature = "std")]
+mod shuffle;
+#[cfg(feature = "alloc")]
+mod slot;
+mod watermarks;
+mod with_header;
+
+#[cfg(feature = "std")]
+pub use aging::Aging;
+pub use balance::Balance;
+#[cfg(feature = "alloc")]
+pub use boxed::BoxedAllocator;
+pub use branded::{Branded, BrandedBox};
+pub use bucketizer::Bucketizer;
+#[cfg(feature = "alloc")]
+pub use budgets::{BudgetHandle, BudgetReport, Budgets};
+pub use by_deref::ByDeref;
+pub use color::Color;
+#[cfg(feature = "alloc")]
+pub use drop_arena::DropArena;
+#[cfg(feature = "std")]
+pub use false_sharing::{Block, FalseSharing, SharedLine, CACHE_LINE};
+pub use intercept::Intercept;
+#[cfg(feature = "std")]
+pub use layout_table::{LayoutRow, LayoutTable, TopLayouts};
+pub use min_align::MinAlign;
+#[cfg(feature = "alloc")]
+pub use mirror::Mirror;
+#[cfg(feature = "std")]
+pub use priority::{current_priority, with_priority};
+pub use priority::{Priority, PriorityRoute};
+pub use probe::Probe;
+#[cfg(feature = "std")]
+pub use profiler::Profiler;
+pub use sample::{Sample, SampleSnapshot};
+#[cfg(feature = "std")]
+pub use shuffle::Shuffle;
+#[cfg(feature = "alloc")]
+pub use slot::Slot;
+pub use watermarks::Watermarks;
+pub use with_header::WithHeader;
 
 /// An allocator that forwards allocation to `alloc` if the passed predicate succeeds. Fails allocation otherwise.
 ///
 /// This `struct` is created by [`fallback`](crate::Allocandrescu::cond) method on [`Allocandrescu`](crate::Allocandrescu).
 /// See its documentation for more details.
-#[derive(Debug)]
+#[derive(Clone, Copy, Default)]
 pub struct Cond<A, F> {
     alloc: A,
     pred: F,
 }
 
+impl<A, F> fmt::Debug for Cond<A, F>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cond")
+            .field("alloc", &self.alloc)
+            .field("pred", &format_args!("<fn>"))
+            .finish()
+    }
+}
+
 impl<A, F> Cond<A, F> {
     #[inline]
-    pub fn new(alloc: A, pred: F) -> Self {
+    pub const fn new(alloc: A, pred: F) -> Self {
         Self { alloc, pred }
     }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
 }
 
 unsafe impl<A, F> Allocator for Cond<A, F>
 where
     A: Allocator,
-    F: Fn(Layout) -> bool,
+    F: Predicate,
 {
     fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        if (self.pred)(layout) {
+        if self.pred.test(layout) {
             self.alloc.allocate(layout)
         } else {
             Err(AllocError)
         }
     }
 
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.pred.test(layout) {
+            self.alloc.allocate_zeroed(layout)
+        } else {
+            Err(AllocError)
+        }
+    }
+
     #[inline]
     unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
         self.alloc.deallocate(ptr, layout)
     }
 
-    // TODO: optimize default implementations where applicable
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.pred.test(new_layout) {
+            self.alloc.grow(ptr, old_layout, new_layout)
+        } else {
+            Err(AllocError)
+        }
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.pred.test(new_layout) {
+            self.alloc.grow_zeroed(ptr, old_layout, new_layout)
+        } else {
+            Err(AllocError)
+        }
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.pred.test(new_layout) {
+            self.alloc.shrink(ptr, old_layout, new_layout)
+        } else {
+            Err(AllocError)
+        }
+    }
+}
+
+impl<A, F> TrimAllocator for Cond<A, F>
+where
+    A: TrimAllocator,
+    F: Predicate,
+{
+    #[inline]
+    fn trim(&self) -> usize {
+        self.alloc.trim()
+    }
+}
+
+impl<A, F> GoodSizeAllocator for Cond<A, F>
+where
+    A: GoodSizeAllocator,
+    F: Predicate,
+{
+    /// Returns the preferred layout of the underlying allocator if the predicate accepts it,
+    /// `layout` otherwise.
+    #[inline]
+    fn preferred_layout(&self, layout: Layout) -> Layout {
+        let preferred = self.alloc.preferred_layout(layout);
+        if self.pred.test(preferred) {
+            preferred
+        } else {
+            layout
+        }
+    }
+}
+
+impl<A, F> InPlaceGrow for Cond<A, F>
+where
+    A: InPlaceGrow,
+    F: Predicate,
+{
+    unsafe fn try_grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.pred.test(new_layout) {
+            self.alloc.try_grow_in_place(ptr, old_layout, new_layout)
+        } else {
+            Err(AllocError)
+        }
+    }
+}
+
+impl<A, F> SameAllocator for Cond<A, F>
+where
+    A: SameAllocator,
+    F: Predicate,
+{
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        self.alloc.allocator_id()
+    }
+}
+
+impl<A, F> OwnsAllocations for Cond<A, F>
+where
+    A: OwnsAllocations,
+    F: Predicate,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        self.alloc.is_live(ptr)
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.alloc.live_count()
+    }
+}
+
+impl<A, F> ResetAllocator for Cond<A, F>
+where
+    A: ResetAllocator,
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.alloc.reset()
+    }
 }
 
 impl<A, F> ArenaAllocator for Cond<A, F>
 where
     A: ArenaAllocator,
-    F: Fn(Layout) -> bool,
+    F: Predicate,
 {
     #[inline]
     fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
         self.alloc.contains(ptr, layout)
     }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+impl<A, F> ProbeAllocator for Cond<A, F>
+where
+    A: ProbeAllocator,
+    F: Predicate,
+{
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        self.pred.test(layout) && self.alloc.can_allocate(layout)
+    }
+}
+
+/// A condition checked by [`Cond`] before each allocation.
+///
+/// It is implemented for all closures taking a [`Layout`]. Closures can't be created in const
+/// context, so the closure-free predicates below, or your own unit `struct`s implementing this
+/// trait, can be used to build allocators in `const` and `static` items:
+/// ```
+/// use allocandrescu::{
+///     alloc::{Failing, Stack},
+///     combinator::{Cond, Fallback, SizeAtMost},
+/// };
+/// use allocator_api2::{alloc::Allocator, boxed::Box};
+///
+/// const ALLOC: Fallback<Cond<Stack<4096>, SizeAtMost<64>>, Failing> =
+///     Fallback::new(Cond::new(Stack::new(), SizeAtMost), Failing);
+///
+/// let alloc = ALLOC;
+/// let small = Box::new_in([0u8; 64], &alloc);
+/// assert!(Box::try_new_in([0u8; 65], &alloc).is_err());
+/// ```
+///
+/// Note that a `static` must be `Sync`, which allocators with interior mutability, like
+/// [`Stack`](crate::alloc::Stack), are not. Putting them in a `static` requires a wrapper that
+/// makes sure they are never used from multiple threads at once.
+pub trait Predicate {
+    /// Returns `true` if an allocation with `layout` should be attempted.
+    fn test(&self, layout: Layout) -> bool;
+}
+
+impl<F> Predicate for F
+where
+    F: Fn(Layout) -> bool,
+{
+    #[inline]
+    fn test(&self, layout: Layout) -> bool {
+        self(layout)
+    }
+}
+
+/// A [`Predicate`] that accepts layouts of at most `N` bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeAtMost<const N: usize>;
+
+impl<const N: usize> Predicate for SizeAtMost<N> {
+    #[inline]
+    fn test(&self, layout: Layout) -> bool {
+        layout.size() <= N
+    }
+}
+
+/// A [`Predicate`] that accepts layouts aligned to at most `N` bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlignAtMost<const N: usize>;
+
+impl<const N: usize> Predicate for AlignAtMost<N> {
+    #[inline]
+    fn test(&self, layout: Layout) -> bool {
+        layout.align() <= N
+    }
 }
 
 /// An allocator that forwards allocation to `primary` allocator. If the allocation fails, it fallbacks to the `secondary` allocator.
 ///
-/// This `struct` is created by [`fallback`](crate::Allocandrescu::fallback) method on [`Allocandrescu`](crate::Allocandrescu).
-/// See its documentation for more details.
-#[derive(Debug)]
-pub struct Fallback<P, S> {
+/// Deallocation and reallocation are routed to the allocator that [contains](ArenaAllocator::contains)
+/// the block. If the owning allocator can't grow or shrink the block, it is moved to the other one.
+/// Zero-sized allocations are served by `Fallback` itself and never reach either allocator,
+/// see [zero-sized allocations](crate#zero-sized-allocations).
+///
+/// Every allocation outcome is reported to `counter`, which does nothing by default.
+/// Use [`SpillStats`] to find out how often the secondary allocator is used.
+///
+/// To skip attempts that are known to fail, wrap a primary implementing [`ProbeAllocator`] with
+/// [`probe`](crate::Allocandrescu::probe).
+///
+/// This `struct` is created by [`fallback`](crate::Allocandrescu::fallback) and
+/// [`fallback_counted`](crate::Allocandrescu::fallback_counted) methods on [`Allocandrescu`](crate::Allocandrescu).
+/// See their documentation for more details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fallback<P, S, C = ()> {
     primary: P,
     secondary: S,
+    counter: C,
 }
 
 impl<P, S> Fallback<P, S> {
     #[inline]
-    pub fn new(primary: P, secondary: S) -> Self {
-        Self { primary, secondary }
+    pub const fn new(primary: P, secondary: S) -> Self {
+        Self {
+            primary,
+            secondary,
+            counter: (),
+        }
+    }
+}
+
+impl<P, S> Fallback<P, S, SpillStats> {
+    /// Creates a `Fallback` that counts the allocations served by each side.
+    #[inline]
+    pub const fn counted(primary: P, secondary: S) -> Self {
+        Self {
+            primary,
+            secondary,
+            counter: SpillStats::new(),
+        }
     }
+}
 
+impl<P, S, C> Fallback<P, S, C> {
     #[inline]
     pub fn primary(&self) -> &P {
         &self.primary
@@ -80,80 +425,1940 @@ impl<P, S> Fallback<P, S> {
     pub fn secondary(&self) -> &S {
         &self.secondary
     }
+
+    #[inline]
+    pub fn counter(&self) -> &C {
+        &self.counter
+    }
+
+    #[inline]
+    pub fn primary_mut(&mut self) -> &mut P {
+        &mut self.primary
+    }
+
+    #[inline]
+    pub fn secondary_mut(&mut self) -> &mut S {
+        &mut self.secondary
+    }
+
+    /// Consumes the combinator, returning the primary and the secondary allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocators.
+    #[inline]
+    pub fn into_parts(self) -> (P, S) {
+        (self.primary, self.secondary)
+    }
+}
+
+impl<P, S> Fallback<P, S, SpillStats> {
+    /// Returns the statistics of allocations served by each side.
+    #[inline]
+    pub fn stats(&self) -> &SpillStats {
+        &self.counter
+    }
 }
 
-unsafe impl<P, S> Allocator for Fallback<P, S>
+unsafe impl<P, S, C> Allocator for Fallback<P, S, C>
 where
     P: ArenaAllocator,
     S: Allocator,
+    C: SpillCounter,
 {
     fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        self.primary
-            .allocate(layout)
-            .or_else(|_| self.secondary.allocate(layout))
+        self.alloc(layout, false)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, true)
     }
 
     unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.dealloc(self.owner(ptr, layout), ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        self.realloc(
+            owner,
+            ptr,
+            old_layout,
+            new_layout,
+            false,
+            |side| match side {
+                Side::Primary => self.primary.grow(ptr, old_layout, new_layout),
+                Side::Secondary => self.secondary.grow(ptr, old_layout, new_layout),
+            },
+        )
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        self.realloc(
+            owner,
+            ptr,
+            old_layout,
+            new_layout,
+            true,
+            |side| match side {
+                Side::Primary => self.primary.grow_zeroed(ptr, old_layout, new_layout),
+                Side::Secondary => self.secondary.grow_zeroed(ptr, old_layout, new_layout),
+            },
+        )
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        self.realloc(
+            owner,
+            ptr,
+            old_layout,
+            new_layout,
+            false,
+            |side| match side {
+                Side::Primary => self.primary.shrink(ptr, old_layout, new_layout),
+                Side::Secondary => self.secondary.shrink(ptr, old_layout, new_layout),
+            },
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    Primary,
+    Secondary,
+}
+
+impl<P, S, C> Fallback<P, S, C>
+where
+    P: ArenaAllocator,
+{
+    #[inline]
+    fn owner(&self, ptr: NonNull<u8>, layout: Layout) -> Side {
         if self.primary.contains(ptr, layout) {
-            self.primary.deallocate(ptr, layout)
+            Side::Primary
         } else {
-            self.secondary.deallocate(ptr, layout)
+            Side::Secondary
         }
     }
 }
 
-impl<P, S> ArenaAllocator for Fallback<P, S>
+impl<P, S, C> Fallback<P, S, C>
 where
-    P: ArenaAllocator,
-    S: ArenaAllocator,
+    P: Allocator,
+    S: Allocator,
+    C: SpillCounter,
 {
+    fn alloc(&self, layout: Layout, zeroed: bool) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        if let Ok(ptr) = alloc_with(&self.primary, layout, zeroed) {
+            self.counter.primary_hit(layout.size());
+            return Ok(ptr);
+        }
+        self.counter.primary_failure();
+        let ptr = alloc_with(&self.secondary, layout, zeroed)?;
+        self.counter.secondary_hit(layout.size());
+        Ok(ptr)
+    }
+
     #[inline]
-    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
-        self.primary.contains(ptr, layout) || self.secondary.contains(ptr, layout)
+    unsafe fn dealloc(&self, owner: Side, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        match owner {
+            Side::Primary => self.primary.deallocate(ptr, layout),
+            Side::Secondary => self.secondary.deallocate(ptr, layout),
+        }
+    }
+
+    /// Reallocates a block with `op` in the `owner` allocator.
+    /// If that fails, moves the block to the other allocator.
+    unsafe fn realloc(
+        &self,
+        owner: Side,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+        op: impl Fn(Side) -> Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        // Zero-sized blocks don't belong to either side.
+        if old_layout.size() == 0 {
+            return self.alloc(new_layout, zeroed);
+        }
+        let size = new_layout.size();
+        if size == 0 {
+            self.dealloc(owner, ptr, old_layout);
+            return Ok(dangling(new_layout));
+        }
+        if let Side::Primary = owner {
+            if let Ok(new_ptr) = op(Side::Primary) {
+                self.counter.primary_hit(size);
+                return Ok(new_ptr);
+            }
+            self.counter.primary_failure();
+            let new_ptr = migrate(
+                &self.primary,
+                &self.secondary,
+                ptr,
+                old_layout,
+                new_layout,
+                zeroed,
+            )?;
+            self.counter.secondary_hit(size);
+            Ok(new_ptr)
+        } else {
+            if let Ok(new_ptr) = op(Side::Secondary) {
+                self.counter.secondary_hit(size);
+                return Ok(new_ptr);
+            }
+            let result = migrate(
+                &self.secondary,
+                &self.primary,
+                ptr,
+                old_layout,
+                new_layout,
+                zeroed,
+            );
+            match result {
+                Ok(_) => self.counter.primary_hit(size),
+                Err(_) => self.counter.primary_failure(),
+            }
+            result
+        }
     }
 }
 
-/// An allocator that forwards allocation to `alloc` and calls the provided closure on each result.
-///
-/// This `struct` is created by [`fallback`](crate::Allocandrescu::inspect) method on [`Allocandrescu`](crate::Allocandrescu).
-/// See its documentation for more details.
-#[derive(Debug)]
-pub struct Inspect<A, F> {
-    alloc: A,
-    f: F,
+#[inline]
+fn alloc_with<A>(alloc: &A, layout: Layout, zeroed: bool) -> Result<NonNull<[u8]>, AllocError>
+where
+    A: Allocator,
+{
+    if zeroed {
+        alloc.allocate_zeroed(layout)
+    } else {
+        alloc.allocate(layout)
+    }
 }
 
-impl<A, F> Inspect<A, F> {
-    pub fn new(alloc: A, f: F) -> Self {
-        Self { alloc, f }
+/// Moves a block allocated in `from` to a new allocation in `to`.
+unsafe fn migrate<F, T>(
+    from: &F,
+    to: &T,
+    ptr: NonNull<u8>,
+    old_layout: Layout,
+    new_layout: Layout,
+    zeroed: bool,
+) -> Result<NonNull<[u8]>, AllocError>
+where
+    F: Allocator,
+    T: Allocator,
+{
+    let new_ptr = alloc_with(to, new_layout, zeroed)?;
+    let len = old_layout.size().min(new_layout.size());
+    core::ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.cast::<u8>().as_ptr(), len);
+    from.deallocate(ptr, old_layout);
+    Ok(new_ptr)
+}
+
+impl<P, S, C> TrimAllocator for Fallback<P, S, C>
+where
+    P: TrimAllocator + ArenaAllocator,
+    S: TrimAllocator,
+    C: SpillCounter,
+{
+    /// Trims both allocators, returning the sum.
+    #[inline]
+    fn trim(&self) -> usize {
+        self.primary.trim() + self.secondary.trim()
     }
 }
 
-unsafe impl<A, F> Allocator for Inspect<A, F>
+impl<P, S, C> GoodSizeAllocator for Fallback<P, S, C>
 where
-    A: Allocator,
-    F: Fn(Layout, Result<NonNull<[u8]>, AllocError>),
+    P: GoodSizeAllocator + ArenaAllocator + ProbeAllocator,
+    S: GoodSizeAllocator,
+    C: SpillCounter,
 {
+    /// Returns the preferred layout of the primary allocator if its probe accepts it, or of the
+    /// secondary allocator otherwise.
     #[inline]
-    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        let result = self.alloc.allocate(layout);
-        (self.f)(layout, result);
-        result
+    fn preferred_layout(&self, layout: Layout) -> Layout {
+        preferred_by_either(&self.primary, &self.secondary, layout)
+    }
+}
+
+/// Returns the preferred layout of the allocator that would serve `layout`.
+#[inline]
+fn preferred_by_either<P, S>(primary: &P, secondary: &S, layout: Layout) -> Layout
+where
+    P: GoodSizeAllocator + ProbeAllocator,
+    S: GoodSizeAllocator,
+{
+    let preferred = primary.preferred_layout(layout);
+    if primary.can_allocate(preferred) {
+        preferred
+    } else {
+        secondary.preferred_layout(layout)
+    }
+}
+
+impl<P, S, C> InPlaceGrow for Fallback<P, S, C>
+where
+    P: InPlaceGrow + ArenaAllocator,
+    S: InPlaceGrow,
+    C: SpillCounter,
+{
+    /// Grows the block in the allocator that owns it. Blocks are never moved to the other side.
+    unsafe fn try_grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        // Zero-sized blocks don't belong to either side.
+        if old_layout.size() == 0 {
+            return Err(AllocError);
+        }
+        let size = new_layout.size();
+        match self.owner(ptr, old_layout) {
+            Side::Primary => {
+                let block = self
+                    .primary
+                    .try_grow_in_place(ptr, old_layout, new_layout)?;
+                self.counter.primary_hit(size);
+                Ok(block)
+            }
+            Side::Secondary => {
+                let block = self
+                    .secondary
+                    .try_grow_in_place(ptr, old_layout, new_layout)?;
+                self.counter.secondary_hit(size);
+                Ok(block)
+            }
+        }
     }
+}
 
+impl<P, S, C> SameAllocator for Fallback<P, S, C>
+where
+    P: SameAllocator + ArenaAllocator,
+    S: SameAllocator,
+    C: SpillCounter,
+{
     #[inline]
-    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
-        self.alloc.deallocate(ptr, layout);
+    fn allocator_id(&self) -> AllocatorId {
+        let parts = [self.primary.allocator_id(), self.secondary.allocator_id()];
+        AllocatorId::composite(tag::FALLBACK, &parts)
     }
 }
 
-impl<A, F> ArenaAllocator for Inspect<A, F>
+impl<P, S, C> OwnsAllocations for Fallback<P, S, C>
 where
-    A: ArenaAllocator,
-    F: Fn(Layout, Result<NonNull<[u8]>, AllocError>),
+    P: OwnsAllocations + ArenaAllocator,
+    S: OwnsAllocations,
+    C: SpillCounter,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        self.primary.is_live(ptr) || self.secondary.is_live(ptr)
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.primary.live_count() + self.secondary.live_count()
+    }
+}
+
+impl<P, S, C> ResetAllocator for Fallback<P, S, C>
+where
+    P: ResetAllocator,
+{
+    /// Resets only the primary allocator. Memory that spilled over to the secondary stays allocated.
+    #[inline]
+    fn reset(&mut self) {
+        self.primary.reset()
+    }
+}
+
+impl<P, S, C> ArenaAllocator for Fallback<P, S, C>
+where
+    P: ArenaAllocator,
+    S: ArenaAllocator,
+    C: SpillCounter,
 {
     #[inline]
     fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
-        self.alloc.contains(ptr, layout)
+        self.primary.contains(ptr, layout) || self.secondary.contains(ptr, layout)
+    }
+
+    /// The sum for both allocators, as allocations spill over to the secondary.
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        add(
+            self.primary.remaining_capacity(),
+            self.secondary.remaining_capacity(),
+        )
+    }
+
+    /// The sum for both allocators.
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        add(
+            self.primary.allocated_bytes(),
+            self.secondary.allocated_bytes(),
+        )
+    }
+}
+
+/// Adds the numbers if both are known.
+#[inline]
+fn add(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    a?.checked_add(b?)
+}
+
+impl<P, S, C> ProbeAllocator for Fallback<P, S, C>
+where
+    P: ArenaAllocator + ProbeAllocator,
+    S: ProbeAllocator,
+    C: SpillCounter,
+{
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        self.primary.can_allocate(layout) || self.secondary.can_allocate(layout)
+    }
+}
+
+/// An allocator that forwards allocation to `primary` allocator. If the allocation fails,
+/// it fallbacks to the `secondary` arena allocator.
+///
+/// Unlike [`Fallback`], ownership of blocks is decided by the `secondary` allocator: blocks it
+/// [contains](ArenaAllocator::contains) are routed to it, all the others to the `primary`.
+/// This makes it possible to put a catch-all allocator, or a chain ending with one, in front of
+/// an arena, which `Fallback` can't do, because it needs its primary to be an arena:
+/// ```compile_fail
+/// use allocandrescu::{alloc::Stack, prelude::*};
+///
+/// let stack = Stack::<64>::new();
+/// let reserve = Stack::<1024>::new();
+/// // `Fallback<&Stack<64>, System>` is not an `ArenaAllocator`.
+/// let alloc = stack.by_ref().fallback(std::alloc::System).fallback(reserve.by_ref());
+/// ```
+///
+/// This `struct` is created by [`fallback_arena`](crate::Allocandrescu::fallback_arena) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FallbackArena<P, S, C = ()> {
+    inner: Fallback<P, S, C>,
+}
+
+impl<P, S> FallbackArena<P, S> {
+    #[inline]
+    pub const fn new(primary: P, secondary: S) -> Self {
+        Self {
+            inner: Fallback::new(primary, secondary),
+        }
+    }
+}
+
+impl<P, S, C> FallbackArena<P, S, C> {
+    #[inline]
+    pub fn primary(&self) -> &P {
+        &self.inner.primary
+    }
+
+    #[inline]
+    pub fn secondary(&self) -> &S {
+        &self.inner.secondary
+    }
+
+    #[inline]
+    pub fn counter(&self) -> &C {
+        &self.inner.counter
+    }
+
+    #[inline]
+    pub fn primary_mut(&mut self) -> &mut P {
+        &mut self.inner.primary
+    }
+
+    #[inline]
+    pub fn secondary_mut(&mut self) -> &mut S {
+        &mut self.inner.secondary
+    }
+
+    /// Consumes the combinator, returning the primary and the secondary allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocators.
+    #[inline]
+    pub fn into_parts(self) -> (P, S) {
+        self.inner.into_parts()
+    }
+}
+
+impl<P, S, C> FallbackArena<P, S, C>
+where
+    S: ArenaAllocator,
+{
+    #[inline]
+    fn owner(&self, ptr: NonNull<u8>, layout: Layout) -> Side {
+        if self.inner.secondary.contains(ptr, layout) {
+            Side::Secondary
+        } else {
+            Side::Primary
+        }
+    }
+}
+
+unsafe impl<P, S, C> Allocator for FallbackArena<P, S, C>
+where
+    P: Allocator,
+    S: ArenaAllocator,
+    C: SpillCounter,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.inner.alloc(layout, false)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.inner.alloc(layout, true)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.inner.dealloc(self.owner(ptr, layout), ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        let Fallback {
+            primary, secondary, ..
+        } = &self.inner;
+        self.inner.realloc(
+            owner,
+            ptr,
+            old_layout,
+            new_layout,
+            false,
+            |side| match side {
+                Side::Primary => primary.grow(ptr, old_layout, new_layout),
+                Side::Secondary => secondary.grow(ptr, old_layout, new_layout),
+            },
+        )
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        let Fallback {
+            primary, secondary, ..
+        } = &self.inner;
+        self.inner.realloc(
+            owner,
+            ptr,
+            old_layout,
+            new_layout,
+            true,
+            |side| match side {
+                Side::Primary => primary.grow_zeroed(ptr, old_layout, new_layout),
+                Side::Secondary => secondary.grow_zeroed(ptr, old_layout, new_layout),
+            },
+        )
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        let Fallback {
+            primary, secondary, ..
+        } = &self.inner;
+        self.inner.realloc(
+            owner,
+            ptr,
+            old_layout,
+            new_layout,
+            false,
+            |side| match side {
+                Side::Primary => primary.shrink(ptr, old_layout, new_layout),
+                Side::Secondary => secondary.shrink(ptr, old_layout, new_layout),
+            },
+        )
+    }
+}
+
+impl<P, S, C> TrimAllocator for FallbackArena<P, S, C>
+where
+    P: TrimAllocator + ArenaAllocator,
+    S: TrimAllocator + ArenaAllocator,
+    C: SpillCounter,
+{
+    #[inline]
+    fn trim(&self) -> usize {
+        self.inner.trim()
+    }
+}
+
+impl<P, S, C> GoodSizeAllocator for FallbackArena<P, S, C>
+where
+    P: GoodSizeAllocator + ProbeAllocator,
+    S: GoodSizeAllocator + ArenaAllocator,
+    C: SpillCounter,
+{
+    /// Asks the allocators in the same order as [`Fallback`].
+    #[inline]
+    fn preferred_layout(&self, layout: Layout) -> Layout {
+        preferred_by_either(&self.inner.primary, &self.inner.secondary, layout)
+    }
+}
+
+impl<P, S, C> InPlaceGrow for FallbackArena<P, S, C>
+where
+    P: InPlaceGrow,
+    S: InPlaceGrow + ArenaAllocator,
+    C: SpillCounter,
+{
+    /// Grows the block in the allocator that owns it. Blocks are never moved to the other side.
+    unsafe fn try_grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() == 0 {
+            return Err(AllocError);
+        }
+        let size = new_layout.size();
+        let Fallback {
+            primary, secondary, ..
+        } = &self.inner;
+        match self.owner(ptr, old_layout) {
+            Side::Primary => {
+                let block = primary.try_grow_in_place(ptr, old_layout, new_layout)?;
+                self.inner.counter.primary_hit(size);
+                Ok(block)
+            }
+            Side::Secondary => {
+                let block = secondary.try_grow_in_place(ptr, old_layout, new_layout)?;
+                self.inner.counter.secondary_hit(size);
+                Ok(block)
+            }
+        }
+    }
+}
+
+impl<P, S, C> SameAllocator for FallbackArena<P, S, C>
+where
+    P: SameAllocator,
+    S: SameAllocator + ArenaAllocator,
+    C: SpillCounter,
+{
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        let Fallback {
+            primary, secondary, ..
+        } = &self.inner;
+        let parts = [primary.allocator_id(), secondary.allocator_id()];
+        AllocatorId::composite(tag::FALLBACK_ARENA, &parts)
+    }
+}
+
+impl<P, S, C> OwnsAllocations for FallbackArena<P, S, C>
+where
+    P: OwnsAllocations,
+    S: OwnsAllocations + ArenaAllocator,
+    C: SpillCounter,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        self.inner.primary.is_live(ptr) || self.inner.secondary.is_live(ptr)
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.inner.primary.live_count() + self.inner.secondary.live_count()
+    }
+}
+
+impl<P, S, C> ResetAllocator for FallbackArena<P, S, C>
+where
+    P: ResetAllocator,
+{
+    /// Resets only the primary allocator, like [`Fallback`].
+    #[inline]
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+}
+
+impl<P, S, C> ArenaAllocator for FallbackArena<P, S, C>
+where
+    P: ArenaAllocator,
+    S: ArenaAllocator,
+    C: SpillCounter,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.inner.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.inner.allocated_bytes()
+    }
+}
+
+impl<P, S, C> ProbeAllocator for FallbackArena<P, S, C>
+where
+    P: ProbeAllocator,
+    S: ArenaAllocator + ProbeAllocator,
+    C: SpillCounter,
+{
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        self.inner.primary.can_allocate(layout) || self.inner.secondary.can_allocate(layout)
+    }
+}
+
+/// Receives the outcomes of allocations made by [`Fallback`] and [`FallbackArena`] allocators.
+///
+/// Reallocations are reported like allocations of the new layout.
+pub trait SpillCounter {
+    /// Called when the primary allocator served `bytes` bytes.
+    fn primary_hit(&self, bytes: usize);
+
+    /// Called when the secondary allocator served `bytes` bytes.
+    fn secondary_hit(&self, bytes: usize);
+
+    /// Called when the primary allocator failed to serve an allocation.
+    fn primary_failure(&self);
+}
+
+impl SpillCounter for () {
+    #[inline]
+    fn primary_hit(&self, _bytes: usize) {}
+
+    #[inline]
+    fn secondary_hit(&self, _bytes: usize) {}
+
+    #[inline]
+    fn primary_failure(&self) {}
+}
+
+/// Statistics of allocations served by each side of a [`Fallback`] allocator.
+///
+/// Cloning takes a snapshot of the counters.
+#[derive(Debug, Clone, Default)]
+pub struct SpillStats {
+    primary_hits: Cell<usize>,
+    secondary_hits: Cell<usize>,
+    primary_failures: Cell<usize>,
+    primary_bytes: Cell<usize>,
+    secondary_bytes: Cell<usize>,
+}
+
+impl SpillStats {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            primary_hits: Cell::new(0),
+            secondary_hits: Cell::new(0),
+            primary_failures: Cell::new(0),
+            primary_bytes: Cell::new(0),
+            secondary_bytes: Cell::new(0),
+        }
+    }
+
+    /// Returns the number of allocations served by the primary allocator.
+    #[inline]
+    pub fn primary_hits(&self) -> usize {
+        self.primary_hits.get()
+    }
+
+    /// Returns the number of allocations served by the secondary allocator.
+    #[inline]
+    pub fn secondary_hits(&self) -> usize {
+        self.secondary_hits.get()
+    }
+
+    /// Returns the number of allocations the primary allocator failed to serve.
+    #[inline]
+    pub fn primary_failures(&self) -> usize {
+        self.primary_failures.get()
+    }
+
+    /// Returns the total number of bytes served by the primary allocator.
+    #[inline]
+    pub fn primary_bytes(&self) -> usize {
+        self.primary_bytes.get()
+    }
+
+    /// Returns the total number of bytes served by the secondary allocator.
+    #[inline]
+    pub fn secondary_bytes(&self) -> usize {
+        self.secondary_bytes.get()
+    }
+}
+
+impl SpillCounter for SpillStats {
+    #[inline]
+    fn primary_hit(&self, bytes: usize) {
+        self.primary_hits.set(self.primary_hits.get() + 1);
+        self.primary_bytes
+            .set(self.primary_bytes.get().saturating_add(bytes));
+    }
+
+    #[inline]
+    fn secondary_hit(&self, bytes: usize) {
+        self.secondary_hits.set(self.secondary_hits.get() + 1);
+        self.secondary_bytes
+            .set(self.secondary_bytes.get().saturating_add(bytes));
+    }
+
+    #[inline]
+    fn primary_failure(&self) {
+        self.primary_failures.set(self.primary_failures.get() + 1);
+    }
+}
+
+impl fmt::Display for SpillStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "primary: {} hits ({} B), secondary: {} hits ({} B), primary failures: {}",
+            self.primary_hits(),
+            self.primary_bytes(),
+            self.secondary_hits(),
+            self.secondary_bytes(),
+            self.primary_failures(),
+        )
+    }
+}
+
+/// An allocator that forwards allocation to `alloc` and calls the provided closure on each result.
+///
+/// Reallocations made with `grow`, `grow_zeroed` and `shrink` are reported to
+/// [`Observer::observe_realloc`] with both layouts, closures only see new allocations.
+///
+/// This `struct` is created by [`fallback`](crate::Allocandrescu::inspect) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Clone, Copy, Default)]
+pub struct Inspect<A, F> {
+    alloc: A,
+    f: F,
+}
+
+impl<A, F> fmt::Debug for Inspect<A, F>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Inspect")
+            .field("alloc", &self.alloc)
+            .field("f", &format_args!("<fn>"))
+            .finish()
+    }
+}
+
+impl<A, F> Inspect<A, F> {
+    pub const fn new(alloc: A, f: F) -> Self {
+        Self { alloc, f }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+}
+
+unsafe impl<A, F> Allocator for Inspect<A, F>
+where
+    A: Allocator,
+    F: Observer,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.allocate(layout);
+        self.f.observe(layout, result);
+        result
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.allocate_zeroed(layout);
+        self.f.observe(layout, result);
+        result
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout);
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.grow(ptr, old_layout, new_layout);
+        self.f.observe_realloc(old_layout, new_layout, result);
+        result
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.grow_zeroed(ptr, old_layout, new_layout);
+        self.f.observe_realloc(old_layout, new_layout, result);
+        result
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.shrink(ptr, old_layout, new_layout);
+        self.f.observe_realloc(old_layout, new_layout, result);
+        result
+    }
+}
+
+impl<A, F> TrimAllocator for Inspect<A, F>
+where
+    A: TrimAllocator,
+    F: Observer,
+{
+    #[inline]
+    fn trim(&self) -> usize {
+        self.alloc.trim()
+    }
+}
+
+impl<A, F> GoodSizeAllocator for Inspect<A, F>
+where
+    A: GoodSizeAllocator,
+    F: Observer,
+{
+    #[inline]
+    fn preferred_layout(&self, layout: Layout) -> Layout {
+        self.alloc.preferred_layout(layout)
+    }
+}
+
+impl<A, F> InPlaceGrow for Inspect<A, F>
+where
+    A: InPlaceGrow,
+    F: Observer,
+{
+    #[inline]
+    unsafe fn try_grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.try_grow_in_place(ptr, old_layout, new_layout);
+        self.f.observe_realloc(old_layout, new_layout, result);
+        result
+    }
+}
+
+impl<A, F> SameAllocator for Inspect<A, F>
+where
+    A: SameAllocator,
+    F: Observer,
+{
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        self.alloc.allocator_id()
+    }
+}
+
+impl<A, F> OwnsAllocations for Inspect<A, F>
+where
+    A: OwnsAllocations,
+    F: Observer,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        self.alloc.is_live(ptr)
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.alloc.live_count()
+    }
+}
+
+impl<A, F> ResetAllocator for Inspect<A, F>
+where
+    A: ResetAllocator,
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.alloc.reset()
+    }
+}
+
+impl<A, F> ArenaAllocator for Inspect<A, F>
+where
+    A: ArenaAllocator,
+    F: Observer,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+impl<A, F> ProbeAllocator for Inspect<A, F>
+where
+    A: ProbeAllocator,
+    F: Observer,
+{
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        self.alloc.can_allocate(layout)
+    }
+}
+
+/// A callback invoked by [`Inspect`] with the result of each allocation.
+///
+/// It is implemented for all closures taking a [`Layout`] and the result. Like with [`Predicate`],
+/// implementing it for a unit `struct` makes [`Inspect`] usable in const context.
+pub trait Observer {
+    /// Called with the result of a new allocation.
+    fn observe(&self, layout: Layout, result: Result<NonNull<[u8]>, AllocError>);
+
+    /// Called with the result of reallocating a block from `old_layout` to `new_layout`.
+    ///
+    /// It does nothing by default, so an observer counting allocations doesn't count a grown
+    /// block twice.
+    #[inline]
+    fn observe_realloc(
+        &self,
+        _old_layout: Layout,
+        _new_layout: Layout,
+        _result: Result<NonNull<[u8]>, AllocError>,
+    ) {
+    }
+}
+
+impl<F> Observer for F
+where
+    F: Fn(Layout, Result<NonNull<[u8]>, AllocError>),
+{
+    #[inline]
+    fn observe(&self, layout: Layout, result: Result<NonNull<[u8]>, AllocError>) {
+        self(layout, result)
+    }
+}
+
+/// Observes nothing.
+impl Observer for () {
+    #[inline]
+    fn observe(&self, _layout: Layout, _result: Result<NonNull<[u8]>, AllocError>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        alloc::{Failing, Stack},
+        Allocandrescu as _,
+    };
+    use allocator_api2::vec::Vec;
+
+    #[test]
+    fn cond_rejects_growth_beyond_predicate() {
+        let stack = Stack::<256>::new();
+        let alloc = stack.by_ref().cond(|layout| layout.size() <= 16);
+
+        let mut v: Vec<u8, _> = Vec::with_capacity_in(16, &alloc);
+        v.extend(0..16);
+        assert!(v.try_reserve(1).is_err());
+
+        assert_eq!(v.len(), 16);
+        assert!(v.iter().copied().eq(0..16));
+        v.pop();
+        v.push(42);
+        assert_eq!(v[15], 42);
+    }
+
+    #[test]
+    fn cond_leaves_block_intact_when_rejecting() {
+        let stack = Stack::<256>::new();
+        let alloc = stack
+            .by_ref()
+            .cond(|layout| (8..=16).contains(&layout.size()));
+
+        let layout = Layout::new::<[u8; 8]>();
+        let ptr = alloc.allocate_zeroed(layout).unwrap().cast::<u8>();
+        unsafe {
+            ptr.as_ptr().write(7);
+            let grown = Layout::new::<[u8; 32]>();
+            assert!(alloc.grow(ptr, layout, grown).is_err());
+            assert!(alloc.grow_zeroed(ptr, layout, grown).is_err());
+            assert!(alloc.shrink(ptr, layout, Layout::new::<[u8; 4]>()).is_err());
+            assert_eq!(ptr.as_ptr().read(), 7);
+
+            let grown = Layout::new::<[u8; 16]>();
+            let ptr = alloc.grow_zeroed(ptr, layout, grown).unwrap().cast::<u8>();
+            assert_eq!(ptr.as_ptr().read(), 7);
+            alloc.deallocate(ptr, grown);
+        }
+        assert!(alloc.allocate_zeroed(Layout::new::<[u8; 17]>()).is_err());
+    }
+
+    #[test]
+    fn fallback_allocates_zeroed_memory_on_both_sides() {
+        use core::cell::Cell;
+
+        let secondary_calls = Cell::new(0);
+        let stack = Stack::<16>::new();
+        let alloc = stack.by_ref().fallback(
+            std::alloc::System.inspect(|_, _| secondary_calls.set(secondary_calls.get() + 1)),
+        );
+
+        let layout = Layout::new::<[u8; 16]>();
+        let primary = alloc.allocate_zeroed(layout).unwrap();
+        assert!(stack.contains(primary.cast(), layout));
+        assert!(unsafe { primary.as_ref() }.iter().all(|&b| b == 0));
+        assert_eq!(secondary_calls.get(), 0);
+
+        let secondary = alloc.allocate_zeroed(layout).unwrap();
+        assert!(!stack.contains(secondary.cast(), layout));
+        assert!(unsafe { secondary.as_ref() }.iter().all(|&b| b == 0));
+        assert_eq!(secondary_calls.get(), 1);
+
+        unsafe {
+            alloc.deallocate(secondary.cast(), layout);
+            alloc.deallocate(primary.cast(), layout);
+        }
+    }
+
+    #[test]
+    fn fallback_grows_zeroed_into_secondary() {
+        use core::cell::Cell;
+
+        let secondary_calls = Cell::new(0);
+        let stack = Stack::<16>::new();
+        let alloc = stack.by_ref().fallback(
+            std::alloc::System.inspect(|_, _| secondary_calls.set(secondary_calls.get() + 1)),
+        );
+
+        let mut v: Vec<u8, _> = Vec::with_capacity_in(4, &alloc);
+        v.extend([1, 2, 3, 4]);
+        unsafe {
+            let ptr = NonNull::new(v.as_mut_ptr()).unwrap();
+            let old_layout = Layout::new::<[u8; 4]>();
+            let new_layout = Layout::new::<[u8; 8]>();
+            core::mem::forget(v);
+
+            let ptr = alloc.grow_zeroed(ptr, old_layout, new_layout).unwrap();
+            assert!(stack.contains(ptr.cast(), new_layout));
+            assert_eq!(ptr.as_ref(), &[1, 2, 3, 4, 0, 0, 0, 0]);
+            assert_eq!(secondary_calls.get(), 0);
+
+            let old_layout = new_layout;
+            let new_layout = Layout::new::<[u8; 32]>();
+            let ptr = alloc
+                .grow_zeroed(ptr.cast(), old_layout, new_layout)
+                .unwrap();
+            assert!(!stack.contains(ptr.cast(), new_layout));
+            assert_eq!(&ptr.as_ref()[..8], &[1, 2, 3, 4, 0, 0, 0, 0]);
+            assert!(ptr.as_ref()[8..].iter().all(|&b| b == 0));
+            assert_eq!(secondary_calls.get(), 1);
+
+            alloc.deallocate(ptr.cast(), new_layout);
+        }
+    }
+
+    #[test]
+    fn fallback_migrates_growing_block_to_secondary() {
+        let stack = Stack::<16>::new();
+        let alloc = stack.by_ref().fallback(std::alloc::System);
+
+        let mut v: Vec<u8, _> = Vec::with_capacity_in(8, &alloc);
+        v.extend(0..8);
+        assert!(stack.contains(NonNull::new(v.as_mut_ptr()).unwrap(), Layout::new::<u8>()));
+
+        v.reserve_exact(24);
+        assert!(!stack.contains(NonNull::new(v.as_mut_ptr()).unwrap(), Layout::new::<u8>()));
+        assert!(v.iter().copied().eq(0..8));
+    }
+
+    #[test]
+    fn fallback_shrinks_within_primary() {
+        let stack = Stack::<64>::new();
+        let alloc = stack.by_ref().fallback(Failing);
+
+        let mut v: Vec<u8, _> = Vec::with_capacity_in(16, &alloc);
+        v.extend(0..4);
+        v.shrink_to_fit();
+        assert!(stack.contains(
+            NonNull::new(v.as_mut_ptr()).unwrap(),
+            Layout::new::<[u8; 4]>()
+        ));
+        assert!(v.iter().copied().eq(0..4));
+    }
+
+    #[test]
+    fn fallback_reallocates_secondary_blocks() {
+        let stack = Stack::<64>::new();
+        let alloc = stack
+            .by_ref()
+            .cond(|layout| layout.size() >= 16)
+            .fallback(std::alloc::System.cond(|layout| layout.size() <= 16));
+
+        let mut v: Vec<u8, _> = Vec::with_capacity_in(8, &alloc);
+        v.extend(0..8);
+        let layout = Layout::new::<u8>();
+        assert!(!stack.contains(NonNull::new(v.as_mut_ptr()).unwrap(), layout));
+
+        // Grows within the secondary.
+        v.reserve_exact(8);
+        assert!(!stack.contains(NonNull::new(v.as_mut_ptr()).unwrap(), layout));
+
+        // The secondary can't fit the block anymore, so it moves to the primary.
+        v.reserve_exact(16);
+        assert!(stack.contains(NonNull::new(v.as_mut_ptr()).unwrap(), layout));
+        assert!(v.iter().copied().eq(0..8));
+    }
+
+    #[test]
+    fn fallback_counts_spills() {
+        let stack = Stack::<16>::new();
+        let alloc = stack.by_ref().fallback_counted(std::alloc::System);
+        let layout = Layout::new::<[u8; 4]>();
+
+        let mut ptrs = std::vec::Vec::new();
+        for _ in 0..4 {
+            ptrs.push(alloc.allocate(layout).unwrap());
+        }
+        for _ in 0..3 {
+            ptrs.push(alloc.allocate(layout).unwrap());
+        }
+        let stats = alloc.stats();
+        assert_eq!(stats.primary_hits(), 4);
+        assert_eq!(stats.primary_bytes(), 16);
+        assert_eq!(stats.secondary_hits(), 3);
+        assert_eq!(stats.secondary_bytes(), 12);
+        assert_eq!(stats.primary_failures(), 3);
+
+        // The primary is full, so growing its last block spills it to the secondary.
+        let grown = Layout::new::<[u8; 8]>();
+        let ptr = unsafe { alloc.grow(ptrs[3].cast(), layout, grown).unwrap() };
+        ptrs[3] = ptr;
+        assert_eq!(stats.primary_failures(), 4);
+        assert_eq!(stats.secondary_hits(), 4);
+        assert_eq!(stats.secondary_bytes(), 20);
+
+        assert_eq!(
+            stats.to_string(),
+            "primary: 4 hits (16 B), secondary: 4 hits (20 B), primary failures: 4"
+        );
+
+        for (i, ptr) in ptrs.into_iter().enumerate() {
+            let layout = if i == 3 { grown } else { layout };
+            unsafe { alloc.deallocate(ptr.cast(), layout) };
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn fallback_arena_routes_deallocations_through_chain() {
+        use std::alloc::System;
+
+        // `Mirror` panics whenever a block it didn't allocate is deallocated through it.
+        let stack = Stack::<16>::new();
+        let catch_all = System.mirror(System);
+        let reserve = Stack::<256>::new();
+        let reserve = reserve.by_ref().mirror(System);
+
+        let alloc = stack
+            .by_ref()
+            .fallback(catch_all.by_ref().cond(|layout| layout.size() <= 32))
+            .fallback_arena(reserve.by_ref());
+
+        let small = Layout::new::<[u8; 16]>();
+        let large = Layout::new::<[u8; 64]>();
+        let in_stack = alloc.allocate(small).unwrap();
+        let in_catch_all = alloc.allocate(small).unwrap();
+        let in_reserve = alloc.allocate(large).unwrap();
+        assert!(stack.contains(in_stack.cast(), small));
+        assert_eq!(catch_all.live_allocations(), 1);
+        assert_eq!(reserve.live_allocations(), 1);
+
+        unsafe {
+            let grown = alloc.grow(in_catch_all.cast(), small, large).unwrap();
+            assert!(reserve.contains(grown.cast(), large));
+            assert_eq!(catch_all.live_allocations(), 0);
+
+            alloc.deallocate(in_reserve.cast(), large);
+            alloc.deallocate(grown.cast(), large);
+            alloc.deallocate(in_stack.cast(), small);
+        }
+        assert_eq!(reserve.live_allocations(), 0);
+    }
+
+    #[test]
+    fn combinators_holding_closures_are_debug() {
+        use allocator_api2::alloc::AllocError;
+
+        type Pred<'a> = &'a dyn Fn(Layout) -> bool;
+        type Observer<'a> = &'a dyn Fn(Layout, Result<NonNull<[u8]>, AllocError>);
+
+        #[derive(Debug)]
+        struct Holder<'a> {
+            alloc: Inspect<Cond<Stack<64>, Pred<'a>>, Observer<'a>>,
+        }
+
+        let pred: Pred = &|layout| layout.size() <= 8;
+        let observer: Observer = &|_, _| {};
+        let holder = Holder {
+            alloc: Stack::<64>::new().cond(pred).inspect(observer),
+        };
+        let debug = std::format!("{holder:?}");
+        assert!(debug.starts_with("Holder { alloc: Inspect { alloc: Cond { alloc: Stack {"));
+        assert!(debug.contains("pred: <fn> }, f: <fn> }"));
+        assert!(holder.alloc.allocate(Layout::new::<u8>()).is_ok());
+    }
+
+    #[test]
+    fn stack_round_trips_through_chain() {
+        let layout = Layout::new::<[u8; 32]>();
+        let small = |layout: Layout| layout.size() <= 32;
+        let mut alloc = Stack::<64>::new().cond(small).fallback(Stack::<64>::new());
+
+        for _ in 0..2 {
+            for _ in 0..4 {
+                alloc.allocate(layout).unwrap();
+            }
+            assert!(alloc.allocate(layout).is_err());
+
+            unsafe { alloc.primary_mut().inner_mut().reset_unchecked() };
+            alloc.allocate(layout).unwrap();
+
+            let (primary, secondary) = alloc.into_parts();
+            let stack = primary.into_inner();
+            unsafe {
+                stack.reset_unchecked();
+                secondary.reset_unchecked();
+            }
+            alloc = stack.cond(small).fallback(secondary);
+        }
+    }
+
+    #[test]
+    fn closure_free_combinators_are_clone_copy_default() {
+        fn assert_copy<T: Copy>() {}
+        fn assert_default<T: Default>() {}
+
+        type Pred = fn(Layout) -> bool;
+        type Observer = fn(Layout, Result<NonNull<[u8]>, AllocError>);
+        type Chain<'a> = Fallback<Inspect<Cond<&'a Stack<64>, Pred>, Observer>, Failing>;
+
+        assert_copy::<Chain<'_>>();
+        assert_copy::<FallbackArena<Failing, &Stack<64>>>();
+        assert_copy::<Failing>();
+        assert_default::<Fallback<Failing, Stack<64>>>();
+        assert_default::<Fallback<Failing, Failing, SpillStats>>();
+        assert_default::<FallbackArena<Stack<64>, Failing>>();
+
+        let stack = Stack::<64>::new();
+        let pred: Pred = |layout| layout.size() <= 8;
+        let observer: Observer = |_, _| {};
+        let alloc: Chain<'_> = stack
+            .by_ref()
+            .cond(pred)
+            .inspect(observer)
+            .fallback(Failing);
+        let copy = alloc;
+        let a = alloc.allocate(Layout::new::<u64>()).unwrap();
+        let b = copy.allocate(Layout::new::<u64>()).unwrap();
+        assert!(stack.contains(a.cast(), Layout::new::<u64>()));
+        assert!(stack.contains(b.cast(), Layout::new::<u64>()));
+        assert_ne!(a.cast::<u8>(), b.cast::<u8>());
+    }
+
+    fn assert_send<T: Send>() {}
+
+    fn assert_sync<T: Sync>() {}
+
+    /// Fails to compile if any of the types is `Sync`, as both impls of `AmbiguousIfSync` apply then.
+    macro_rules! assert_not_sync {
+        ($($ty:ty),* $(,)?) => {$({
+            trait AmbiguousIfSync<A> {
+                fn some_item() {}
+            }
+            impl<T: ?Sized> AmbiguousIfSync<()> for T {}
+            impl<T: ?Sized + Sync> AmbiguousIfSync<u8> for T {}
+            let _ = <$ty as AmbiguousIfSync<_>>::some_item;
+        })*};
+    }
+
+    #[test]
+    fn auto_traits() {
+        type Pred = fn(Layout) -> bool;
+        type Observer = fn(Layout, Result<NonNull<[u8]>, AllocError>);
+
+        assert_send::<Stack<64>>();
+        assert_send::<Cond<Stack<64>, SizeAtMost<8>>>();
+        assert_send::<Fallback<Stack<64>, Failing>>();
+        assert_send::<Fallback<Stack<64>, Stack<64>, SpillStats>>();
+        assert_send::<Inspect<Stack<64>, Observer>>();
+        assert_not_sync!(
+            Stack<64>,
+            Cond<Stack<64>, SizeAtMost<8>>,
+            Fallback<Stack<64>, Failing>,
+            Fallback<Failing, Failing, SpillStats>,
+            Inspect<Stack<64>, Observer>,
+        );
+
+        // `&Stack` is neither `Send` nor `Sync`, as `Stack` is not `Sync`.
+        assert_not_sync!(&Stack<64>, Cond<&Stack<64>, Pred>);
+
+        assert_send::<Cond<Failing, Pred>>();
+        assert_sync::<Cond<Failing, Pred>>();
+        assert_send::<Fallback<Failing, Failing>>();
+        assert_sync::<Fallback<Failing, Failing>>();
+        assert_send::<Inspect<Failing, Observer>>();
+        assert_sync::<Inspect<Failing, Observer>>();
+    }
+
+    #[test]
+    fn chain_reports_capacity() {
+        let primary = Stack::<64>::new();
+        let secondary = Stack::<256>::new();
+        let alloc = primary
+            .by_ref()
+            .cond(|layout: Layout| layout.size() <= 16)
+            .fallback(secondary.by_ref())
+            .inspect(|_, _| {});
+        assert_eq!(alloc.remaining_capacity(), Some(320));
+        assert_eq!(alloc.allocated_bytes(), Some(0));
+
+        let small = Layout::new::<[u8; 16]>();
+        let large = Layout::new::<[u8; 100]>();
+        let a = alloc.allocate(small).unwrap().cast();
+        alloc.allocate(large).unwrap();
+        assert_eq!(alloc.allocated_bytes(), Some(116));
+        assert_eq!(alloc.remaining_capacity(), Some(204));
+        assert_eq!(primary.remaining_capacity(), Some(48));
+
+        unsafe { alloc.deallocate(a, small) };
+        assert_eq!(alloc.allocated_bytes(), Some(100));
+
+        // Headers are counted as allocated bytes.
+        let headers = Failing.fallback_arena(primary.by_ref().with_header());
+        headers.allocate(Layout::new::<u8>()).unwrap();
+        assert!(headers.allocated_bytes().unwrap() > 1);
+        assert_eq!(headers.allocated_bytes(), primary.allocated_bytes());
+    }
+
+    #[test]
+    fn chain_reset_reaches_primary_only() {
+        let secondary = Stack::<256>::new();
+        let mut alloc = Stack::<64>::new()
+            .cond(|layout: Layout| layout.size() <= 16)
+            .fallback(secondary.by_ref())
+            .inspect(|_, _| {});
+        let small = Layout::new::<[u8; 16]>();
+        let a = alloc.allocate(small).unwrap().cast();
+        let b = alloc.allocate(small).unwrap().cast();
+        alloc.allocate(Layout::new::<[u8; 100]>()).unwrap();
+        // Deallocating out of order leaves the space of `a` in use.
+        unsafe {
+            alloc.deallocate(a, small);
+            alloc.deallocate(b, small);
+        }
+        assert_eq!(alloc.allocated_bytes(), Some(116));
+
+        alloc.reset();
+        assert_eq!(alloc.allocated_bytes(), Some(100));
+        assert_eq!(alloc.allocate(small).unwrap().cast(), a);
+    }
+
+    #[test]
+    fn static_allocator_chain() {
+        type Chain = Fallback<Cond<Stack<256>, SizeAtMost<64>>, Cond<Stack<256>, AlignAtMost<8>>>;
+
+        /// Makes the allocator `Sync`, tests using it must not allocate from multiple threads.
+        struct Unsync<A>(A);
+        unsafe impl<A> Sync for Unsync<A> {}
+
+        static ALLOC: Unsync<Chain> = Unsync(Fallback::new(
+            Cond::new(Stack::new(), SizeAtMost),
+            Cond::new(Stack::new(), AlignAtMost),
+        ));
+        let alloc = &ALLOC.0;
+
+        let small = alloc.allocate(Layout::new::<[u8; 64]>()).unwrap();
+        let large = alloc.allocate(Layout::new::<[u8; 65]>()).unwrap();
+        assert!(alloc
+            .primary()
+            .contains(small.cast(), Layout::new::<[u8; 64]>()));
+        assert!(alloc
+            .secondary()
+            .contains(large.cast(), Layout::new::<[u8; 65]>()));
+        assert!(alloc
+            .allocate(Layout::from_size_align(128, 16).unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn inspect_reports_reallocations() {
+        use core::cell::RefCell;
+
+        struct Log<'a>(&'a RefCell<std::vec::Vec<(Option<usize>, usize, bool)>>);
+
+        impl Observer for Log<'_> {
+            fn observe(&self, layout: Layout, result: Result<NonNull<[u8]>, AllocError>) {
+                self.0
+                    .borrow_mut()
+                    .push((None, layout.size(), result.is_ok()));
+            }
+
+            fn observe_realloc(
+                &self,
+                old_layout: Layout,
+                new_layout: Layout,
+                result: Result<NonNull<[u8]>, AllocError>,
+            ) {
+                self.0.borrow_mut().push((
+                    Some(old_layout.size()),
+                    new_layout.size(),
+                    result.is_ok(),
+                ));
+            }
+        }
+
+        let reported = RefCell::new(std::vec::Vec::new());
+        let stack = Stack::<256>::new();
+        let alloc = Inspect::new(stack.by_ref(), Log(&reported));
+
+        let mut v: Vec<u8, _> = Vec::with_capacity_in(8, &alloc);
+        v.reserve_exact(24);
+        v.extend([1, 2, 3, 4]);
+        v.shrink_to_fit();
+        drop(v);
+        let zeroed = alloc.allocate_zeroed(Layout::new::<[u8; 512]>());
+
+        assert!(zeroed.is_err());
+        assert_eq!(
+            *reported.borrow(),
+            [
+                (None, 8, true),
+                (Some(8), 24, true),
+                (Some(24), 4, true),
+                (None, 512, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn inspect_closure_counts_grown_blocks_once() {
+        let allocations = Cell::new(0);
+        let stack = Stack::<256>::new();
+        let alloc = stack
+            .by_ref()
+            .inspect(|_, _| allocations.set(allocations.get() + 1));
+
+        let mut v: Vec<u8, _> = Vec::with_capacity_in(8, &alloc);
+        v.extend(0..64);
+        v.shrink_to_fit();
+        assert_eq!(allocations.get(), 1);
+    }
+
+    #[cfg(feature = "bumpalo")]
+    #[test]
+    fn inspect_forwards_grow_to_bumpalo() {
+        let bump = &crate::alloc::Bump::with_capacity(1024);
+        let alloc = bump.inspect(|_, _| {});
+
+        let mut v: Vec<u8, _> = Vec::with_capacity_in(8, &alloc);
+        v.reserve_exact(64);
+
+        // Bump grows its most recent allocation without leaving the old block behind.
+        let used: usize = unsafe { bump.iter_allocated_chunks_raw() }
+            .map(|(_, len)| len)
+            .sum();
+        assert_eq!(used, 64);
+    }
+
+    mod zst {
+        use super::*;
+        use allocator_api2::boxed::Box;
+        use core::cell::RefCell;
+
+        const ZST: Layout = Layout::new::<[u64; 0]>();
+
+        #[test]
+        fn stack_serves_zst_when_full() {
+            let stack = Stack::<8>::new();
+            let _ = stack.allocate(Layout::new::<[u8; 8]>()).unwrap();
+            let ptr = stack.allocate(ZST).unwrap();
+            assert_eq!(ptr.len(), 0);
+            assert_eq!(ptr.cast::<u8>().as_ptr() as usize % ZST.align(), 0);
+            assert!(stack.can_allocate(ZST));
+            assert!(stack.allocate(Layout::new::<u8>()).is_err());
+        }
+
+        #[test]
+        fn cond_applies_predicate_to_zst() {
+            let stack = Stack::<8>::new();
+            let alloc = stack.by_ref().cond(|layout| layout.size() > 0);
+            assert!(alloc.allocate(ZST).is_err());
+            let alloc = stack.by_ref().cond(|layout| layout.size() == 0);
+            assert!(alloc.allocate(ZST).is_ok());
+        }
+
+        #[test]
+        fn fallback_serves_zst_without_either_side() {
+            let stack = Stack::<8>::new();
+            let reserve = Stack::<8>::new();
+            let hit = Cell::new(false);
+            let alloc = stack
+                .by_ref()
+                .inspect(|_, _| hit.set(true))
+                .fallback_counted(reserve.by_ref().inspect(|_, _| hit.set(true)));
+
+            let ptr = alloc.allocate(ZST).unwrap().cast::<u8>();
+            unsafe { alloc.deallocate(ptr, ZST) };
+            // A pointer one past the end of `stack` may be the start of `reserve`, neither is touched.
+            let block = stack
+                .allocate(Layout::new::<[u8; 8]>())
+                .unwrap()
+                .cast::<u8>();
+            let end = unsafe { block.add(8) };
+            unsafe { alloc.deallocate(end, Layout::new::<()>()) };
+            assert!(!hit.get());
+            assert_eq!(
+                alloc.stats().primary_hits() + alloc.stats().secondary_hits(),
+                0
+            );
+
+            let grown = unsafe { alloc.grow(ptr, ZST, Layout::new::<u64>()).unwrap() };
+            assert!(reserve.contains(grown.cast(), Layout::new::<u64>()));
+            let shrunk = unsafe {
+                alloc
+                    .shrink(grown.cast(), Layout::new::<u64>(), ZST)
+                    .unwrap()
+            };
+            assert_eq!(shrunk.len(), 0);
+            assert!(hit.get());
+        }
+
+        #[test]
+        fn inspect_reports_zst() {
+            let layouts = RefCell::new(std::vec::Vec::new());
+            let stack = Stack::<8>::new();
+            let alloc = stack
+                .by_ref()
+                .inspect(|layout, result| layouts.borrow_mut().push((layout, result.is_ok())));
+            let ptr = alloc.allocate(ZST).unwrap();
+            unsafe { alloc.deallocate(ptr.cast(), ZST) };
+            assert_eq!(*layouts.borrow(), [(ZST, true)]);
+        }
+
+        #[test]
+        fn zst_boxes_and_empty_vecs_leave_arena_untouched() {
+            let stack = Stack::<8>::new();
+            let alloc = stack
+                .by_ref()
+                .cond(|_| true)
+                .inspect(|_, _| {})
+                .fallback_counted(Failing);
+
+            let unit = Box::new_in((), &alloc);
+            let empty = Vec::<u64, _>::new_in(&alloc);
+            let zsts: Vec<(), _> = Vec::with_capacity_in(16, &alloc);
+            let mut grown = Vec::<u8, _>::new_in(&alloc);
+            grown.reserve_exact(8);
+            drop((unit, empty, zsts));
+            assert_eq!(alloc.stats().primary_hits(), 1);
+            assert_eq!(alloc.stats().primary_bytes(), 8);
+            assert_eq!(alloc.stats().primary_failures(), 0);
+        }
+    }
+
+    /// An arena pretending to cache `cached` bytes, which it releases on trim.
+    #[derive(Default)]
+    struct Cached {
+        stack: Stack<64>,
+        cached: Cell<usize>,
+        trims: Cell<usize>,
+    }
+
+    unsafe impl Allocator for Cached {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.stack.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.stack.deallocate(ptr, layout)
+        }
+    }
+
+    impl ArenaAllocator for Cached {
+        fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+            self.stack.contains(ptr, layout)
+        }
+    }
+
+    impl TrimAllocator for Cached {
+        fn trim(&self) -> usize {
+            self.trims.set(self.trims.get() + 1);
+            self.cached.replace(0)
+        }
+    }
+
+    #[test]
+    fn trim_propagates_through_chain() {
+        let (primary, secondary, reserve) =
+            (Cached::default(), Cached::default(), Cached::default());
+        primary.cached.set(100);
+        secondary.cached.set(20);
+        reserve.cached.set(3);
+        let alloc = primary
+            .by_ref()
+            .cond(|layout: Layout| layout.size() <= 16)
+            .fallback(secondary.by_ref().inspect(|_, _| {}))
+            .fallback(reserve.by_ref().with_header());
+
+        assert_eq!(alloc.trim(), 123);
+        assert_eq!(alloc.trim(), 0);
+        for arena in [&primary, &secondary, &reserve] {
+            assert_eq!(arena.trims.get(), 2);
+        }
+    }
+
+    /// A size-class allocator rounding sizes up to a power of two of at least 16 bytes.
+    #[derive(Default)]
+    struct Classes {
+        stack: Stack<1024>,
+    }
+
+    unsafe impl Allocator for Classes {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.stack.allocate(self.preferred_layout(layout))
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.stack.deallocate(ptr, self.preferred_layout(layout))
+        }
+    }
+
+    impl GoodSizeAllocator for Classes {
+        fn preferred_layout(&self, layout: Layout) -> Layout {
+            let size = layout.size().next_power_of_two().max(16);
+            Layout::from_size_align(size, layout.align()).unwrap()
+        }
+    }
+
+    impl ArenaAllocator for Classes {
+        fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+            self.stack.contains(ptr, layout)
+        }
+    }
+
+    impl ProbeAllocator for Classes {
+        fn can_allocate(&self, layout: Layout) -> bool {
+            self.stack.can_allocate(self.preferred_layout(layout))
+        }
+    }
+
+    /// Checks that allocating the preferred layout for `size` gives a block of `expected` bytes.
+    fn assert_preferred(alloc: &impl GoodSizeAllocator, size: usize, expected: usize) {
+        let preferred = alloc.preferred_layout(Layout::from_size_align(size, 8).unwrap());
+        assert_eq!(preferred.size(), expected);
+        let block = alloc.allocate(preferred).unwrap();
+        assert_eq!(block.len(), expected);
+        unsafe { alloc.deallocate(block.cast(), preferred) };
+    }
+
+    #[test]
+    fn preferred_layout_comes_from_serving_allocator() {
+        let classes = Classes::default();
+        assert_preferred(&classes, 20, 32);
+        assert_preferred(&classes.by_ref().inspect(|_, _| {}), 100, 128);
+        assert_preferred(&classes.by_ref().probe(), 3, 16);
+        // The header takes 16 bytes of the 64-byte class.
+        assert_preferred(&classes.by_ref().with_header(), 40, 48);
+
+        let small = Cond::new(&classes, SizeAtMost::<64>);
+        assert_preferred(&small, 40, 64);
+        // The class of 100 bytes is rejected, so the layout is left as is.
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        assert_eq!(small.preferred_layout(layout), layout);
+
+        let stack = Stack::<64>::new();
+        let alloc = stack.by_ref().fallback(classes.by_ref());
+        assert_preferred(&alloc, 40, 40);
+        assert_preferred(&alloc, 100, 128);
+        let alloc = classes.by_ref().fallback_arena(stack.by_ref());
+        assert_preferred(&alloc, 40, 64);
+        assert_eq!((stack.used(), classes.stack.used()), (0, 0));
+    }
+
+    #[test]
+    fn combinators_pass_block_length_through() {
+        fn assert_len(alloc: &impl Allocator) {
+            let (layout, new_layout) = (Layout::new::<[u64; 3]>(), Layout::new::<[u64; 6]>());
+            let block = alloc.allocate(layout).unwrap();
+            assert_eq!(block.len(), 32);
+            let grown = unsafe { alloc.grow(block.cast(), layout, new_layout) }.unwrap();
+            assert_eq!(grown.len(), 64);
+            unsafe { alloc.deallocate(grown.cast(), new_layout) };
+            let zeroed = alloc.allocate_zeroed(layout).unwrap();
+            assert_eq!(zeroed.len(), 32);
+            unsafe { alloc.deallocate(zeroed.cast(), layout) };
+        }
+
+        let classes = Classes::default();
+        let stack = Stack::<16>::new();
+        assert_len(&Cond::new(&classes, SizeAtMost::<64>));
+        assert_len(&classes.by_ref().inspect(|_, _| {}));
+        assert_len(&classes.by_ref().probe());
+        assert_len(&stack.by_ref().fallback(classes.by_ref()));
+        assert_len(&classes.by_ref().fallback_arena(stack.by_ref()));
+    }
+
+    #[test]
+    fn vec_uses_reported_slack() {
+        struct Calls<'a>(&'a Cell<usize>);
+
+        impl Observer for Calls<'_> {
+            fn observe(&self, _: Layout, _: Result<NonNull<[u8]>, AllocError>) {
+                self.0.set(self.0.get() + 1);
+            }
+
+            fn observe_realloc(&self, _: Layout, _: Layout, _: Result<NonNull<[u8]>, AllocError>) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let allocations = Cell::new(0);
+        let classes = Classes::default();
+        let alloc = Inspect::new(classes.by_ref(), Calls(&allocations));
+
+        let mut plain: Vec<u8, _> = Vec::with_capacity_in(17, &alloc);
+        plain.extend(0..32);
+        assert_eq!(allocations.replace(0), 2);
+
+        // `Vec` doesn't look at the block length, but it can adopt the block with its full capacity.
+        let block = alloc.allocate(Layout::array::<u8>(17).unwrap()).unwrap();
+        let mut adopted =
+            unsafe { Vec::from_raw_parts_in(block.cast::<u8>().as_ptr(), 0, block.len(), &alloc) };
+        adopted.extend(0..32);
+        assert_eq!((adopted.capacity(), allocations.get()), (32, 1));
+    }
+
+    #[test]
+    fn try_grow_in_place_routes_to_owner() {
+        let (primary, secondary) = (Stack::<32>::new(), Stack::<256>::new());
+        let alloc = primary.by_ref().fallback(secondary.by_ref());
+        let layout = |size| Layout::from_size_align(size, 8).unwrap();
+        let small = alloc.allocate(layout(8)).unwrap().cast::<u8>();
+        let large = alloc.allocate(layout(64)).unwrap().cast::<u8>();
+
+        let grown = unsafe { alloc.try_grow_in_place(large, layout(64), layout(128)) }.unwrap();
+        assert_eq!(grown.cast::<u8>(), large);
+        let grown = unsafe { alloc.try_grow_in_place(small, layout(8), layout(32)) }.unwrap();
+        assert_eq!(grown.cast::<u8>(), small);
+        // The primary is full, and the block is not moved to the secondary.
+        assert!(unsafe { alloc.try_grow_in_place(small, layout(32), layout(40)) }.is_err());
+        assert_eq!((primary.used(), secondary.used()), (32, 128));
+        unsafe {
+            alloc.deallocate(small, layout(32));
+            alloc.deallocate(large, layout(128));
+        }
+
+        let alloc = secondary.by_ref().with_header();
+        let block = alloc.allocate(layout(8)).unwrap().cast::<u8>();
+        let grown = unsafe { alloc.try_grow_in_place(block, layout(8), layout(24)) }.unwrap();
+        assert_eq!(grown.cast::<u8>(), block);
+        assert_eq!(
+            unsafe { WithHeader::<&Stack<256>>::layout_of(block) },
+            layout(24)
+        );
+        // A larger alignment would move the payload further from the header.
+        let aligned = Layout::from_size_align(32, 64).unwrap();
+        assert!(unsafe { alloc.try_grow_in_place(block, layout(24), aligned) }.is_err());
+        assert_eq!(
+            unsafe { WithHeader::<&Stack<256>>::layout_of(block) },
+            layout(24)
+        );
     }
 }
@@ -0,0 +1,320 @@
+use crate::ArenaAllocator;
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::RefCell, fmt, panic::Location, ptr::NonNull};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+    vec::Vec,
+};
+
+/// An allocator that forwards all operations to `alloc` and timestamps each live allocation, so
+/// that blocks living longer than expected can be [reported](Aging::older_than).
+///
+/// Growing or shrinking a block keeps its timestamp and location, so a collection is as old as
+/// its first allocation. Like with [`Profiler`](super::Profiler), the location is that of the
+/// nearest caller that isn't `#[track_caller]`. Zero-sized allocations are not recorded.
+///
+/// Records are kept in a hash map by address, so recording and forgetting a block takes constant
+/// time on average.
+///
+/// This `struct` is created by [`track_ages`](crate::Allocandrescu::track_ages) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+pub struct Aging<A, C = fn() -> Instant> {
+    alloc: A,
+    now: C,
+    locations: bool,
+    live: RefCell<HashMap<usize, Record>>,
+}
+
+#[derive(Clone, Copy)]
+struct Record {
+    layout: Layout,
+    born: Instant,
+    location: Option<&'static Location<'static>>,
+}
+
+impl<A> Aging<A> {
+    #[inline]
+    pub fn new(alloc: A) -> Self {
+        Self::with_clock(alloc, Instant::now)
+    }
+}
+
+impl<A, C> Aging<A, C> {
+    /// Creates a tracker that reads the time with `now`, e.g. to control it in tests.
+    #[inline]
+    pub fn with_clock(alloc: A, now: C) -> Self {
+        Self {
+            alloc,
+            now,
+            locations: true,
+            live: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Stops recording the locations of allocations, which are reported as `None` afterwards.
+    #[inline]
+    pub fn without_locations(mut self) -> Self {
+        self.locations = false;
+        self
+    }
+
+    /// Returns the number of live allocations.
+    #[inline]
+    pub fn live_count(&self) -> usize {
+        self.live.borrow().len()
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+}
+
+impl<A, C> Aging<A, C>
+where
+    C: Fn() -> Instant,
+{
+    /// Returns the layout, allocation time and location of each live block allocated at least
+    /// `age` ago, oldest first.
+    #[inline]
+    pub fn older_than(
+        &self,
+        age: Duration,
+    ) -> Vec<(Layout, Instant, Option<&'static Location<'static>>)> {
+        self.older_than_min_size(age, 0)
+    }
+
+    /// Like [`older_than`](Aging::older_than), but only reports blocks of at least `min_size`
+    /// bytes.
+    pub fn older_than_min_size(
+        &self,
+        age: Duration,
+        min_size: usize,
+    ) -> Vec<(Layout, Instant, Option<&'static Location<'static>>)> {
+        let now = (self.now)();
+        let live = self.live.borrow();
+        let mut old: Vec<_> = live
+            .values()
+            .filter(|record| record.layout.size() >= min_size)
+            .filter(|record| now.saturating_duration_since(record.born) >= age)
+            .map(|record| (record.layout, record.born, record.location))
+            .collect();
+        old.sort_by_key(|&(_, born, _)| born);
+        old
+    }
+
+    #[inline]
+    fn record(&self, location: &'static Location<'static>, block: NonNull<[u8]>, layout: Layout) {
+        if layout.size() != 0 {
+            let record = Record {
+                layout,
+                born: (self.now)(),
+                location: self.locations.then_some(location),
+            };
+            let addr = block.cast::<u8>().as_ptr() as usize;
+            self.live.borrow_mut().insert(addr, record);
+        }
+    }
+
+    /// Moves the record of a reallocated block, keeping its timestamp and location, or records
+    /// it anew if it was zero-sized.
+    #[inline]
+    fn moved(
+        &self,
+        location: &'static Location<'static>,
+        ptr: NonNull<u8>,
+        block: NonNull<[u8]>,
+        layout: Layout,
+    ) {
+        let mut live = self.live.borrow_mut();
+        let Some(record) = live.remove(&(ptr.as_ptr() as usize)) else {
+            drop(live);
+            return self.record(location, block, layout);
+        };
+        if layout.size() != 0 {
+            let addr = block.cast::<u8>().as_ptr() as usize;
+            live.insert(addr, Record { layout, ..record });
+        }
+    }
+}
+
+impl<A, C> fmt::Debug for Aging<A, C>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Aging")
+            .field("alloc", &self.alloc)
+            .field("live", &self.live_count())
+            .finish_non_exhaustive()
+    }
+}
+
+unsafe impl<A, C> Allocator for Aging<A, C>
+where
+    A: Allocator,
+    C: Fn() -> Instant,
+{
+    #[track_caller]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let location = Location::caller();
+        let block = self.alloc.allocate(layout)?;
+        self.record(location, block, layout);
+        Ok(block)
+    }
+
+    #[track_caller]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let location = Location::caller();
+        let block = self.alloc.allocate_zeroed(layout)?;
+        self.record(location, block, layout);
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            self.live.borrow_mut().remove(&(ptr.as_ptr() as usize));
+        }
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    #[track_caller]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let location = Location::caller();
+        let block = self.alloc.grow(ptr, old_layout, new_layout)?;
+        self.moved(location, ptr, block, new_layout);
+        Ok(block)
+    }
+
+    #[track_caller]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let location = Location::caller();
+        let block = self.alloc.grow_zeroed(ptr, old_layout, new_layout)?;
+        self.moved(location, ptr, block, new_layout);
+        Ok(block)
+    }
+
+    #[track_caller]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let location = Location::caller();
+        let block = self.alloc.shrink(ptr, old_layout, new_layout)?;
+        self.moved(location, ptr, block, new_layout);
+        Ok(block)
+    }
+}
+
+impl<A, C> ArenaAllocator for Aging<A, C>
+where
+    A: ArenaAllocator,
+    C: Fn() -> Instant,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::Stack;
+    use core::cell::Cell;
+
+    #[test]
+    fn aging_reports_only_survivors() {
+        let start = Instant::now();
+        let elapsed = Cell::new(Duration::ZERO);
+        let stack = Stack::<1024>::new();
+        let alloc = Aging::with_clock(&stack, || start + elapsed.get());
+
+        let survivor = Layout::new::<[u64; 4]>();
+        let kept = alloc.allocate(survivor).unwrap();
+        let line = line!() - 1;
+        elapsed.set(Duration::from_secs(10));
+        let freed = alloc.allocate(Layout::new::<u32>()).unwrap();
+        assert_eq!(alloc.older_than(Duration::from_secs(5)).len(), 1);
+        unsafe { alloc.deallocate(freed.cast(), Layout::new::<u32>()) };
+
+        elapsed.set(Duration::from_secs(3600));
+        let old = alloc.older_than(Duration::from_secs(60));
+        assert_eq!(old.len(), 1);
+        let (layout, born, location) = old[0];
+        assert_eq!((layout, born), (survivor, start));
+        let location = location.unwrap();
+        assert_eq!((location.file(), location.line()), (file!(), line));
+
+        assert!(alloc
+            .older_than_min_size(Duration::from_secs(60), 64)
+            .is_empty());
+        unsafe { alloc.deallocate(kept.cast(), survivor) };
+        assert!(alloc.older_than(Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn aging_keeps_timestamp_across_reallocation() {
+        let start = Instant::now();
+        let elapsed = Cell::new(Duration::ZERO);
+        let stack = Stack::<1024>::new();
+        let alloc = Aging::with_clock(&stack, || start + elapsed.get()).without_locations();
+
+        let mut v = allocator_api2::vec::Vec::new_in(&alloc);
+        v.push(1u8);
+        elapsed.set(Duration::from_secs(1));
+        let w = allocator_api2::vec![in &alloc; 0u8; 4];
+        elapsed.set(Duration::from_secs(2));
+        // The block moves behind `w`.
+        v.extend_from_slice(&[2; 100]);
+        assert_eq!(alloc.live_count(), 2);
+        let old = alloc.older_than(Duration::ZERO);
+        assert_eq!(
+            old.iter().map(|&(_, born, _)| born).collect::<Vec<_>>(),
+            [start, start + Duration::from_secs(1)]
+        );
+        assert_eq!(old[0].0.size(), v.capacity());
+        assert!(old.iter().all(|&(_, _, location)| location.is_none()));
+        drop((v, w));
+        assert_eq!(alloc.live_count(), 0);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::ArenaAllocator;
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, ptr, ptr::NonNull};
+
+/// An allocator that forwards each allocation to whichever of `a` and `b` has more
+/// [remaining capacity](ArenaAllocator::remaining_capacity), trying the other one if it fails.
+///
+/// Capacities are compared at every allocation. On a tie, `a` is tried first. An unknown capacity
+/// ranks below every known one, so a side reporting `None` is only tried first if both do, in
+/// which case `a` is.
+///
+/// Deallocation and reallocation are routed to the allocator that
+/// [contains](ArenaAllocator::contains) the block. A block that can't be resized in place is moved
+/// to whichever side has more room.
+///
+/// This `struct` is created by [`balance`](crate::Allocandrescu::balance) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Balance<A, B> {
+    a: A,
+    b: B,
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    A,
+    B,
+}
+
+impl<A, B> Balance<A, B> {
+    #[inline]
+    pub const fn new(a: A, b: B) -> Self {
+        Self { a, b }
+    }
+
+    #[inline]
+    pub fn a(&self) -> &A {
+        &self.a
+    }
+
+    #[inline]
+    pub fn b(&self) -> &B {
+        &self.b
+    }
+
+    /// Consumes the combinator, returning both allocators.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocators.
+    #[inline]
+    pub fn into_parts(self) -> (A, B) {
+        (self.a, self.b)
+    }
+}
+
+impl<A, B> Balance<A, B>
+where
+    A: ArenaAllocator,
+    B: ArenaAllocator,
+{
+    /// Returns the side to try first.
+    #[inline]
+    fn preferred(&self) -> Side {
+        // `None` compares less than any `Some`.
+        if self.b.remaining_capacity() > self.a.remaining_capacity() {
+            Side::B
+        } else {
+            Side::A
+        }
+    }
+
+    #[inline]
+    fn owner(&self, ptr: NonNull<u8>, layout: Layout) -> Side {
+        if self.a.contains(ptr, layout) {
+            Side::A
+        } else {
+            Side::B
+        }
+    }
+
+    #[inline]
+    fn side(&self, side: Side) -> &dyn Allocator {
+        match side {
+            Side::A => &self.a,
+            Side::B => &self.b,
+        }
+    }
+
+    fn alloc(&self, layout: Layout, zeroed: bool) -> Result<NonNull<[u8]>, AllocError> {
+        let alloc = |side| {
+            let alloc = self.side(side);
+            if zeroed {
+                alloc.allocate_zeroed(layout)
+            } else {
+                alloc.allocate(layout)
+            }
+        };
+        let (first, second) = match self.preferred() {
+            Side::A => (Side::A, Side::B),
+            Side::B => (Side::B, Side::A),
+        };
+        alloc(first).or_else(|_| alloc(second))
+    }
+
+    /// Moves the block to memory allocated on either side.
+    unsafe fn relocate(
+        &self,
+        owner: Side,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc(new_layout, zeroed)?;
+        let len = old_layout.size().min(new_layout.size());
+        ptr::copy_nonoverlapping(ptr.as_ptr(), block.cast().as_ptr(), len);
+        self.side(owner).deallocate(ptr, old_layout);
+        Ok(block)
+    }
+}
+
+unsafe impl<A, B> Allocator for Balance<A, B>
+where
+    A: ArenaAllocator,
+    B: ArenaAllocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, false)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, true)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.side(self.owner(ptr, layout)).deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        self.side(owner)
+            .grow(ptr, old_layout, new_layout)
+            .or_else(|_| self.relocate(owner, ptr, old_layout, new_layout, false))
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        self.side(owner)
+            .grow_zeroed(ptr, old_layout, new_layout)
+            .or_else(|_| self.relocate(owner, ptr, old_layout, new_layout, true))
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        self.side(owner)
+            .shrink(ptr, old_layout, new_layout)
+            .or_else(|_| self.relocate(owner, ptr, old_layout, new_layout, false))
+    }
+}
+
+impl<A, B> ArenaAllocator for Balance<A, B>
+where
+    A: ArenaAllocator,
+    B: ArenaAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.a.contains(ptr, layout) || self.b.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        Some(
+            self.a
+                .remaining_capacity()?
+                .saturating_add(self.b.remaining_capacity()?),
+        )
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        Some(
+            self.a
+                .allocated_bytes()?
+                .saturating_add(self.b.allocated_bytes()?),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _};
+
+    #[test]
+    fn balance_tracks_remaining_capacity() {
+        let (small, large) = (Stack::<1024>::new(), Stack::<3072>::new());
+        let alloc = small.by_ref().balance(large.by_ref());
+        let layout = Layout::new::<[u8; 16]>();
+        let mut blocks = [0usize; 2];
+        for _ in 0..200 {
+            let ptr = alloc.allocate(layout).unwrap().cast();
+            blocks[usize::from(large.contains(ptr, layout))] += 1;
+        }
+        // The large stack takes everything until it is down to the size of the small one, then
+        // they alternate.
+        assert_eq!(blocks, [36, 164]);
+        assert_eq!(small.remaining(), large.remaining());
+
+        // Once both are full, allocation fails.
+        while alloc.allocate(layout).is_ok() {}
+        assert_eq!((small.remaining(), large.remaining()), (0, 0));
+        assert_eq!(alloc.remaining_capacity(), Some(0));
+    }
+
+    #[test]
+    fn balance_breaks_ties_towards_a() {
+        let (a, b) = (Stack::<64>::new(), Stack::<64>::new());
+        let alloc = Balance::new(&a, &b);
+        let first = alloc.allocate(Layout::new::<u32>()).unwrap().cast();
+        assert!(a.contains(first, Layout::new::<u32>()));
+        let second = alloc.allocate(Layout::new::<u32>()).unwrap().cast();
+        assert!(b.contains(second, Layout::new::<u32>()));
+
+        // Deallocation and growth are routed to the owner.
+        unsafe {
+            let grown = alloc.grow(first, Layout::new::<u32>(), Layout::new::<u64>());
+            assert_eq!(grown.unwrap().cast(), first);
+            alloc.deallocate(first, Layout::new::<u64>());
+            alloc.deallocate(second, Layout::new::<u32>());
+        }
+        assert_eq!(alloc.allocated_bytes(), Some(0));
+    }
+
+    #[test]
+    fn balance_moves_blocks_that_outgrow_their_side() {
+        let (a, b) = (Stack::<32>::new(), Stack::<128>::new());
+        let alloc = Balance::new(&a, &b);
+        let mut v = allocator_api2::vec![in &alloc; 1u8; 100];
+        let w = allocator_api2::vec![in &alloc; 2u8; 20];
+        assert_eq!((a.used(), b.used()), (20, 100));
+        // Neither side fits 200 bytes.
+        assert!(v.try_reserve_exact(100).is_err());
+        v.truncate(10);
+        v.shrink_to_fit();
+        assert_eq!(b.used(), 10);
+        assert_eq!((&v[..], &w[..]), (&[1u8; 10][..], &[2u8; 20][..]));
+    }
+}
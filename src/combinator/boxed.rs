@@ -0,0 +1,155 @@
+use crate::ArenaAllocator;
+use alloc_crate::boxed::Box;
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, fmt, ops::Range, ptr::NonNull};
+
+/// A type-erased arena allocator stored on the heap.
+///
+/// Composed allocators have long, often unnameable types. Boxing one gives it a single type that
+/// can appear in public APIs and be stored in `struct`s, at the cost of a dynamic dispatch on each
+/// operation. Share it by reference: `&BoxedAllocator` is also an allocator.
+///
+/// This `struct` is created by [`boxed`](crate::Allocandrescu::boxed) and
+/// [`boxed_scoped`](crate::Allocandrescu::boxed_scoped) methods on [`Allocandrescu`](crate::Allocandrescu).
+/// See their documentation for more details.
+pub struct BoxedAllocator<'a> {
+    alloc: Box<dyn ArenaAllocator + 'a>,
+}
+
+impl<'a> BoxedAllocator<'a> {
+    #[inline]
+    pub fn new<A>(alloc: A) -> Self
+    where
+        A: ArenaAllocator + 'a,
+    {
+        Self {
+            alloc: Box::new(alloc),
+        }
+    }
+}
+
+impl fmt::Debug for BoxedAllocator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxedAllocator").finish_non_exhaustive()
+    }
+}
+
+unsafe impl Allocator for BoxedAllocator<'_> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.allocate(layout)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.allocate_zeroed(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.grow(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.grow_zeroed(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.shrink(ptr, old_layout, new_layout)
+    }
+}
+
+impl ArenaAllocator for BoxedAllocator<'_> {
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn arena_range(&self) -> Option<Range<usize>> {
+        self.alloc.arena_range()
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        alloc::{Failing, Stack},
+        Allocandrescu as _,
+    };
+    use allocator_api2::vec::Vec;
+
+    struct Pool {
+        alloc: BoxedAllocator<'static>,
+    }
+
+    fn collect_in<A: Allocator>(alloc: A, n: u8) -> Vec<u8, A> {
+        let mut v = Vec::new_in(alloc);
+        v.extend(0..n);
+        v
+    }
+
+    #[test]
+    fn boxed_allocator_erases_chain() {
+        let pool = Pool {
+            alloc: Stack::<64>::new()
+                .cond(|layout| layout.size() <= 32)
+                .fallback(Stack::<256>::new())
+                .boxed(),
+        };
+        let first = collect_in(&pool.alloc, 32);
+        let second = collect_in(&pool.alloc, 100);
+        assert!(first.iter().copied().eq(0..32));
+        assert!(second.iter().copied().eq(0..100));
+
+        let layout = Layout::array::<u8>(32).unwrap();
+        assert!(pool.alloc.contains(NonNull::from(&first[0]), layout));
+        assert!(!pool
+            .alloc
+            .contains(NonNull::from(&0u8), Layout::new::<u8>()));
+    }
+
+    #[test]
+    fn boxed_scoped_allocator_borrows_arena() {
+        let stack = Stack::<64>::new();
+        let alloc = stack.by_ref().fallback(Failing).boxed_scoped();
+        let v = collect_in(&alloc, 16);
+        let layout = Layout::array::<u8>(16).unwrap();
+        assert!(stack.contains(NonNull::from(&v[0]), layout));
+        assert!(alloc.contains(NonNull::from(&v[0]), layout));
+        assert!(alloc.allocate(Layout::new::<[u8; 64]>()).is_err());
+    }
+}
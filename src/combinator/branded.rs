@@ -0,0 +1,239 @@
+use crate::{ArenaExt, Error};
+use allocator_api2::alloc::Allocator;
+use core::{
+    alloc::Layout,
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+/// An invariant lifetime that no two [`with_branded`](crate::Allocandrescu::with_branded) scopes share.
+type Brand<'brand> = PhantomData<fn(&'brand ()) -> &'brand ()>;
+
+/// A view of an allocator whose allocations are branded with the unique lifetime `'brand`, so that
+/// they can only be deallocated by the allocator that made them.
+///
+/// The values are returned as [`BrandedBox`]es carrying the brand. Passing a box to
+/// [`dealloc`](Branded::dealloc) of another allocator, or taking it out of the scope, fails to
+/// compile:
+/// ```compile_fail
+/// use allocandrescu::{alloc::Stack, prelude::*};
+///
+/// let (a, b) = (Stack::<64>::new(), Stack::<64>::new());
+/// a.with_branded(|a| {
+///     b.with_branded(|b| {
+///         let value = a.alloc_value(1u32).unwrap();
+///         b.dealloc(value);
+///     })
+/// });
+/// ```
+/// ```compile_fail
+/// use allocandrescu::{alloc::Stack, prelude::*};
+///
+/// let stack = Stack::<64>::new();
+/// let value = stack.with_branded(|arena| arena.alloc_value(1u32).unwrap());
+/// ```
+///
+/// Only the typed allocation helpers of [`ArenaExt`] are branded. The [`Allocator`] interface stays
+/// available through [`inner`](Branded::inner).
+///
+/// This `struct` is created by [`with_branded`](crate::Allocandrescu::with_branded) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+pub struct Branded<'brand, A> {
+    alloc: A,
+    brand: Brand<'brand>,
+}
+
+impl<A> Clone for Branded<'_, &A>
+where
+    A: ?Sized,
+{
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<A> Copy for Branded<'_, &A> where A: ?Sized {}
+
+impl<A> fmt::Debug for Branded<'_, A>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Branded")
+            .field("alloc", &self.alloc)
+            .finish()
+    }
+}
+
+impl<'brand, 'a, A> Branded<'brand, &'a A>
+where
+    A: Allocator + ?Sized,
+{
+    /// Brands `alloc`. The brand must be a lifetime argument of a closure
+    /// generic over it, so that it is unique.
+    #[inline]
+    pub(crate) fn new(alloc: &'a A) -> Self {
+        Self {
+            alloc,
+            brand: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &'a A {
+        self.alloc
+    }
+
+    /// Moves `value` into the allocator. See [`ArenaExt::alloc_value`].
+    #[inline]
+    pub fn alloc_value<T>(&self, value: T) -> Result<BrandedBox<'brand, T>, Error> {
+        self.alloc.alloc_value(value).map(BrandedBox::new)
+    }
+
+    /// Moves the value returned by `f` into the allocator. See [`ArenaExt::try_alloc_with`].
+    #[inline]
+    pub fn try_alloc_with<T, F>(&self, f: F) -> Result<BrandedBox<'brand, T>, Error>
+    where
+        F: FnOnce() -> T,
+    {
+        self.alloc.try_alloc_with(f).map(BrandedBox::new)
+    }
+
+    /// Copies `src` into the allocator. See [`ArenaExt::alloc_slice_copy`].
+    #[inline]
+    pub fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> Result<BrandedBox<'brand, [T]>, Error> {
+        self.alloc.alloc_slice_copy(src).map(BrandedBox::new)
+    }
+
+    /// Copies `src` into the allocator. See [`ArenaExt::alloc_str`].
+    #[inline]
+    pub fn alloc_str(&self, src: &str) -> Result<BrandedBox<'brand, str>, Error> {
+        self.alloc.alloc_str(src).map(BrandedBox::new)
+    }
+
+    /// Allocates a slice of the values yielded by `iter`. See [`ArenaExt::alloc_iter`].
+    #[inline]
+    pub fn alloc_iter<T, I>(&self, iter: I) -> Result<BrandedBox<'brand, [T]>, Error>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.alloc.alloc_iter(iter).map(BrandedBox::new)
+    }
+
+    /// Drops the value of `boxed` and deallocates it.
+    #[inline]
+    pub fn dealloc<T: ?Sized>(&self, boxed: BrandedBox<'brand, T>) {
+        let layout = Layout::for_value::<T>(&boxed);
+        unsafe {
+            boxed.ptr.as_ptr().drop_in_place();
+            // The brand proves that the box was allocated here.
+            self.alloc.deallocate(boxed.ptr.cast(), layout);
+        }
+    }
+}
+
+/// An owning pointer to a value allocated by a [`Branded`] allocator.
+///
+/// Dropping the box doesn't drop the value nor deallocate it, like with the values of [`ArenaExt`],
+/// see [`Branded::dealloc`] for that.
+///
+/// This `struct` is created by the allocation methods of [`Branded`].
+/// See its documentation for more details.
+pub struct BrandedBox<'brand, T: ?Sized> {
+    ptr: NonNull<T>,
+    brand: PhantomData<(Brand<'brand>, T)>,
+}
+
+impl<T: ?Sized> BrandedBox<'_, T> {
+    #[inline]
+    fn new(value: &mut T) -> Self {
+        Self {
+            ptr: NonNull::from(value),
+            brand: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for BrandedBox<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // The allocator is borrowed for the whole scope that the brand is valid in.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for BrandedBox<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for BrandedBox<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{alloc::Stack, Allocandrescu as _, ArenaAllocator};
+    use core::{alloc::Layout, cell::Cell, ptr::NonNull};
+
+    #[test]
+    fn branded_values_round_trip() {
+        let stack = Stack::<256>::new();
+        let sum = stack.with_branded(|arena| {
+            let mut value = arena.alloc_value(40u32).unwrap();
+            let numbers = arena.alloc_slice_copy(&[1u32, 1]).unwrap();
+            let name = arena.alloc_str("branded").unwrap();
+            let squares = arena.alloc_iter((0..4u32).map(|i| i * i)).unwrap();
+            *value += numbers.iter().sum::<u32>();
+            assert_eq!(&*name, "branded");
+            assert_eq!(*squares, [0, 1, 4, 9]);
+            let ptr = NonNull::from(&*name).cast();
+            assert!(arena.inner().contains(ptr, Layout::new::<[u8; 7]>()));
+
+            let sum = *value;
+            // Deallocating in reverse order brings the stack back to empty.
+            arena.dealloc(squares);
+            arena.dealloc(name);
+            arena.dealloc(numbers);
+            arena.dealloc(value);
+            sum
+        });
+        assert_eq!(sum, 42);
+        assert_eq!(stack.used(), 0);
+    }
+
+    #[test]
+    fn branded_dealloc_drops_value() {
+        struct Counted<'a>(&'a Cell<u32>);
+
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let (a, b) = (Stack::<64>::new(), Stack::<64>::new());
+        a.with_branded(|a| {
+            b.with_branded(|b| {
+                let in_a = a.try_alloc_with(|| Counted(&drops)).unwrap();
+                let in_b = b.alloc_value(Counted(&drops)).unwrap();
+                let copy = a;
+                copy.dealloc(in_a);
+                b.dealloc(in_b);
+            })
+        });
+        assert_eq!(drops.get(), 2);
+        assert_eq!(a.used() + b.used(), 0);
+    }
+}
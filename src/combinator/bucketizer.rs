@@ -0,0 +1,346 @@
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::Cell, ptr, ptr::NonNull};
+
+/// An allocator that forwards each allocation to the first of `N` buckets whose upper bound is
+/// at least its size, or to `overflow` if there is none.
+///
+/// Blocks are routed purely by their size, so the allocators don't have to implement
+/// [`ArenaAllocator`](crate::ArenaAllocator). Growing or shrinking a block across a bucket
+/// boundary moves it to the new bucket. Spare capacity reported by a bucket is capped at its
+/// bound, so the whole returned block can be freed through the same bucket.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, combinator::Bucketizer};
+/// use allocator_api2::vec::Vec;
+///
+/// let (small, medium) = (Stack::<1024>::new(), Stack::<1024>::new());
+/// let buckets = Bucketizer::new([(32, &small), (128, &medium)], &medium);
+/// let v = Vec::<u8, _>::with_capacity_in(100, &buckets);
+/// assert_eq!((small.used(), medium.used()), (0, 100));
+/// assert_eq!(buckets.hits(1), 1);
+/// # drop(v);
+/// ```
+#[derive(Debug)]
+pub struct Bucketizer<A, const N: usize, S = A> {
+    buckets: [(usize, A); N],
+    overflow: S,
+    hits: [Cell<usize>; N],
+    overflow_hits: Cell<usize>,
+}
+
+impl<A, const N: usize, S> Bucketizer<A, N, S> {
+    /// Creates a bucketizer from pairs of upper bounds and allocators, sorted by the bound.
+    ///
+    /// # Panics
+    /// Panics if the bounds aren't strictly increasing.
+    #[inline]
+    #[track_caller]
+    pub fn new(buckets: [(usize, A); N], overflow: S) -> Self {
+        assert!(
+            buckets.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "bucket bounds must be strictly increasing"
+        );
+        Self {
+            buckets,
+            overflow,
+            hits: [const { Cell::new(0) }; N],
+            overflow_hits: Cell::new(0),
+        }
+    }
+
+    /// Returns the upper bound of the bucket at `idx`.
+    ///
+    /// # Panics
+    /// Panics if `idx >= N`.
+    #[inline]
+    pub fn bound(&self, idx: usize) -> usize {
+        self.buckets[idx].0
+    }
+
+    /// Returns the allocator of the bucket at `idx`.
+    ///
+    /// # Panics
+    /// Panics if `idx >= N`.
+    #[inline]
+    pub fn bucket(&self, idx: usize) -> &A {
+        &self.buckets[idx].1
+    }
+
+    /// Returns the allocator serving sizes larger than every bound.
+    #[inline]
+    pub fn overflow(&self) -> &S {
+        &self.overflow
+    }
+
+    /// Returns the number of blocks allocated in, or moved to, the bucket at `idx`.
+    ///
+    /// # Panics
+    /// Panics if `idx >= N`.
+    #[inline]
+    pub fn hits(&self, idx: usize) -> usize {
+        self.hits[idx].get()
+    }
+
+    /// Returns the number of blocks allocated in, or moved to, the overflow allocator.
+    #[inline]
+    pub fn overflow_hits(&self) -> usize {
+        self.overflow_hits.get()
+    }
+
+    /// Consumes the combinator, returning the buckets and the overflow allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocators.
+    #[inline]
+    pub fn into_parts(self) -> ([(usize, A); N], S) {
+        (self.buckets, self.overflow)
+    }
+
+    /// Returns the index of the bucket serving `size` bytes, or `N` for the overflow allocator.
+    #[inline]
+    fn route(&self, size: usize) -> usize {
+        self.buckets
+            .iter()
+            .position(|&(bound, _)| size <= bound)
+            .unwrap_or(N)
+    }
+
+    /// Trims `block` to the bound of the bucket at `idx`, so that freeing it with any layout
+    /// that fits routes back to the same bucket.
+    #[inline]
+    fn trim(&self, idx: usize, block: NonNull<[u8]>) -> NonNull<[u8]> {
+        match self.buckets.get(idx) {
+            Some(&(bound, _)) => {
+                NonNull::slice_from_raw_parts(block.cast(), block.len().min(bound))
+            }
+            None => block,
+        }
+    }
+
+    #[inline]
+    fn record(&self, idx: usize) {
+        let hits = self.hits.get(idx).unwrap_or(&self.overflow_hits);
+        hits.set(hits.get() + 1);
+    }
+}
+
+impl<A, const N: usize, S> Bucketizer<A, N, S>
+where
+    A: Allocator,
+    S: Allocator,
+{
+    #[inline]
+    fn alloc(&self, idx: usize) -> &dyn Allocator {
+        match self.buckets.get(idx) {
+            Some((_, alloc)) => alloc,
+            None => &self.overflow,
+        }
+    }
+
+    #[inline]
+    fn allocate_in(
+        &self,
+        idx: usize,
+        layout: Layout,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let alloc = self.alloc(idx);
+        let block = if zeroed {
+            alloc.allocate_zeroed(layout)
+        } else {
+            alloc.allocate(layout)
+        }?;
+        self.record(idx);
+        Ok(self.trim(idx, block))
+    }
+
+    /// Reallocates the block with `realloc` if it stays in the same bucket, or moves it to the
+    /// bucket of the new layout.
+    #[inline]
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+        realloc: impl FnOnce(&dyn Allocator) -> Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let (old_idx, new_idx) = (self.route(old_layout.size()), self.route(new_layout.size()));
+        if old_idx == new_idx {
+            return realloc(self.alloc(old_idx)).map(|block| self.trim(old_idx, block));
+        }
+        let block = self.allocate_in(new_idx, new_layout, zeroed)?;
+        let len = old_layout.size().min(new_layout.size());
+        ptr::copy_nonoverlapping(ptr.as_ptr(), block.cast().as_ptr(), len);
+        self.alloc(old_idx).deallocate(ptr, old_layout);
+        Ok(block)
+    }
+}
+
+unsafe impl<A, const N: usize, S> Allocator for Bucketizer<A, N, S>
+where
+    A: Allocator,
+    S: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate_in(self.route(layout.size()), layout, false)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate_in(self.route(layout.size()), layout, true)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc(self.route(layout.size()))
+            .deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout, false, |alloc| {
+            alloc.grow(ptr, old_layout, new_layout)
+        })
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout, true, |alloc| {
+            alloc.grow_zeroed(ptr, old_layout, new_layout)
+        })
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout, false, |alloc| {
+            alloc.shrink(ptr, old_layout, new_layout)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, ArenaAllocator};
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(size, 1).unwrap()
+    }
+
+    #[test]
+    fn bucketizer_routes_boundary_sizes() {
+        let stacks = [(); 3].map(|()| Stack::<4096>::new());
+        let overflow = Stack::<4096>::new();
+        let buckets = Bucketizer::new(
+            [(32, &stacks[0]), (128, &stacks[1]), (1024, &stacks[2])],
+            &overflow,
+        );
+        for (size, idx) in [
+            (0, 0),
+            (1, 0),
+            (32, 0),
+            (33, 1),
+            (128, 1),
+            (129, 2),
+            (1024, 2),
+        ] {
+            let block = buckets.allocate(layout(size)).unwrap();
+            assert_eq!(block.len(), size);
+            if size != 0 {
+                assert!(stacks[idx].contains(block.cast(), layout(size)));
+            }
+        }
+        let block = buckets.allocate_zeroed(layout(1025)).unwrap();
+        assert!(overflow.contains(block.cast(), layout(1025)));
+        assert_eq!(
+            [buckets.hits(0), buckets.hits(1), buckets.hits(2)],
+            [3, 2, 2]
+        );
+        assert_eq!(buckets.overflow_hits(), 1);
+
+        unsafe { buckets.deallocate(block.cast(), layout(1025)) };
+        assert_eq!(overflow.used(), 0);
+        assert_eq!(buckets.bound(1), 128);
+    }
+
+    #[test]
+    fn bucketizer_moves_blocks_across_buckets() {
+        let (small, large) = (Stack::<64>::new(), Stack::<256>::new());
+        let buckets = Bucketizer::new([(16, &small)], &large);
+        let mut v = allocator_api2::vec![in &buckets; 1u8; 16];
+        assert_eq!((small.used(), large.used()), (16, 0));
+
+        // Growing within the bucket stays in place.
+        v.truncate(8);
+        v.shrink_to_fit();
+        v.push(2);
+        assert_eq!(small.used(), 16);
+
+        v.extend_from_slice(&[3; 20]);
+        assert_eq!((small.used(), large.used()), (0, v.capacity()));
+        assert_eq!(&v[..10], [1, 1, 1, 1, 1, 1, 1, 1, 2, 3]);
+        assert_eq!(buckets.overflow_hits(), 1);
+
+        v.truncate(4);
+        v.shrink_to_fit();
+        assert_eq!((small.used(), large.used()), (4, 0));
+        assert_eq!(v, [1; 4]);
+        assert_eq!(buckets.hits(0), 2);
+    }
+
+    /// Rounds every size up to a multiple of 64 bytes.
+    struct Rounding(Stack<256>);
+
+    impl Rounding {
+        fn round(layout: Layout) -> Layout {
+            Layout::from_size_align(layout.size().next_multiple_of(64), layout.align()).unwrap()
+        }
+    }
+
+    unsafe impl Allocator for Rounding {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.allocate(Self::round(layout))
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.deallocate(ptr, Self::round(layout))
+        }
+    }
+
+    #[test]
+    fn bucketizer_trims_slack_to_the_bound() {
+        let (small, overflow) = (Rounding(Stack::new()), Stack::<256>::new());
+        let buckets = Bucketizer::new([(32, &small)], &overflow);
+        let block = buckets.allocate(layout(16)).unwrap();
+        assert_eq!(block.len(), 32);
+        assert_eq!(small.0.used(), 64);
+
+        // Freeing with the full returned length must route back to the bucket.
+        unsafe { buckets.deallocate(block.cast(), layout(block.len())) };
+        assert_eq!((small.0.used(), overflow.used()), (0, 0));
+    }
+
+    #[test]
+    #[should_panic = "strictly increasing"]
+    fn bucketizer_rejects_unsorted_bounds() {
+        let stack = Stack::<64>::new();
+        let _ = Bucketizer::new([(64, &stack), (64, &stack)], &stack);
+    }
+}
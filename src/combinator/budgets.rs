@@ -0,0 +1,362 @@
+use alloc_crate::{string::String, vec::Vec};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::RefCell, fmt, ptr::NonNull};
+
+/// An allocator split into named budgets, each capping the bytes allocated through its
+/// [`BudgetHandle`].
+///
+/// Budgets only count the sizes of the layouts allocated through them, not the padding or
+/// metadata that `alloc` may add.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, combinator::Budgets};
+/// use allocator_api2::{alloc::Allocator, vec::Vec};
+/// use std::alloc::Layout;
+///
+/// let budgets = Budgets::new(Stack::<1024>::new());
+/// let audio = budgets.budget("audio", 256);
+/// let ui = budgets.budget("ui", 512);
+///
+/// let samples = Vec::<u8, _>::with_capacity_in(200, audio);
+/// assert!(audio.allocate(Layout::new::<[u8; 100]>()).is_err());
+/// let widgets = Vec::<u8, _>::with_capacity_in(300, ui);
+///
+/// for report in budgets.report() {
+///     println!("{report}");
+/// }
+/// # drop((samples, widgets));
+/// ```
+pub struct Budgets<A> {
+    alloc: A,
+    budgets: RefCell<Vec<Budget>>,
+}
+
+struct Budget {
+    name: String,
+    cap: usize,
+    used: usize,
+    peak: usize,
+}
+
+impl<A> Budgets<A> {
+    #[inline]
+    pub const fn new(alloc: A) -> Self {
+        Self {
+            alloc,
+            budgets: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Creates a budget named `name` that allows at most `cap` bytes to be allocated at once.
+    ///
+    /// The budget lives as long as `self`. Names don't have to be unique.
+    pub fn budget(&self, name: impl Into<String>, cap: usize) -> BudgetHandle<'_, A> {
+        let mut budgets = self.budgets.borrow_mut();
+        budgets.push(Budget {
+            name: name.into(),
+            cap,
+            used: 0,
+            peak: 0,
+        });
+        BudgetHandle {
+            budgets: self,
+            idx: budgets.len() - 1,
+        }
+    }
+
+    /// Returns the usage of every budget, in the order they were created.
+    pub fn report(&self) -> Vec<BudgetReport> {
+        let budgets = self.budgets.borrow();
+        budgets
+            .iter()
+            .map(|budget| BudgetReport {
+                name: budget.name.clone(),
+                cap: budget.cap,
+                used: budget.used,
+                peak: budget.peak,
+            })
+            .collect()
+    }
+}
+
+impl<A> fmt::Debug for Budgets<A>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Budgets")
+            .field("alloc", &self.alloc)
+            .field("budgets", &self.report())
+            .finish()
+    }
+}
+
+/// An allocator that allocates from the allocator of [`Budgets`] within one of its budgets.
+///
+/// Allocations that would exceed the cap of the budget fail. Deallocated bytes return to it.
+///
+/// This `struct` is created by [`budget`](Budgets::budget) method on [`Budgets`].
+pub struct BudgetHandle<'a, A> {
+    budgets: &'a Budgets<A>,
+    idx: usize,
+}
+
+impl<A> BudgetHandle<'_, A> {
+    /// Returns the name of the budget.
+    pub fn name(&self) -> String {
+        self.with(|budget| budget.name.clone())
+    }
+
+    /// Returns the number of bytes the budget allows.
+    #[inline]
+    pub fn cap(&self) -> usize {
+        self.with(|budget| budget.cap)
+    }
+
+    /// Returns the number of bytes currently allocated within the budget.
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.with(|budget| budget.used)
+    }
+
+    /// Returns the highest number of bytes allocated within the budget at once.
+    #[inline]
+    pub fn peak(&self) -> usize {
+        self.with(|budget| budget.peak)
+    }
+
+    /// Returns the number of bytes that can still be allocated within the budget.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.with(|budget| budget.cap - budget.used)
+    }
+
+    #[inline]
+    fn with<R>(&self, f: impl FnOnce(&mut Budget) -> R) -> R {
+        f(&mut self.budgets.budgets.borrow_mut()[self.idx])
+    }
+
+    /// Charges `new_size - old_size` bytes to the budget, or fails if it would exceed the cap.
+    #[inline]
+    fn charge(&self, old_size: usize, new_size: usize) -> Result<(), AllocError> {
+        self.with(|budget| {
+            let used = budget.used - old_size;
+            if new_size > budget.cap - used {
+                return Err(AllocError);
+            }
+            budget.used = used + new_size;
+            Ok(())
+        })
+    }
+
+    /// Charges the budget for a block that will replace one of `old_size` bytes, undoing it if
+    /// `realloc` fails.
+    #[inline]
+    fn charged(
+        &self,
+        old_size: usize,
+        new_size: usize,
+        realloc: impl FnOnce() -> Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.charge(old_size, new_size)?;
+        let result = realloc();
+        self.with(|budget| match result {
+            Ok(_) => budget.peak = budget.peak.max(budget.used),
+            Err(_) => budget.used = budget.used - new_size + old_size,
+        });
+        result
+    }
+}
+
+impl<A> Clone for BudgetHandle<'_, A> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<A> Copy for BudgetHandle<'_, A> {}
+
+impl<A> fmt::Debug for BudgetHandle<'_, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BudgetHandle")
+            .field("name", &self.name())
+            .field("cap", &self.cap())
+            .field("used", &self.used())
+            .field("peak", &self.peak())
+            .finish()
+    }
+}
+
+unsafe impl<A> Allocator for BudgetHandle<'_, A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.charged(0, layout.size(), || self.budgets.alloc.allocate(layout))
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.charged(0, layout.size(), || {
+            self.budgets.alloc.allocate_zeroed(layout)
+        })
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.with(|budget| budget.used -= layout.size());
+        self.budgets.alloc.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.charged(old_layout.size(), new_layout.size(), || {
+            self.budgets.alloc.grow(ptr, old_layout, new_layout)
+        })
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.charged(old_layout.size(), new_layout.size(), || {
+            self.budgets.alloc.grow_zeroed(ptr, old_layout, new_layout)
+        })
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.charged(old_layout.size(), new_layout.size(), || {
+            self.budgets.alloc.shrink(ptr, old_layout, new_layout)
+        })
+    }
+}
+
+/// Usage of a budget, created by [`report`](Budgets::report).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetReport {
+    name: String,
+    cap: usize,
+    used: usize,
+    peak: usize,
+}
+
+impl BudgetReport {
+    /// Returns the name of the budget.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the number of bytes the budget allows.
+    #[inline]
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    /// Returns the number of bytes allocated within the budget.
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Returns the highest number of bytes allocated within the budget at once.
+    #[inline]
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+}
+
+impl fmt::Display for BudgetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} of {} B used, {} B peak",
+            self.name, self.used, self.cap, self.peak
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::Stack;
+
+    fn summary<A>(budgets: &Budgets<A>) -> Vec<(String, usize, usize, usize)> {
+        let report = budgets.report().into_iter();
+        report
+            .map(|r| (r.name().into(), r.cap(), r.used(), r.peak()))
+            .collect()
+    }
+
+    #[test]
+    fn budgets_are_enforced_independently() {
+        let budgets = Budgets::new(Stack::<1024>::new());
+        let audio = budgets.budget("audio", 256);
+        let ui = budgets.budget("ui", 512);
+
+        let a = allocator_api2::vec![in audio; 0u8; 200];
+        let mut b = allocator_api2::vec![in audio; 0u8; 56];
+        assert_eq!(audio.remaining(), 0);
+        assert!(audio.allocate(Layout::new::<u8>()).is_err());
+        assert!(b.try_reserve(1).is_err());
+
+        // The other budget is unaffected.
+        let c = allocator_api2::vec![in ui; 0u8; 500];
+        assert_eq!((ui.used(), ui.remaining()), (500, 12));
+        assert_eq!(budgets.inner().used(), 756);
+
+        drop(b);
+        assert_eq!(
+            summary(&budgets),
+            [
+                ("audio".into(), 256, 200, 256),
+                ("ui".into(), 512, 500, 500),
+            ]
+        );
+        assert_eq!(
+            budgets.report()[0].to_string(),
+            "audio: 200 of 256 B used, 256 B peak"
+        );
+        drop((a, c));
+        assert_eq!(audio.used() + ui.used(), 0);
+        assert_eq!((audio.peak(), ui.peak()), (256, 500));
+    }
+
+    #[test]
+    fn failed_allocation_is_not_charged() {
+        let budgets = Budgets::new(Stack::<64>::new());
+        let big = budgets.budget("big", 1024);
+        assert!(big.allocate(Layout::new::<[u8; 128]>()).is_err());
+        assert_eq!((big.used(), big.peak()), (0, 0));
+
+        let mut v = allocator_api2::vec![in big; 0u8; 32];
+        assert!(v.try_reserve_exact(64).is_err());
+        assert_eq!(big.used(), 32);
+        v.truncate(8);
+        v.shrink_to_fit();
+        assert_eq!((big.used(), big.peak(), big.name()), (8, 32, "big".into()));
+    }
+}
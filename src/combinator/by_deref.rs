@@ -0,0 +1,291 @@
+use crate::{
+    AllocatorId, ArenaAllocator, DeallocByPtr, GoodSizeAllocator, InPlaceGrow, OwnsAllocations,
+    ProbeAllocator, ResetAllocator, SameAllocator, TrimAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{
+    alloc::Layout,
+    ops::{Deref, DerefMut, Range},
+    ptr::NonNull,
+};
+
+/// An allocator that forwards everything to the allocator behind the pointer `ptr`,
+/// e.g. a `Box`, an `Rc`, an `Arc` or a `&mut` reference.
+///
+/// The allocator traits can't be implemented for these pointers directly: allocator-api2 only
+/// implements `Allocator` for `&A`, and coherence rules forbid this crate from adding the rest.
+/// Wrapping the pointer instead lets, e.g., an arena owned by an `Rc` be shared among several
+/// collections, each holding a clone of the `Rc`.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, combinator::ByDeref};
+/// use allocator_api2::vec::Vec;
+/// use std::rc::Rc;
+///
+/// let stack = Rc::new(Stack::<256>::new());
+/// let mut a = Vec::with_capacity_in(1, ByDeref::new(stack.clone()));
+/// let mut b = Vec::with_capacity_in(1, ByDeref::new(stack.clone()));
+/// a.push(1u8);
+/// b.push(2u8);
+/// assert_eq!(stack.used(), 2);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ByDeref<P> {
+    ptr: P,
+}
+
+impl<P> ByDeref<P> {
+    #[inline]
+    pub const fn new(ptr: P) -> Self {
+        Self { ptr }
+    }
+
+    /// Returns a reference to the underlying pointer.
+    #[inline]
+    pub fn inner(&self) -> &P {
+        &self.ptr
+    }
+
+    /// Returns a mutable reference to the underlying pointer.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.ptr
+    }
+
+    /// Consumes the combinator, returning the underlying pointer.
+    ///
+    /// Memory allocated through the combinator stays allocated in the allocator behind the pointer.
+    #[inline]
+    pub fn into_inner(self) -> P {
+        self.ptr
+    }
+}
+
+unsafe impl<P> Allocator for ByDeref<P>
+where
+    P: Deref,
+    P::Target: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.ptr.allocate(layout)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.ptr.allocate_zeroed(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.ptr.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.ptr.grow(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.ptr.grow_zeroed(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.ptr.shrink(ptr, old_layout, new_layout)
+    }
+}
+
+impl<P> ArenaAllocator for ByDeref<P>
+where
+    P: Deref,
+    P::Target: ArenaAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.ptr.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn arena_range(&self) -> Option<Range<usize>> {
+        self.ptr.arena_range()
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.ptr.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.ptr.allocated_bytes()
+    }
+}
+
+impl<P> ProbeAllocator for ByDeref<P>
+where
+    P: Deref,
+    P::Target: ProbeAllocator,
+{
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        self.ptr.can_allocate(layout)
+    }
+}
+
+impl<P> TrimAllocator for ByDeref<P>
+where
+    P: Deref,
+    P::Target: TrimAllocator,
+{
+    #[inline]
+    fn trim(&self) -> usize {
+        self.ptr.trim()
+    }
+}
+
+impl<P> GoodSizeAllocator for ByDeref<P>
+where
+    P: Deref,
+    P::Target: GoodSizeAllocator,
+{
+    #[inline]
+    fn preferred_layout(&self, layout: Layout) -> Layout {
+        self.ptr.preferred_layout(layout)
+    }
+}
+
+impl<P> InPlaceGrow for ByDeref<P>
+where
+    P: Deref,
+    P::Target: InPlaceGrow,
+{
+    #[inline]
+    unsafe fn try_grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.ptr.try_grow_in_place(ptr, old_layout, new_layout)
+    }
+}
+
+impl<P> SameAllocator for ByDeref<P>
+where
+    P: Deref,
+    P::Target: SameAllocator,
+{
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        self.ptr.allocator_id()
+    }
+}
+
+impl<P> OwnsAllocations for ByDeref<P>
+where
+    P: Deref,
+    P::Target: OwnsAllocations,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        self.ptr.is_live(ptr)
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.ptr.live_count()
+    }
+}
+
+impl<P> ResetAllocator for ByDeref<P>
+where
+    P: DerefMut,
+    P::Target: ResetAllocator,
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.ptr.reset()
+    }
+}
+
+impl<P> DeallocByPtr for ByDeref<P>
+where
+    P: Deref,
+    P::Target: DeallocByPtr,
+{
+    #[inline]
+    unsafe fn deallocate_ptr(&self, ptr: NonNull<u8>) {
+        self.ptr.deallocate_ptr(ptr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _};
+    use allocator_api2::{boxed::Box, vec::Vec};
+    use std::{alloc::System, rc::Rc, sync::Arc};
+
+    fn contains<T>(alloc: &impl ArenaAllocator, value: &T) -> bool {
+        alloc.contains(NonNull::from(value).cast(), Layout::new::<T>())
+    }
+
+    #[test]
+    fn rc_stack_is_shared_by_vecs_and_fallback() {
+        let stack = Rc::new(Stack::<256>::new());
+        let mut a = Vec::new_in(ByDeref::new(stack.clone()));
+        let mut b = Vec::new_in(ByDeref::new(stack.clone()));
+        let reserve = Stack::<1024>::new();
+        let chain = ByDeref::new(stack.clone()).fallback(&reserve);
+        a.extend(0..16u32);
+        b.extend(0..16u32);
+        let small = Box::new_in(7u64, &chain);
+        let large = Box::new_in([0u8; 256], &chain);
+
+        let shared = ByDeref::new(stack.clone());
+        assert!(a.iter().chain(&b).all(|v| contains(&shared, v)));
+        assert!(contains(&shared, &*small));
+        assert!(contains(&chain, &*small));
+        assert!(!contains(&shared, &*large));
+        assert!(contains(&reserve, &*large));
+        assert!(contains(&chain, &*large));
+        assert_eq!(shared.allocated_bytes(), Some(stack.used()));
+    }
+
+    #[test]
+    fn deref_pointers_forward_to_the_arena() {
+        let mut owned = Stack::<64>::new();
+        let by_mut = ByDeref::new(&mut owned);
+        let ptr = by_mut.allocate(Layout::new::<u64>()).unwrap().cast::<u64>();
+        assert!(by_mut.contains(ptr.cast(), Layout::new::<u64>()));
+        assert!(by_mut.can_allocate(Layout::new::<[u8; 56]>()));
+        unsafe { by_mut.deallocate(ptr.cast(), Layout::new::<u64>()) };
+        assert_eq!(owned.used(), 0);
+
+        let boxed = ByDeref::new(std::boxed::Box::new(Stack::<64>::new()));
+        let value = Box::new_in(1u32, &boxed);
+        assert!(contains(&boxed, &*value));
+
+        let arc = ByDeref::new(Arc::new(System.with_header()));
+        let ptr = arc.allocate(Layout::new::<u32>()).unwrap();
+        unsafe { arc.deallocate_ptr(ptr.cast()) };
+    }
+}
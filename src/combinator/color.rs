@@ -0,0 +1,318 @@
+use crate::{
+    util::{align_up, layout_with_prefix},
+    ArenaAllocator, ResetAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::Cell, mem::align_of, ptr, ptr::NonNull};
+
+/// An allocator that shifts each allocation made with `alloc` by a rotating multiple of `stride`,
+/// so that same-sized buffers don't all map to the same cache sets.
+///
+/// The `n`-th allocation is shifted by `(n % colors) * stride` bytes, rounded up to the alignment
+/// of the block. Each allocation is over-allocated by its shift plus a header storing it, which
+/// `deallocate` and reallocations use to recover the block of `alloc`. Choosing `stride` as a
+/// multiple of the alignment of the allocations keeps the shifts exact.
+///
+/// This `struct` is created by [`color`](crate::Allocandrescu::color) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Debug, Clone, Default)]
+pub struct Color<A> {
+    alloc: A,
+    colors: usize,
+    stride: usize,
+    next: Cell<usize>,
+}
+
+impl<A> Color<A> {
+    /// # Panics
+    /// Panics if `colors` is zero.
+    #[inline]
+    #[track_caller]
+    pub const fn new(alloc: A, colors: usize, stride: usize) -> Self {
+        assert!(colors > 0, "there must be at least one color");
+        Self {
+            alloc,
+            colors,
+            stride,
+            next: Cell::new(0),
+        }
+    }
+
+    /// Returns the number of colors the allocations rotate through.
+    #[inline]
+    pub fn colors(&self) -> usize {
+        self.colors
+    }
+
+    /// Returns the distance between consecutive colors.
+    #[inline]
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    /// Blocks handed out by the combinator are shifted into larger blocks of the returned allocator,
+    /// so they can't be deallocated with it directly.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+
+    /// Returns the offset of the payload in the block of the next allocation, and advances the
+    /// color.
+    #[inline]
+    fn next_offset(&self, layout: Layout) -> Result<usize, AllocError> {
+        let color = self.next.get();
+        self.next.set((color + 1) % self.colors);
+        let (block_layout, min_offset) =
+            layout_with_prefix(Layout::new::<usize>(), layout).ok_or(AllocError)?;
+        let shift = color.checked_mul(self.stride).ok_or(AllocError)?;
+        // Rounding to the block alignment keeps both the payload and the header aligned.
+        let shift = align_up(shift, block_layout.align()).ok_or(AllocError)?;
+        min_offset.checked_add(shift).ok_or(AllocError)
+    }
+
+    /// Returns the layout of the block whose payload is at `offset`.
+    #[inline]
+    fn block_layout(layout: Layout, offset: usize) -> Result<Layout, AllocError> {
+        let size = offset.checked_add(layout.size()).ok_or(AllocError)?;
+        let align = layout.align().max(align_of::<usize>());
+        Layout::from_size_align(size, align).map_err(|_| AllocError)
+    }
+
+    #[inline]
+    unsafe fn header(ptr: NonNull<u8>) -> NonNull<usize> {
+        ptr.cast::<usize>().sub(1)
+    }
+
+    /// Writes the offset in front of the payload and returns the payload.
+    #[inline]
+    unsafe fn init(block: NonNull<[u8]>, offset: usize, layout: Layout) -> NonNull<[u8]> {
+        let payload = block.cast::<u8>().add(offset);
+        Self::header(payload).write(offset);
+        NonNull::slice_from_raw_parts(payload, layout.size())
+    }
+}
+
+impl<A> Color<A>
+where
+    A: Allocator,
+{
+    fn alloc(&self, layout: Layout, zeroed: bool) -> Result<NonNull<[u8]>, AllocError> {
+        let offset = self.next_offset(layout)?;
+        let block_layout = Self::block_layout(layout, offset)?;
+        let block = if zeroed {
+            self.alloc.allocate_zeroed(block_layout)
+        } else {
+            self.alloc.allocate(block_layout)
+        }?;
+        Ok(unsafe { Self::init(block, offset, layout) })
+    }
+
+    /// Reallocates the whole block with `op` if the payload offset suits the new layout.
+    /// Otherwise, moves the payload to a new allocation.
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+        op: impl FnOnce(NonNull<u8>, Layout, Layout) -> Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let offset = Self::header(ptr).read();
+        if offset % new_layout.align() == 0 {
+            let old_block_layout = Self::block_layout(old_layout, offset)?;
+            let new_block_layout = Self::block_layout(new_layout, offset)?;
+            let new_block = op(ptr.sub(offset), old_block_layout, new_block_layout)?;
+            return Ok(Self::init(new_block, offset, new_layout));
+        }
+
+        let new_ptr = self.alloc(new_layout, zeroed)?;
+        let len = old_layout.size().min(new_layout.size());
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.cast::<u8>().as_ptr(), len);
+        self.deallocate(ptr, old_layout);
+        Ok(new_ptr)
+    }
+}
+
+unsafe impl<A> Allocator for Color<A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, false)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, true)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let offset = Self::header(ptr).read();
+        let block_layout = Self::block_layout(layout, offset).unwrap_unchecked();
+        self.alloc.deallocate(ptr.sub(offset), block_layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout, false, |block, old, new| {
+            self.alloc.grow(block, old, new)
+        })
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout, true, |block, old, new| {
+            self.alloc.grow_zeroed(block, old, new)
+        })
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout, false, |block, old, new| {
+            self.alloc.shrink(block, old, new)
+        })
+    }
+}
+
+impl<A> ResetAllocator for Color<A>
+where
+    A: ResetAllocator,
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.alloc.reset();
+        self.next.set(0);
+    }
+}
+
+impl<A> ArenaAllocator for Color<A>
+where
+    A: ArenaAllocator,
+{
+    /// Payloads lie within the blocks of `alloc`, so containment is delegated to it.
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Allocandrescu as _;
+    use allocator_api2::{alloc::Global, vec::Vec};
+    use core::cell::RefCell;
+
+    /// Records the blocks of `Global` it hands out and checks each one is freed as allocated.
+    #[derive(Default)]
+    struct Recording {
+        live: RefCell<Vec<(usize, Layout)>>,
+    }
+
+    unsafe impl Allocator for Recording {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            let block = Global.allocate(layout)?;
+            let addr = block.cast::<u8>().as_ptr() as usize;
+            self.live.borrow_mut().push((addr, layout));
+            Ok(block)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            let mut live = self.live.borrow_mut();
+            let idx = live
+                .iter()
+                .position(|&block| block == (ptr.as_ptr() as usize, layout))
+                .expect("deallocated block wasn't allocated with this layout");
+            live.swap_remove(idx);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn color_rotates_offsets() {
+        let inner = Recording::default();
+        // Page-aligned blocks make the offsets visible in the addresses.
+        let alloc = (&inner).min_align::<4096>().color(4, 64);
+        let layout = Layout::from_size_align(4096, 64).unwrap();
+        let blocks: Vec<_> = (0..6)
+            .map(|_| alloc.allocate(layout).unwrap().cast::<u8>())
+            .collect();
+        let colors: Vec<_> = blocks
+            .iter()
+            .map(|ptr| ptr.as_ptr() as usize % 4096)
+            .collect();
+        // The header takes the first 64 bytes.
+        assert_eq!(colors, [64, 128, 192, 256, 64, 128]);
+
+        for ptr in blocks {
+            unsafe { alloc.deallocate(ptr, layout) };
+        }
+        assert!(inner.live.borrow().is_empty());
+    }
+
+    #[test]
+    fn color_round_trips_reallocations() {
+        let inner = Recording::default();
+        let alloc = (&inner).color(3, 16);
+        let mut v = Vec::<u8, _>::new_in(&alloc);
+        let mut w = allocator_api2::vec![in &alloc; 7u64; 2];
+        for i in 0..100 {
+            v.push(i);
+        }
+        w.truncate(1);
+        w.shrink_to_fit();
+        // Raising the alignment past the offset of the block moves it.
+        let raised = Layout::from_size_align(8, 32).unwrap();
+        let ptr = NonNull::new(w.as_mut_ptr()).unwrap().cast::<u8>();
+        let moved = unsafe { alloc.grow(ptr, Layout::new::<u64>(), raised).unwrap() };
+        assert_eq!(moved.cast::<u8>().as_ptr() as usize % 32, 0);
+        assert_eq!(unsafe { moved.cast::<u64>().read() }, 7);
+        core::mem::forget(w);
+
+        assert_eq!(inner.live.borrow().len(), 2);
+        assert!(v.iter().copied().eq(0..100));
+        drop(v);
+        unsafe { alloc.deallocate(moved.cast(), raised) };
+        assert!(inner.live.borrow().is_empty());
+    }
+}
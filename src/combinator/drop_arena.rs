@@ -0,0 +1,373 @@
+use crate::{ArenaAllocator, Error, Operation, ResetAllocator};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::Cell, fmt, ptr::NonNull};
+
+/// An arena that runs the destructors of values allocated with [`alloc_value`](DropArena::alloc_value)
+/// when it is reset or dropped.
+///
+/// Each value is registered in a list allocated from the same arena, which costs four words per
+/// value. The destructors run in the reverse order of allocation, and each value is deallocated
+/// right after its destructor, so that arenas like [`Stack`](crate::alloc::Stack) see no live
+/// allocations when they are reset. Memory allocated through the [`Allocator`] interface or the other
+/// [`ArenaExt`](crate::ArenaExt) methods is not registered, so no destructors run for it, and
+/// [`reset`](DropArena::reset) discards it along with the rest.
+///
+/// The arena is borrowed mutably, so that it can't move, taking the registered values with it,
+/// nor be reset before the destructors run.
+///
+/// This `struct` is created by [`drop_arena`](crate::Allocandrescu::drop_arena) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+pub struct DropArena<'a, A: Allocator> {
+    alloc: &'a mut A,
+    /// The most recently registered value.
+    head: Cell<Option<NonNull<Entry>>>,
+}
+
+struct Entry {
+    value: NonNull<u8>,
+    layout: Layout,
+    drop: unsafe fn(NonNull<u8>),
+    next: Option<NonNull<Entry>>,
+}
+
+impl<A> fmt::Debug for DropArena<'_, A>
+where
+    A: Allocator + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropArena")
+            .field("alloc", &self.alloc)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, A> DropArena<'a, A>
+where
+    A: Allocator,
+{
+    #[inline]
+    pub fn new(alloc: &'a mut A) -> Self {
+        Self {
+            alloc,
+            head: Cell::new(None),
+        }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        self.alloc
+    }
+
+    /// Drops and deallocates all the registered values, most recent first.
+    ///
+    /// If a destructor panics, the remaining ones still run before the panic is propagated.
+    fn run_drops(&mut self) {
+        struct Guard<'a, A: Allocator> {
+            alloc: &'a A,
+            head: Option<NonNull<Entry>>,
+        }
+
+        impl<A: Allocator> Guard<'_, A> {
+            fn run(&mut self) {
+                while let Some(entry) = self.head {
+                    let Entry {
+                        value,
+                        layout,
+                        drop,
+                        next,
+                    } = unsafe { entry.as_ptr().read() };
+                    self.head = next;
+                    unsafe {
+                        drop(value);
+                        self.alloc.deallocate(value, layout);
+                        self.alloc.deallocate(entry.cast(), Layout::new::<Entry>());
+                    }
+                }
+            }
+        }
+
+        impl<A: Allocator> Drop for Guard<'_, A> {
+            // Continues with the rest of the list when a destructor panics.
+            fn drop(&mut self) {
+                self.run();
+            }
+        }
+
+        let head = self.head.take();
+        Guard {
+            alloc: &*self.alloc,
+            head,
+        }
+        .run();
+    }
+
+    /// Moves `value` into the arena, registering it to be dropped when the arena is reset or dropped.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use std::rc::Rc;
+    ///
+    /// let shared = Rc::new(());
+    /// let mut stack = Stack::<256>::new();
+    /// let arena = stack.drop_arena();
+    /// arena.alloc_value(shared.clone()).unwrap();
+    /// arena.alloc_value(shared.clone()).unwrap();
+    /// assert_eq!(Rc::strong_count(&shared), 3);
+    /// drop(arena);
+    /// assert_eq!(Rc::strong_count(&shared), 1);
+    /// ```
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_value<T>(&self, value: T) -> Result<&mut T, Error> {
+        let entry_layout = Layout::new::<Entry>();
+        let entry = self
+            .alloc
+            .allocate(entry_layout)
+            .map_err(Error::map(Operation::Allocate, entry_layout))?
+            .cast::<Entry>();
+        let layout = Layout::new::<T>();
+        let ptr = match self.alloc.allocate(layout) {
+            Ok(ptr) => ptr.cast::<T>(),
+            Err(_) => {
+                unsafe { self.alloc.deallocate(entry.cast(), entry_layout) };
+                return Err(Error::new(Operation::Allocate, Some(layout)));
+            }
+        };
+        unsafe {
+            ptr.as_ptr().write(value);
+            entry.as_ptr().write(Entry {
+                value: ptr.cast(),
+                layout,
+                drop: drop_value::<T>,
+                next: self.head.get(),
+            });
+        }
+        self.head.set(Some(entry));
+        Ok(unsafe { &mut *ptr.as_ptr() })
+    }
+}
+
+impl<A> DropArena<'_, A>
+where
+    A: Allocator + ResetAllocator,
+{
+    /// Runs the destructors of the registered values, most recent first, and resets the arena.
+    ///
+    /// If a destructor panics, the remaining ones still run, but the arena is not reset.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.run_drops();
+        self.alloc.reset();
+    }
+}
+
+impl<A> Drop for DropArena<'_, A>
+where
+    A: Allocator,
+{
+    fn drop(&mut self) {
+        self.run_drops();
+    }
+}
+
+unsafe fn drop_value<T>(value: NonNull<u8>) {
+    value.cast::<T>().as_ptr().drop_in_place()
+}
+
+unsafe impl<A> Allocator for DropArena<'_, A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.allocate(layout)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.allocate_zeroed(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.grow(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.grow_zeroed(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.shrink(ptr, old_layout, new_layout)
+    }
+}
+
+impl<A> ArenaAllocator for DropArena<'_, A>
+where
+    A: ArenaAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    /// Includes the registration entries.
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    /// Includes the registration entries.
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+impl<A> ResetAllocator for DropArena<'_, A>
+where
+    A: Allocator + ResetAllocator,
+{
+    /// See [`DropArena::reset`].
+    #[inline]
+    fn reset(&mut self) {
+        DropArena::reset(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _, ArenaExt as _};
+    use core::{cell::RefCell, mem::size_of};
+    use std::{
+        panic::{catch_unwind, AssertUnwindSafe},
+        vec::Vec,
+    };
+
+    /// Records its id in `log` when dropped, and panics afterwards if `panics` is set.
+    struct Logged<'a> {
+        id: u32,
+        log: &'a RefCell<Vec<u32>>,
+        panics: bool,
+    }
+
+    impl Drop for Logged<'_> {
+        fn drop(&mut self) {
+            self.log.borrow_mut().push(self.id);
+            assert!(!self.panics, "destructor of {} failed", self.id);
+        }
+    }
+
+    fn logged(log: &RefCell<Vec<u32>>, id: u32) -> Logged<'_> {
+        Logged {
+            id,
+            log,
+            panics: false,
+        }
+    }
+
+    #[test]
+    fn drop_arena_drops_in_reverse_order_once() {
+        let log = RefCell::new(Vec::new());
+        let mut stack = Stack::<1024>::new();
+        let mut arena = stack.drop_arena();
+        for id in 0..3 {
+            arena.alloc_value(logged(&log, id)).unwrap();
+        }
+        let plain = arena.alloc_value(7u64).unwrap();
+        assert_eq!(*plain, 7);
+
+        arena.reset();
+        assert_eq!(*log.borrow(), [2, 1, 0]);
+        assert_eq!(arena.inner().used(), 0);
+
+        arena.alloc_value(logged(&log, 3)).unwrap();
+        drop(arena);
+        assert_eq!(*log.borrow(), [2, 1, 0, 3]);
+    }
+
+    #[test]
+    fn drop_arena_resets_with_unregistered_allocations() {
+        let log = RefCell::new(Vec::new());
+        let mut stack = Stack::<1024>::new();
+        let mut arena = stack.drop_arena();
+        arena.alloc_value(logged(&log, 0)).unwrap();
+        let name = arena.alloc_str("unregistered").unwrap();
+        arena.alloc_value(logged(&log, 1)).unwrap();
+        assert_eq!(name, "unregistered");
+
+        // The string has no destructor to run and leaves a live block in the stack.
+        arena.reset();
+        assert_eq!(*log.borrow(), [1, 0]);
+        assert_eq!(arena.inner().used(), 0);
+    }
+
+    #[test]
+    fn drop_arena_deallocates_values_and_entries() {
+        let mut stack = Stack::<64>::new();
+        let log = RefCell::new(Vec::new());
+        let arena = stack.drop_arena();
+        let value = arena.alloc_value(logged(&log, 0)).unwrap();
+        assert!(arena.contains(NonNull::from(&*value).cast(), Layout::new::<Logged>()));
+        assert_eq!(
+            arena.allocated_bytes(),
+            Some(size_of::<Entry>() + size_of::<Logged>())
+        );
+
+        // No room is left for the entry, so the value is dropped right away.
+        assert!(arena.alloc_value(logged(&log, 1)).is_err());
+        assert_eq!(*log.borrow(), [1]);
+
+        drop(arena);
+        assert_eq!(*log.borrow(), [1, 0]);
+        assert_eq!(stack.used(), 0);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn drop_arena_runs_remaining_destructors_after_panic() {
+        let log = RefCell::new(Vec::new());
+        let mut stack = Stack::<1024>::new();
+        let mut arena = stack.drop_arena();
+        arena.alloc_value(logged(&log, 0)).unwrap();
+        arena
+            .alloc_value(Logged {
+                id: 1,
+                log: &log,
+                panics: true,
+            })
+            .unwrap();
+        arena.alloc_value(logged(&log, 2)).unwrap();
+
+        let result = catch_unwind(AssertUnwindSafe(|| arena.reset()));
+        assert!(result.is_err());
+        assert_eq!(*log.borrow(), [2, 1, 0]);
+
+        drop(arena);
+        assert_eq!(*log.borrow(), [2, 1, 0]);
+    }
+}
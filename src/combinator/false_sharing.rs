@@ -0,0 +1,371 @@
+use crate::ArenaAllocator;
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, fmt, ptr::NonNull};
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    thread::{self, ThreadId},
+    vec::Vec,
+};
+
+/// The cache line size assumed by [`FalseSharing`].
+pub const CACHE_LINE: usize = 64;
+
+/// An allocator that forwards all operations to `alloc` and reports live allocations made by
+/// different threads that share a [cache line](CACHE_LINE).
+///
+/// Each live allocation is recorded with the thread that made it. When a new or reallocated block
+/// lands on a line occupied by a block of another thread, `f` is called with both of them, after
+/// the block is handed out. By default, it panics. Memory accesses aren't observed, so this only
+/// detects co-location, which is where most false sharing comes from.
+/// Zero-sized allocations are not recorded.
+///
+/// This `struct` is created by [`detect_false_sharing`](crate::Allocandrescu::detect_false_sharing) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+pub struct FalseSharing<A, F = fn(&SharedLine)> {
+    alloc: A,
+    f: F,
+    /// Live blocks by their start address.
+    live: Mutex<BTreeMap<usize, Block>>,
+}
+
+/// A live block recorded by [`FalseSharing`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Block {
+    pub addr: usize,
+    pub layout: Layout,
+    pub thread: ThreadId,
+}
+
+/// A cache line occupied by blocks of two different threads, reported by [`FalseSharing`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharedLine {
+    /// The address of the line.
+    pub line: usize,
+    /// The block already occupying the line.
+    pub existing: Block,
+    /// The block that was just allocated.
+    pub new: Block,
+}
+
+impl fmt::Display for SharedLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            line,
+            existing,
+            new,
+        } = self;
+        write!(
+            f,
+            "cache line {line:#x} is shared by {} bytes at {:#x} allocated by {:?} and {} bytes at {:#x} allocated by {:?}",
+            existing.layout.size(),
+            existing.addr,
+            existing.thread,
+            new.layout.size(),
+            new.addr,
+            new.thread,
+        )
+    }
+}
+
+fn panic_on_shared_line(shared: &SharedLine) {
+    panic!("false sharing detected: {shared}")
+}
+
+impl<A> FalseSharing<A> {
+    /// Creates a detector that panics on the first shared line.
+    #[inline]
+    pub fn new(alloc: A) -> Self {
+        Self::with_handler(alloc, panic_on_shared_line)
+    }
+}
+
+impl<A, F> FalseSharing<A, F> {
+    /// Creates a detector that calls `f` with each shared line.
+    #[inline]
+    pub fn with_handler(alloc: A, f: F) -> Self {
+        Self {
+            alloc,
+            f,
+            live: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the number of live allocations.
+    #[inline]
+    pub fn live_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+
+    #[inline]
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<usize, Block>> {
+        // A panicking handler is called without the lock, so the map is never left inconsistent.
+        self.live
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Forgets the block at `ptr`.
+    #[inline]
+    fn forget(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            self.lock().remove(&(ptr.as_ptr() as usize));
+        }
+    }
+}
+
+impl<A, F> FalseSharing<A, F>
+where
+    F: Fn(&SharedLine),
+{
+    /// Records the block and reports the lines it shares with blocks of other threads.
+    fn record(&self, block: NonNull<[u8]>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        let new = Block {
+            addr: block.cast::<u8>().as_ptr() as usize,
+            layout,
+            thread: thread::current().id(),
+        };
+        let first_line = new.addr / CACHE_LINE * CACHE_LINE;
+        let end = new.addr + layout.size();
+        let mut shared = Vec::new();
+        {
+            let mut live = self.lock();
+            // Blocks don't overlap, so at most the one before `new` starts before its first line.
+            let before = live.range(..first_line).next_back().map(|(_, block)| block);
+            let within = live.range(first_line..end.next_multiple_of(CACHE_LINE));
+            for existing in before.into_iter().chain(within.map(|(_, block)| block)) {
+                if existing.thread == new.thread {
+                    continue;
+                }
+                let existing_end = existing.addr + existing.layout.size();
+                let lo = existing.addr.max(first_line) / CACHE_LINE;
+                let hi = existing_end.min(end).div_ceil(CACHE_LINE);
+                shared.extend((lo..hi).map(|line| SharedLine {
+                    line: line * CACHE_LINE,
+                    existing: *existing,
+                    new,
+                }));
+            }
+            live.insert(new.addr, new);
+        }
+        for shared in &shared {
+            (self.f)(shared);
+        }
+    }
+}
+
+impl<A, F> fmt::Debug for FalseSharing<A, F>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FalseSharing")
+            .field("alloc", &self.alloc)
+            .field("live", &self.live_count())
+            .finish_non_exhaustive()
+    }
+}
+
+unsafe impl<A, F> Allocator for FalseSharing<A, F>
+where
+    A: Allocator,
+    F: Fn(&SharedLine),
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc.allocate(layout)?;
+        self.record(block, layout);
+        Ok(block)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc.allocate_zeroed(layout)?;
+        self.record(block, layout);
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.forget(ptr, layout);
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc.grow(ptr, old_layout, new_layout)?;
+        self.forget(ptr, old_layout);
+        self.record(block, new_layout);
+        Ok(block)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc.grow_zeroed(ptr, old_layout, new_layout)?;
+        self.forget(ptr, old_layout);
+        self.record(block, new_layout);
+        Ok(block)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc.shrink(ptr, old_layout, new_layout)?;
+        self.forget(ptr, old_layout);
+        self.record(block, new_layout);
+        Ok(block)
+    }
+}
+
+impl<A, F> ArenaAllocator for FalseSharing<A, F>
+where
+    A: ArenaAllocator,
+    F: Fn(&SharedLine),
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _};
+
+    /// A stack shared between threads behind a lock.
+    #[derive(Default)]
+    struct Locked(Mutex<Stack<1024, 64>>);
+
+    unsafe impl Allocator for Locked {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.lock().unwrap().allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.lock().unwrap().deallocate(ptr, layout)
+        }
+    }
+
+    fn allocate_on_two_threads<A: Allocator + Sync>(alloc: &A) -> [NonNull<u8>; 2] {
+        // Pointers aren't `Send`, so the first block is sent across wrapped.
+        struct Block(NonNull<u8>);
+        unsafe impl Send for Block {}
+
+        let layout = Layout::new::<u64>();
+        let first = thread::scope(|scope| {
+            let first = scope.spawn(|| Block(alloc.allocate(layout).unwrap().cast()));
+            first.join().unwrap()
+        });
+        let second = alloc.allocate(layout).unwrap().cast::<u8>();
+        [first.0, second]
+    }
+
+    #[test]
+    fn false_sharing_reports_cross_thread_neighbours() {
+        let reports = Mutex::new(Vec::new());
+        let alloc = FalseSharing::with_handler(Locked::default(), |shared: &SharedLine| {
+            reports.lock().unwrap().push(*shared)
+        });
+        let [first, second] = allocate_on_two_threads(&alloc);
+
+        let reports = reports.lock().unwrap().clone();
+        assert_eq!(reports.len(), 1);
+        let SharedLine {
+            line,
+            existing,
+            new,
+        } = reports[0];
+        assert_eq!(
+            (existing.addr, new.addr),
+            (first.as_ptr() as usize, second.as_ptr() as usize)
+        );
+        assert_eq!(line, existing.addr);
+        assert_ne!(existing.thread, new.thread);
+        assert_eq!(new.thread, thread::current().id());
+
+        unsafe {
+            alloc.deallocate(second, Layout::new::<u64>());
+            alloc.deallocate(first, Layout::new::<u64>());
+        }
+        assert_eq!(alloc.live_count(), 0);
+    }
+
+    #[test]
+    fn false_sharing_ignores_padded_and_same_thread_blocks() {
+        let fired = Mutex::new(0);
+        let locked = Locked::default();
+        let alloc =
+            FalseSharing::with_handler((&locked).min_align::<CACHE_LINE>(), |_: &SharedLine| {
+                *fired.lock().unwrap() += 1
+            });
+        let [first, second] = allocate_on_two_threads(&alloc);
+        assert_ne!(
+            first.as_ptr() as usize / CACHE_LINE,
+            second.as_ptr() as usize / CACHE_LINE
+        );
+
+        // Neighbours from the same thread are fine.
+        let alloc =
+            FalseSharing::with_handler(&locked, |_: &SharedLine| *fired.lock().unwrap() += 1);
+        let a = alloc.allocate(Layout::new::<u8>()).unwrap();
+        let b = alloc.allocate(Layout::new::<u8>()).unwrap();
+        assert_eq!(*fired.lock().unwrap(), 0);
+        assert_eq!(alloc.live_count(), 2);
+        unsafe {
+            alloc.deallocate(b.cast(), Layout::new::<u8>());
+            alloc.deallocate(a.cast(), Layout::new::<u8>());
+        }
+    }
+
+    #[test]
+    #[should_panic = "false sharing detected"]
+    fn false_sharing_panics_by_default() {
+        let alloc = Locked::default().detect_false_sharing();
+        allocate_on_two_threads(&alloc);
+    }
+}
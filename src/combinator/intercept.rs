@@ -0,0 +1,253 @@
+use crate::{ArenaAllocator, ResetAllocator};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, fmt, ops::ControlFlow, ptr, ptr::NonNull};
+
+/// An allocator that passes each block allocated by `alloc` to a hook, which can veto it.
+///
+/// A vetoed block is deallocated right away and the caller sees [`AllocError`]. Failed allocations
+/// aren't passed to the hook.
+///
+/// Growing is done by allocating a new block and moving the old one only once the hook accepted
+/// it, so a vetoed grow leaves the old block intact. This means `alloc` never grows in place.
+/// Shrinking isn't intercepted.
+///
+/// This `struct` is created by [`intercept`](crate::Allocandrescu::intercept) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Clone, Copy, Default)]
+pub struct Intercept<A, F> {
+    alloc: A,
+    f: F,
+}
+
+impl<A, F> fmt::Debug for Intercept<A, F>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Intercept")
+            .field("alloc", &self.alloc)
+            .field("f", &format_args!("<fn>"))
+            .finish()
+    }
+}
+
+impl<A, F> Intercept<A, F> {
+    #[inline]
+    pub const fn new(alloc: A, f: F) -> Self {
+        Self { alloc, f }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+}
+
+impl<A, F> Intercept<A, F>
+where
+    A: Allocator,
+    F: Fn(Layout, NonNull<[u8]>) -> ControlFlow<()>,
+{
+    fn alloc(&self, layout: Layout, zeroed: bool) -> Result<NonNull<[u8]>, AllocError> {
+        let block = if zeroed {
+            self.alloc.allocate_zeroed(layout)
+        } else {
+            self.alloc.allocate(layout)
+        }?;
+        match (self.f)(layout, block) {
+            ControlFlow::Continue(()) => Ok(block),
+            ControlFlow::Break(()) => {
+                unsafe { self.alloc.deallocate(block.cast(), layout) };
+                Err(AllocError)
+            }
+        }
+    }
+
+    unsafe fn grow_by_moving(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc(new_layout, zeroed)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), block.cast().as_ptr(), old_layout.size());
+        self.alloc.deallocate(ptr, old_layout);
+        Ok(block)
+    }
+}
+
+unsafe impl<A, F> Allocator for Intercept<A, F>
+where
+    A: Allocator,
+    F: Fn(Layout, NonNull<[u8]>) -> ControlFlow<()>,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, false)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, true)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.grow_by_moving(ptr, old_layout, new_layout, false)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.grow_by_moving(ptr, old_layout, new_layout, true)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.shrink(ptr, old_layout, new_layout)
+    }
+}
+
+impl<A, F> ResetAllocator for Intercept<A, F>
+where
+    A: ResetAllocator,
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.alloc.reset()
+    }
+}
+
+impl<A, F> ArenaAllocator for Intercept<A, F>
+where
+    A: ArenaAllocator,
+    F: Fn(Layout, NonNull<[u8]>) -> ControlFlow<()>,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Allocandrescu as _;
+    use allocator_api2::{alloc::Global, vec::Vec};
+    use core::cell::Cell;
+
+    /// Counts live blocks of `Global`.
+    #[derive(Default)]
+    struct Counting {
+        live: Cell<usize>,
+        allocs: Cell<usize>,
+    }
+
+    unsafe impl Allocator for Counting {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            let block = Global.allocate(layout)?;
+            self.live.set(self.live.get() + 1);
+            self.allocs.set(self.allocs.get() + 1);
+            Ok(block)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.live.set(self.live.get() - 1);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn intercept_frees_vetoed_allocations() {
+        let inner = Counting::default();
+        let pressure = Cell::new(false);
+        let alloc = (&inner).intercept(|_, _| {
+            if pressure.get() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+
+        let kept = alloc.allocate(Layout::new::<u64>()).unwrap();
+        pressure.set(true);
+        assert_eq!(alloc.allocate(Layout::new::<u64>()), Err(AllocError));
+        assert_eq!(
+            alloc.allocate_zeroed(Layout::new::<[u8; 64]>()),
+            Err(AllocError)
+        );
+        // The vetoed blocks were allocated, then freed.
+        assert_eq!((inner.allocs.get(), inner.live.get()), (3, 1));
+        unsafe { alloc.deallocate(kept.cast(), Layout::new::<u64>()) };
+        assert_eq!(inner.live.get(), 0);
+    }
+
+    #[test]
+    fn intercept_vetoes_grow() {
+        let inner = Counting::default();
+        let alloc = (&inner).intercept(|layout: Layout, block: NonNull<[u8]>| {
+            assert_eq!(block.len(), layout.size());
+            if layout.size() > 16 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+
+        let mut v = Vec::<u8, _>::with_capacity_in(8, &alloc);
+        v.extend_from_slice(&[1; 8]);
+        v.reserve_exact(8);
+        assert_eq!(v.capacity(), 16);
+        assert!(v.try_reserve_exact(9).is_err());
+        // The vetoed grow left the vector and its block alone.
+        assert_eq!((&v[..], v.capacity()), (&[1; 8][..], 16));
+        assert_eq!((inner.allocs.get(), inner.live.get()), (3, 1));
+        drop(v);
+        assert_eq!(inner.live.get(), 0);
+    }
+}
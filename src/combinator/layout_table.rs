@@ -0,0 +1,302 @@
+use crate::ArenaAllocator;
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::RefCell, fmt, ptr::NonNull};
+use std::{collections::HashMap, vec::Vec};
+
+/// The number and total size of the live allocations of one layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct LayoutRow {
+    pub live_count: usize,
+    pub live_bytes: usize,
+}
+
+/// An allocator that forwards all operations to `alloc` and counts its live allocations by
+/// layout.
+///
+/// The table is updated with every operation, so reading it doesn't walk the allocations. Growing
+/// or shrinking a block moves it from the row of its old layout to the row of the new one.
+/// Zero-sized allocations are counted too.
+///
+/// This `struct` is created by [`count_layouts`](crate::Allocandrescu::count_layouts) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+pub struct LayoutTable<A> {
+    alloc: A,
+    /// Rows by `(size, align)`. Rows without live allocations are removed.
+    rows: RefCell<HashMap<(usize, usize), LayoutRow>>,
+}
+
+impl<A> LayoutTable<A> {
+    #[inline]
+    pub fn new(alloc: A) -> Self {
+        Self {
+            alloc,
+            rows: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the live allocations by `(size, align)`.
+    #[inline]
+    pub fn by_layout(&self) -> HashMap<(usize, usize), LayoutRow> {
+        self.rows.borrow().clone()
+    }
+
+    /// Returns the row of `layout`, which is empty if it has no live allocations.
+    #[inline]
+    pub fn row(&self, layout: Layout) -> LayoutRow {
+        let rows = self.rows.borrow();
+        let key = (layout.size(), layout.align());
+        rows.get(&key).copied().unwrap_or_default()
+    }
+
+    /// Returns up to `n` rows with the most live bytes, for display.
+    ///
+    /// Rows with equal bytes are ordered by size and alignment.
+    pub fn top(&self, n: usize) -> TopLayouts {
+        let rows = self.rows.borrow();
+        let mut rows: Vec<_> = rows.iter().map(|(&key, &row)| (key, row)).collect();
+        rows.sort_by(|(a_key, a), (b_key, b)| {
+            b.live_bytes.cmp(&a.live_bytes).then(a_key.cmp(b_key))
+        });
+        rows.truncate(n);
+        TopLayouts { rows }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+
+    #[inline]
+    fn add(&self, layout: Layout) {
+        let mut rows = self.rows.borrow_mut();
+        let row = rows.entry((layout.size(), layout.align())).or_default();
+        row.live_count += 1;
+        row.live_bytes += layout.size();
+    }
+
+    #[inline]
+    fn remove(&self, layout: Layout) {
+        let mut rows = self.rows.borrow_mut();
+        let key = (layout.size(), layout.align());
+        let Some(row) = rows.get_mut(&key) else {
+            debug_assert!(false, "no live allocation of {layout:?}");
+            return;
+        };
+        row.live_count -= 1;
+        row.live_bytes -= layout.size();
+        if row.live_count == 0 {
+            rows.remove(&key);
+        }
+    }
+
+    #[inline]
+    fn moved(&self, old_layout: Layout, new_layout: Layout) {
+        self.remove(old_layout);
+        self.add(new_layout);
+    }
+}
+
+impl<A> fmt::Debug for LayoutTable<A>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayoutTable")
+            .field("alloc", &self.alloc)
+            .field("rows", &self.rows.borrow())
+            .finish()
+    }
+}
+
+/// The rows of a [`LayoutTable`] with the most live bytes, printable as a table.
+///
+/// This `struct` is created by [`LayoutTable::top`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopLayouts {
+    rows: Vec<((usize, usize), LayoutRow)>,
+}
+
+impl TopLayouts {
+    /// Returns the `(size, align)` and row of each layout, with the most live bytes first.
+    #[inline]
+    pub fn rows(&self) -> &[((usize, usize), LayoutRow)] {
+        &self.rows
+    }
+}
+
+impl fmt::Display for TopLayouts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>9}  {:>5}  {:>9}  {:>9}",
+            "size", "align", "count", "bytes"
+        )?;
+        for &((size, align), row) in &self.rows {
+            writeln!(
+                f,
+                "{:>9}  {:>5}  {:>9}  {:>9}",
+                size, align, row.live_count, row.live_bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+unsafe impl<A> Allocator for LayoutTable<A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc.allocate(layout)?;
+        self.add(layout);
+        Ok(block)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc.allocate_zeroed(layout)?;
+        self.add(layout);
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.remove(layout);
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc.grow(ptr, old_layout, new_layout)?;
+        self.moved(old_layout, new_layout);
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc.grow_zeroed(ptr, old_layout, new_layout)?;
+        self.moved(old_layout, new_layout);
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc.shrink(ptr, old_layout, new_layout)?;
+        self.moved(old_layout, new_layout);
+        Ok(block)
+    }
+}
+
+impl<A> ArenaAllocator for LayoutTable<A>
+where
+    A: ArenaAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Allocandrescu as _;
+    use allocator_api2::{alloc::Global, boxed::Box, vec::Vec};
+
+    fn row(live_count: usize, live_bytes: usize) -> LayoutRow {
+        LayoutRow {
+            live_count,
+            live_bytes,
+        }
+    }
+
+    #[test]
+    fn layout_table_matches_leaks() {
+        let alloc = Global.count_layouts();
+        let mut leaked = std::vec::Vec::new();
+        for i in 0..30 {
+            let small = Box::new_in(i as u8, &alloc);
+            let large = Box::new_in([i as u64; 8], &alloc);
+            // Leak every third small box and every fifth large one.
+            if i % 3 == 0 {
+                leaked.push((Box::into_raw(small).cast::<u8>(), Layout::new::<u8>()));
+            }
+            if i % 5 == 0 {
+                leaked.push((Box::into_raw(large).cast::<u8>(), Layout::new::<[u64; 8]>()));
+            }
+        }
+        let table = alloc.by_layout();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table[&(1, 1)], row(10, 10));
+        assert_eq!(table[&(64, 8)], row(6, 384));
+
+        assert_eq!(
+            alloc.top(1).to_string(),
+            "     size  align      count      bytes\n       64      8          6        384\n"
+        );
+        for (ptr, layout) in leaked {
+            unsafe { alloc.deallocate(NonNull::new(ptr).unwrap(), layout) };
+        }
+        assert!(alloc.by_layout().is_empty());
+    }
+
+    #[test]
+    fn layout_table_moves_grown_blocks_between_rows() {
+        let alloc = Global.count_layouts();
+        let mut v = Vec::<u32, _>::with_capacity_in(4, &alloc);
+        let w = Vec::<u32, _>::with_capacity_in(4, &alloc);
+        assert_eq!(alloc.row(Layout::new::<[u32; 4]>()), row(2, 32));
+        v.extend([1; 4]);
+        v.reserve_exact(12);
+        assert_eq!(alloc.row(Layout::new::<[u32; 4]>()), row(1, 16));
+        assert_eq!(alloc.row(Layout::new::<[u32; 16]>()), row(1, 64));
+
+        v.shrink_to_fit();
+        assert_eq!(alloc.row(Layout::new::<[u32; 4]>()), row(2, 32));
+        assert_eq!(alloc.by_layout().len(), 1);
+        drop((v, w));
+        assert!(alloc.by_layout().is_empty());
+        assert_eq!(alloc.top(10).rows(), []);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{
+    tagged::Aligned, ArenaAllocator, OwnsAllocations, ProbeAllocator, ResetAllocator, TrimAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, ptr::NonNull};
+
+/// An allocator that raises the alignment of each allocation made with `alloc` to at least `ALIGN`.
+///
+/// Every pointer it returns is aligned to `ALIGN`, so its low bits can store a tag, see
+/// [`witness`](MinAlign::witness). `ALIGN` must be a power of two.
+///
+/// This `struct` is created by [`min_align`](crate::Allocandrescu::min_align) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinAlign<A, const ALIGN: usize> {
+    alloc: A,
+}
+
+impl<A, const ALIGN: usize> MinAlign<A, ALIGN> {
+    #[inline]
+    pub const fn new(alloc: A) -> Self {
+        const { assert!(ALIGN.is_power_of_two(), "alignment must be a power of two") };
+        Self { alloc }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    /// Blocks handed out by the combinator were allocated with the raised alignment, so it has to be
+    /// passed to the returned allocator when deallocating them.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+
+    /// Returns a witness that the pointers of this allocator are aligned to `ALIGN`, for creating
+    /// [`TaggedPtr`](crate::tagged::TaggedPtr)s.
+    #[inline]
+    pub fn witness(&self) -> Aligned<ALIGN> {
+        // Every allocation is aligned to `ALIGN`, which `new` checked to be a power of two.
+        unsafe { Aligned::new_unchecked() }
+    }
+
+    #[inline]
+    fn raise(layout: Layout) -> Result<Layout, AllocError> {
+        layout.align_to(ALIGN).map_err(|_| AllocError)
+    }
+
+    /// # Safety
+    /// `layout` must have been raised successfully when its block was allocated.
+    #[inline]
+    unsafe fn raise_unchecked(layout: Layout) -> Layout {
+        Self::raise(layout).unwrap_unchecked()
+    }
+}
+
+unsafe impl<A, const ALIGN: usize> Allocator for MinAlign<A, ALIGN>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.allocate(Self::raise(layout)?)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.allocate_zeroed(Self::raise(layout)?)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, Self::raise_unchecked(layout))
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let old_layout = Self::raise_unchecked(old_layout);
+        self.alloc.grow(ptr, old_layout, Self::raise(new_layout)?)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let old_layout = Self::raise_unchecked(old_layout);
+        self.alloc
+            .grow_zeroed(ptr, old_layout, Self::raise(new_layout)?)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let old_layout = Self::raise_unchecked(old_layout);
+        self.alloc.shrink(ptr, old_layout, Self::raise(new_layout)?)
+    }
+}
+
+impl<A, const ALIGN: usize> ArenaAllocator for MinAlign<A, ALIGN>
+where
+    A: ArenaAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        Self::raise(layout).is_ok_and(|layout| self.alloc.contains(ptr, layout))
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+impl<A, const ALIGN: usize> TrimAllocator for MinAlign<A, ALIGN>
+where
+    A: TrimAllocator,
+{
+    #[inline]
+    fn trim(&self) -> usize {
+        self.alloc.trim()
+    }
+}
+
+impl<A, const ALIGN: usize> OwnsAllocations for MinAlign<A, ALIGN>
+where
+    A: OwnsAllocations,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        self.alloc.is_live(ptr)
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.alloc.live_count()
+    }
+}
+
+impl<A, const ALIGN: usize> ResetAllocator for MinAlign<A, ALIGN>
+where
+    A: ResetAllocator,
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.alloc.reset()
+    }
+}
+
+impl<A, const ALIGN: usize> ProbeAllocator for MinAlign<A, ALIGN>
+where
+    A: ProbeAllocator,
+{
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        Self::raise(layout).is_ok_and(|layout| self.alloc.can_allocate(layout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _};
+
+    #[test]
+    fn min_align_raises_alignment() {
+        let stack = Stack::<256, 16>::new();
+        let alloc = stack.by_ref().min_align::<16>();
+        let byte = Layout::new::<u8>();
+        let blocks = [(); 4].map(|()| alloc.allocate(byte).unwrap().cast::<u8>());
+        assert!(blocks.iter().all(|ptr| ptr.as_ptr() as usize % 16 == 0));
+        assert!(blocks.iter().all(|&ptr| alloc.contains(ptr, byte)));
+
+        assert_eq!(stack.used(), 3 * 16 + 1);
+
+        let top = blocks[3];
+        let grown = unsafe { alloc.grow(top, byte, Layout::new::<[u8; 24]>()) };
+        assert_eq!(grown.unwrap().cast(), top);
+        assert_eq!(stack.used(), 3 * 16 + 24);
+        // The padding in front of the top block is reclaimed with it.
+        unsafe { alloc.deallocate(top, Layout::new::<[u8; 24]>()) };
+        assert_eq!(stack.used(), 2 * 16 + 1);
+        assert!(!alloc.can_allocate(Layout::from_size_align(256, 16).unwrap()));
+    }
+}
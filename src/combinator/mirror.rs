@@ -0,0 +1,515 @@
+use crate::{ArenaAllocator, OwnsAllocations};
+use alloc_crate::collections::BTreeMap;
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{
+    alloc::Layout,
+    cell::{Cell, RefCell},
+    ptr::NonNull,
+};
+
+/// An allocator that performs every operation on both `alloc` and a known-good `reference`
+/// allocator and panics as soon as their observable behavior diverges.
+///
+/// Only blocks returned by `alloc` are handed out to the caller, the blocks from `reference` serve
+/// as shadow copies. `Mirror` checks that:
+/// - both allocators agree on whether an operation succeeds,
+/// - blocks returned by `alloc` are aligned and don't overlap any other live block,
+/// - a pattern written to freshly allocated blocks reads back the same through both pointers,
+/// - zeroed allocations are zeroed and `grow`/`shrink` preserve the contents the same way
+///   the reference allocator does,
+/// - deallocated pointers were allocated by this `Mirror` with a matching layout.
+///
+/// The returned blocks are exactly as large as requested, even if `alloc` reports more usable space.
+///
+/// It is meant for testing new allocators, not for production use.
+///
+/// This `struct` is created by [`mirror`](crate::Allocandrescu::mirror) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Debug)]
+pub struct Mirror<A, B> {
+    alloc: A,
+    reference: B,
+    /// Live blocks keyed by the address returned by `alloc` and a unique id, so that multiple
+    /// zero-sized blocks can share an address.
+    blocks: RefCell<BTreeMap<(usize, u64), Shadow>>,
+    next_id: Cell<u64>,
+}
+
+/// A block returned by the tested allocator and its shadow returned by the reference allocator.
+type Blocks = (NonNull<[u8]>, NonNull<[u8]>);
+
+#[derive(Debug)]
+struct Shadow {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+impl<A, B> Mirror<A, B> {
+    #[inline]
+    pub fn new(alloc: A, reference: B) -> Self {
+        Self {
+            alloc,
+            reference,
+            blocks: RefCell::new(BTreeMap::new()),
+            next_id: Cell::new(0),
+        }
+    }
+
+    /// Returns the number of live allocations.
+    #[inline]
+    pub fn live_allocations(&self) -> usize {
+        self.blocks.borrow().len()
+    }
+
+    fn check_outcomes(
+        &self,
+        op: &str,
+        layout: Layout,
+        result: Result<NonNull<[u8]>, AllocError>,
+        reference: Result<NonNull<[u8]>, AllocError>,
+    ) -> Option<Blocks> {
+        match (result, reference) {
+            (Ok(ptr), Ok(reference)) => {
+                assert!(
+                    ptr.len() >= layout.size(),
+                    "{op} returned a block of {} bytes for {layout:?}",
+                    ptr.len()
+                );
+                assert!(
+                    as_usize(ptr) % layout.align() == 0,
+                    "{op} returned a misaligned block {:p} for {layout:?}",
+                    ptr.cast::<u8>()
+                );
+                // The shadow only mirrors the requested bytes and deallocations must match the
+                // layout exactly, so any slack after the block is not handed out.
+                let ptr = NonNull::slice_from_raw_parts(ptr.cast(), layout.size());
+                Some((ptr, reference))
+            }
+            (Err(AllocError), Err(AllocError)) => None,
+            (Ok(_), Err(AllocError)) => {
+                panic!("{op} succeeded for {layout:?} but the reference allocator failed")
+            }
+            (Err(AllocError), Ok(_)) => {
+                panic!("{op} failed for {layout:?} but the reference allocator succeeded")
+            }
+        }
+    }
+
+    fn insert(&self, ptr: NonNull<[u8]>, reference: NonNull<[u8]>, layout: Layout) {
+        let start = as_usize(ptr);
+        let mut blocks = self.blocks.borrow_mut();
+        if layout.size() != 0 {
+            let end = start + layout.size();
+            let overlapping = blocks
+                .range(..(end, 0))
+                .rev()
+                .find(|(_, shadow)| shadow.layout.size() != 0)
+                .filter(|((addr, _), shadow)| addr + shadow.layout.size() > start);
+            if let Some(((addr, _), shadow)) = overlapping {
+                panic!(
+                    "block {start:#x} with {layout:?} overlaps live block {addr:#x} with {:?}",
+                    shadow.layout
+                );
+            }
+        }
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let shadow = Shadow {
+            ptr: reference.cast(),
+            layout,
+        };
+        blocks.insert((start, id), shadow);
+    }
+
+    fn remove(&self, ptr: NonNull<u8>, layout: Layout) -> NonNull<u8> {
+        let addr = ptr.as_ptr() as usize;
+        let mut blocks = self.blocks.borrow_mut();
+        let key = blocks
+            .range((addr, 0)..=(addr, u64::MAX))
+            .find(|(_, shadow)| shadow.layout.size() == layout.size())
+            .map(|(key, _)| *key);
+        let Some(key) = key else {
+            panic!("{addr:#x} with {layout:?} was not allocated by this allocator")
+        };
+        let shadow = blocks.remove(&key).unwrap();
+        assert_eq!(
+            shadow.layout, layout,
+            "{addr:#x} was allocated with a different layout"
+        );
+        shadow.ptr
+    }
+}
+
+unsafe impl<A, B> Allocator for Mirror<A, B>
+where
+    A: Allocator,
+    B: Allocator,
+{
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.allocate(layout);
+        let reference = self.reference.allocate(layout);
+        let (ptr, reference) = self
+            .check_outcomes("allocate", layout, result, reference)
+            .ok_or(AllocError)?;
+        self.insert(ptr, reference, layout);
+        let seed = self.next_id.get();
+        unsafe {
+            fill_pattern(ptr.cast(), layout.size(), seed);
+            fill_pattern(reference.cast(), layout.size(), seed);
+            compare(ptr.cast(), reference.cast(), layout.size(), "allocate");
+        }
+        Ok(ptr)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.allocate_zeroed(layout);
+        let reference = self.reference.allocate_zeroed(layout);
+        let (ptr, reference) = self
+            .check_outcomes("allocate_zeroed", layout, result, reference)
+            .ok_or(AllocError)?;
+        self.insert(ptr, reference, layout);
+        unsafe {
+            assert_zeroed(ptr.cast(), layout.size(), "allocate_zeroed");
+            compare(
+                ptr.cast(),
+                reference.cast(),
+                layout.size(),
+                "allocate_zeroed",
+            );
+        }
+        Ok(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let reference = self.remove(ptr, layout);
+        self.alloc.deallocate(ptr, layout);
+        self.reference.deallocate(reference, layout);
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new_ptr = self.realloc("grow", ptr, old_layout, new_layout, |a, ptr| {
+            a.grow(ptr, old_layout, new_layout)
+        })?;
+        // Like fresh allocations, the new part is initialized, so that comparing it on the next
+        // reallocation never reads uninitialized memory. The shadow is synced at that point.
+        fill_pattern(
+            new_ptr.cast::<u8>().add(old_layout.size()),
+            new_layout.size() - old_layout.size(),
+            self.next_id.get(),
+        );
+        Ok(new_ptr)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new_ptr = self.realloc("grow_zeroed", ptr, old_layout, new_layout, |a, ptr| {
+            a.grow_zeroed(ptr, old_layout, new_layout)
+        })?;
+        assert_zeroed(
+            new_ptr.cast::<u8>().add(old_layout.size()),
+            new_layout.size() - old_layout.size(),
+            "grow_zeroed",
+        );
+        Ok(new_ptr)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc("shrink", ptr, old_layout, new_layout, |a, ptr| {
+            a.shrink(ptr, old_layout, new_layout)
+        })
+    }
+}
+
+impl<A, B> Mirror<A, B>
+where
+    A: Allocator,
+    B: Allocator,
+{
+    /// Performs `op` on both allocators after bringing the shadow block up to date with the
+    /// caller's writes, then checks that both preserved the contents.
+    unsafe fn realloc(
+        &self,
+        name: &str,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        op: impl Fn(&dyn Allocator, NonNull<u8>) -> Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let reference = self.remove(ptr, old_layout);
+        core::ptr::copy_nonoverlapping(ptr.as_ptr(), reference.as_ptr(), old_layout.size());
+
+        let result = op(&self.alloc, ptr);
+        let reference_result = op(&self.reference, reference);
+        let Some((new_ptr, new_reference)) =
+            self.check_outcomes(name, new_layout, result, reference_result)
+        else {
+            self.insert(
+                NonNull::slice_from_raw_parts(ptr, old_layout.size()),
+                NonNull::slice_from_raw_parts(reference, old_layout.size()),
+                old_layout,
+            );
+            return Err(AllocError);
+        };
+        self.insert(new_ptr, new_reference, new_layout);
+        compare(
+            new_ptr.cast(),
+            new_reference.cast(),
+            old_layout.size().min(new_layout.size()),
+            name,
+        );
+        Ok(new_ptr)
+    }
+}
+
+/// Zero-sized blocks are tracked as well.
+impl<A, B> OwnsAllocations for Mirror<A, B>
+where
+    A: Allocator,
+    B: Allocator,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        let addr = ptr.as_ptr() as usize;
+        self.blocks
+            .borrow()
+            .range((addr, 0)..=(addr, u64::MAX))
+            .next()
+            .is_some()
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.live_allocations()
+    }
+}
+
+impl<A, B> ArenaAllocator for Mirror<A, B>
+where
+    A: ArenaAllocator,
+    B: Allocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+unsafe fn fill_pattern(ptr: NonNull<u8>, len: usize, seed: u64) {
+    for i in 0..len {
+        let byte = (seed as u8).wrapping_mul(31).wrapping_add(i as u8) ^ 0xa5;
+        ptr.as_ptr().add(i).write(byte);
+    }
+}
+
+unsafe fn compare(ptr: NonNull<u8>, reference: NonNull<u8>, len: usize, op: &str) {
+    for i in 0..len {
+        let byte = ptr.as_ptr().add(i).read();
+        let expected = reference.as_ptr().add(i).read();
+        assert_eq!(
+            byte, expected,
+            "{op}: byte {i} of {:p} differs from the reference allocator",
+            ptr
+        );
+    }
+}
+
+unsafe fn assert_zeroed(ptr: NonNull<u8>, len: usize, op: &str) {
+    for i in 0..len {
+        assert_eq!(
+            ptr.as_ptr().add(i).read(),
+            0,
+            "{op}: byte {i} is not zeroed"
+        );
+    }
+}
+
+#[inline]
+fn as_usize(ptr: NonNull<[u8]>) -> usize {
+    ptr.cast::<u8>().as_ptr() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _};
+    use std::alloc::System;
+
+    /// Hands out blocks of at least 64 bytes, reporting the whole block.
+    struct Slack;
+
+    unsafe impl Allocator for Slack {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            System.allocate(Self::block(layout))
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            System.deallocate(ptr, Self::block(layout))
+        }
+    }
+
+    impl Slack {
+        fn block(layout: Layout) -> Layout {
+            Layout::from_size_align(layout.size().max(64), layout.align()).unwrap()
+        }
+    }
+
+    #[test]
+    fn mirror_knows_live_blocks() {
+        let stack = Stack::<64>::new();
+        let alloc = stack.by_ref().mirror(System);
+        let layout = Layout::new::<u64>();
+        let freed = alloc.allocate(layout).unwrap().cast();
+        let empty = alloc.allocate(Layout::new::<()>()).unwrap().cast();
+        let live = alloc.allocate(layout).unwrap().cast();
+        unsafe { alloc.deallocate(freed, layout) };
+
+        assert!(alloc.contains(freed, layout) && !alloc.is_live(freed));
+        assert!(alloc.is_live(live) && alloc.is_live(empty));
+        assert_eq!(alloc.live_count(), 2);
+        let inspected = alloc.inspect(|_, _| {});
+        assert!(!inspected.is_live(freed) && inspected.is_live(live));
+        unsafe { inspected.deallocate(live, layout) };
+    }
+
+    #[test]
+    fn mirror_hides_slack() {
+        let alloc = Slack.mirror(System);
+        let layout = Layout::new::<u32>();
+        let block = alloc.allocate(layout).unwrap();
+        assert_eq!(block.len(), 4);
+        let block = unsafe { alloc.grow(block.cast(), layout, Layout::new::<u64>()) }.unwrap();
+        assert_eq!(block.len(), 8);
+        unsafe { alloc.deallocate(block.cast(), Layout::new::<u64>()) };
+    }
+
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn mirror_of_stack_and_system_agrees_on_random_workload() {
+        let stack = Box::new(Stack::<{ 128 * 1024 }>::new());
+        let alloc = stack.by_ref().mirror(System);
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        let mut live: Vec<(NonNull<u8>, Layout)> = Vec::new();
+
+        for _ in 0..1000 {
+            let size = rng.below(64);
+            let layout = Layout::from_size_align(size, 1 << rng.below(7)).unwrap();
+            match rng.below(5) {
+                0 | 1 => {
+                    let ptr = alloc.allocate(layout).unwrap();
+                    live.push((ptr.cast(), layout));
+                }
+                2 => {
+                    let ptr = alloc.allocate_zeroed(layout).unwrap();
+                    live.push((ptr.cast(), layout));
+                }
+                3 if !live.is_empty() => {
+                    let (ptr, old) = live.swap_remove(rng.below(live.len()));
+                    let new = Layout::from_size_align(old.size() + size, old.align()).unwrap();
+                    let ptr = unsafe { alloc.grow(ptr, old, new).unwrap() };
+                    live.push((ptr.cast(), new));
+                }
+                _ if !live.is_empty() => {
+                    let (ptr, layout) = live.swap_remove(rng.below(live.len()));
+                    unsafe { alloc.deallocate(ptr, layout) };
+                }
+                _ => {}
+            }
+        }
+
+        for (ptr, layout) in live {
+            unsafe { alloc.deallocate(ptr, layout) };
+        }
+        assert_eq!(alloc.live_allocations(), 0);
+    }
+
+    #[test]
+    fn mirror_preserves_contents_on_grow_and_shrink() {
+        let stack = Stack::<256>::new();
+        let alloc = stack.by_ref().mirror(System);
+        let mut v = allocator_api2::vec::Vec::new_in(&alloc);
+        v.extend(0..100u8);
+        v.truncate(10);
+        v.shrink_to_fit();
+        assert_eq!(v.as_slice(), (0..10).collect::<Vec<u8>>().as_slice());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "leaks the blocks of `System` when it panics")]
+    #[should_panic(expected = "reference allocator succeeded")]
+    fn mirror_catches_diverging_failures() {
+        let alloc = Stack::<4>::new().mirror(System);
+        let _ = alloc.allocate(Layout::new::<u64>());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "leaks the blocks of `System` when it panics")]
+    #[should_panic(expected = "overlaps live block")]
+    fn mirror_catches_overlapping_blocks() {
+        /// A broken allocator that hands out the same block over and over.
+        struct Aliasing(core::cell::UnsafeCell<[u64; 8]>);
+
+        unsafe impl Allocator for Aliasing {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                if layout.size() > 64 || layout.align() > 8 {
+                    return Err(AllocError);
+                }
+                let ptr = NonNull::new(self.0.get().cast::<u8>()).unwrap();
+                Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+            }
+
+            unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {}
+        }
+
+        let alloc = Aliasing(core::cell::UnsafeCell::new([0; 8])).mirror(System);
+        let _a = alloc.allocate(Layout::new::<u32>()).unwrap();
+        let _b = alloc.allocate(Layout::new::<u32>()).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "leaks the blocks of `System` when it panics")]
+    #[should_panic(expected = "was not allocated by this allocator")]
+    fn mirror_catches_foreign_deallocation() {
+        let alloc = Stack::<64>::new().mirror(System);
+        let layout = Layout::new::<u32>();
+        let foreign = System.allocate(layout).unwrap();
+        unsafe { alloc.deallocate(foreign.cast(), layout) };
+    }
+}
@@ -0,0 +1,360 @@
+use crate::ArenaAllocator;
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::Cell, ptr, ptr::NonNull};
+
+/// The priority of the code allocating through a [`PriorityRoute`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Priority {
+    #[default]
+    High,
+    Low,
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static PRIORITY: Cell<Priority> = const { Cell::new(Priority::High) };
+}
+
+/// Calls `f` with the priority of the current thread set to `priority`.
+///
+/// The previous priority is restored when `f` returns or panics, so scopes can be nested.
+///
+/// # Example
+/// ```
+/// use allocandrescu::combinator::{current_priority, with_priority, Priority};
+///
+/// with_priority(Priority::Low, || {
+///     assert_eq!(current_priority(), Priority::Low);
+///     with_priority(Priority::High, || assert_eq!(current_priority(), Priority::High));
+///     assert_eq!(current_priority(), Priority::Low);
+/// });
+/// assert_eq!(current_priority(), Priority::High);
+/// ```
+#[cfg(feature = "std")]
+pub fn with_priority<R>(priority: Priority, f: impl FnOnce() -> R) -> R {
+    let previous = PRIORITY.with(|current| current.replace(priority));
+    let _restore = Restore(|| PRIORITY.with(|current| current.set(previous)));
+    f()
+}
+
+/// Returns the priority of the current thread, which is [`Priority::High`] outside of
+/// [`with_priority`].
+#[cfg(feature = "std")]
+#[inline]
+pub fn current_priority() -> Priority {
+    PRIORITY.with(Cell::get)
+}
+
+/// Runs the closure when dropped.
+struct Restore<F: FnMut()>(F);
+
+impl<F: FnMut()> Drop for Restore<F> {
+    #[inline]
+    fn drop(&mut self) {
+        (self.0)()
+    }
+}
+
+/// An allocator that forwards each allocation to `hi` or `lo`, depending on the active
+/// [`Priority`].
+///
+/// The priority is the one set with [`PriorityRoute::with_priority`] if it is called, or else the
+/// one of the current thread, set with [`with_priority`]. Without the `std` feature, only the
+/// former is available and the priority is [`Priority::High`] outside of it.
+///
+/// Deallocation and reallocation are routed to `lo` unless `hi`
+/// [contains](ArenaAllocator::contains) the block. A block that can't be resized in place is moved
+/// to the allocator of the active priority.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, combinator::{Priority, PriorityRoute}};
+/// use allocator_api2::boxed::Box;
+///
+/// let (frame, spill) = (Stack::<1024>::new(), Stack::<1024>::new());
+/// let alloc = PriorityRoute::new(&frame, &spill);
+/// let critical = Box::new_in(1u32, &alloc);
+/// let background = alloc.with_priority(Priority::Low, || Box::new_in(2u32, &alloc));
+/// assert_eq!((frame.used(), spill.used()), (4, 4));
+/// # drop((critical, background));
+/// ```
+#[derive(Debug, Default)]
+pub struct PriorityRoute<Hi, Lo> {
+    hi: Hi,
+    lo: Lo,
+    priority: Cell<Option<Priority>>,
+}
+
+impl<Hi, Lo> PriorityRoute<Hi, Lo> {
+    #[inline]
+    pub const fn new(hi: Hi, lo: Lo) -> Self {
+        Self {
+            hi,
+            lo,
+            priority: Cell::new(None),
+        }
+    }
+
+    /// Returns the allocator serving [`Priority::High`].
+    #[inline]
+    pub fn hi(&self) -> &Hi {
+        &self.hi
+    }
+
+    /// Returns the allocator serving [`Priority::Low`].
+    #[inline]
+    pub fn lo(&self) -> &Lo {
+        &self.lo
+    }
+
+    /// Calls `f` with the priority of this combinator set to `priority`, overriding the priority
+    /// of the thread.
+    ///
+    /// The previous priority is restored when `f` returns or panics, so scopes can be nested.
+    pub fn with_priority<R>(&self, priority: Priority, f: impl FnOnce() -> R) -> R {
+        let previous = self.priority.replace(Some(priority));
+        let _restore = Restore(|| self.priority.set(previous));
+        f()
+    }
+
+    /// Returns the priority that allocations are currently routed by.
+    #[inline]
+    pub fn priority(&self) -> Priority {
+        #[cfg(feature = "std")]
+        let default = current_priority();
+        #[cfg(not(feature = "std"))]
+        let default = Priority::High;
+        self.priority.get().unwrap_or(default)
+    }
+
+    /// Consumes the combinator, returning both allocators.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocators.
+    #[inline]
+    pub fn into_parts(self) -> (Hi, Lo) {
+        (self.hi, self.lo)
+    }
+}
+
+impl<Hi, Lo> PriorityRoute<Hi, Lo>
+where
+    Hi: ArenaAllocator,
+    Lo: Allocator,
+{
+    #[inline]
+    fn side(&self, priority: Priority) -> &dyn Allocator {
+        match priority {
+            Priority::High => &self.hi,
+            Priority::Low => &self.lo,
+        }
+    }
+
+    #[inline]
+    fn owner(&self, ptr: NonNull<u8>, layout: Layout) -> Priority {
+        if self.hi.contains(ptr, layout) {
+            Priority::High
+        } else {
+            Priority::Low
+        }
+    }
+
+    #[inline]
+    fn alloc(&self, layout: Layout, zeroed: bool) -> Result<NonNull<[u8]>, AllocError> {
+        let alloc = self.side(self.priority());
+        if zeroed {
+            alloc.allocate_zeroed(layout)
+        } else {
+            alloc.allocate(layout)
+        }
+    }
+
+    /// Moves the block to memory allocated for the active priority.
+    unsafe fn relocate(
+        &self,
+        owner: Priority,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.alloc(new_layout, zeroed)?;
+        let len = old_layout.size().min(new_layout.size());
+        ptr::copy_nonoverlapping(ptr.as_ptr(), block.cast().as_ptr(), len);
+        self.side(owner).deallocate(ptr, old_layout);
+        Ok(block)
+    }
+}
+
+unsafe impl<Hi, Lo> Allocator for PriorityRoute<Hi, Lo>
+where
+    Hi: ArenaAllocator,
+    Lo: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, false)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, true)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.side(self.owner(ptr, layout)).deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        self.side(owner)
+            .grow(ptr, old_layout, new_layout)
+            .or_else(|_| self.relocate(owner, ptr, old_layout, new_layout, false))
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        self.side(owner)
+            .grow_zeroed(ptr, old_layout, new_layout)
+            .or_else(|_| self.relocate(owner, ptr, old_layout, new_layout, true))
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = self.owner(ptr, old_layout);
+        self.side(owner)
+            .shrink(ptr, old_layout, new_layout)
+            .or_else(|_| self.relocate(owner, ptr, old_layout, new_layout, false))
+    }
+}
+
+impl<Hi, Lo> ArenaAllocator for PriorityRoute<Hi, Lo>
+where
+    Hi: ArenaAllocator,
+    Lo: ArenaAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.hi.contains(ptr, layout) || self.lo.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        Some(
+            self.hi
+                .remaining_capacity()?
+                .saturating_add(self.lo.remaining_capacity()?),
+        )
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        Some(
+            self.hi
+                .allocated_bytes()?
+                .saturating_add(self.lo.allocated_bytes()?),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::Stack;
+
+    fn lands_in<A: ArenaAllocator>(arena: &A, ptr: NonNull<[u8]>) -> bool {
+        arena.contains(ptr.cast(), Layout::new::<u8>())
+    }
+
+    #[test]
+    fn priority_route_follows_nested_scopes() {
+        let (hi, lo) = (Stack::<256>::new(), Stack::<256>::new());
+        let alloc = PriorityRoute::new(&hi, &lo);
+        let layout = Layout::new::<u64>();
+        let mut blocks = Vec::new();
+        let mut allocate = |expected: Priority| {
+            assert_eq!(alloc.priority(), expected);
+            let block = alloc.allocate(layout).unwrap();
+            let arena: &dyn ArenaAllocator = match expected {
+                Priority::High => &hi,
+                Priority::Low => &lo,
+            };
+            assert!(arena.contains(block.cast(), layout));
+            blocks.push(block);
+        };
+
+        allocate(Priority::High);
+        alloc.with_priority(Priority::Low, || {
+            allocate(Priority::Low);
+            alloc.with_priority(Priority::High, || allocate(Priority::High));
+            allocate(Priority::Low);
+        });
+        allocate(Priority::High);
+        assert_eq!((hi.used(), lo.used()), (24, 16));
+
+        for block in blocks.into_iter().rev() {
+            unsafe { alloc.deallocate(block.cast(), layout) };
+        }
+        assert_eq!((hi.used(), lo.used()), (0, 0));
+    }
+
+    #[test]
+    fn priority_route_restores_priority_on_panic() {
+        let (hi, lo) = (Stack::<64>::new(), Stack::<64>::new());
+        let alloc = PriorityRoute::new(&hi, &lo);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            alloc.with_priority(Priority::Low, || panic!("background job failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(alloc.priority(), Priority::High);
+        assert!(lands_in(&hi, alloc.allocate(Layout::new::<u8>()).unwrap()));
+    }
+
+    #[test]
+    fn priority_route_moves_blocks_to_active_priority() {
+        let (hi, lo) = (Stack::<16>::new(), Stack::<256>::new());
+        let alloc = PriorityRoute::new(&hi, &lo);
+        let mut v = allocator_api2::vec![in &alloc; 1u8; 16];
+        assert!(lands_in(&hi, NonNull::from(&v[..])));
+        // The frame arena is full, so growing in low priority moves the block to the spill arena.
+        alloc.with_priority(Priority::Low, || v.push(2));
+        assert!(lands_in(&lo, NonNull::from(&v[..])));
+        assert_eq!(hi.used(), 0);
+        assert_eq!(&v[15..], [1, 2]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn priority_route_reads_thread_priority() {
+        let (hi, lo) = (Stack::<64>::new(), Stack::<64>::new());
+        let alloc = PriorityRoute::new(&hi, &lo);
+        let layout = Layout::new::<u8>();
+        with_priority(Priority::Low, || {
+            assert!(lands_in(&lo, alloc.allocate(layout).unwrap()));
+            // The combinator's own scope takes precedence.
+            alloc.with_priority(Priority::High, || {
+                assert!(lands_in(&hi, alloc.allocate(layout).unwrap()));
+                with_priority(Priority::Low, || {
+                    assert!(lands_in(&hi, alloc.allocate(layout).unwrap()));
+                });
+            });
+            assert!(lands_in(&lo, alloc.allocate(layout).unwrap()));
+        });
+        assert_eq!(current_priority(), Priority::High);
+        assert_eq!((hi.used(), lo.used()), (2, 2));
+    }
+}
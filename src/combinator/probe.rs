@@ -0,0 +1,262 @@
+use crate::{
+    AllocatorId, ArenaAllocator, GoodSizeAllocator, InPlaceGrow, OwnsAllocations, ProbeAllocator,
+    ResetAllocator, SameAllocator, TrimAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, ptr::NonNull};
+
+/// An allocator that forwards allocation to `alloc` only if its [probe](ProbeAllocator::can_allocate)
+/// accepts the layout. Fails allocation otherwise.
+///
+/// Reallocations are always forwarded.
+///
+/// This `struct` is created by [`probe`](crate::Allocandrescu::probe) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Probe<A> {
+    alloc: A,
+}
+
+impl<A> Probe<A> {
+    #[inline]
+    pub const fn new(alloc: A) -> Self {
+        Self { alloc }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+}
+
+unsafe impl<A> Allocator for Probe<A>
+where
+    A: ProbeAllocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.alloc.can_allocate(layout) {
+            self.alloc.allocate(layout)
+        } else {
+            Err(AllocError)
+        }
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.alloc.can_allocate(layout) {
+            self.alloc.allocate_zeroed(layout)
+        } else {
+            Err(AllocError)
+        }
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.grow(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.grow_zeroed(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.shrink(ptr, old_layout, new_layout)
+    }
+}
+
+impl<A> ArenaAllocator for Probe<A>
+where
+    A: ArenaAllocator + ProbeAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+impl<A> TrimAllocator for Probe<A>
+where
+    A: TrimAllocator + ProbeAllocator,
+{
+    #[inline]
+    fn trim(&self) -> usize {
+        self.alloc.trim()
+    }
+}
+
+impl<A> GoodSizeAllocator for Probe<A>
+where
+    A: GoodSizeAllocator + ProbeAllocator,
+{
+    /// Returns the preferred layout of the underlying allocator if its probe accepts it,
+    /// `layout` otherwise.
+    #[inline]
+    fn preferred_layout(&self, layout: Layout) -> Layout {
+        let preferred = self.alloc.preferred_layout(layout);
+        if self.alloc.can_allocate(preferred) {
+            preferred
+        } else {
+            layout
+        }
+    }
+}
+
+impl<A> InPlaceGrow for Probe<A>
+where
+    A: InPlaceGrow + ProbeAllocator,
+{
+    /// Always forwarded, like the other reallocations.
+    #[inline]
+    unsafe fn try_grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.try_grow_in_place(ptr, old_layout, new_layout)
+    }
+}
+
+impl<A> SameAllocator for Probe<A>
+where
+    A: SameAllocator + ProbeAllocator,
+{
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        self.alloc.allocator_id()
+    }
+}
+
+impl<A> OwnsAllocations for Probe<A>
+where
+    A: OwnsAllocations + ProbeAllocator,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        self.alloc.is_live(ptr)
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.alloc.live_count()
+    }
+}
+
+impl<A> ResetAllocator for Probe<A>
+where
+    A: ResetAllocator,
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.alloc.reset()
+    }
+}
+
+impl<A> ProbeAllocator for Probe<A>
+where
+    A: ProbeAllocator,
+{
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        self.alloc.can_allocate(layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        alloc::{Failing, Stack},
+        Allocandrescu as _,
+    };
+
+    // Align 1 only, so that stacks at different addresses need the same padding.
+    const SIZES: [usize; 8] = [1, 7, 16, 0, 33, 3, 64, 2];
+
+    #[test]
+    fn stack_probe_has_no_false_negatives() {
+        let stack = Stack::<64>::new();
+        for &size in SIZES.iter().cycle().take(64) {
+            let layout = Layout::array::<u8>(size).unwrap();
+            let probed = stack.can_allocate(layout);
+            assert_eq!(probed, stack.allocate(layout).is_ok());
+        }
+    }
+
+    #[test]
+    fn fallback_behaves_the_same_with_probe() {
+        let (stack, probed_stack) = (Stack::<64>::new(), Stack::<64>::new());
+        let (reserve, probed_reserve) = (Stack::<1024>::new(), Stack::<1024>::new());
+        let plain = stack.by_ref().fallback(reserve.by_ref()).fallback(Failing);
+        let probed = probed_stack
+            .by_ref()
+            .probe()
+            .fallback(probed_reserve.by_ref().probe())
+            .fallback(Failing);
+
+        for &size in SIZES.iter().cycle().take(64) {
+            let layout = Layout::array::<u8>(size).unwrap();
+            let a = plain.allocate(layout);
+            let b = probed.allocate(layout);
+            assert_eq!(a.is_ok(), b.is_ok());
+            let (Ok(a), Ok(b)) = (a, b) else { continue };
+            assert_eq!(
+                stack.contains(a.cast(), layout),
+                probed_stack.contains(b.cast(), layout)
+            );
+            assert_eq!(
+                reserve.contains(a.cast(), layout),
+                probed_reserve.contains(b.cast(), layout)
+            );
+        }
+        assert!(!probed.can_allocate(Layout::new::<[u8; 1024]>()));
+    }
+}
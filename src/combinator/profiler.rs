@@ -0,0 +1,487 @@
+use crate::ArenaAllocator;
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::RefCell, panic::Location, ptr::NonNull};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+/// An allocator that forwards all operations to `alloc` and records a heap profile of them.
+///
+/// Allocations are aggregated into program points by the location of the code calling the
+/// [`Allocator`] method. The location is captured with `#[track_caller]`, so it is the nearest
+/// caller that isn't itself `#[track_caller]` — for collections like `Vec` that is a place inside
+/// the collection's implementation. Growing or shrinking a block is recorded as freeing the old
+/// block and allocating a new one at the location of the `grow`/`shrink` call.
+/// Zero-sized allocations are not recorded.
+///
+/// The profile can be written in the [DHAT](https://valgrind.org/docs/manual/dh-manual.html) JSON
+/// format (`dhat-heap.json`) with [`save`](Profiler::save) and explored in the
+/// [DHAT viewer](https://nnethercote.github.io/dh_view/dh_view.html).
+///
+/// This `struct` is created by [`profile`](crate::Allocandrescu::profile) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Debug)]
+pub struct Profiler<A> {
+    alloc: A,
+    start: Instant,
+    state: RefCell<State>,
+}
+
+impl<A> Profiler<A> {
+    #[inline]
+    pub fn new(alloc: A) -> Self {
+        Self {
+            alloc,
+            start: Instant::now(),
+            state: RefCell::new(State::default()),
+        }
+    }
+
+    /// Writes the recorded profile to a file at `path` in the DHAT JSON format.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_json(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Writes the recorded profile to `writer` in the DHAT JSON format.
+    ///
+    /// Blocks that are still live are reported as if they were freed now.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let end = self.start.elapsed().as_micros();
+        let mut state = self.state.borrow_mut();
+        state.snapshot_peak_if_pending();
+
+        let mut live_lifetimes = vec![0u128; state.pps.len()];
+        for block in state.live.values() {
+            live_lifetimes[block.pp] += end - block.start;
+        }
+
+        let cmd = std::env::args().collect::<Vec<_>>().join(" ");
+        writeln!(writer, "{{")?;
+        writeln!(writer, "\"dhatFileVersion\": 2,")?;
+        writeln!(writer, "\"mode\": \"rust-heap\",")?;
+        writeln!(writer, "\"verb\": \"Allocated\",")?;
+        writeln!(writer, "\"bklt\": true,")?;
+        writeln!(writer, "\"bkacc\": false,")?;
+        writeln!(writer, "\"tu\": \"µs\",")?;
+        writeln!(writer, "\"Mtu\": \"s\",")?;
+        writeln!(writer, "\"tuth\": 10,")?;
+        writeln!(writer, "\"cmd\": \"{}\",", Escaped(&cmd))?;
+        writeln!(writer, "\"pid\": {},", std::process::id())?;
+        writeln!(writer, "\"tg\": {},", state.peak_time)?;
+        writeln!(writer, "\"te\": {},", end)?;
+        writeln!(writer, "\"pps\": [")?;
+        for (idx, pp) in state.pps.iter().enumerate() {
+            let separator = if idx + 1 < state.pps.len() { "," } else { "" };
+            writeln!(
+                writer,
+                "{{\"tb\":{},\"tbk\":{},\"tl\":{},\"mb\":{},\"mbk\":{},\"gb\":{},\"gbk\":{},\"eb\":{},\"ebk\":{},\"fs\":[{}]}}{}",
+                pp.total_bytes,
+                pp.total_blocks,
+                pp.total_lifetimes + live_lifetimes[idx],
+                pp.max_bytes,
+                pp.max_blocks,
+                pp.peak_bytes,
+                pp.peak_blocks,
+                pp.curr_bytes,
+                pp.curr_blocks,
+                idx + 1,
+                separator,
+            )?;
+        }
+        writeln!(writer, "],")?;
+        writeln!(writer, "\"ftbl\": [")?;
+        write!(writer, "\"[root]\"")?;
+        for pp in &state.pps {
+            let location = pp.location;
+            write!(
+                writer,
+                ",\n\"{}:{}:{}\"",
+                Escaped(location.file()),
+                location.line(),
+                location.column()
+            )?;
+        }
+        writeln!(writer, "\n]")?;
+        writeln!(writer, "}}")
+    }
+
+    #[inline]
+    fn now(&self) -> u128 {
+        self.start.elapsed().as_micros()
+    }
+
+    fn record_alloc(&self, location: &'static Location<'static>, ptr: NonNull<[u8]>, size: usize) {
+        if size != 0 {
+            let now = self.now();
+            self.state
+                .borrow_mut()
+                .alloc(location, ptr.cast::<u8>().as_ptr() as usize, size, now);
+        }
+    }
+
+    fn record_dealloc(&self, ptr: NonNull<u8>) {
+        let now = self.now();
+        self.state.borrow_mut().dealloc(ptr.as_ptr() as usize, now);
+    }
+}
+
+unsafe impl<A> Allocator for Profiler<A>
+where
+    A: Allocator,
+{
+    #[track_caller]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let location = Location::caller();
+        let ptr = self.alloc.allocate(layout)?;
+        self.record_alloc(location, ptr, layout.size());
+        Ok(ptr)
+    }
+
+    #[track_caller]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let location = Location::caller();
+        let ptr = self.alloc.allocate_zeroed(layout)?;
+        self.record_alloc(location, ptr, layout.size());
+        Ok(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            self.record_dealloc(ptr);
+        }
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    #[track_caller]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let location = Location::caller();
+        let new_ptr = self.alloc.grow(ptr, old_layout, new_layout)?;
+        if old_layout.size() != 0 {
+            self.record_dealloc(ptr);
+        }
+        self.record_alloc(location, new_ptr, new_layout.size());
+        Ok(new_ptr)
+    }
+
+    #[track_caller]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let location = Location::caller();
+        let new_ptr = self.alloc.grow_zeroed(ptr, old_layout, new_layout)?;
+        if old_layout.size() != 0 {
+            self.record_dealloc(ptr);
+        }
+        self.record_alloc(location, new_ptr, new_layout.size());
+        Ok(new_ptr)
+    }
+
+    #[track_caller]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let location = Location::caller();
+        let new_ptr = self.alloc.shrink(ptr, old_layout, new_layout)?;
+        if old_layout.size() != 0 {
+            self.record_dealloc(ptr);
+        }
+        self.record_alloc(location, new_ptr, new_layout.size());
+        Ok(new_ptr)
+    }
+}
+
+impl<A> ArenaAllocator for Profiler<A>
+where
+    A: ArenaAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    pps: Vec<ProgramPoint>,
+    pp_indices: HashMap<&'static Location<'static>, usize>,
+    live: HashMap<usize, Block>,
+    curr_bytes: usize,
+    curr_blocks: usize,
+    max_bytes: usize,
+    peak_time: u128,
+    /// Set when the global peak was reached and the per program point numbers at the peak
+    /// have not been recorded yet. They're recorded lazily, right before the usage decreases.
+    peak_pending: bool,
+}
+
+impl State {
+    fn alloc(&mut self, location: &'static Location<'static>, addr: usize, size: usize, now: u128) {
+        let pps = &mut self.pps;
+        let pp = *self.pp_indices.entry(location).or_insert_with(|| {
+            pps.push(ProgramPoint::new(location));
+            pps.len() - 1
+        });
+        self.pps[pp].alloc(size);
+        self.live.insert(
+            addr,
+            Block {
+                pp,
+                size,
+                start: now,
+            },
+        );
+        self.curr_bytes += size;
+        self.curr_blocks += 1;
+        if self.curr_bytes >= self.max_bytes {
+            self.max_bytes = self.curr_bytes;
+            self.peak_time = now;
+            self.peak_pending = true;
+        }
+    }
+
+    fn dealloc(&mut self, addr: usize, now: u128) {
+        let Some(block) = self.live.remove(&addr) else {
+            return;
+        };
+        self.snapshot_peak_if_pending();
+        self.pps[block.pp].dealloc(block.size, now - block.start);
+        self.curr_bytes -= block.size;
+        self.curr_blocks -= 1;
+    }
+
+    fn snapshot_peak_if_pending(&mut self) {
+        if self.peak_pending {
+            for pp in &mut self.pps {
+                pp.peak_bytes = pp.curr_bytes;
+                pp.peak_blocks = pp.curr_blocks;
+            }
+            self.peak_pending = false;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Block {
+    pp: usize,
+    size: usize,
+    start: u128,
+}
+
+#[derive(Debug)]
+struct ProgramPoint {
+    location: &'static Location<'static>,
+    total_bytes: u64,
+    total_blocks: u64,
+    total_lifetimes: u128,
+    max_bytes: usize,
+    max_blocks: usize,
+    peak_bytes: usize,
+    peak_blocks: usize,
+    curr_bytes: usize,
+    curr_blocks: usize,
+}
+
+impl ProgramPoint {
+    fn new(location: &'static Location<'static>) -> Self {
+        Self {
+            location,
+            total_bytes: 0,
+            total_blocks: 0,
+            total_lifetimes: 0,
+            max_bytes: 0,
+            max_blocks: 0,
+            peak_bytes: 0,
+            peak_blocks: 0,
+            curr_bytes: 0,
+            curr_blocks: 0,
+        }
+    }
+
+    fn alloc(&mut self, size: usize) {
+        self.total_bytes += size as u64;
+        self.total_blocks += 1;
+        self.curr_bytes += size;
+        self.curr_blocks += 1;
+        if self.curr_bytes >= self.max_bytes {
+            self.max_bytes = self.curr_bytes;
+            self.max_blocks = self.curr_blocks;
+        }
+    }
+
+    fn dealloc(&mut self, size: usize, lifetime: u128) {
+        self.curr_bytes -= size;
+        self.curr_blocks -= 1;
+        self.total_lifetimes += lifetime;
+    }
+}
+
+/// Escapes a string for use inside a JSON string literal.
+struct Escaped<'a>(&'a str);
+
+impl core::fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use core::fmt::Write as _;
+
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Allocandrescu as _;
+    use serde_json::Value;
+
+    fn to_json<A>(profiler: &Profiler<A>) -> Value {
+        let mut buf = Vec::new();
+        profiler.write_json(&mut buf).unwrap();
+        serde_json::from_slice(&buf).unwrap()
+    }
+
+    #[test]
+    fn profiler_aggregates_allocations_by_call_site() {
+        let alloc = std::alloc::System.profile();
+
+        let mut blocks = Vec::new();
+        for _ in 0..3 {
+            blocks.push(alloc.allocate(Layout::new::<u64>()).unwrap());
+        }
+        let big = alloc.allocate(Layout::new::<[u8; 100]>()).unwrap();
+        unsafe {
+            alloc.deallocate(big.cast(), Layout::new::<[u8; 100]>());
+            alloc.deallocate(blocks.pop().unwrap().cast(), Layout::new::<u64>());
+        }
+
+        let json = to_json(&alloc);
+        assert_eq!(json["dhatFileVersion"], 2);
+        assert_eq!(json["mode"], "rust-heap");
+
+        let pps = json["pps"].as_array().unwrap();
+        assert_eq!(pps.len(), 2);
+
+        let small = &pps[0];
+        assert_eq!(small["tb"], 24);
+        assert_eq!(small["tbk"], 3);
+        assert_eq!(small["mb"], 24);
+        assert_eq!(small["mbk"], 3);
+        assert_eq!(small["gb"], 24);
+        assert_eq!(small["gbk"], 3);
+        assert_eq!(small["eb"], 16);
+        assert_eq!(small["ebk"], 2);
+
+        let big = &pps[1];
+        assert_eq!(big["tb"], 100);
+        assert_eq!(big["tbk"], 1);
+        assert_eq!(big["gb"], 100);
+        assert_eq!(big["gbk"], 1);
+        assert_eq!(big["eb"], 0);
+        assert_eq!(big["ebk"], 0);
+
+        let ftbl = json["ftbl"].as_array().unwrap();
+        assert_eq!(ftbl.len(), 3);
+        assert_eq!(ftbl[0], "[root]");
+        assert_eq!(small["fs"], serde_json::json!([1]));
+        assert!(ftbl[1].as_str().unwrap().starts_with(file!()));
+
+        for block in blocks {
+            unsafe { alloc.deallocate(block.cast(), Layout::new::<u64>()) };
+        }
+    }
+
+    #[test]
+    fn profiler_records_global_peak() {
+        let alloc = std::alloc::System.profile();
+        let layout = Layout::new::<u32>();
+
+        let a = alloc.allocate(layout).unwrap();
+        let b = alloc.allocate(layout).unwrap();
+        unsafe { alloc.deallocate(a.cast(), layout) };
+        let c = alloc.allocate(Layout::new::<u8>()).unwrap();
+        unsafe {
+            alloc.deallocate(b.cast(), layout);
+            alloc.deallocate(c.cast(), Layout::new::<u8>());
+        }
+
+        let json = to_json(&alloc);
+        let pps = json["pps"].as_array().unwrap();
+        let peak_bytes: u64 = pps.iter().map(|pp| pp["gb"].as_u64().unwrap()).sum();
+        let peak_blocks: u64 = pps.iter().map(|pp| pp["gbk"].as_u64().unwrap()).sum();
+        let end_bytes: u64 = pps.iter().map(|pp| pp["eb"].as_u64().unwrap()).sum();
+        assert_eq!(peak_bytes, 8);
+        assert_eq!(peak_blocks, 2);
+        assert_eq!(end_bytes, 0);
+    }
+
+    #[test]
+    fn profiler_tracks_vec_growth() {
+        use allocator_api2::vec::Vec;
+
+        let alloc = crate::alloc::Stack::<256>::new().profile();
+        let mut v = Vec::new_in(&alloc);
+        for i in 0..32u8 {
+            v.push(i);
+        }
+        drop(v);
+
+        let json = to_json(&alloc);
+        let pps = json["pps"].as_array().unwrap();
+        let total_blocks: u64 = pps.iter().map(|pp| pp["tbk"].as_u64().unwrap()).sum();
+        let end_blocks: u64 = pps.iter().map(|pp| pp["ebk"].as_u64().unwrap()).sum();
+        assert!(total_blocks >= 2);
+        assert_eq!(end_blocks, 0);
+    }
+
+    #[test]
+    fn profiler_saves_to_file() {
+        let alloc = std::alloc::System.profile();
+        let block = alloc.allocate(Layout::new::<u64>()).unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("allocandrescu-dhat-{}.json", std::process::id()));
+        alloc.save(&path).unwrap();
+        let json: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(json["pps"][0]["tb"], 8);
+        assert_eq!(json["pps"][0]["eb"], 8);
+
+        unsafe { alloc.deallocate(block.cast(), Layout::new::<u64>()) };
+    }
+}
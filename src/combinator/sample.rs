@@ -0,0 +1,406 @@
+use super::Observer;
+use crate::{ArenaAllocator, ResetAllocator};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::Cell, fmt, ptr::NonNull};
+
+/// An allocator that forwards all operations to `alloc` and passes only a sample of their
+/// results to an [`Observer`] and to its own statistics.
+///
+/// Like with [`Inspect`](super::Inspect), allocations are passed to [`Observer::observe`] and
+/// reallocations to [`Observer::observe_realloc`]. One in `n` of them is sampled, either every `n`-th one or with a
+/// seeded random chance of `1 / n`, see [`new`](Sample::new) and [`random`](Sample::random).
+/// [Snapshots](Sample::snapshot) are tagged with the rate, so the sampled numbers can be
+/// extrapolated.
+///
+/// This `struct` is created by [`sample`](crate::Allocandrescu::sample) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+///
+/// # Example
+/// ```
+/// use allocandrescu::prelude::*;
+/// use std::{alloc::Layout, cell::Cell};
+///
+/// let calls = Cell::new(0);
+/// let alloc = std::alloc::System.sample(4).observe(|_, _| calls.set(calls.get() + 1));
+/// for _ in 0..10 {
+///     let block = alloc.allocate(Layout::new::<[u8; 100]>()).unwrap();
+///     unsafe { alloc.deallocate(block.cast(), Layout::new::<[u8; 100]>()) };
+/// }
+/// let snapshot = alloc.snapshot();
+/// assert_eq!((calls.get(), snapshot.sampled()), (2, 2));
+/// assert_eq!(snapshot.estimated_bytes(), 800);
+/// ```
+#[derive(Clone)]
+pub struct Sample<A, F = ()> {
+    alloc: A,
+    f: F,
+    rate: u64,
+    /// Operations seen in counter mode, or the random state in random mode.
+    state: Cell<u64>,
+    random: bool,
+    operations: Cell<u64>,
+    sampled: Cell<u64>,
+    sampled_bytes: Cell<u64>,
+}
+
+/// The statistics of a [`Sample`] at some point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SampleSnapshot {
+    rate: u64,
+    operations: u64,
+    sampled: u64,
+    sampled_bytes: u64,
+}
+
+impl SampleSnapshot {
+    /// Returns `n`, where one in `n` operations is sampled.
+    #[inline]
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    /// Returns the number of all observable operations, sampled or not.
+    #[inline]
+    pub fn operations(&self) -> u64 {
+        self.operations
+    }
+
+    /// Returns the number of sampled operations.
+    #[inline]
+    pub fn sampled(&self) -> u64 {
+        self.sampled
+    }
+
+    /// Returns the number of bytes requested by the sampled operations that succeeded.
+    #[inline]
+    pub fn sampled_bytes(&self) -> u64 {
+        self.sampled_bytes
+    }
+
+    /// Returns the number of bytes requested by all operations that succeeded, extrapolated from
+    /// the sample.
+    #[inline]
+    pub fn estimated_bytes(&self) -> u64 {
+        self.sampled_bytes.saturating_mul(self.rate)
+    }
+}
+
+impl fmt::Display for SampleSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sampled {} of {} operations at 1 in {}, ~{} bytes",
+            self.sampled,
+            self.operations,
+            self.rate,
+            self.estimated_bytes(),
+        )
+    }
+}
+
+impl<A, F> fmt::Debug for Sample<A, F>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sample")
+            .field("alloc", &self.alloc)
+            .field("random", &self.random)
+            .field("snapshot", &self.snapshot())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> Sample<A> {
+    /// Creates a combinator sampling every `n`-th operation, starting with the `n`-th one.
+    ///
+    /// # Panics
+    /// Panics if `n` is zero.
+    #[inline]
+    #[track_caller]
+    pub const fn new(alloc: A, n: u64) -> Self {
+        Self::with_mode(alloc, n, 0, false)
+    }
+
+    /// Creates a combinator sampling each operation with a chance of `1 / n`, using a random
+    /// sequence that is deterministic for a given `seed`.
+    ///
+    /// # Panics
+    /// Panics if `n` is zero.
+    #[inline]
+    #[track_caller]
+    pub const fn random(alloc: A, n: u64, seed: u64) -> Self {
+        Self::with_mode(alloc, n, seed, true)
+    }
+
+    #[inline]
+    #[track_caller]
+    const fn with_mode(alloc: A, n: u64, state: u64, random: bool) -> Self {
+        assert!(n > 0, "sampling rate must be at least 1");
+        Self {
+            alloc,
+            f: (),
+            rate: n,
+            state: Cell::new(state),
+            random,
+            operations: Cell::new(0),
+            sampled: Cell::new(0),
+            sampled_bytes: Cell::new(0),
+        }
+    }
+}
+
+impl<A, F> Sample<A, F> {
+    /// Replaces the observer of the sampled operations with `f`.
+    #[inline]
+    pub fn observe<G>(self, f: G) -> Sample<A, G>
+    where
+        G: Fn(Layout, Result<NonNull<[u8]>, AllocError>),
+    {
+        Sample {
+            alloc: self.alloc,
+            f,
+            rate: self.rate,
+            state: self.state,
+            random: self.random,
+            operations: self.operations,
+            sampled: self.sampled,
+            sampled_bytes: self.sampled_bytes,
+        }
+    }
+
+    /// Returns the statistics of the sampled operations, tagged with the sampling rate.
+    #[inline]
+    pub fn snapshot(&self) -> SampleSnapshot {
+        SampleSnapshot {
+            rate: self.rate,
+            operations: self.operations.get(),
+            sampled: self.sampled.get(),
+            sampled_bytes: self.sampled_bytes.get(),
+        }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+
+    /// Returns `true` if the next operation is sampled.
+    #[inline]
+    fn tick(&self) -> bool {
+        self.operations.set(self.operations.get() + 1);
+        if !self.random {
+            let seen = self.state.get() + 1;
+            self.state.set(seen % self.rate);
+            return seen == self.rate;
+        }
+        // splitmix64, like `Shuffle`.
+        let state = self.state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        z % self.rate == 0
+    }
+}
+
+impl<A, F> Sample<A, F>
+where
+    F: Observer,
+{
+    #[inline]
+    fn record(
+        &self,
+        old_layout: Option<Layout>,
+        layout: Layout,
+        result: Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.tick() {
+            self.sampled.set(self.sampled.get() + 1);
+            if result.is_ok() {
+                let bytes = self.sampled_bytes.get();
+                self.sampled_bytes
+                    .set(bytes.saturating_add(layout.size() as u64));
+            }
+            match old_layout {
+                Some(old_layout) => self.f.observe_realloc(old_layout, layout, result),
+                None => self.f.observe(layout, result),
+            }
+        }
+        result
+    }
+}
+
+unsafe impl<A, F> Allocator for Sample<A, F>
+where
+    A: Allocator,
+    F: Observer,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.record(None, layout, self.alloc.allocate(layout))
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.record(None, layout, self.alloc.allocate_zeroed(layout))
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout);
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.record(
+            Some(old_layout),
+            new_layout,
+            self.alloc.grow(ptr, old_layout, new_layout),
+        )
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.record(
+            Some(old_layout),
+            new_layout,
+            self.alloc.grow_zeroed(ptr, old_layout, new_layout),
+        )
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.record(
+            Some(old_layout),
+            new_layout,
+            self.alloc.shrink(ptr, old_layout, new_layout),
+        )
+    }
+}
+
+impl<A, F> ResetAllocator for Sample<A, F>
+where
+    A: ResetAllocator,
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.alloc.reset()
+    }
+}
+
+impl<A, F> ArenaAllocator for Sample<A, F>
+where
+    A: ArenaAllocator,
+    F: Observer,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Allocandrescu as _;
+    use allocator_api2::alloc::Global;
+
+    /// Allocates and frees blocks of 1 to 64 bytes, returning the total number of bytes.
+    fn workload(alloc: &impl Allocator, count: usize) -> u64 {
+        let mut total = 0;
+        for i in 0..count {
+            let layout = Layout::from_size_align(i % 64 + 1, 1).unwrap();
+            let block = alloc.allocate(layout).unwrap();
+            unsafe { alloc.deallocate(block.cast(), layout) };
+            total += layout.size() as u64;
+        }
+        total
+    }
+
+    #[test]
+    fn sample_observes_every_nth_operation() {
+        let calls = Cell::new(0);
+        let alloc = Global.sample(10).observe(|layout: Layout, result| {
+            assert_eq!(result.unwrap().len(), layout.size());
+            calls.set(calls.get() + 1);
+        });
+        let total = workload(&alloc, 1005);
+        let snapshot = alloc.snapshot();
+        assert_eq!(calls.get(), 1005 / 10);
+        assert_eq!(
+            (snapshot.rate(), snapshot.operations(), snapshot.sampled()),
+            (10, 1005, 100)
+        );
+        // Sizes cycle through 1..=64, so every tenth one is a fair sample.
+        let error = snapshot.estimated_bytes().abs_diff(total) as f64 / total as f64;
+        assert!(error < 0.05, "{snapshot}, actual {total} bytes");
+    }
+
+    #[test]
+    fn sample_extrapolates_random_sample() {
+        let alloc = Sample::random(Global, 16, 42);
+        let total = workload(&alloc, 20_000);
+        let snapshot = alloc.snapshot();
+        let expected = 20_000 / 16;
+        assert!(snapshot.sampled().abs_diff(expected) < expected / 10);
+        let error = snapshot.estimated_bytes().abs_diff(total) as f64 / total as f64;
+        assert!(error < 0.1, "{snapshot}, actual {total} bytes");
+
+        // The same seed samples the same operations.
+        let again = Sample::random(Global, 16, 42);
+        workload(&again, 20_000);
+        assert_eq!(again.snapshot(), snapshot);
+    }
+
+    #[test]
+    fn sample_counts_failures_without_bytes() {
+        let stack = crate::alloc::Stack::<16>::new();
+        let alloc = Sample::new(&stack, 1);
+        assert!(alloc.allocate(Layout::new::<[u8; 32]>()).is_err());
+        let _ = alloc.allocate(Layout::new::<[u8; 8]>()).unwrap();
+        let snapshot = alloc.snapshot();
+        assert_eq!((snapshot.sampled(), snapshot.sampled_bytes()), (2, 8));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{ArenaAllocator, OwnsAllocations};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{
+    alloc::Layout,
+    cell::{Cell, RefCell},
+    ptr::NonNull,
+};
+use std::collections::HashMap;
+
+/// Maximum number of alignment-sized slots added in front of and behind each allocation.
+const MAX_SLOTS: u64 = 8;
+
+/// An allocator that randomizes the placement of allocations made with `alloc`.
+///
+/// Each allocation is over-allocated by a random number of alignment-sized slots and the returned
+/// pointer is offset by a random number of them, so consecutive allocations are neither adjacent
+/// nor in ascending address order. It is meant for shaking out code that accidentally relies
+/// on the placement guaranteed by bump allocators, not for production use.
+///
+/// The randomness is deterministic for a given seed.
+///
+/// This `struct` is created by [`shuffle`](crate::Allocandrescu::shuffle) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Debug)]
+pub struct Shuffle<A> {
+    alloc: A,
+    rng: Cell<u64>,
+    /// Maps returned addresses to the blocks allocated with `alloc`.
+    blocks: RefCell<HashMap<usize, (NonNull<u8>, Layout)>>,
+}
+
+impl<A> Shuffle<A> {
+    #[inline]
+    pub fn new(alloc: A, seed: u64) -> Self {
+        Self {
+            alloc,
+            rng: Cell::new(seed),
+            blocks: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns a random number of slots in `0..=MAX_SLOTS`, using splitmix64.
+    fn random_slots(&self) -> usize {
+        let state = self.rng.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.rng.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z % (MAX_SLOTS + 1)) as usize
+    }
+
+    /// Returns the layout of the block to allocate with `alloc` and the offset of the returned pointer.
+    fn shuffled_layout(&self, layout: Layout) -> Result<(Layout, usize), AllocError> {
+        let front = self.random_slots();
+        // A zero-sized block always gets a slot behind it, so that the returned addresses are
+        // unique among live allocations.
+        let back = self.random_slots().max(usize::from(layout.size() == 0));
+        let offset = front.checked_mul(layout.align()).ok_or(AllocError)?;
+        let padding = back.checked_mul(layout.align()).ok_or(AllocError)?;
+        let size = layout
+            .size()
+            .checked_add(offset)
+            .and_then(|size| size.checked_add(padding))
+            .ok_or(AllocError)?;
+        let layout = Layout::from_size_align(size, layout.align()).map_err(|_| AllocError)?;
+        Ok((layout, offset))
+    }
+
+    fn insert(
+        &self,
+        block: NonNull<[u8]>,
+        block_layout: Layout,
+        offset: usize,
+        size: usize,
+    ) -> NonNull<[u8]> {
+        let block = block.cast::<u8>();
+        let ptr = unsafe { block.add(offset) };
+        self.blocks
+            .borrow_mut()
+            .insert(ptr.as_ptr() as usize, (block, block_layout));
+        NonNull::slice_from_raw_parts(ptr, size)
+    }
+}
+
+unsafe impl<A> Allocator for Shuffle<A>
+where
+    A: Allocator,
+{
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (block_layout, offset) = self.shuffled_layout(layout)?;
+        let block = self.alloc.allocate(block_layout)?;
+        Ok(self.insert(block, block_layout, offset, layout.size()))
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (block_layout, offset) = self.shuffled_layout(layout)?;
+        let block = self.alloc.allocate_zeroed(block_layout)?;
+        Ok(self.insert(block, block_layout, offset, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        let (block, block_layout) = self
+            .blocks
+            .borrow_mut()
+            .remove(&(ptr.as_ptr() as usize))
+            .expect("pointer was not allocated by this allocator");
+        self.alloc.deallocate(block, block_layout)
+    }
+
+    // `grow` and `shrink` use the default implementations on purpose, so that every reallocation
+    // moves the block to a new random place.
+}
+
+impl<A> OwnsAllocations for Shuffle<A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        self.blocks.borrow().contains_key(&(ptr.as_ptr() as usize))
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        self.blocks.borrow().len()
+    }
+}
+
+impl<A> ArenaAllocator for Shuffle<A>
+where
+    A: ArenaAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Allocandrescu as _;
+    use std::alloc::System;
+
+    #[test]
+    fn shuffle_preserves_alignment() {
+        let alloc = System.shuffle(42);
+        for align in [1, 2, 8, 64, 4096] {
+            for size in [0, 1, 3, 100] {
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let ptr = alloc.allocate(layout).unwrap();
+                assert_eq!(ptr.len(), size);
+                assert_eq!(ptr.cast::<u8>().as_ptr() as usize % align, 0);
+                unsafe { alloc.deallocate(ptr.cast(), layout) };
+            }
+        }
+    }
+
+    #[test]
+    fn shuffle_breaks_contiguity() {
+        let stack = crate::alloc::Stack::<4096>::new();
+        let alloc = stack.by_ref().shuffle(7);
+        let layout = Layout::new::<u8>();
+        let addrs: Vec<usize> = (0..16)
+            .map(|_| alloc.allocate(layout).unwrap().cast::<u8>().as_ptr() as usize)
+            .collect();
+        assert!(addrs.windows(2).any(|w| w[1] != w[0] + 1));
+    }
+
+    #[test]
+    fn shuffle_deallocates_original_blocks() {
+        // `Mirror` panics if a pointer or layout that it didn't hand out reaches it.
+        let inner = System.mirror(System);
+        let alloc = inner.by_ref().shuffle(1);
+
+        let mut v = allocator_api2::vec::Vec::new_in(&alloc);
+        v.extend(0..1000u32);
+        let boxed = allocator_api2::boxed::Box::new_in([0u8; 0], &alloc);
+        assert!(v.iter().copied().eq(0..1000));
+        drop(boxed);
+        drop(v);
+        assert_eq!(inner.live_allocations(), 0);
+    }
+}
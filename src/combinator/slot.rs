@@ -0,0 +1,409 @@
+use crate::util::layout_with_prefix;
+use alloc_crate::{boxed::Box, vec::Vec};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{
+    alloc::Layout,
+    cell::{Cell, RefCell},
+    fmt, ptr,
+    ptr::NonNull,
+};
+
+/// An allocator that forwards allocations to the currently installed allocator, which can be
+/// [replaced](Slot::replace) while blocks allocated by the previous ones are still live.
+///
+/// Each block is prefixed with a header storing the generation of the allocator that served it,
+/// so deallocation reaches the right one. A replaced allocator is dropped once its last block is
+/// deallocated. Growing a block of a replaced allocator moves it to the current one, shrinking it
+/// keeps it in place. Each allocation costs an extra word, plus padding for allocations aligned to
+/// more than a word.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, combinator::Slot};
+/// use allocator_api2::{alloc::Global, boxed::Box};
+///
+/// let startup = Stack::<256>::new();
+/// let slot = Slot::<&dyn allocator_api2::alloc::Allocator>::new(&startup);
+/// let config = Box::new_in(42u32, &slot);
+/// slot.replace(&Global);
+/// let state = Box::new_in(7u32, &slot);
+/// assert_eq!(slot.live_generations(), 2);
+/// drop(config);
+/// assert_eq!(slot.live_generations(), 1);
+/// # drop(state);
+/// ```
+pub struct Slot<A> {
+    generations: RefCell<Vec<Option<Box<Generation<A>>>>>,
+    current: Cell<usize>,
+}
+
+struct Generation<A> {
+    alloc: A,
+    /// Number of blocks allocated by `alloc` that weren't deallocated.
+    live: Cell<usize>,
+}
+
+impl<A> Slot<A> {
+    #[inline]
+    pub fn new(alloc: A) -> Self {
+        Self {
+            generations: RefCell::new(alloc_crate::vec![Some(Box::new(Generation {
+                alloc,
+                live: Cell::new(0),
+            }))]),
+            current: Cell::new(0),
+        }
+    }
+
+    /// Installs `alloc` as the allocator serving new allocations.
+    ///
+    /// The previous allocator is dropped right away if none of its blocks are live, or else when
+    /// the last of them is deallocated.
+    pub fn replace(&self, alloc: A) {
+        let mut generations = self.generations.borrow_mut();
+        let previous = self.current.get();
+        generations.push(Some(Box::new(Generation {
+            alloc,
+            live: Cell::new(0),
+        })));
+        self.current.set(generations.len() - 1);
+        Self::retire_if_idle(&mut generations, previous);
+    }
+
+    /// Returns the generation of the current allocator, which starts at 0 and is incremented by
+    /// each [`replace`](Slot::replace).
+    #[inline]
+    pub fn generation(&self) -> usize {
+        self.current.get()
+    }
+
+    /// Returns the number of allocators that haven't been dropped yet, including the current one.
+    #[inline]
+    pub fn live_generations(&self) -> usize {
+        let generations = self.generations.borrow();
+        generations
+            .iter()
+            .filter(|generation| generation.is_some())
+            .count()
+    }
+
+    /// Returns the number of live blocks allocated by the allocator of `generation`, or `None` if
+    /// it was dropped.
+    #[inline]
+    pub fn live_count(&self, generation: usize) -> Option<usize> {
+        let generations = self.generations.borrow();
+        let generation = generations.get(generation)?.as_ref()?;
+        Some(generation.live.get())
+    }
+
+    /// Calls `f` with the current allocator.
+    #[inline]
+    pub fn with_current<R>(&self, f: impl FnOnce(&A) -> R) -> R {
+        self.with(self.current.get(), |generation| f(&generation.alloc))
+    }
+
+    #[inline]
+    fn with<R>(&self, generation: usize, f: impl FnOnce(&Generation<A>) -> R) -> R {
+        let generations = self.generations.borrow();
+        // Generations are only dropped once they aren't current and have no live blocks.
+        let generation = generations[generation].as_deref();
+        f(unsafe { generation.unwrap_unchecked() })
+    }
+
+    #[inline]
+    fn retire_if_idle(generations: &mut [Option<Box<Generation<A>>>], generation: usize) {
+        let slot = &mut generations[generation];
+        if slot
+            .as_ref()
+            .is_some_and(|generation| generation.live.get() == 0)
+        {
+            *slot = None;
+        }
+    }
+
+    /// Returns the layout of the whole block, including the header, and the offset of the payload.
+    #[inline]
+    fn block_layout(layout: Layout) -> Result<(Layout, usize), AllocError> {
+        layout_with_prefix(Layout::new::<usize>(), layout).ok_or(AllocError)
+    }
+
+    #[inline]
+    unsafe fn header(ptr: NonNull<u8>) -> NonNull<usize> {
+        ptr.cast::<usize>().sub(1)
+    }
+
+    /// Writes the generation in front of the payload and returns the payload.
+    #[inline]
+    unsafe fn init(
+        block: NonNull<[u8]>,
+        offset: usize,
+        layout: Layout,
+        generation: usize,
+    ) -> NonNull<[u8]> {
+        let payload = block.cast::<u8>().add(offset);
+        Self::header(payload).write(generation);
+        NonNull::slice_from_raw_parts(payload, layout.size())
+    }
+}
+
+impl<A> Slot<A>
+where
+    A: Allocator,
+{
+    fn alloc(&self, layout: Layout, zeroed: bool) -> Result<NonNull<[u8]>, AllocError> {
+        let (block_layout, offset) = Self::block_layout(layout)?;
+        let current = self.current.get();
+        self.with(current, |generation| {
+            let block = if zeroed {
+                generation.alloc.allocate_zeroed(block_layout)
+            } else {
+                generation.alloc.allocate(block_layout)
+            }?;
+            generation.live.set(generation.live.get() + 1);
+            Ok(unsafe { Self::init(block, offset, layout, current) })
+        })
+    }
+
+    /// Reallocates the whole block with `op` if its allocator is still current, or `keep` is set,
+    /// and the payload offset doesn't change. Otherwise, moves the payload to a new allocation.
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+        keep: bool,
+        op: impl FnOnce(&A, NonNull<u8>, Layout, Layout) -> Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let owner = Self::header(ptr).read();
+        let (old_block_layout, old_offset) = Self::block_layout(old_layout)?;
+        let (new_block_layout, new_offset) = Self::block_layout(new_layout)?;
+
+        if old_offset == new_offset && (keep || owner == self.current.get()) {
+            let old_block = ptr.sub(old_offset);
+            let new_block = self.with(owner, |generation| {
+                op(
+                    &generation.alloc,
+                    old_block,
+                    old_block_layout,
+                    new_block_layout,
+                )
+            })?;
+            return Ok(Self::init(new_block, new_offset, new_layout, owner));
+        }
+
+        let new_ptr = self.alloc(new_layout, zeroed)?;
+        let len = old_layout.size().min(new_layout.size());
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.cast::<u8>().as_ptr(), len);
+        self.deallocate(ptr, old_layout);
+        Ok(new_ptr)
+    }
+}
+
+impl<A> fmt::Debug for Slot<A>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let generations = self.generations.borrow();
+        let mut list = f.debug_map();
+        for (idx, generation) in generations.iter().enumerate() {
+            if let Some(generation) = generation {
+                list.entry(&idx, &(&generation.alloc, generation.live.get()));
+            }
+        }
+        list.finish()
+    }
+}
+
+unsafe impl<A> Allocator for Slot<A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, false)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc(layout, true)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let owner = Self::header(ptr).read();
+        let (block_layout, offset) = Self::block_layout(layout).unwrap_unchecked();
+        self.with(owner, |generation| {
+            generation.alloc.deallocate(ptr.sub(offset), block_layout);
+            generation.live.set(generation.live.get() - 1);
+        });
+        if owner != self.current.get() {
+            Self::retire_if_idle(&mut self.generations.borrow_mut(), owner);
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(
+            ptr,
+            old_layout,
+            new_layout,
+            false,
+            false,
+            |alloc, block, old, new| alloc.grow(block, old, new),
+        )
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(
+            ptr,
+            old_layout,
+            new_layout,
+            true,
+            false,
+            |alloc, block, old, new| alloc.grow_zeroed(block, old, new),
+        )
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(
+            ptr,
+            old_layout,
+            new_layout,
+            false,
+            true,
+            |alloc, block, old, new| alloc.shrink(block, old, new),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc_crate::rc::Rc;
+    use allocator_api2::{alloc::Global, vec::Vec};
+
+    /// Events seen by a backend.
+    #[derive(Debug, Default)]
+    struct Log {
+        allocs: Cell<usize>,
+        frees: RefCell<Vec<usize>>,
+        dropped: Cell<bool>,
+    }
+
+    struct Backend(Rc<Log>);
+
+    impl Backend {
+        fn new() -> (Self, Rc<Log>) {
+            let log = Rc::new(Log::default());
+            (Self(log.clone()), log)
+        }
+    }
+
+    impl Drop for Backend {
+        fn drop(&mut self) {
+            self.0.dropped.set(true);
+        }
+    }
+
+    unsafe impl Allocator for Backend {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.allocs.set(self.0.allocs.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.frees.borrow_mut().push(ptr.as_ptr() as usize);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    fn block_of(ptr: NonNull<u8>, layout: Layout) -> usize {
+        ptr.as_ptr() as usize - Slot::<Global>::block_layout(layout).unwrap().1
+    }
+
+    #[test]
+    fn slot_routes_frees_to_their_generation() {
+        let (first, first_log) = Backend::new();
+        let (second, second_log) = Backend::new();
+        let slot = Slot::new(first);
+        let layout = Layout::new::<u64>();
+
+        let a = slot.allocate(layout).unwrap().cast::<u8>();
+        slot.replace(second);
+        assert_eq!((slot.generation(), slot.live_generations()), (1, 2));
+        let b = slot.allocate(layout).unwrap().cast::<u8>();
+        assert_eq!((first_log.allocs.get(), second_log.allocs.get()), (1, 1));
+
+        unsafe { slot.deallocate(a, layout) };
+        assert_eq!(*first_log.frees.borrow(), [block_of(a, layout)]);
+        assert!(second_log.frees.borrow().is_empty());
+        // The replaced backend is dropped with its last block.
+        assert!(first_log.dropped.get());
+        assert_eq!((slot.live_count(0), slot.live_count(1)), (None, Some(1)));
+
+        unsafe { slot.deallocate(b, layout) };
+        assert_eq!(*second_log.frees.borrow(), [block_of(b, layout)]);
+        assert_eq!(first_log.frees.borrow().len(), 1);
+        assert!(!second_log.dropped.get());
+        drop(slot);
+        assert!(second_log.dropped.get());
+    }
+
+    #[test]
+    fn slot_moves_growing_blocks_to_current_generation() {
+        let (first, first_log) = Backend::new();
+        let (second, second_log) = Backend::new();
+        let slot = Slot::new(first);
+        let mut v = Vec::with_capacity_in(2, &slot);
+        v.extend([1u32, 2]);
+        let mut kept = Vec::with_capacity_in(8, &slot);
+        kept.extend([3u16; 8]);
+        slot.replace(second);
+
+        kept.truncate(2);
+        kept.shrink_to_fit();
+        // The backend shrinks by moving within itself, which is still its own free.
+        assert_eq!(slot.live_count(0), Some(2));
+        assert_eq!(first_log.frees.borrow().len(), 1);
+        v.push(3);
+        assert_eq!(&v[..], [1, 2, 3]);
+        assert_eq!(
+            (first_log.frees.borrow().len(), second_log.allocs.get()),
+            (2, 1)
+        );
+        assert_eq!(slot.live_count(0), Some(1));
+
+        drop(kept);
+        assert!(first_log.dropped.get());
+        drop(v);
+        assert_eq!(second_log.frees.borrow().len(), 1);
+        assert_eq!(slot.live_generations(), 1);
+    }
+
+    #[test]
+    fn slot_drops_idle_allocator_on_replace() {
+        let (first, first_log) = Backend::new();
+        let slot = Slot::new(first);
+        let v = allocator_api2::vec![in &slot; 0u8; 4];
+        drop(v);
+        slot.replace(Backend::new().0);
+        assert!(first_log.dropped.get());
+        assert_eq!(slot.live_generations(), 1);
+        slot.with_current(|backend| assert_eq!(backend.0.allocs.get(), 0));
+    }
+}
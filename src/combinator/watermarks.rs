@@ -0,0 +1,280 @@
+use crate::{ArenaAllocator, ResetAllocator};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, cell::Cell, fmt, ptr::NonNull};
+
+/// An allocator that forwards all operations to `alloc`, tracks the bytes it has live and calls
+/// `f` once each time they rise to one of `N` thresholds.
+///
+/// `f` is called with the threshold and the live bytes right after the operation that crossed it.
+/// A threshold that fired is re-armed once the live bytes fall below it by more than the
+/// [hysteresis](Watermarks::with_hysteresis), which is zero by default. Live bytes are counted by
+/// the sizes of the layouts, so padding of the underlying allocator isn't included.
+///
+/// This `struct` is created by [`watermarks`](crate::Allocandrescu::watermarks) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+pub struct Watermarks<A, F, const N: usize> {
+    alloc: A,
+    f: F,
+    thresholds: [usize; N],
+    armed: [Cell<bool>; N],
+    hysteresis: usize,
+    live: Cell<usize>,
+}
+
+impl<A, F, const N: usize> fmt::Debug for Watermarks<A, F, N>
+where
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watermarks")
+            .field("alloc", &self.alloc)
+            .field("thresholds", &self.thresholds)
+            .field("hysteresis", &self.hysteresis)
+            .field("live", &self.live.get())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A, F, const N: usize> Watermarks<A, F, N> {
+    #[inline]
+    pub const fn new(alloc: A, thresholds: [usize; N], f: F) -> Self {
+        Self {
+            alloc,
+            f,
+            thresholds,
+            armed: [const { Cell::new(true) }; N],
+            hysteresis: 0,
+            live: Cell::new(0),
+        }
+    }
+
+    /// Sets how many bytes below a threshold the live bytes have to fall to re-arm it.
+    #[inline]
+    pub const fn with_hysteresis(mut self, hysteresis: usize) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// Returns the number of bytes allocated through the combinator that weren't deallocated.
+    #[inline]
+    pub fn live_bytes(&self) -> usize {
+        self.live.get()
+    }
+
+    /// Returns `true` if the threshold at `idx` fires the next time it is reached.
+    ///
+    /// # Panics
+    /// Panics if `idx >= N`.
+    #[inline]
+    pub fn is_armed(&self, idx: usize) -> bool {
+        self.armed[idx].get()
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+}
+
+impl<A, F, const N: usize> Watermarks<A, F, N>
+where
+    F: Fn(usize, usize),
+{
+    /// Replaces `old` live bytes with `new` ones, firing the thresholds crossed on the way up and
+    /// re-arming the ones left on the way down.
+    fn update(&self, old: usize, new: usize) {
+        let live = self.live.get() - old + new;
+        self.live.set(live);
+        for (&threshold, armed) in self.thresholds.iter().zip(&self.armed) {
+            if armed.get() && live >= threshold {
+                armed.set(false);
+                (self.f)(threshold, live);
+            } else if !armed.get() && live < threshold.saturating_sub(self.hysteresis) {
+                armed.set(true);
+            }
+        }
+    }
+
+    #[inline]
+    fn tracked(
+        &self,
+        old: usize,
+        new: usize,
+        result: Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if result.is_ok() {
+            self.update(old, new);
+        }
+        result
+    }
+}
+
+unsafe impl<A, F, const N: usize> Allocator for Watermarks<A, F, N>
+where
+    A: Allocator,
+    F: Fn(usize, usize),
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.tracked(0, layout.size(), self.alloc.allocate(layout))
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.tracked(0, layout.size(), self.alloc.allocate_zeroed(layout))
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout);
+        self.update(layout.size(), 0);
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.grow(ptr, old_layout, new_layout);
+        self.tracked(old_layout.size(), new_layout.size(), result)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.grow_zeroed(ptr, old_layout, new_layout);
+        self.tracked(old_layout.size(), new_layout.size(), result)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.shrink(ptr, old_layout, new_layout);
+        self.tracked(old_layout.size(), new_layout.size(), result)
+    }
+}
+
+impl<A, F, const N: usize> ResetAllocator for Watermarks<A, F, N>
+where
+    A: ResetAllocator,
+{
+    /// Resets the underlying allocator and re-arms every threshold.
+    #[inline]
+    fn reset(&mut self) {
+        self.alloc.reset();
+        self.live.set(0);
+        for armed in &self.armed {
+            armed.set(true);
+        }
+    }
+}
+
+impl<A, F, const N: usize> ArenaAllocator for Watermarks<A, F, N>
+where
+    A: ArenaAllocator,
+    F: Fn(usize, usize),
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.alloc.contains(ptr, layout)
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _};
+    use core::cell::RefCell;
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(size, 1).unwrap()
+    }
+
+    #[test]
+    fn watermarks_fire_once_per_crossing() {
+        let stack = Stack::<1024>::new();
+        let fired = RefCell::new(Vec::new());
+        let alloc = stack.by_ref().watermarks([800], |crossed, live| {
+            fired.borrow_mut().push((crossed, live))
+        });
+
+        let a = alloc.allocate(layout(500)).unwrap().cast();
+        let b = alloc.allocate(layout(400)).unwrap().cast();
+        assert!(!alloc.is_armed(0));
+        let c = alloc.allocate(layout(50)).unwrap().cast();
+        assert_eq!(*fired.borrow(), [(800, 900)]);
+
+        unsafe {
+            alloc.deallocate(c, layout(50));
+            alloc.deallocate(b, layout(400));
+        }
+        assert!(alloc.is_armed(0));
+        let mut v = allocator_api2::vec![in &alloc; 0u8; 200];
+        v.reserve_exact(200);
+        assert_eq!(*fired.borrow(), [(800, 900), (800, 900)]);
+        assert_eq!(alloc.live_bytes(), 900);
+        drop(v);
+        unsafe { alloc.deallocate(a, layout(500)) };
+        assert_eq!(alloc.live_bytes(), 0);
+    }
+
+    #[test]
+    fn watermarks_rearm_below_hysteresis() {
+        let fired = RefCell::new(Vec::new());
+        let alloc = Stack::<1024>::new()
+            .watermarks([100, 200], |crossed, live| {
+                fired.borrow_mut().push((crossed, live))
+            })
+            .with_hysteresis(50);
+
+        // One allocation can cross several thresholds.
+        let a = alloc.allocate(layout(250)).unwrap().cast();
+        assert_eq!(*fired.borrow(), [(100, 250), (200, 250)]);
+        let a = unsafe { alloc.shrink(a, layout(250), layout(160)) }
+            .unwrap()
+            .cast();
+        // 160 isn't below 200 - 50.
+        assert!(!alloc.is_armed(1));
+        let a = unsafe { alloc.shrink(a, layout(160), layout(120)) }
+            .unwrap()
+            .cast();
+        assert!(alloc.is_armed(1) && !alloc.is_armed(0));
+        unsafe { alloc.grow(a, layout(120), layout(210)) }.unwrap();
+        assert_eq!(*fired.borrow(), [(100, 250), (200, 250), (200, 210)]);
+    }
+}
@@ -0,0 +1,367 @@
+use crate::{
+    id::tag, util::layout_with_prefix, AllocatorId, ArenaAllocator, DeallocByPtr,
+    GoodSizeAllocator, InPlaceGrow, ProbeAllocator, ResetAllocator, SameAllocator, TrimAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, ptr::NonNull};
+
+/// An allocator that prepends a header storing the [`Layout`] to each allocation made with `alloc`.
+///
+/// Thanks to the header, memory can be deallocated knowing only the pointer,
+/// see [`DeallocByPtr`]. Each allocation costs two extra words, plus padding for
+/// allocations aligned to more than 16 bytes.
+///
+/// This `struct` is created by [`with_header`](crate::Allocandrescu::with_header) method on [`Allocandrescu`](crate::Allocandrescu).
+/// See its documentation for more details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WithHeader<A> {
+    alloc: A,
+}
+
+#[derive(Clone, Copy)]
+struct Header {
+    size: usize,
+    align: usize,
+}
+
+impl<A> WithHeader<A> {
+    #[inline]
+    pub const fn new(alloc: A) -> Self {
+        Self { alloc }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a mutable reference to the underlying allocator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Consumes the combinator, returning the underlying allocator.
+    ///
+    /// Memory allocated through the combinator stays allocated in the returned allocator.
+    /// Blocks handed out by the combinator start after their header, so they can't be deallocated
+    /// with the returned allocator directly.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.alloc
+    }
+
+    /// Returns the layout of the whole block, including the header, and the offset of the payload.
+    #[inline]
+    fn block_layout(layout: Layout) -> Result<(Layout, usize), AllocError> {
+        layout_with_prefix(Layout::new::<Header>(), layout).ok_or(AllocError)
+    }
+
+    #[inline]
+    unsafe fn header(ptr: NonNull<u8>) -> NonNull<Header> {
+        ptr.cast::<Header>().sub(1)
+    }
+
+    /// Reads the layout stored in the header of an allocation.
+    ///
+    /// # Safety
+    /// `ptr` must denote a block of memory currently allocated via this allocator.
+    #[inline]
+    pub unsafe fn layout_of(ptr: NonNull<u8>) -> Layout {
+        let Header { size, align } = Self::header(ptr).read();
+        Layout::from_size_align_unchecked(size, align)
+    }
+
+    /// Writes the header in front of the payload and returns the payload.
+    #[inline]
+    unsafe fn init(block: NonNull<[u8]>, offset: usize, layout: Layout) -> NonNull<[u8]> {
+        let payload = block.cast::<u8>().add(offset);
+        Self::header(payload).write(Header {
+            size: layout.size(),
+            align: layout.align(),
+        });
+        NonNull::slice_from_raw_parts(payload, layout.size())
+    }
+}
+
+unsafe impl<A> Allocator for WithHeader<A>
+where
+    A: Allocator,
+{
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (block_layout, offset) = Self::block_layout(layout)?;
+        let block = self.alloc.allocate(block_layout)?;
+        Ok(unsafe { Self::init(block, offset, layout) })
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (block_layout, offset) = Self::block_layout(layout)?;
+        let block = self.alloc.allocate_zeroed(block_layout)?;
+        Ok(unsafe { Self::init(block, offset, layout) })
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        debug_assert_eq!(Self::layout_of(ptr), layout);
+        self.deallocate_ptr(ptr)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout, false, |block, old, new| {
+            self.alloc.grow(block, old, new)
+        })
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout, true, |block, old, new| {
+            self.alloc.grow_zeroed(block, old, new)
+        })
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.realloc(ptr, old_layout, new_layout, false, |block, old, new| {
+            self.alloc.shrink(block, old, new)
+        })
+    }
+}
+
+impl<A> WithHeader<A>
+where
+    A: Allocator,
+{
+    /// Reallocates the whole block with `op` if the payload offset doesn't change.
+    /// Otherwise, moves the payload to a new allocation.
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+        op: impl FnOnce(NonNull<u8>, Layout, Layout) -> Result<NonNull<[u8]>, AllocError>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert_eq!(Self::layout_of(ptr), old_layout);
+        let (old_block_layout, old_offset) = Self::block_layout(old_layout)?;
+        let (new_block_layout, new_offset) = Self::block_layout(new_layout)?;
+
+        if old_offset == new_offset {
+            let old_block = ptr.sub(old_offset);
+            let new_block = op(old_block, old_block_layout, new_block_layout)?;
+            return Ok(Self::init(new_block, new_offset, new_layout));
+        }
+
+        let new_ptr = if zeroed {
+            self.allocate_zeroed(new_layout)?
+        } else {
+            self.allocate(new_layout)?
+        };
+        let len = old_layout.size().min(new_layout.size());
+        core::ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.cast::<u8>().as_ptr(), len);
+        self.deallocate_ptr(ptr);
+        Ok(new_ptr)
+    }
+}
+
+impl<A> DeallocByPtr for WithHeader<A>
+where
+    A: Allocator,
+{
+    #[inline]
+    unsafe fn deallocate_ptr(&self, ptr: NonNull<u8>) {
+        let layout = Self::layout_of(ptr);
+        let (block_layout, offset) = Self::block_layout(layout).unwrap_unchecked();
+        self.alloc.deallocate(ptr.sub(offset), block_layout)
+    }
+}
+
+impl<A> ArenaAllocator for WithHeader<A>
+where
+    A: ArenaAllocator,
+{
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        let Ok((block_layout, offset)) = Self::block_layout(layout) else {
+            return false;
+        };
+        let block = ptr.as_ptr().wrapping_sub(offset);
+        NonNull::new(block).is_some_and(|block| self.alloc.contains(block, block_layout))
+    }
+
+    /// Headers are not subtracted, as their number is unknown.
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.alloc.remaining_capacity()
+    }
+
+    /// Includes the headers.
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        self.alloc.allocated_bytes()
+    }
+}
+
+impl<A> TrimAllocator for WithHeader<A>
+where
+    A: TrimAllocator,
+{
+    #[inline]
+    fn trim(&self) -> usize {
+        self.alloc.trim()
+    }
+}
+
+impl<A> GoodSizeAllocator for WithHeader<A>
+where
+    A: GoodSizeAllocator,
+{
+    /// Grows the payload into the slack that the underlying allocator leaves after the header.
+    fn preferred_layout(&self, layout: Layout) -> Layout {
+        let Ok((block_layout, offset)) = Self::block_layout(layout) else {
+            return layout;
+        };
+        let preferred = self.alloc.preferred_layout(block_layout);
+        Layout::from_size_align(preferred.size() - offset, layout.align()).unwrap_or(layout)
+    }
+}
+
+impl<A> InPlaceGrow for WithHeader<A>
+where
+    A: InPlaceGrow,
+{
+    /// Fails if the new alignment moves the payload further from the start of the block.
+    unsafe fn try_grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert_eq!(Self::layout_of(ptr), old_layout);
+        let (old_block_layout, old_offset) = Self::block_layout(old_layout)?;
+        let (new_block_layout, new_offset) = Self::block_layout(new_layout)?;
+        if old_offset != new_offset {
+            return Err(AllocError);
+        }
+        let block = ptr.sub(old_offset);
+        let block = self
+            .alloc
+            .try_grow_in_place(block, old_block_layout, new_block_layout)?;
+        Ok(Self::init(block, new_offset, new_layout))
+    }
+}
+
+/// Differs from the ID of the underlying allocator, as the blocks start after the header.
+impl<A> SameAllocator for WithHeader<A>
+where
+    A: SameAllocator,
+{
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        AllocatorId::composite(tag::WITH_HEADER, &[self.alloc.allocator_id()])
+    }
+}
+
+impl<A> ResetAllocator for WithHeader<A>
+where
+    A: ResetAllocator,
+{
+    #[inline]
+    fn reset(&mut self) {
+        self.alloc.reset()
+    }
+}
+
+impl<A> ProbeAllocator for WithHeader<A>
+where
+    A: ProbeAllocator,
+{
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        Self::block_layout(layout)
+            .is_ok_and(|(block_layout, _)| self.alloc.can_allocate(block_layout))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::Allocandrescu as _;
+    use std::alloc::System;
+
+    const ALIGNS: [usize; 7] = [1, 2, 8, 16, 32, 64, 4096];
+
+    #[test]
+    fn with_header_deallocates_by_ptr_with_matching_layouts() {
+        let alloc = System.mirror(System).with_header();
+
+        let mut ptrs = std::vec::Vec::new();
+        for align in ALIGNS {
+            for size in [0, 1, 7, 100] {
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let ptr = alloc.allocate(layout).unwrap();
+                assert_eq!(ptr.len(), size);
+                assert_eq!(ptr.cast::<u8>().as_ptr() as usize % align, 0);
+                ptrs.push(ptr.cast::<u8>());
+            }
+        }
+        for ptr in ptrs {
+            unsafe { alloc.deallocate_ptr(ptr) };
+        }
+    }
+
+    #[test]
+    fn with_header_keeps_header_in_sync_on_grow_and_shrink() {
+        let alloc = System.mirror(System).with_header();
+
+        for (old_align, new_align) in [(1, 1), (8, 8), (1, 64), (64, 1), (16, 4096)] {
+            let old = Layout::from_size_align(8, old_align).unwrap();
+            let ptr = alloc.allocate(old).unwrap().cast::<u8>();
+            unsafe {
+                ptr.as_ptr()
+                    .copy_from_nonoverlapping([1, 2, 3, 4, 5, 6, 7, 8].as_ptr(), 8)
+            };
+
+            let grown = Layout::from_size_align(32, new_align).unwrap();
+            let ptr = unsafe { alloc.grow_zeroed(ptr, old, grown).unwrap() }.cast::<u8>();
+            assert_eq!(ptr.as_ptr() as usize % new_align, 0);
+            assert_eq!(unsafe { WithHeader::<System>::layout_of(ptr) }, grown);
+            let bytes = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), 32) };
+            assert_eq!(&bytes[..8], &[1, 2, 3, 4, 5, 6, 7, 8]);
+            assert!(bytes[8..].iter().all(|&b| b == 0));
+
+            let shrunk = Layout::from_size_align(4, old_align).unwrap();
+            let ptr = unsafe { alloc.shrink(ptr, grown, shrunk).unwrap() }.cast::<u8>();
+            assert_eq!(unsafe { WithHeader::<System>::layout_of(ptr) }, shrunk);
+            let bytes = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), 4) };
+            assert_eq!(bytes, &[1, 2, 3, 4]);
+
+            unsafe { alloc.deallocate_ptr(ptr) };
+        }
+    }
+
+    #[test]
+    fn vec_with_header_allocator_grows() {
+        use allocator_api2::vec::Vec;
+
+        let alloc = System.with_header();
+        let mut v = Vec::new_in(&alloc);
+        v.extend(0..1000u32);
+        v.truncate(10);
+        v.shrink_to_fit();
+        assert!(v.iter().copied().eq(0..10));
+    }
+}
@@ -0,0 +1,320 @@
+/// Implements [`Allocator`](allocator_api2::alloc::Allocator) and the listed traits of this crate
+/// for a newtype by forwarding to one of its fields.
+///
+/// Every method is forwarded, including the provided ones like `grow` and `shrink`, so the
+/// newtype behaves exactly like the allocator it wraps. `Allocator` is always implemented. Other
+/// traits are listed after a colon: `ArenaAllocator`, `BulkAllocator`, `DeallocByPtr`,
+/// `GoodSizeAllocator`, `InPlaceGrow`, `OwnsAllocations`, `ProbeAllocator`, `ResetAllocator`,
+/// `SameAllocator` and `TrimAllocator`. Lifetime parameters of the newtype are declared in front
+/// of it.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, delegate_allocator, prelude::*};
+/// use allocator_api2::vec::Vec;
+///
+/// /// Scratch space of a single frame.
+/// struct FrameArena<'a> {
+///     stack: &'a Stack<256>,
+/// }
+///
+/// impl FrameArena<'_> {
+///     fn fill(&self) -> f64 {
+///         self.stack.used() as f64 / self.stack.capacity() as f64
+///     }
+/// }
+///
+/// delegate_allocator!(<'a> FrameArena<'a> => self.stack: ArenaAllocator, ProbeAllocator);
+///
+/// let stack = Stack::<256>::new();
+/// let arena = FrameArena { stack: &stack };
+/// let mut v = Vec::new_in(&arena);
+/// v.extend([1u8; 64]);
+/// assert_eq!(arena.fill(), 0.25);
+/// assert!(!arena.can_allocate(std::alloc::Layout::new::<[u8; 256]>()));
+/// ```
+#[macro_export]
+macro_rules! delegate_allocator {
+    (<$($lt:lifetime),+ $(,)?> $ty:ty => self.$field:tt $(: $($trait:ident),+ $(,)?)?) => {
+        $crate::delegate_allocator!(@impl [$($lt),+] $ty, $field, Allocator);
+        $crate::delegate_allocator!(@each [$($lt),+] $ty, $field, $($($trait),+)?);
+    };
+    ($ty:ty => self.$field:tt $(: $($trait:ident),+ $(,)?)?) => {
+        $crate::delegate_allocator!(@impl [] $ty, $field, Allocator);
+        $crate::delegate_allocator!(@each [] $ty, $field, $($($trait),+)?);
+    };
+    (@each $lts:tt $ty:ty, $field:tt, $($trait:ident),*) => {
+        $($crate::delegate_allocator!(@impl $lts $ty, $field, $trait);)*
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, Allocator) => {
+        unsafe impl<$($lt),*> $crate::__private::Allocator for $ty {
+            #[inline]
+            fn allocate(
+                &self,
+                layout: ::core::alloc::Layout,
+            ) -> ::core::result::Result<::core::ptr::NonNull<[u8]>, $crate::__private::AllocError> {
+                $crate::__private::Allocator::allocate(&self.$field, layout)
+            }
+
+            #[inline]
+            fn allocate_zeroed(
+                &self,
+                layout: ::core::alloc::Layout,
+            ) -> ::core::result::Result<::core::ptr::NonNull<[u8]>, $crate::__private::AllocError> {
+                $crate::__private::Allocator::allocate_zeroed(&self.$field, layout)
+            }
+
+            #[inline]
+            unsafe fn deallocate(&self, ptr: ::core::ptr::NonNull<u8>, layout: ::core::alloc::Layout) {
+                $crate::__private::Allocator::deallocate(&self.$field, ptr, layout)
+            }
+
+            #[inline]
+            unsafe fn grow(
+                &self,
+                ptr: ::core::ptr::NonNull<u8>,
+                old_layout: ::core::alloc::Layout,
+                new_layout: ::core::alloc::Layout,
+            ) -> ::core::result::Result<::core::ptr::NonNull<[u8]>, $crate::__private::AllocError> {
+                $crate::__private::Allocator::grow(&self.$field, ptr, old_layout, new_layout)
+            }
+
+            #[inline]
+            unsafe fn grow_zeroed(
+                &self,
+                ptr: ::core::ptr::NonNull<u8>,
+                old_layout: ::core::alloc::Layout,
+                new_layout: ::core::alloc::Layout,
+            ) -> ::core::result::Result<::core::ptr::NonNull<[u8]>, $crate::__private::AllocError> {
+                $crate::__private::Allocator::grow_zeroed(&self.$field, ptr, old_layout, new_layout)
+            }
+
+            #[inline]
+            unsafe fn shrink(
+                &self,
+                ptr: ::core::ptr::NonNull<u8>,
+                old_layout: ::core::alloc::Layout,
+                new_layout: ::core::alloc::Layout,
+            ) -> ::core::result::Result<::core::ptr::NonNull<[u8]>, $crate::__private::AllocError> {
+                $crate::__private::Allocator::shrink(&self.$field, ptr, old_layout, new_layout)
+            }
+        }
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, ArenaAllocator) => {
+        impl<$($lt),*> $crate::ArenaAllocator for $ty {
+            #[inline]
+            fn contains(&self, ptr: ::core::ptr::NonNull<u8>, layout: ::core::alloc::Layout) -> bool {
+                $crate::ArenaAllocator::contains(&self.$field, ptr, layout)
+            }
+
+            #[inline]
+            fn arena_range(&self) -> ::core::option::Option<::core::ops::Range<usize>> {
+                $crate::ArenaAllocator::arena_range(&self.$field)
+            }
+
+            #[inline]
+            fn remaining_capacity(&self) -> ::core::option::Option<usize> {
+                $crate::ArenaAllocator::remaining_capacity(&self.$field)
+            }
+
+            #[inline]
+            fn allocated_bytes(&self) -> ::core::option::Option<usize> {
+                $crate::ArenaAllocator::allocated_bytes(&self.$field)
+            }
+        }
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, BulkAllocator) => {
+        impl<$($lt),*> $crate::BulkAllocator for $ty {
+            #[inline]
+            fn allocate_many(
+                &self,
+                layout: ::core::alloc::Layout,
+                n: usize,
+                out: &mut dyn FnMut(::core::ptr::NonNull<[u8]>),
+            ) -> ::core::result::Result<usize, $crate::__private::AllocError> {
+                $crate::BulkAllocator::allocate_many(&self.$field, layout, n, out)
+            }
+
+            #[inline]
+            unsafe fn deallocate_many(
+                &self,
+                layout: ::core::alloc::Layout,
+                ptrs: &mut dyn Iterator<Item = ::core::ptr::NonNull<u8>>,
+            ) {
+                $crate::BulkAllocator::deallocate_many(&self.$field, layout, ptrs)
+            }
+        }
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, DeallocByPtr) => {
+        impl<$($lt),*> $crate::DeallocByPtr for $ty {
+            #[inline]
+            unsafe fn deallocate_ptr(&self, ptr: ::core::ptr::NonNull<u8>) {
+                $crate::DeallocByPtr::deallocate_ptr(&self.$field, ptr)
+            }
+        }
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, GoodSizeAllocator) => {
+        impl<$($lt),*> $crate::GoodSizeAllocator for $ty {
+            #[inline]
+            fn preferred_layout(&self, layout: ::core::alloc::Layout) -> ::core::alloc::Layout {
+                $crate::GoodSizeAllocator::preferred_layout(&self.$field, layout)
+            }
+        }
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, InPlaceGrow) => {
+        impl<$($lt),*> $crate::InPlaceGrow for $ty {
+            #[inline]
+            unsafe fn try_grow_in_place(
+                &self,
+                ptr: ::core::ptr::NonNull<u8>,
+                old_layout: ::core::alloc::Layout,
+                new_layout: ::core::alloc::Layout,
+            ) -> ::core::result::Result<::core::ptr::NonNull<[u8]>, $crate::__private::AllocError> {
+                $crate::InPlaceGrow::try_grow_in_place(&self.$field, ptr, old_layout, new_layout)
+            }
+        }
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, OwnsAllocations) => {
+        impl<$($lt),*> $crate::OwnsAllocations for $ty {
+            #[inline]
+            fn is_live(&self, ptr: ::core::ptr::NonNull<u8>) -> bool {
+                $crate::OwnsAllocations::is_live(&self.$field, ptr)
+            }
+
+            #[inline]
+            fn live_count(&self) -> usize {
+                $crate::OwnsAllocations::live_count(&self.$field)
+            }
+        }
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, ProbeAllocator) => {
+        impl<$($lt),*> $crate::ProbeAllocator for $ty {
+            #[inline]
+            fn can_allocate(&self, layout: ::core::alloc::Layout) -> bool {
+                $crate::ProbeAllocator::can_allocate(&self.$field, layout)
+            }
+        }
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, ResetAllocator) => {
+        impl<$($lt),*> $crate::ResetAllocator for $ty {
+            #[inline]
+            fn reset(&mut self) {
+                $crate::ResetAllocator::reset(&mut self.$field)
+            }
+        }
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, SameAllocator) => {
+        impl<$($lt),*> $crate::SameAllocator for $ty {
+            #[inline]
+            fn allocator_id(&self) -> $crate::AllocatorId {
+                $crate::SameAllocator::allocator_id(&self.$field)
+            }
+        }
+    };
+    (@impl [$($lt:lifetime),*] $ty:ty, $field:tt, TrimAllocator) => {
+        impl<$($lt),*> $crate::TrimAllocator for $ty {
+            #[inline]
+            fn trim(&self) -> usize {
+                $crate::TrimAllocator::trim(&self.$field)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{alloc::Stack, ArenaAllocator, BulkAllocator, ProbeAllocator};
+    use allocator_api2::alloc::Allocator;
+    use core::alloc::Layout;
+
+    struct Borrowed<'a> {
+        stack: &'a Stack<128>,
+    }
+
+    delegate_allocator!(<'a> Borrowed<'a> => self.stack: ArenaAllocator, BulkAllocator, ProbeAllocator);
+
+    #[cfg(feature = "std")]
+    mod chain {
+        use super::*;
+        use crate::{
+            combinator::Fallback, same_allocator, GoodSizeAllocator, InPlaceGrow, ResetAllocator,
+            SameAllocator, TrimAllocator,
+        };
+        use allocator_api2::vec::Vec;
+        use core::ptr::NonNull;
+
+        // A stack as the secondary too, so that whether a block moves doesn't depend on the heap.
+        struct Chain(Fallback<Stack<64>, Stack<1024>>);
+
+        delegate_allocator!(Chain => self.0: GoodSizeAllocator, InPlaceGrow, ResetAllocator, SameAllocator, TrimAllocator);
+
+        /// Records for each push whether the vector moved and whether it ended up in `stack`.
+        fn trace(alloc: &impl Allocator, stack: &Stack<64>) -> std::vec::Vec<(bool, bool)> {
+            let mut v = Vec::new_in(alloc);
+            (0..64u32)
+                .map(|i| {
+                    let before = v.as_ptr();
+                    v.push(i);
+                    let ptr = NonNull::from(&v[0]).cast();
+                    (
+                        v.as_ptr() != before,
+                        stack.contains(ptr, Layout::for_value(&v[..])),
+                    )
+                })
+                .collect()
+        }
+
+        #[test]
+        fn delegated_chain_matches_unwrapped() {
+            let plain = Fallback::new(Stack::<64>::new(), Stack::<1024>::new());
+            let mut chain = Chain(Fallback::new(Stack::<64>::new(), Stack::<1024>::new()));
+            let trace = trace(&chain, chain.0.primary());
+            assert_eq!(trace, self::trace(&plain, plain.primary()));
+            // Growing in place within the stack shows that `grow` is forwarded.
+            assert!(trace.contains(&(false, true)) && trace.contains(&(true, false)));
+
+            let layout = Layout::new::<[u8; 16]>();
+            let ours = chain.allocate(layout).unwrap().cast::<u8>();
+            assert!(chain.0.primary().contains(ours, layout));
+            let wider = Layout::new::<[u8; 32]>();
+            let grown = unsafe { chain.try_grow_in_place(ours, layout, wider) }.unwrap();
+            assert_eq!(grown.cast(), ours);
+            assert_eq!(
+                chain.preferred_layout(layout),
+                plain.preferred_layout(layout)
+            );
+            assert_eq!(chain.trim(), 0);
+            assert_eq!(chain.allocator_id(), chain.0.allocator_id());
+            assert!(!same_allocator(&chain, &plain));
+            unsafe { chain.deallocate(ours, wider) };
+            chain.reset();
+            assert_eq!(chain.0.primary().used(), 0);
+        }
+    }
+
+    #[test]
+    fn delegated_arena_forwards_provided_methods() {
+        let stack = Stack::<128>::new();
+        let arena = Borrowed { stack: &stack };
+        let layout = Layout::new::<[u8; 8]>();
+        let block = arena.allocate(layout).unwrap().cast::<u8>();
+        let grown = unsafe { arena.grow(block, layout, Layout::new::<[u8; 24]>()) }.unwrap();
+        assert_eq!(grown.cast(), block);
+        let shrunk = unsafe { arena.shrink(block, Layout::new::<[u8; 24]>(), layout) }.unwrap();
+        assert_eq!((shrunk.cast(), stack.used()), (block, 8));
+        assert!(arena.contains(block, layout));
+        assert_eq!(arena.arena_range(), stack.arena_range());
+        assert_eq!(arena.remaining_capacity(), Some(120));
+        assert_eq!(arena.allocated_bytes(), Some(8));
+        assert!(!arena.can_allocate(Layout::new::<[u8; 121]>()));
+
+        let mut blocks = std::vec::Vec::new();
+        let count = arena.allocate_many(layout, 4, &mut |block| blocks.push(block.cast::<u8>()));
+        assert_eq!(count, Ok(4));
+        unsafe {
+            arena.deallocate_many(layout, &mut blocks.into_iter().rev());
+            arena.deallocate(block, layout);
+        }
+        assert_eq!(stack.used(), 0);
+    }
+}
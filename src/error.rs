@@ -0,0 +1,177 @@
+use allocator_api2::alloc::AllocError;
+use core::{alloc::Layout, fmt};
+
+/// The error type of the typed allocation helpers, like [`ArenaExt`](crate::ArenaExt) and
+/// [`CollectIn`](crate::collections::CollectIn).
+///
+/// Unlike [`AllocError`], which the [`Allocator`](allocator_api2::alloc::Allocator) interface
+/// requires, it tells what failed. It converts from and into `AllocError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error {
+    operation: Operation,
+    layout: Option<Layout>,
+    name: Option<&'static str>,
+}
+
+/// The operation that failed with an [`Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+    /// A new block was being allocated.
+    Allocate,
+    /// A block was being grown.
+    Grow,
+    /// A block was being shrunk.
+    Shrink,
+}
+
+impl Error {
+    /// Creates an error for `operation` requesting `layout`.
+    ///
+    /// `layout` is `None` if it is unknown, e.g. because computing it overflowed.
+    #[inline]
+    pub const fn new(operation: Operation, layout: Option<Layout>) -> Self {
+        Self {
+            operation,
+            layout,
+            name: None,
+        }
+    }
+
+    /// Attaches the name of the allocator that failed.
+    #[inline]
+    pub const fn with_name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Returns the operation that failed.
+    #[inline]
+    pub const fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    /// Returns the requested layout, or `None` if it is unknown.
+    #[inline]
+    pub const fn layout(&self) -> Option<Layout> {
+        self.layout
+    }
+
+    /// Returns the name of the allocator that failed, if it was attached.
+    #[inline]
+    pub const fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Returns a closure that maps an [`AllocError`] to an error for `operation` requesting `layout`.
+    #[inline]
+    pub(crate) fn map(operation: Operation, layout: Layout) -> impl FnOnce(AllocError) -> Self {
+        move |_| Self::new(operation, Some(layout))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operation = match self.operation {
+            Operation::Allocate => "allocate",
+            Operation::Grow => "grow",
+            Operation::Shrink => "shrink",
+        };
+        match self.layout {
+            Some(layout) => write!(
+                f,
+                "failed to {operation} a block of size {} and align {}",
+                layout.size(),
+                layout.align()
+            )?,
+            None => write!(f, "failed to {operation} a block of overflowing size")?,
+        }
+        if let Some(name) = self.name {
+            write!(f, " in {name}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// The error type of the C string helpers of [`ArenaExt`](crate::ArenaExt), like
+/// [`alloc_cstr`](crate::ArenaExt::alloc_cstr).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CStrError {
+    /// The string contains a NUL byte at `position`, so it can't be a C string.
+    InteriorNul {
+        /// The position of the first NUL byte.
+        position: usize,
+    },
+    /// The allocation failed.
+    Alloc(Error),
+}
+
+impl fmt::Display for CStrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InteriorNul { position } => {
+                write!(f, "found a NUL byte at position {position} of a C string")
+            }
+            Self::Alloc(err) => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CStrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InteriorNul { .. } => None,
+            Self::Alloc(err) => Some(err),
+        }
+    }
+}
+
+impl From<Error> for CStrError {
+    #[inline]
+    fn from(err: Error) -> Self {
+        Self::Alloc(err)
+    }
+}
+
+/// The layout is unknown.
+impl From<AllocError> for Error {
+    #[inline]
+    fn from(_: AllocError) -> Self {
+        Self::new(Operation::Allocate, None)
+    }
+}
+
+impl From<Error> for AllocError {
+    #[inline]
+    fn from(_: Error) -> Self {
+        AllocError
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_message_includes_layout_and_name() {
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        let error = Error::new(Operation::Grow, Some(layout));
+        assert_eq!(
+            std::format!("{error}"),
+            "failed to grow a block of size 24 and align 8"
+        );
+        assert_eq!(
+            std::format!("{}", error.with_name("frame arena")),
+            "failed to grow a block of size 24 and align 8 in frame arena"
+        );
+        assert_eq!(
+            std::format!("{}", Error::from(AllocError)),
+            "failed to allocate a block of overflowing size"
+        );
+        assert_eq!(AllocError::from(error), AllocError);
+    }
+}
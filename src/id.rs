@@ -0,0 +1,185 @@
+use allocator_api2::alloc::Allocator;
+
+/// An opaque token identifying an allocator, returned by [`SameAllocator::allocator_id`].
+///
+/// Arenas are identified by the address of the memory they manage, so an ID is only meaningful
+/// while the arena is alive and doesn't move: an arena created at the address of a dropped one
+/// gets the same ID. Allocators with a single global instance, like `System`, have a constant ID.
+/// Combinators that route blocks or change their layout derive a composite ID from the IDs of the
+/// allocators they wrap, which is different from all of them.
+///
+/// IDs of different kinds never collide. Composite IDs are hashes of their parts, so two different
+/// composites collide only with negligible probability.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AllocatorId {
+    kind: Kind,
+    value: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Address,
+    Static,
+    Composite,
+}
+
+/// Tags telling apart composites built by different combinators from the same parts.
+pub(crate) mod tag {
+    pub(crate) const COMBINE: u64 = 0;
+    pub(crate) const FALLBACK: u64 = 1;
+    pub(crate) const FALLBACK_ARENA: u64 = 2;
+    pub(crate) const WITH_HEADER: u64 = 3;
+    pub(crate) const SUB_ARENA: u64 = 4;
+}
+
+impl AllocatorId {
+    pub(crate) const FAILING: Self = Self::fixed(0);
+    #[cfg(feature = "alloc")]
+    pub(crate) const GLOBAL: Self = Self::fixed(1);
+    #[cfg(feature = "std")]
+    pub(crate) const SYSTEM: Self = Self::fixed(2);
+
+    const fn fixed(value: u64) -> Self {
+        Self {
+            kind: Kind::Static,
+            value,
+        }
+    }
+
+    /// Identifies the allocator managing the memory at `ptr`, e.g. the buffer of an arena.
+    #[inline]
+    pub fn from_ptr<T: ?Sized>(ptr: *const T) -> Self {
+        Self {
+            kind: Kind::Address,
+            value: ptr.cast::<u8>() as usize as u64,
+        }
+    }
+
+    /// Returns the ID of a combinator built of the allocators identified by `self` and `other`.
+    #[inline]
+    pub fn combine(self, other: Self) -> Self {
+        Self::composite(tag::COMBINE, &[self, other])
+    }
+
+    /// Hashes the parts with FNV-1a, which is plenty for a handful of words.
+    pub(crate) fn composite(tag: u64, parts: &[Self]) -> Self {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut add = |word: u64| {
+            hash ^= word;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        };
+        add(tag);
+        for part in parts {
+            add(part.kind as u64);
+            add(part.value);
+        }
+        Self {
+            kind: Kind::Composite,
+            value: hash,
+        }
+    }
+}
+
+/// Allocator that can tell whether another allocator is the same one, e.g. to move blocks between
+/// collections without reallocating them.
+///
+/// Allocators with equal [IDs](AllocatorId) can deallocate each other's blocks. Combinators that
+/// forward deallocations unchanged, like [`Cond`](crate::combinator::Cond), have the ID of the
+/// allocator they wrap.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, prelude::*, same_allocator};
+/// use allocator_api2::vec::Vec;
+///
+/// let (a, b) = (Stack::<256>::new(), Stack::<256>::new());
+/// let mut left = Vec::new_in(&a);
+/// let mut right = Vec::new_in(&a);
+/// left.push(1);
+/// right.push(2);
+/// // Both vectors live in `a`, so `right` could be absorbed without copying.
+/// assert!(same_allocator(left.allocator(), right.allocator()));
+/// assert!(!same_allocator(&a, &b));
+/// ```
+pub trait SameAllocator: Allocator {
+    /// Returns the ID of the allocator.
+    fn allocator_id(&self) -> AllocatorId;
+}
+
+impl<A> SameAllocator for &A
+where
+    A: SameAllocator + ?Sized,
+{
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        (**self).allocator_id()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl SameAllocator for allocator_api2::alloc::Global {
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        AllocatorId::GLOBAL
+    }
+}
+
+#[cfg(feature = "std")]
+impl SameAllocator for std::alloc::System {
+    #[inline]
+    fn allocator_id(&self) -> AllocatorId {
+        AllocatorId::SYSTEM
+    }
+}
+
+/// Returns `true` if `a` and `b` have the same [ID](AllocatorId), so each can deallocate the
+/// blocks of the other.
+#[inline]
+pub fn same_allocator<A, B>(a: &A, b: &B) -> bool
+where
+    A: SameAllocator + ?Sized,
+    B: SameAllocator + ?Sized,
+{
+    a.allocator_id() == b.allocator_id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        alloc::{Failing, Stack},
+        Allocandrescu as _,
+    };
+
+    #[test]
+    fn same_allocator_compares_storage() {
+        let (a, b) = (Stack::<64>::new(), Stack::<64>::new());
+        assert!(same_allocator(&a, &&a));
+        assert!(same_allocator(
+            &a,
+            &a.by_ref().cond(|_| true).inspect(|_, _| {})
+        ));
+        assert!(!same_allocator(&a, &b));
+        assert!(same_allocator(&Failing, &Failing));
+        assert!(!same_allocator(&a.by_ref().with_header(), &a));
+
+        let fallback = a.by_ref().fallback(b.by_ref());
+        let copy = fallback;
+        assert!(same_allocator(&fallback, &copy));
+        assert!(!same_allocator(&fallback, fallback.primary()));
+        assert!(!same_allocator(&fallback, fallback.secondary()));
+        assert!(!same_allocator(&fallback, &b.by_ref().fallback(a.by_ref())));
+        assert!(!same_allocator(
+            &fallback,
+            &a.by_ref().fallback_arena(b.by_ref())
+        ));
+    }
+
+    #[test]
+    fn sub_arena_differs_from_parent() {
+        let stack = Stack::<64>::new();
+        let sub = stack.carve(16).unwrap();
+        assert!(!same_allocator(&stack, &sub));
+        assert!(same_allocator(&sub, &&sub));
+    }
+}
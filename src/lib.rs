@@ -3,7 +3,7 @@
 //! This library is inspired by [Andrei Alexandrescu](https://en.wikipedia.org/wiki/Andrei_Alexandrescu)'s
 //! CppCon 2015 talk [std::allocator Is to Allocation what std::vector Is to Vexation](https://www.youtube.com/watch?v=LIb3L4vKZ7U)
 //! and the [Zig programming language](https://ziglang.org/).
-//! 
+//!
 //! `allocandrescu` allows you to safely compose allocators using combinators such as
 //! [`cond`](Allocandrescu::cond) and [`fallback`](Allocandrescu::fallback).
 //! It also provides a variety of simple allocators like [`Stack`](crate::alloc::Stack).
@@ -21,11 +21,11 @@
 //! ```
 //! use allocandrescu::prelude::*;
 //! ```
-//! 
+//!
 //! # Example
 //! Allocator that allocates objects smaller than 16 bytes on a stack of size 1024 bytes.
 //! For larger objects, it falls back to using the system allocator.
-//! Additionally, it prints all allocation results. 
+//! Additionally, it prints all allocation results.
 //! ```
 //! use allocandrescu::{alloc::Stack, prelude::*};
 //! use allocator_api2::vec;
@@ -38,32 +38,155 @@
 //!     .inspect(|layout, result| println!("layout: {layout:?}, result: {result:?}"));
 //! let v = vec![in &alloc; 0; 100];
 //! ```
-//! 
+//!
+//! # Zero-sized allocations
+//! Allocations of zero bytes never fail in the arenas of this crate and don't change their state:
+//! they return a dangling pointer aligned to the requested alignment, and deallocating it does nothing.
+//! [`Fallback`] handles zero-sized allocations itself, so they never reach either of its allocators.
+//! Combinators like [`Cond`] and [`Inspect`] treat them like any other allocation.
+//!
+//! [`ArenaAllocator::contains`] treats an arena as the half-open range of its addresses.
+//! A zero-sized block is contained if its address is in that range, so a pointer one past the
+//! end of an arena's allocated region is never contained in it.
+//!
+//! # Thread safety
+//! Combinators are `Send` and `Sync` exactly when the allocators and closures they wrap are.
+//! Allocators with interior mutability like [`Stack`](crate::alloc::Stack) and [`SpillStats`]
+//! are `Send` but not `Sync`, so chains built on them can be moved to another thread, but not shared.
+//!
 //! # Feature flags
+//! - `alloc` enables items that require the [`alloc`](https://doc.rust-lang.org/alloc/) crate.
+//! - `std` enables items that require the standard library. Implies `alloc`.
 //! - `bumpalo` enables support for [bumpalo](https://crates.io/crates/bumpalo) crate.
-#![cfg_attr(not(any(test, docsrs)), no_std)]
+//! - `talc` enables `TalcArena`, a general-purpose allocator over a buffer, built on
+//!   [talc](https://crates.io/crates/talc).
+//! - `linked_list_allocator` enables `LinkedListArena`, a general-purpose allocator over a
+//!   [linked_list_allocator](https://crates.io/crates/linked_list_allocator) heap.
+//! - `unix` enables allocators built on Unix system calls, like `MmapArena`.
+//! - `debug-tracking` makes arenas record their live allocations, which they can `dump`.
+//!   Implies `alloc`.
+//! - `nightly` makes allocator-api2 re-export the unstable `core::alloc::Allocator` trait, so the
+//!   allocators of this crate work with the collections of the standard library. Requires a
+//!   nightly compiler and conflicts with `bumpalo`, which implements allocator-api2's own trait.
+#![cfg_attr(not(any(test, docsrs, feature = "std")), no_std)]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
+#![cfg_attr(feature = "nightly", doc(test(attr(feature(allocator_api)))))]
+
+#[cfg(feature = "alloc")]
+extern crate alloc as alloc_crate;
 
 use allocator_api2::alloc::{AllocError, Allocator};
-use combinator::{Cond, Fallback, Inspect};
-use core::{alloc::Layout, ptr::NonNull};
+#[cfg(feature = "std")]
+use combinator::{Aging, FalseSharing, LayoutTable, Profiler, Shuffle};
+use combinator::{
+    Balance, Branded, Color, Cond, Fallback, FallbackArena, Inspect, Intercept, MinAlign, Probe,
+    Sample, SpillStats, Watermarks, WithHeader,
+};
+#[cfg(feature = "alloc")]
+use combinator::{BoxedAllocator, DropArena, Mirror};
+use core::{
+    alloc::Layout,
+    ffi::CStr,
+    fmt,
+    mem::{align_of, size_of, MaybeUninit},
+    ops::{ControlFlow, Range},
+    ptr::{self, NonNull},
+};
 
 #[cfg(feature = "bumpalo")]
 pub use bumpalo;
 
 pub mod alloc;
+mod arena_box;
+mod arena_rc;
+#[cfg(feature = "alloc")]
+pub mod collections;
 pub mod combinator;
+mod delegate;
+mod error;
+mod id;
+#[cfg(feature = "std")]
+pub mod preset;
+mod raw_alloc;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod scratch;
+pub mod tagged;
+pub mod util;
+mod writer;
+
+pub use arena_box::ArenaBox;
+pub use arena_rc::{ArenaRc, ArenaWeak};
+pub use error::{CStrError, Error, Operation};
+pub use id::{same_allocator, AllocatorId, SameAllocator};
+pub use raw_alloc::RawAlloc;
+pub use writer::ArenaWriter;
+
+/// Items used by the macros of this crate.
+#[doc(hidden)]
+pub mod __private {
+    pub use allocator_api2::alloc::{AllocError, Allocator};
+    #[cfg(feature = "alloc")]
+    pub use allocator_api2::vec::Vec;
+}
 
 /// Prelude exports all the allocator-related traits.
 pub mod prelude {
-    pub use crate::{Allocandrescu as _, ArenaAllocator as _};
+    #[cfg(feature = "alloc")]
+    pub use crate::collections::{CollectIn as _, TryCloneIn as _};
+    pub use crate::{
+        Allocandrescu as _, ArenaAllocator as _, ArenaExt as _, BulkAllocator as _,
+        DeallocByPtr as _, GoodSizeAllocator as _, InPlaceGrow as _, OwnsAllocations as _,
+        ProbeAllocator as _, ResetAllocator as _, SameAllocator as _, TrimAllocator as _,
+    };
     pub use allocator_api2::alloc::Allocator as _;
 }
 
 /// Allocator that uses region-based memory management.
 pub trait ArenaAllocator: Allocator {
     /// Returns `true` if the allocation specified by `ptr` and `layout` is within the allocator's arena.
-    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool;
+    ///
+    /// Implementations must return `true` for every block currently allocated by the arena and
+    /// `false` for blocks that lie outside of the memory it manages, e.g. allocated by another allocator.
+    /// This is what combinators like [`Fallback`] rely on to route
+    /// deallocations. For memory that the arena manages but hasn't handed out, the answer depends
+    /// on the arena: [`Stack`](crate::alloc::Stack) only considers its allocated prefix, while
+    /// `Bump` considers its whole chunks.
+    ///
+    /// See [zero-sized allocations](crate#zero-sized-allocations) for how zero-sized blocks are handled.
+    ///
+    /// The provided implementation checks the block against [`arena_range`](ArenaAllocator::arena_range).
+    /// Arenas spanning several ranges override it, usually with [`ranges_contain`].
+    #[inline]
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.arena_range()
+            .is_some_and(|range| ranges_contain([range], ptr, layout))
+    }
+
+    /// Returns the range of addresses that [`contains`](ArenaAllocator::contains) considers,
+    /// or `None` if the arena doesn't span a single range.
+    ///
+    /// Implementors must provide either this method or `contains`, otherwise no block is contained.
+    #[inline]
+    fn arena_range(&self) -> Option<Range<usize>> {
+        None
+    }
+
+    /// Returns the number of bytes that can still be allocated, or `None` if it is unknown or unbounded.
+    ///
+    /// It doesn't account for the padding that aligning future allocations may need.
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the number of bytes in use, including alignment padding, or `None` if it is unknown.
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        None
+    }
 }
 
 impl<A> ArenaAllocator for &A
@@ -74,6 +197,381 @@ where
     fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
         (*self).contains(ptr, layout)
     }
+
+    #[inline]
+    fn arena_range(&self) -> Option<Range<usize>> {
+        (*self).arena_range()
+    }
+
+    #[inline]
+    fn remaining_capacity(&self) -> Option<usize> {
+        (*self).remaining_capacity()
+    }
+
+    #[inline]
+    fn allocated_bytes(&self) -> Option<usize> {
+        (*self).allocated_bytes()
+    }
+}
+
+/// Allocator that can deallocate memory knowing only the pointer.
+pub trait DeallocByPtr: Allocator {
+    /// Deallocates the memory referenced by `ptr`.
+    ///
+    /// # Safety
+    /// `ptr` must denote a block of memory currently allocated via this allocator.
+    unsafe fn deallocate_ptr(&self, ptr: NonNull<u8>);
+}
+
+impl<A> DeallocByPtr for &A
+where
+    A: DeallocByPtr,
+{
+    #[inline]
+    unsafe fn deallocate_ptr(&self, ptr: NonNull<u8>) {
+        (*self).deallocate_ptr(ptr)
+    }
+}
+
+/// Allocator that can tell upfront whether an allocation would fail.
+pub trait ProbeAllocator: Allocator {
+    /// Returns `false` if allocating with `layout` is known to fail.
+    ///
+    /// The probe is conservative: it may return `true` for an allocation that then fails,
+    /// but never returns `false` for one that would succeed.
+    fn can_allocate(&self, layout: Layout) -> bool;
+}
+
+impl<A> ProbeAllocator for &A
+where
+    A: ProbeAllocator,
+{
+    #[inline]
+    fn can_allocate(&self, layout: Layout) -> bool {
+        (*self).can_allocate(layout)
+    }
+}
+
+/// Arena that can deallocate everything allocated in it at once.
+///
+/// It is not bound by [`Allocator`], as arenas like `Bump` are allocators only by reference.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, prelude::*, ResetAllocator};
+///
+/// fn recycle<A: ResetAllocator>(arena: &mut A) {
+///     arena.reset();
+/// }
+///
+/// let mut stack = Stack::<64>::new();
+/// for frame in 0..3u8 {
+///     let scratch = allocator_api2::vec![in &stack; frame; 16];
+///     assert_eq!(scratch.len(), 16);
+///     drop(scratch);
+///     recycle(&mut stack);
+/// }
+/// ```
+pub trait ResetAllocator {
+    /// Performs a mass deallocation on everything allocated in the arena.
+    ///
+    /// Does not run any `Drop` implementations on deallocated objects.
+    fn reset(&mut self);
+}
+
+impl<A> ResetAllocator for &mut A
+where
+    A: ResetAllocator + ?Sized,
+{
+    #[inline]
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+/// Allocator that can release memory it holds but doesn't use, e.g. cached blocks.
+///
+/// Combinators forward [`trim`](TrimAllocator::trim) to the allocators they wrap, so a single call
+/// at the top of a chain trims all of it.
+pub trait TrimAllocator: Allocator {
+    /// Releases unused memory, returning the number of bytes released.
+    ///
+    /// The provided implementation releases nothing.
+    #[inline]
+    fn trim(&self) -> usize {
+        0
+    }
+}
+
+impl<A> TrimAllocator for &A
+where
+    A: TrimAllocator,
+{
+    #[inline]
+    fn trim(&self) -> usize {
+        (*self).trim()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl TrimAllocator for allocator_api2::alloc::Global {}
+
+#[cfg(feature = "std")]
+impl TrimAllocator for std::alloc::System {}
+
+/// Allocator that can allocate and deallocate many blocks of the same layout at once, e.g. the
+/// nodes of a linked structure.
+///
+/// The provided methods loop over [`allocate`](Allocator::allocate) and
+/// [`deallocate`](Allocator::deallocate). Arenas override them to bump once for the whole batch.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, prelude::*};
+/// use std::alloc::Layout;
+///
+/// let stack = Stack::<1024>::new();
+/// let mut nodes = Vec::new();
+/// let count = stack
+///     .allocate_many(Layout::new::<[u64; 2]>(), 16, &mut |block| nodes.push(block.cast::<u8>()))
+///     .unwrap();
+/// assert_eq!((count, nodes.len(), stack.used()), (16, 16, 256));
+/// unsafe { stack.deallocate_many(Layout::new::<[u64; 2]>(), &mut nodes.into_iter().rev()) };
+/// assert_eq!(stack.used(), 0);
+/// ```
+pub trait BulkAllocator: Allocator {
+    /// Allocates up to `n` blocks fitting `layout`, passing each to `out`, and returns how many
+    /// were allocated.
+    ///
+    /// Allocates fewer blocks if the allocator runs out of memory, but fails if it can't allocate
+    /// even one. Allocating zero blocks always succeeds.
+    fn allocate_many(
+        &self,
+        layout: Layout,
+        n: usize,
+        out: &mut dyn FnMut(NonNull<[u8]>),
+    ) -> Result<usize, AllocError> {
+        for i in 0..n {
+            match self.allocate(layout) {
+                Ok(block) => out(block),
+                Err(err) if i == 0 => return Err(err),
+                Err(_) => return Ok(i),
+            }
+        }
+        Ok(n)
+    }
+
+    /// Deallocates the blocks fitting `layout` yielded by `ptrs`.
+    ///
+    /// Arenas that only reclaim their topmost allocation, like [`Stack`](crate::alloc::Stack),
+    /// reclaim the whole batch if the blocks are yielded in the reverse order of allocation.
+    ///
+    /// # Safety
+    /// Every pointer must denote a block of memory [currently allocated] by this allocator,
+    /// and `layout` must [fit] it. The pointers must be distinct.
+    ///
+    /// [currently allocated]: allocator_api2::alloc::Allocator#currently-allocated-memory
+    /// [fit]: allocator_api2::alloc::Allocator#memory-fitting
+    unsafe fn deallocate_many(&self, layout: Layout, ptrs: &mut dyn Iterator<Item = NonNull<u8>>) {
+        for ptr in ptrs {
+            self.deallocate(ptr, layout);
+        }
+    }
+}
+
+impl<A> BulkAllocator for &A
+where
+    A: BulkAllocator + ?Sized,
+{
+    #[inline]
+    fn allocate_many(
+        &self,
+        layout: Layout,
+        n: usize,
+        out: &mut dyn FnMut(NonNull<[u8]>),
+    ) -> Result<usize, AllocError> {
+        (**self).allocate_many(layout, n, out)
+    }
+
+    #[inline]
+    unsafe fn deallocate_many(&self, layout: Layout, ptrs: &mut dyn Iterator<Item = NonNull<u8>>) {
+        (**self).deallocate_many(layout, ptrs)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl BulkAllocator for allocator_api2::alloc::Global {}
+
+#[cfg(feature = "std")]
+impl BulkAllocator for std::alloc::System {}
+
+/// Allocator that can tell upfront how large a block it gives for a layout, like `nallocx` of
+/// jemalloc or `goodSize` of Alexandrescu's allocators.
+///
+/// Size-class allocators round the size up to their class, so a container that grows to the
+/// preferred size uses the whole block instead of wasting the rest of it. Combinators ask the
+/// allocator that would serve the layout.
+///
+/// # Example
+/// A vector can round its capacity up to what the allocator gives anyway:
+/// ```
+/// use allocandrescu::{alloc::Stack, prelude::*, GoodSizeAllocator};
+/// use allocator_api2::vec::Vec;
+/// use std::alloc::Layout;
+///
+/// fn with_good_capacity<T, A: GoodSizeAllocator>(capacity: usize, alloc: A) -> Vec<T, A> {
+///     let layout = Layout::array::<T>(capacity).unwrap();
+///     let good = alloc.preferred_layout(layout).size() / size_of::<T>().max(1);
+///     Vec::with_capacity_in(good, alloc)
+/// }
+///
+/// let stack = Stack::<256>::new();
+/// // `Stack` gives exactly the requested size.
+/// let v = with_good_capacity::<u32, _>(10, &stack);
+/// assert_eq!((v.capacity(), stack.used()), (10, 40));
+/// ```
+pub trait GoodSizeAllocator: Allocator {
+    /// Returns the layout that allocating with `layout` actually gives a block of.
+    ///
+    /// The preferred layout fits `layout`: it is at least as large and as aligned. Allocating
+    /// with it succeeds whenever allocating with `layout` does and gives a block of exactly its
+    /// size, unless the allocator state changes in between.
+    ///
+    /// The provided implementation returns `layout`.
+    #[inline]
+    fn preferred_layout(&self, layout: Layout) -> Layout {
+        layout
+    }
+}
+
+impl<A> GoodSizeAllocator for &A
+where
+    A: GoodSizeAllocator + ?Sized,
+{
+    #[inline]
+    fn preferred_layout(&self, layout: Layout) -> Layout {
+        (**self).preferred_layout(layout)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl GoodSizeAllocator for allocator_api2::alloc::Global {}
+
+#[cfg(feature = "std")]
+impl GoodSizeAllocator for std::alloc::System {}
+
+/// Allocator that can grow a block without moving it, for containers whose contents must stay
+/// where they are, e.g. because they are pinned or point into themselves.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, prelude::*};
+/// use std::alloc::Layout;
+///
+/// let stack = Stack::<64>::new();
+/// let (small, large) = (Layout::new::<[u8; 8]>(), Layout::new::<[u8; 32]>());
+/// let block = stack.allocate(small).unwrap().cast::<u8>();
+/// let grown = unsafe { stack.try_grow_in_place(block, small, large) }.unwrap();
+/// assert_eq!((grown.cast::<u8>(), grown.len()), (block, 32));
+/// // Another block on top pins the first one in place.
+/// let _top = stack.allocate(small).unwrap();
+/// assert!(unsafe { stack.try_grow_in_place(block, large, Layout::new::<[u8; 40]>()) }.is_err());
+/// ```
+pub trait InPlaceGrow: Allocator {
+    /// Extends the block to fit `new_layout` without moving it, or fails leaving it untouched.
+    ///
+    /// On success the returned block starts at `ptr` and, like with [`grow`](Allocator::grow),
+    /// keeps the contents of the old one. On failure the block stays allocated with `old_layout`.
+    ///
+    /// The provided implementation always fails.
+    ///
+    /// # Safety
+    /// `ptr` must denote a block of memory [currently allocated] by this allocator, `old_layout`
+    /// must [fit] it, and `new_layout.size()` must be greater than or equal to `old_layout.size()`.
+    ///
+    /// [currently allocated]: allocator_api2::alloc::Allocator#currently-allocated-memory
+    /// [fit]: allocator_api2::alloc::Allocator#memory-fitting
+    #[inline]
+    unsafe fn try_grow_in_place(
+        &self,
+        _ptr: NonNull<u8>,
+        _old_layout: Layout,
+        _new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        Err(AllocError)
+    }
+}
+
+impl<A> InPlaceGrow for &A
+where
+    A: InPlaceGrow + ?Sized,
+{
+    #[inline]
+    unsafe fn try_grow_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        (**self).try_grow_in_place(ptr, old_layout, new_layout)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl InPlaceGrow for allocator_api2::alloc::Global {}
+
+#[cfg(feature = "std")]
+impl InPlaceGrow for std::alloc::System {}
+
+/// Allocator that keeps track of the blocks it handed out, for debugging.
+///
+/// [`ArenaAllocator::contains`] answers whether a block lies within the memory of an arena,
+/// which stays true after the block is deallocated. [`is_live`](OwnsAllocations::is_live)
+/// answers whether the block is still allocated, which catches use after free and double frees.
+///
+/// It is implemented by [`Stack`](crate::alloc::Stack) with the `debug-tracking` feature and by
+/// the debugging combinators, and forwarded by the combinators that don't change pointers.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "debug-tracking")] {
+/// use allocandrescu::{alloc::Stack, prelude::*};
+/// use std::alloc::Layout;
+///
+/// let stack = Stack::<64>::new();
+/// let layout = Layout::new::<u64>();
+/// let block = stack.allocate(layout).unwrap().cast::<u8>();
+/// let _top = stack.allocate(layout).unwrap();
+/// unsafe { stack.deallocate(block, layout) };
+/// // The stack only reclaims its topmost block, so the freed one is still in its used region.
+/// assert!(stack.contains(block, layout));
+/// assert!(!stack.is_live(block));
+/// assert_eq!(stack.live_count(), 1);
+/// # }
+/// ```
+pub trait OwnsAllocations: Allocator {
+    /// Returns `true` if `ptr` is the start of a block currently allocated by this allocator.
+    ///
+    /// Whether zero-sized blocks are tracked depends on the allocator.
+    fn is_live(&self, ptr: NonNull<u8>) -> bool;
+
+    /// Returns the number of blocks currently allocated.
+    fn live_count(&self) -> usize;
+}
+
+impl<A> OwnsAllocations for &A
+where
+    A: OwnsAllocations + ?Sized,
+{
+    #[inline]
+    fn is_live(&self, ptr: NonNull<u8>) -> bool {
+        (**self).is_live(ptr)
+    }
+
+    #[inline]
+    fn live_count(&self) -> usize {
+        (**self).live_count()
+    }
 }
 
 /// Extension trait for [`Allocator`] trait that provides methods for combining allocators.
@@ -95,6 +593,9 @@ pub trait Allocandrescu: Sized {
     /// let layout = Layout::new::<u8>();
     /// assert!(stack.contains(NonNull::new(addr_of!(v[0]).cast_mut()).unwrap(), layout));
     /// assert!(stack.contains(NonNull::new(addr_of!(v[15]).cast_mut()).unwrap(), layout));
+    /// // The rest of the stack hasn't been handed out.
+    /// let past_end = addr_of!(v[15]).cast_mut().wrapping_add(1);
+    /// assert!(!stack.contains(NonNull::new(past_end).unwrap(), layout));
     ///
     /// let result = v.try_reserve(1);
     /// assert!(result.is_err());
@@ -134,6 +635,60 @@ pub trait Allocandrescu: Sized {
         Fallback::new(self, secondary)
     }
 
+    /// Combines allocator with a secondary arena allocator to be used if the primary one fails.
+    ///
+    /// Unlike [`fallback`](Allocandrescu::fallback), the primary doesn't need to be an arena,
+    /// because ownership of blocks is decided by the secondary arena.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::vec;
+    /// use std::{alloc::Layout, ptr::{addr_of, NonNull}};
+    ///
+    /// let stack = Stack::<16>::new();
+    /// let reserve = Stack::<1024>::new();
+    /// let alloc = stack
+    ///     .by_ref()
+    ///     .fallback(std::alloc::System.cond(|layout| layout.size() <= 64))
+    ///     .fallback_arena(reserve.by_ref());
+    /// let layout = Layout::new::<u8>();
+    ///
+    /// let v = vec![in &alloc; 0u8; 128];
+    /// assert!(reserve.contains(NonNull::new(addr_of!(v[0]).cast_mut()).unwrap(), layout));
+    /// ```
+    fn fallback_arena<S>(self, secondary: S) -> FallbackArena<Self, S>
+    where
+        S: ArenaAllocator,
+    {
+        FallbackArena::new(self, secondary)
+    }
+
+    /// Combines allocator with a secondary allocator to be used if the primary one fails,
+    /// counting the allocations served by each side.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::vec;
+    ///
+    /// let stack = Stack::<16>::new();
+    /// let alloc = stack.by_ref().fallback_counted(std::alloc::System);
+    ///
+    /// let a = vec![in &alloc; 0u8; 16];
+    /// let b = vec![in &alloc; 0u8; 16];
+    /// assert_eq!(alloc.stats().primary_hits(), 1);
+    /// assert_eq!(alloc.stats().secondary_hits(), 1);
+    /// println!("{}", alloc.stats());
+    /// ```
+    fn fallback_counted<S>(self, secondary: S) -> Fallback<Self, S, SpillStats>
+    where
+        Self: ArenaAllocator,
+        S: Allocator,
+    {
+        Fallback::counted(self, secondary)
+    }
+
     /// Combines allocator with a function that does something to each allocation result.
     ///
     /// This combinator is useful for adding logging to allocators.
@@ -142,7 +697,7 @@ pub trait Allocandrescu: Sized {
     /// ```
     /// use allocandrescu::{alloc::Stack, prelude::*};
     /// use allocator_api2::vec;
-    /// use std::ptr::{addr_of, NonNull};
+    /// use std::{alloc::Layout, ptr::NonNull};
     ///
     /// let alloc = Stack::<4>::new().inspect(|layout, result| {
     ///     match result {
@@ -160,8 +715,8 @@ pub trait Allocandrescu: Sized {
     ///     }
     /// });
     ///
-    /// let mut v = vec![in &alloc; 0u8; 4];
-    /// assert!(v.try_reserve(1).is_err());
+    /// let v = vec![in &alloc; 0u8; 4];
+    /// assert!(alloc.allocate(Layout::new::<[u8; 8]>()).is_err());
     /// ```
     ///
     /// Outputs:
@@ -175,6 +730,1354 @@ pub trait Allocandrescu: Sized {
     {
         Inspect::new(self, f)
     }
-}
 
-impl<A: Allocator> Allocandrescu for A {}
+    /// Makes allocator sample every `n`-th allocation or reallocation into statistics, which can
+    /// be extrapolated to all of them.
+    ///
+    /// Use [`Sample::observe`] to also pass the sampled results to a function, like with
+    /// [`inspect`](Allocandrescu::inspect), and [`Sample::random`] to sample randomly instead.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::vec::Vec;
+    ///
+    /// let alloc = Stack::<1024>::new().sample(2);
+    /// let a = Vec::<u8, _>::with_capacity_in(100, &alloc);
+    /// let b = Vec::<u8, _>::with_capacity_in(200, &alloc);
+    /// let snapshot = alloc.snapshot();
+    /// assert_eq!((snapshot.sampled(), snapshot.estimated_bytes()), (1, 400));
+    /// # drop((a, b));
+    /// ```
+    fn sample(self, n: u64) -> Sample<Self> {
+        Sample::new(self, n)
+    }
+
+    /// Combines allocator with a function called once each time the live bytes rise to one of
+    /// `thresholds`, with the threshold and the live bytes.
+    ///
+    /// A threshold fires again only after the live bytes fall back below it, see
+    /// [`Watermarks::with_hysteresis`] to require a deeper fall.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::vec::Vec;
+    /// use std::cell::Cell;
+    ///
+    /// let shed_load = Cell::new(false);
+    /// let alloc = Stack::<1000>::new().watermarks([800], |_, _| shed_load.set(true));
+    /// let v = Vec::<u8, _>::with_capacity_in(500, &alloc);
+    /// assert!(!shed_load.get());
+    /// let w = Vec::<u8, _>::with_capacity_in(400, &alloc);
+    /// assert!(shed_load.get());
+    /// # drop((v, w));
+    /// ```
+    fn watermarks<F, const N: usize>(self, thresholds: [usize; N], f: F) -> Watermarks<Self, F, N>
+    where
+        F: Fn(usize, usize),
+    {
+        Watermarks::new(self, thresholds, f)
+    }
+
+    /// Combines allocator with a hook that can veto each block it allocates or grows.
+    ///
+    /// Useful for policies that can only be decided once the allocation succeeded, e.g. ones
+    /// consulting global memory pressure. A vetoed block is deallocated and the caller sees
+    /// [`AllocError`].
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::vec::Vec;
+    /// use std::{alloc::Layout, ops::ControlFlow};
+    ///
+    /// let stack = Stack::<1024>::new();
+    /// let alloc = stack.by_ref().intercept(|_, _| {
+    ///     if stack.used() > 512 {
+    ///         ControlFlow::Break(())
+    ///     } else {
+    ///         ControlFlow::Continue(())
+    ///     }
+    /// });
+    ///
+    /// let v = Vec::<u8, _>::with_capacity_in(256, &alloc);
+    /// assert!(alloc.allocate(Layout::new::<[u8; 512]>()).is_err());
+    /// assert_eq!(stack.used(), 256);
+    /// # drop(v);
+    /// ```
+    fn intercept<F>(self, f: F) -> Intercept<Self, F>
+    where
+        F: Fn(Layout, NonNull<[u8]>) -> ControlFlow<()>,
+    {
+        Intercept::new(self, f)
+    }
+
+    /// Makes allocator fail fast, without attempting allocations that its
+    /// [probe](ProbeAllocator::can_allocate) rejects.
+    ///
+    /// Useful as the primary of [`fallback`](Allocandrescu::fallback) when attempting a doomed
+    /// allocation is more expensive than probing, e.g. in deep allocator chains.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::vec;
+    ///
+    /// let stack = Stack::<64>::new();
+    /// let alloc = stack.by_ref().probe().fallback(std::alloc::System);
+    /// let v = vec![in &alloc; 0u8; 128];
+    /// ```
+    fn probe(self) -> Probe<Self>
+    where
+        Self: ProbeAllocator,
+    {
+        Probe::new(self)
+    }
+
+    /// Combines allocator with a header storing the layout of each allocation.
+    ///
+    /// The resulting allocator implements [`DeallocByPtr`].
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::prelude::*;
+    /// use std::alloc::Layout;
+    ///
+    /// let alloc = std::alloc::System.with_header();
+    /// let ptr = alloc.allocate(Layout::new::<u64>()).unwrap();
+    /// unsafe { alloc.deallocate_ptr(ptr.cast()) };
+    /// ```
+    fn with_header(self) -> WithHeader<Self> {
+        WithHeader::new(self)
+    }
+
+    /// Combines allocator with a rotating offset of `(n % colors) * stride` bytes for the `n`-th
+    /// allocation, spreading same-sized buffers over different cache sets.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::prelude::*;
+    /// use std::alloc::Layout;
+    ///
+    /// let alloc = std::alloc::System.min_align::<4096>().color(8, 64);
+    /// let layout = Layout::from_size_align(4096, 64).unwrap();
+    /// let a = alloc.allocate(layout).unwrap().cast::<u8>();
+    /// let b = alloc.allocate(layout).unwrap().cast::<u8>();
+    /// assert_ne!(a.as_ptr() as usize % 4096, b.as_ptr() as usize % 4096);
+    /// # unsafe { alloc.deallocate(a, layout); alloc.deallocate(b, layout) };
+    /// ```
+    fn color(self, colors: usize, stride: usize) -> Color<Self> {
+        Color::new(self, colors, stride)
+    }
+
+    /// Combines allocator with a minimum alignment of `ALIGN` for every allocation.
+    ///
+    /// The low bits of the returned pointers are free for [tags](crate::tagged).
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use std::alloc::Layout;
+    ///
+    /// let stack = Stack::<64>::new();
+    /// let alloc = stack.by_ref().min_align::<16>();
+    /// let ptr = alloc.allocate(Layout::new::<u8>()).unwrap();
+    /// assert_eq!(ptr.cast::<u8>().as_ptr() as usize % 16, 0);
+    /// ```
+    fn min_align<const ALIGN: usize>(self) -> MinAlign<Self, ALIGN> {
+        MinAlign::new(self)
+    }
+
+    /// Combines two allocators, serving each allocation from the one with more remaining capacity.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use std::alloc::Layout;
+    ///
+    /// let (a, b) = (Stack::<64>::new(), Stack::<128>::new());
+    /// let alloc = a.by_ref().balance(b.by_ref());
+    /// alloc.allocate(Layout::new::<[u8; 32]>()).unwrap();
+    /// assert_eq!((a.used(), b.used()), (0, 32));
+    /// ```
+    fn balance<B>(self, other: B) -> Balance<Self, B> {
+        Balance::new(self, other)
+    }
+
+    /// Moves allocator to the heap, erasing its type.
+    ///
+    /// See [`boxed_scoped`](Allocandrescu::boxed_scoped) for allocators that borrow data.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, combinator::BoxedAllocator, prelude::*};
+    /// use allocator_api2::vec::Vec;
+    ///
+    /// fn make_alloc() -> BoxedAllocator<'static> {
+    ///     Stack::<1024>::new()
+    ///         .cond(|layout| layout.size() <= 64)
+    ///         .fallback(Stack::<4096>::new())
+    ///         .boxed()
+    /// }
+    ///
+    /// let alloc = make_alloc();
+    /// let mut v = Vec::new_in(&alloc);
+    /// v.extend(0..100u8);
+    /// ```
+    #[cfg(feature = "alloc")]
+    fn boxed(self) -> BoxedAllocator<'static>
+    where
+        Self: ArenaAllocator + 'static,
+    {
+        BoxedAllocator::new(self)
+    }
+
+    /// Moves allocator to the heap, erasing its type. Unlike [`boxed`](Allocandrescu::boxed),
+    /// the allocator may borrow data, like a `&Stack`.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, combinator::BoxedAllocator, prelude::*};
+    /// use allocator_api2::vec::Vec;
+    ///
+    /// let stack = Stack::<1024>::new();
+    /// let alloc: BoxedAllocator<'_> = stack.by_ref().boxed_scoped();
+    /// let mut v = Vec::new_in(&alloc);
+    /// v.extend(0..100u8);
+    /// ```
+    #[cfg(feature = "alloc")]
+    fn boxed_scoped<'a>(self) -> BoxedAllocator<'a>
+    where
+        Self: ArenaAllocator + 'a,
+    {
+        BoxedAllocator::new(self)
+    }
+
+    /// Combines allocator with a known-good `reference` allocator that shadows every operation.
+    ///
+    /// The resulting allocator panics as soon as the behavior of the two allocators diverges.
+    /// This combinator is useful for testing new allocators.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::vec::Vec;
+    ///
+    /// let stack = Stack::<1024>::new();
+    /// let alloc = stack.by_ref().mirror(std::alloc::System);
+    ///
+    /// let mut v = Vec::new_in(&alloc);
+    /// v.extend(0..100u8);
+    /// v.shrink_to_fit();
+    /// ```
+    #[cfg(feature = "alloc")]
+    fn mirror<B>(self, reference: B) -> Mirror<Self, B>
+    where
+        B: Allocator,
+    {
+        Mirror::new(self, reference)
+    }
+
+    /// Makes arena run the destructors of values allocated with [`DropArena::alloc_value`]
+    /// when it is reset or dropped.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    ///
+    /// let mut stack = Stack::<1024>::new();
+    /// let mut arena = stack.drop_arena();
+    /// for frame in 0..3 {
+    ///     let name = arena.alloc_value(format!("frame {frame}")).unwrap();
+    ///     name.push('!');
+    ///     arena.reset();
+    /// }
+    /// ```
+    #[cfg(feature = "alloc")]
+    fn drop_arena(&mut self) -> DropArena<'_, Self>
+    where
+        Self: Allocator,
+    {
+        DropArena::new(self)
+    }
+
+    /// Calls `f` with a view of the allocator that brands its allocations with a lifetime unique to
+    /// the call, so that deallocating them in another allocator fails to compile.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    ///
+    /// let (a, b) = (Stack::<64>::new(), Stack::<64>::new());
+    /// a.with_branded(|a| {
+    ///     b.with_branded(|b| {
+    ///         let x = a.alloc_value(1u32).unwrap();
+    ///         let y = b.alloc_value(2u32).unwrap();
+    ///         assert_eq!(*x + *y, 3);
+    ///         a.dealloc(x);
+    ///         b.dealloc(y);
+    ///     })
+    /// });
+    /// ```
+    fn with_branded<R, F>(&self, f: F) -> R
+    where
+        Self: Allocator,
+        F: for<'brand> FnOnce(Branded<'brand, &Self>) -> R,
+    {
+        f(Branded::new(self))
+    }
+
+    /// Combines allocator with a heap profiler that records every allocation by its call site.
+    ///
+    /// The recorded profile can be saved in the [DHAT](https://valgrind.org/docs/manual/dh-manual.html)
+    /// JSON format with [`Profiler::save`] and explored in the
+    /// [DHAT viewer](https://nnethercote.github.io/dh_view/dh_view.html).
+    ///
+    /// # Example
+    /// ```no_run
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::vec;
+    ///
+    /// let alloc = Stack::<1024>::new().profile();
+    /// let v = vec![in &alloc; 0u8; 100];
+    /// drop(v);
+    /// alloc.save("dhat-heap.json").unwrap();
+    /// ```
+    #[cfg(feature = "std")]
+    fn profile(self) -> Profiler<Self> {
+        Profiler::new(self)
+    }
+
+    /// Combines allocator with a tracker of the age of each live allocation, for finding blocks
+    /// that live longer than they should.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::boxed::Box;
+    /// use std::time::Duration;
+    ///
+    /// let alloc = Stack::<1024>::new().track_ages();
+    /// let cache = Box::new_in([0u8; 64], &alloc);
+    /// for (layout, born, location) in alloc.older_than(Duration::ZERO) {
+    ///     println!("{} bytes from {location:?} live for {:?}", layout.size(), born.elapsed());
+    /// }
+    /// # drop(cache);
+    /// ```
+    #[cfg(feature = "std")]
+    fn track_ages(self) -> Aging<Self> {
+        Aging::new(self)
+    }
+
+    /// Combines allocator with a table of its live allocations grouped by layout, for finding
+    /// which sizes leak.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::boxed::Box;
+    ///
+    /// let alloc = Stack::<1024>::new().count_layouts();
+    /// let a = Box::new_in(1u32, &alloc);
+    /// let b = Box::new_in(2u32, &alloc);
+    /// let row = alloc.by_layout()[&(4, 4)];
+    /// assert_eq!((row.live_count, row.live_bytes), (2, 8));
+    /// print!("{}", alloc.top(10));
+    /// # drop((a, b));
+    /// ```
+    #[cfg(feature = "std")]
+    fn count_layouts(self) -> LayoutTable<Self> {
+        LayoutTable::new(self)
+    }
+
+    /// Combines allocator with randomized placement of allocations, seeded with `seed`.
+    ///
+    /// This combinator is useful for catching code that relies on allocations being adjacent or
+    /// in ascending address order, which bump allocators happen to guarantee.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::boxed::Box;
+    ///
+    /// let stack = Stack::<1024>::new();
+    /// let alloc = stack.by_ref().shuffle(42);
+    ///
+    /// let a = Box::new_in(1u8, &alloc);
+    /// let b = Box::new_in(2u8, &alloc);
+    /// assert_eq!(*a + *b, 3);
+    /// ```
+    #[cfg(feature = "std")]
+    fn shuffle(self, seed: u64) -> Shuffle<Self> {
+        Shuffle::new(self, seed)
+    }
+
+    /// Combines allocator with a detector that panics when live allocations made by different
+    /// threads share a cache line.
+    ///
+    /// Use [`FalseSharing::with_handler`] to handle the reports differently.
+    ///
+    /// # Example
+    /// ```should_panic
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use allocator_api2::alloc::{AllocError, Allocator};
+    /// use std::{alloc::Layout, ptr::NonNull, sync::Mutex, thread};
+    ///
+    /// // Packs allocations next to each other, wherever they come from.
+    /// struct Packed(Mutex<Stack<1024, 64>>);
+    ///
+    /// unsafe impl Allocator for Packed {
+    ///     fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+    ///         self.0.lock().unwrap().allocate(layout)
+    ///     }
+    ///
+    ///     unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+    ///         self.0.lock().unwrap().deallocate(ptr, layout)
+    ///     }
+    /// }
+    ///
+    /// let alloc = Packed(Mutex::default()).detect_false_sharing();
+    /// let counter = Layout::new::<u64>();
+    /// thread::scope(|scope| scope.spawn(|| alloc.allocate(counter).map(drop)).join().unwrap())?;
+    /// // Panics, the counters of both threads are on the same line.
+    /// alloc.allocate(counter)?;
+    /// # Ok::<(), AllocError>(())
+    /// ```
+    #[cfg(feature = "std")]
+    fn detect_false_sharing(self) -> FalseSharing<Self> {
+        FalseSharing::new(self)
+    }
+}
+
+impl<A: Allocator> Allocandrescu for A {}
+
+/// Extension trait for [`Allocator`] trait that provides methods for allocating typed values,
+/// like [`Bump`](crate::alloc::Bump) does.
+///
+/// The returned references borrow the allocator, so the values can't outlive it.
+/// The values are never dropped nor deallocated: their memory is reclaimed when the arena is reset
+/// or dropped, and running `Drop` of values that need it is the caller's concern.
+/// With allocators that don't reclaim memory in bulk, like `System`, the values are leaked.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, prelude::*};
+///
+/// let stack = Stack::<64>::new();
+/// let value = stack.alloc_value(1u32).unwrap();
+/// let numbers = stack.alloc_slice_copy(&[1u16, 2, 3]).unwrap();
+/// let name = stack.alloc_str("stack").unwrap();
+/// *value += numbers.iter().sum::<u16>() as u32;
+/// name.make_ascii_uppercase();
+/// assert_eq!((*value, &*name), (7, "STACK"));
+/// ```
+// Every call returns a new allocation, so the mutable references never alias.
+#[allow(clippy::mut_from_ref)]
+pub trait ArenaExt: Allocator {
+    /// Moves `value` into the allocator.
+    #[inline]
+    fn alloc_value<T>(&self, value: T) -> Result<&mut T, Error> {
+        let layout = Layout::new::<T>();
+        let ptr = self
+            .allocate(layout)
+            .map_err(Error::map(Operation::Allocate, layout))?
+            .cast::<T>();
+        unsafe {
+            ptr.as_ptr().write(value);
+            Ok(&mut *ptr.as_ptr())
+        }
+    }
+
+    /// Moves the value returned by `f` into the allocator.
+    ///
+    /// Unlike with [`alloc_value`](ArenaExt::alloc_value), the memory is allocated before `f` is
+    /// called, which lets the optimizer construct the value directly in it instead of copying it
+    /// from the call stack. This is not guaranteed and doesn't happen in unoptimized builds, see
+    /// [`try_alloc_with_init`](ArenaExt::try_alloc_with_init) for a variant that guarantees it.
+    ///
+    /// If `f` panics, the memory is deallocated.
+    #[inline(always)]
+    fn try_alloc_with<T, F>(&self, f: F) -> Result<&mut T, Error>
+    where
+        F: FnOnce() -> T,
+    {
+        let mut slot = SliceGuard::new(self, 1)?;
+        unsafe { slot.push_unchecked(f()) };
+        Ok(&mut slot.finish()[0])
+    }
+
+    /// Allocates a value and lets `init` initialize it in place, so that it is never on the call stack.
+    ///
+    /// If `init` panics, the memory is deallocated.
+    ///
+    /// # Safety
+    /// `init` must initialize the whole value, unless it panics.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    /// use std::mem::MaybeUninit;
+    ///
+    /// let stack = Box::new(Stack::<4096>::new());
+    /// let page = unsafe {
+    ///     stack.try_alloc_with_init(|page: &mut MaybeUninit<[u8; 4096]>| {
+    ///         page.as_mut_ptr().write_bytes(0, 1)
+    ///     })
+    /// };
+    /// assert!(page.unwrap().iter().all(|&b| b == 0));
+    /// ```
+    #[inline]
+    unsafe fn try_alloc_with_init<T, F>(&self, init: F) -> Result<&mut T, Error>
+    where
+        F: FnOnce(&mut MaybeUninit<T>),
+    {
+        let mut slot = SliceGuard::<T, _>::new(self, 1)?;
+        init(&mut *slot.ptr.as_ptr().cast::<MaybeUninit<T>>());
+        slot.len = 1;
+        Ok(&mut slot.finish()[0])
+    }
+
+    /// Copies `src` into the allocator.
+    #[inline]
+    fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> Result<&mut [T], Error> {
+        let layout = Layout::for_value(src);
+        let ptr = self
+            .allocate(layout)
+            .map_err(Error::map(Operation::Allocate, layout))?
+            .cast::<T>();
+        unsafe {
+            ptr.as_ptr()
+                .copy_from_nonoverlapping(src.as_ptr(), src.len());
+            Ok(core::slice::from_raw_parts_mut(ptr.as_ptr(), src.len()))
+        }
+    }
+
+    /// Copies `src` into the allocator.
+    #[inline]
+    fn alloc_str(&self, src: &str) -> Result<&mut str, Error> {
+        let bytes = self.alloc_slice_copy(src.as_bytes())?;
+        Ok(unsafe { core::str::from_utf8_unchecked_mut(bytes) })
+    }
+
+    /// Formats `args` into the allocator. See [`format_in!`] for a more convenient way to call it.
+    ///
+    /// The string is written into a buffer that [grows](Allocator::grow) twice as large whenever
+    /// it is full, and is shrunk to fit at the end. If growing fails, e.g. because the allocator
+    /// doesn't support it or has no room left for the doubled buffer, `args` are formatted twice:
+    /// once to measure the string and once into an allocation of the exact size.
+    /// An empty string takes no space in the allocator.
+    ///
+    /// # Panics
+    /// Panics if a formatting trait implementation returns an error, like `format!` does.
+    fn alloc_fmt(&self, args: fmt::Arguments<'_>) -> Result<&mut str, Error> {
+        if let Some(s) = args.as_str() {
+            return self.alloc_str(s);
+        }
+        let mut writer = FmtWriter {
+            slice: SliceGuard::new(self, 0)?,
+            error: None,
+        };
+        if let Err(err) = writer.format(args) {
+            // Measures the string to allocate it at once.
+            drop(writer);
+            let mut len = FmtLen(0);
+            fmt::write(&mut len, args).expect(FMT_ERROR);
+            let slice = SliceGuard::new(self, len.0).map_err(|_| err)?;
+            writer = FmtWriter { slice, error: None };
+            writer.format(args)?;
+        }
+        writer.slice.shrink_to_fit();
+        let bytes = writer.slice.finish();
+        Ok(unsafe { core::str::from_utf8_unchecked_mut(bytes) })
+    }
+
+    /// Copies `src` into the allocator as a NUL-terminated C string, e.g. for passing it to a C API.
+    ///
+    /// Fails with [`CStrError::InteriorNul`] if `src` contains a NUL byte.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*, CStrError};
+    ///
+    /// let stack = Stack::<64>::new();
+    /// let name = stack.alloc_cstr("scratch").unwrap();
+    /// assert_eq!(name.to_bytes_with_nul(), b"scratch\0");
+    /// assert_eq!(
+    ///     stack.alloc_cstr("nul\0inside").unwrap_err(),
+    ///     CStrError::InteriorNul { position: 3 }
+    /// );
+    /// ```
+    #[inline]
+    fn alloc_cstr(&self, src: &str) -> Result<&CStr, CStrError> {
+        self.alloc_cstr_bytes(src.as_bytes())
+    }
+
+    /// Copies `src` into the allocator as a NUL-terminated C string.
+    ///
+    /// Fails with [`CStrError::InteriorNul`] if `src` contains a NUL byte.
+    fn alloc_cstr_bytes(&self, src: &[u8]) -> Result<&CStr, CStrError> {
+        if let Some(position) = src.iter().position(|&b| b == 0) {
+            return Err(CStrError::InteriorNul { position });
+        }
+        let len = src
+            .len()
+            .checked_add(1)
+            .ok_or(Error::new(Operation::Allocate, None))?;
+        let mut slice = SliceGuard::new(self, len)?;
+        slice.extend_from_slice(src)?;
+        slice.extend_from_slice(&[0])?;
+        Ok(unsafe { CStr::from_bytes_with_nul_unchecked(slice.finish()) })
+    }
+
+    /// Concatenates `segments` into the allocator as a NUL-terminated C string, e.g. for building
+    /// a path without an intermediate `String`.
+    ///
+    /// The string [grows](Allocator::grow) like with [`alloc_iter`](ArenaExt::alloc_iter).
+    /// Fails with [`CStrError::InteriorNul`] if a segment contains a NUL byte, with the
+    /// position of the byte in the concatenated string.
+    ///
+    /// # Example
+    /// ```
+    /// use allocandrescu::{alloc::Stack, prelude::*};
+    ///
+    /// let stack = Stack::<64>::new();
+    /// let path = stack.alloc_cstr_from_iter(["/tmp", "/", "arena"]).unwrap();
+    /// assert_eq!(path.to_str(), Ok("/tmp/arena"));
+    /// ```
+    fn alloc_cstr_from_iter<I>(&self, segments: I) -> Result<&CStr, CStrError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut slice = SliceGuard::new(self, 0)?;
+        for segment in segments {
+            let segment = segment.as_ref();
+            if let Some(position) = segment.iter().position(|&b| b == 0) {
+                return Err(CStrError::InteriorNul {
+                    position: slice.len + position,
+                });
+            }
+            slice.extend_from_slice(segment)?;
+        }
+        slice.extend_from_slice(&[0])?;
+        slice.shrink_to_fit();
+        Ok(unsafe { CStr::from_bytes_with_nul_unchecked(slice.finish()) })
+    }
+
+    /// Allocates a slice of `len` uninitialized values.
+    #[inline]
+    fn alloc_slice_uninit<T>(&self, len: usize) -> Result<&mut [MaybeUninit<T>], Error> {
+        let layout = Layout::array::<T>(len).map_err(|_| Error::new(Operation::Allocate, None))?;
+        let ptr = self
+            .allocate(layout)
+            .map_err(Error::map(Operation::Allocate, layout))?
+            .cast::<MaybeUninit<T>>();
+        Ok(unsafe { core::slice::from_raw_parts_mut(ptr.as_ptr(), len) })
+    }
+
+    /// Allocates a slice of `len` values returned by `f` called with the index of each.
+    ///
+    /// If `f` panics, the values returned so far are dropped and the slice is deallocated.
+    fn alloc_slice_fill_with<T, F>(&self, len: usize, mut f: F) -> Result<&mut [T], Error>
+    where
+        F: FnMut(usize) -> T,
+    {
+        let mut slice = SliceGuard::new(self, len)?;
+        for i in 0..len {
+            unsafe { slice.push_unchecked(f(i)) };
+        }
+        Ok(slice.finish())
+    }
+
+    /// Allocates a slice of the values yielded by `iter`.
+    ///
+    /// The exact length of the iterator isn't known upfront, so the slice starts with the capacity
+    /// of its lower size hint and [grows](Allocator::grow) twice as large whenever it is full.
+    /// Arenas grow their topmost allocation in place, other allocators may move it. At the end the
+    /// slice is shrunk to fit the values.
+    ///
+    /// If the iterator panics or the slice fails to grow, the values yielded so far are dropped
+    /// and the slice is deallocated.
+    fn alloc_iter<T, I>(&self, iter: I) -> Result<&mut [T], Error>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let iter = iter.into_iter();
+        let mut slice = SliceGuard::new(self, iter.size_hint().0)?;
+        for value in iter {
+            if slice.len == slice.cap {
+                slice.grow()?;
+            }
+            unsafe { slice.push_unchecked(value) };
+        }
+        slice.shrink_to_fit();
+        Ok(slice.finish())
+    }
+}
+
+impl<A: Allocator + ?Sized> ArenaExt for A {}
+
+/// Formats a string into an allocator, returning `Result<&mut str, Error>`.
+///
+/// The first argument is the allocator, followed by the arguments of `format!`.
+/// See [`ArenaExt::alloc_fmt`] for details.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, format_in};
+///
+/// let stack = Stack::<64>::new();
+/// let (x, y) = (3, 4);
+/// let point = format_in!(&stack, "({x}, {y})").unwrap();
+/// assert_eq!(point, "(3, 4)");
+/// assert_eq!(stack.used(), 6);
+/// ```
+#[macro_export]
+macro_rules! format_in {
+    ($alloc:expr, $($arg:tt)*) => {{
+        use $crate::ArenaExt as _;
+        ($alloc).alloc_fmt(::core::format_args!($($arg)*))
+    }};
+}
+
+/// Declares a [`Stack`](crate::alloc::Stack) of the given size in the current scope and binds a
+/// reference to it, optionally [falling back](Allocandrescu::fallback) to another allocator.
+///
+/// The stack itself can't be named, so the allocator is the only way to reach it, and it can't
+/// outlive the scope:
+/// ```compile_fail
+/// use allocandrescu::{alloc::Stack, stack_arena};
+///
+/// fn scratch() -> &'static Stack<64> {
+///     stack_arena!(let alloc: 64);
+///     alloc
+/// }
+/// ```
+///
+/// # Example
+/// ```
+/// use allocandrescu::stack_arena;
+/// use allocator_api2::vec::Vec;
+///
+/// fn sum_of_squares(n: u64) -> u64 {
+///     stack_arena!(let alloc: 256, else std::alloc::System);
+///     let mut squares = Vec::new_in(&alloc);
+///     squares.extend((1..=n).map(|i| i * i));
+///     squares.iter().sum()
+/// }
+///
+/// assert_eq!(sum_of_squares(3), 14);
+/// // Spills over to the system allocator.
+/// assert_eq!(sum_of_squares(100), 338_350);
+/// ```
+#[macro_export]
+macro_rules! stack_arena {
+    (let $name:ident: $size:expr $(;)?) => {
+        let stack = $crate::alloc::Stack::<{ $size }>::new();
+        let $name = &stack;
+    };
+    (let $name:ident: $size:expr, else $fallback:expr $(;)?) => {
+        let stack = $crate::alloc::Stack::<{ $size }>::new();
+        let $name = $crate::Allocandrescu::fallback(&stack, $fallback);
+    };
+}
+
+const FMT_ERROR: &str =
+    "a formatting trait implementation returned an error when the underlying stream did not";
+
+/// A string being formatted by [`ArenaExt::alloc_fmt`].
+struct FmtWriter<'a, A: Allocator + ?Sized> {
+    slice: SliceGuard<'a, u8, A>,
+    /// The reason why writing failed.
+    error: Option<Error>,
+}
+
+impl<A> FmtWriter<'_, A>
+where
+    A: Allocator + ?Sized,
+{
+    fn format(&mut self, args: fmt::Arguments<'_>) -> Result<(), Error> {
+        match fmt::write(self, args) {
+            Ok(()) => Ok(()),
+            Err(fmt::Error) => Err(self.error.take().expect(FMT_ERROR)),
+        }
+    }
+}
+
+impl<A> fmt::Write for FmtWriter<'_, A>
+where
+    A: Allocator + ?Sized,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.slice.extend_from_slice(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
+
+/// Counts the bytes of a formatted string.
+struct FmtLen(usize);
+
+impl fmt::Write for FmtLen {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// A slice being filled by [`ArenaExt`]. Drops its values and deallocates on unwinding.
+struct SliceGuard<'a, T, A: Allocator + ?Sized> {
+    alloc: &'a A,
+    ptr: NonNull<T>,
+    cap: usize,
+    len: usize,
+}
+
+impl<'a, T, A> SliceGuard<'a, T, A>
+where
+    A: Allocator + ?Sized,
+{
+    fn new(alloc: &'a A, cap: usize) -> Result<Self, Error> {
+        // Zero-sized values never need to grow.
+        let cap = if size_of::<T>() == 0 { usize::MAX } else { cap };
+        let layout = Layout::array::<T>(cap).map_err(|_| Error::new(Operation::Allocate, None))?;
+        let ptr = alloc
+            .allocate(layout)
+            .map_err(Error::map(Operation::Allocate, layout))?
+            .cast();
+        Ok(Self {
+            alloc,
+            ptr,
+            cap,
+            len: 0,
+        })
+    }
+
+    #[inline]
+    fn layout(&self) -> Layout {
+        // The layout was checked when the slice was allocated with this capacity.
+        unsafe { Layout::from_size_align_unchecked(self.cap * size_of::<T>(), align_of::<T>()) }
+    }
+
+    /// # Safety
+    /// The slice must not be full.
+    #[inline]
+    unsafe fn push_unchecked(&mut self, value: T) {
+        debug_assert!(self.len < self.cap);
+        self.ptr.as_ptr().add(self.len).write(value);
+        self.len += 1;
+    }
+
+    fn grow(&mut self) -> Result<(), Error> {
+        let overflow = Error::new(Operation::Grow, None);
+        let cap = self.cap.checked_mul(2).ok_or(overflow)?.max(4);
+        let layout = Layout::array::<T>(cap).map_err(|_| overflow)?;
+        let ptr = unsafe { self.alloc.grow(self.ptr.cast(), self.layout(), layout) }
+            .map_err(Error::map(Operation::Grow, layout))?;
+        self.ptr = ptr.cast();
+        self.cap = cap;
+        Ok(())
+    }
+
+    /// Like [`grow`](Self::grow), but moves the values to a new block if the allocator can't grow
+    /// the current one.
+    fn grow_or_move(&mut self) -> Result<(), Error> {
+        if self.grow().is_ok() {
+            return Ok(());
+        }
+        let cap = self.cap.saturating_mul(2).max(4);
+        let mut moved =
+            Self::new(self.alloc, cap).map_err(|err| Error::new(Operation::Grow, err.layout()))?;
+        unsafe {
+            let ptr = moved.ptr.as_ptr();
+            ptr.copy_from_nonoverlapping(self.ptr.as_ptr(), self.len);
+        }
+        moved.len = core::mem::replace(&mut self.len, 0);
+        core::mem::swap(self, &mut moved);
+        Ok(())
+    }
+
+    /// Shrinks the slice to its length, if the allocator manages to.
+    fn shrink_to_fit(&mut self) {
+        if size_of::<T>() == 0 || self.len == self.cap {
+            return;
+        }
+        let layout = unsafe {
+            Layout::from_size_align_unchecked(self.len * size_of::<T>(), align_of::<T>())
+        };
+        if let Ok(ptr) = unsafe { self.alloc.shrink(self.ptr.cast(), self.layout(), layout) } {
+            self.ptr = ptr.cast();
+            self.cap = self.len;
+        }
+    }
+
+    fn finish(self) -> &'a mut [T] {
+        let slice = unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) };
+        core::mem::forget(self);
+        slice
+    }
+}
+
+impl<A> SliceGuard<'_, u8, A>
+where
+    A: Allocator + ?Sized,
+{
+    /// Appends `bytes`, growing the slice as many times as needed.
+    fn extend_from_slice(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        while self.cap - self.len < bytes.len() {
+            self.grow()?;
+        }
+        unsafe {
+            let end = self.ptr.as_ptr().add(self.len);
+            end.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+        }
+        self.len += bytes.len();
+        Ok(())
+    }
+}
+
+impl<T, A> Drop for SliceGuard<'_, T, A>
+where
+    A: Allocator + ?Sized,
+{
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.ptr.as_ptr(), self.len));
+            self.alloc.deallocate(self.ptr.cast(), self.layout());
+        }
+    }
+}
+
+/// Returns `true` if the block specified by `ptr` and `layout` lies within one of `ranges` of addresses.
+///
+/// A zero-sized block is within a range if its address is, so a pointer one past the end of a range
+/// is not. Meant for implementing [`ArenaAllocator::contains`] for arenas spanning several ranges.
+///
+/// # Example
+/// ```
+/// use allocandrescu::ranges_contain;
+/// use std::{alloc::Layout, ptr::NonNull};
+///
+/// let mut buf = [0u8; 16];
+/// let start = buf.as_mut_ptr() as usize;
+/// let ptr = NonNull::new(buf[8..].as_mut_ptr()).unwrap();
+/// assert!(ranges_contain([start..start + 16], ptr, Layout::new::<u64>()));
+/// assert!(!ranges_contain([start..start + 12], ptr, Layout::new::<u64>()));
+/// ```
+#[inline]
+pub fn ranges_contain<I>(ranges: I, ptr: NonNull<u8>, layout: Layout) -> bool
+where
+    I: IntoIterator<Item = Range<usize>>,
+{
+    let Some((alloc_start, alloc_end)) = util::checked_range(ptr, layout) else {
+        return false;
+    };
+    ranges.into_iter().any(|range| {
+        range.start <= alloc_start && alloc_start < range.end && alloc_end <= range.end
+    })
+}
+
+/// Returns a dangling pointer to a zero-sized block aligned to `layout.align()`.
+#[inline]
+pub(crate) fn dangling(layout: Layout) -> NonNull<[u8]> {
+    debug_assert_eq!(layout.size(), 0);
+    // The alignment is never zero. The pointer has no provenance, as it is never dereferenced.
+    let ptr =
+        unsafe { NonNull::new_unchecked(core::ptr::null_mut::<u8>().wrapping_add(layout.align())) };
+    NonNull::slice_from_raw_parts(ptr, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::Stack;
+
+    fn intern<'a>(alloc: &'a impl Allocator, name: &str) -> &'a str {
+        alloc.alloc_str(name).unwrap()
+    }
+
+    #[test]
+    fn arena_ext_allocates_in_stack() {
+        let stack = Stack::<64>::new();
+        let byte = stack.alloc_value(1u8).unwrap();
+        let word = stack.alloc_value(2u64).unwrap();
+        assert_eq!(word as *mut u64 as usize % 8, 0);
+        *byte += 1;
+        *word += 1;
+        assert_eq!((*byte, *word), (2, 3));
+        assert_eq!(stack.used(), 16);
+
+        let names = [intern(&stack, "alpha"), intern(&stack, "beta")];
+        let empty = stack.alloc_slice_copy::<u32>(&[]).unwrap();
+        assert_eq!(names, ["alpha", "beta"]);
+        assert!(empty.is_empty());
+        assert_eq!(stack.used(), 25);
+
+        assert!(stack.alloc_value(()).is_ok());
+        assert!(stack.alloc_slice_copy(&[0u8; 64]).is_err());
+        assert!(stack.alloc_value([0u8; 40]).is_err());
+    }
+
+    #[test]
+    fn arena_ext_allocates_in_fallback_chain() {
+        let (primary, secondary) = (Stack::<16>::new(), Stack::<256>::new());
+        let alloc = primary.by_ref().fallback(secondary.by_ref());
+
+        let small = alloc.alloc_slice_copy(&[1u32, 2, 3]).unwrap();
+        let large = alloc.alloc_slice_copy(&[7u32; 16]).unwrap();
+        small[0] = 10;
+        large[15] = 70;
+        assert_eq!(small, [10, 2, 3]);
+        assert_eq!(large.iter().sum::<u32>(), 7 * 15 + 70);
+        assert!(primary.contains(NonNull::from(&small[0]).cast(), Layout::new::<[u32; 3]>()));
+        assert!(secondary.contains(NonNull::from(&large[0]).cast(), Layout::new::<[u32; 16]>()));
+        assert_eq!(intern(&alloc, "interned"), "interned");
+    }
+
+    /// Yields `len` values while claiming to yield one.
+    struct Underestimated {
+        len: u32,
+    }
+
+    impl Iterator for Underestimated {
+        type Item = u32;
+
+        fn next(&mut self) -> Option<u32> {
+            self.len = self.len.checked_sub(1)?;
+            Some(self.len)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (1, Some(1))
+        }
+    }
+
+    #[test]
+    fn arena_ext_allocates_empty_slices() {
+        let stack = Stack::<64>::new();
+        assert!(stack.alloc_slice_uninit::<u64>(0).unwrap().is_empty());
+        assert!(stack
+            .alloc_slice_fill_with::<u64, _>(0, |_| unreachable!())
+            .unwrap()
+            .is_empty());
+        assert!(stack
+            .alloc_iter(core::iter::empty::<u64>())
+            .unwrap()
+            .is_empty());
+        assert_eq!(stack.used(), 0);
+        assert_eq!(stack.alloc_iter((0..1000).map(|_| ())).unwrap().len(), 1000);
+    }
+
+    #[test]
+    fn arena_ext_rejects_huge_lengths() {
+        let stack = Stack::<64>::new();
+        assert!(stack.alloc_slice_uninit::<u64>(usize::MAX).is_err());
+        assert!(stack
+            .alloc_slice_fill_with::<u64, _>(usize::MAX / 4, |_| unreachable!())
+            .is_err());
+        assert!(stack.alloc_slice_fill_with(9, |i| i as u64).is_err());
+        assert_eq!(stack.used(), 0);
+    }
+
+    #[test]
+    fn arena_ext_fills_slices() {
+        let stack = Stack::<256>::new();
+        let uninit = stack.alloc_slice_uninit::<u16>(3).unwrap();
+        uninit[1].write(7);
+        assert_eq!(uninit.len(), 3);
+        let squares = stack.alloc_slice_fill_with(8, |i| i * i).unwrap();
+        assert_eq!(squares[7], 49);
+
+        let before = stack.used();
+        let values = stack.alloc_iter(Underestimated { len: 10 }).unwrap();
+        assert!(values.iter().copied().eq((0..10).rev()));
+        assert_eq!(stack.used(), before.next_multiple_of(4) + 40);
+    }
+
+    #[test]
+    fn arena_ext_reports_failed_layout() {
+        let stack = Stack::<64>::new();
+        let error = stack.alloc_slice_copy(&[0u16; 40]).unwrap_err();
+        assert_eq!(error.operation(), Operation::Allocate);
+        assert_eq!(error.layout(), Some(Layout::new::<[u16; 40]>()));
+        assert_eq!(
+            std::format!("{}", error.with_name("scratch")),
+            "failed to allocate a block of size 80 and align 2 in scratch"
+        );
+
+        let error = stack.alloc_iter(Underestimated { len: 17 }).unwrap_err();
+        assert_eq!(
+            std::format!("{error}"),
+            "failed to grow a block of size 128 and align 4"
+        );
+        let error = stack.alloc_slice_uninit::<u64>(usize::MAX).unwrap_err();
+        assert_eq!(error.layout(), None);
+        assert_eq!(AllocError::from(error), AllocError);
+    }
+
+    #[test]
+    fn arena_ext_iter_fails_when_out_of_memory() {
+        let stack = Stack::<64>::new();
+        assert!(stack.alloc_iter(Underestimated { len: 17 }).is_err());
+        assert_eq!(stack.used(), 0);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn arena_ext_drops_filled_values_on_panic() {
+        use core::cell::Cell;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        struct Counted<'a>(&'a Cell<usize>);
+
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let stack = Stack::<256>::new();
+        let drops = Cell::new(0);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            stack.alloc_slice_fill_with(8, |i| {
+                assert!(i < 5, "fill failed");
+                Counted(&drops)
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 5);
+        assert_eq!(stack.used(), 0);
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let iter = (0..8).map(|i| {
+                assert!(i < 3, "iteration failed");
+                Counted(&drops)
+            });
+            stack.alloc_iter(iter)
+        }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 8);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn arena_ext_constructs_values_after_allocating() {
+        let stack = Stack::<64>::new();
+        let value = stack
+            .try_alloc_with(|| {
+                assert_eq!(stack.used(), 8);
+                [1u32, 2]
+            })
+            .unwrap();
+        assert_eq!(*value, [1, 2]);
+        assert!(stack.try_alloc_with(|| [0u8; 64]).is_err());
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            stack.try_alloc_with::<u64, _>(|| panic!("construction failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(stack.used(), 8);
+    }
+
+    #[test]
+    fn arena_ext_initializes_large_values_under_small_thread_stack() {
+        const SIZE: usize = 64 * 1024;
+
+        let stack = std::boxed::Box::new(Stack::<SIZE>::new());
+        let worker = std::thread::Builder::new().stack_size(32 * 1024);
+        let worker = worker.spawn(move || {
+            let page = unsafe {
+                stack.try_alloc_with_init(|page: &mut MaybeUninit<[u8; SIZE]>| {
+                    page.as_mut_ptr().write_bytes(0xa5, 1)
+                })
+            };
+            let page = page.unwrap();
+            page[SIZE - 1] = 0;
+            assert!(page[..SIZE - 1].iter().all(|&b| b == 0xa5));
+            assert_eq!(stack.remaining(), 0);
+        });
+        worker.unwrap().join().unwrap();
+    }
+
+    /// Forwards to a stack, but fails to grow.
+    struct NoGrow<'a>(&'a Stack<256>);
+
+    unsafe impl Allocator for NoGrow<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.deallocate(ptr, layout)
+        }
+
+        unsafe fn grow(
+            &self,
+            _: NonNull<u8>,
+            _: Layout,
+            _: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            Err(AllocError)
+        }
+    }
+
+    fn str_in(alloc: &impl ArenaAllocator, s: &str) -> bool {
+        alloc.contains(NonNull::from(s.as_bytes()).cast(), Layout::for_value(s))
+    }
+
+    #[test]
+    fn format_in_matches_format() {
+        let stack = Stack::<1024>::new();
+        let values = [1.5, -2.0, 1e10];
+        let short = format_in!(&stack, "{}", 42).unwrap();
+        let long = format_in!(&stack, "{values:?} {:>40}|{:08.3}", "right", 1.23456).unwrap();
+        let literal = format_in!(stack, "no arguments").unwrap();
+        assert_eq!(short, "42");
+        assert_eq!(
+            *long,
+            std::format!("{values:?} {:>40}|{:08.3}", "right", 1.23456)
+        );
+        assert_eq!(literal, "no arguments");
+        assert!([&*short, long, literal].iter().all(|s| str_in(&stack, s)));
+        assert_eq!(stack.used(), short.len() + long.len() + literal.len());
+    }
+
+    #[test]
+    fn format_in_handles_empty_strings() {
+        let stack = Stack::<64>::new();
+        let empty = format_in!(&stack, "{}{}", "", std::string::String::new()).unwrap();
+        assert_eq!(empty, "");
+        assert_eq!(format_in!(&stack, "").unwrap(), "");
+        assert_eq!(stack.used(), 0);
+    }
+
+    #[test]
+    fn format_in_falls_back_to_exact_allocation() {
+        let stack = Stack::<256>::new();
+        let no_grow = NoGrow(&stack);
+        let s = format_in!(&no_grow, "{}-{}", "a".repeat(100), 7).unwrap();
+        assert_eq!(s.len(), 102);
+        assert!(s.starts_with("aaa") && s.ends_with("a-7"));
+        assert!(str_in(&stack, s));
+        assert_eq!(stack.used(), 102);
+
+        // The doubled buffer doesn't fit, but the exact one does.
+        let stack = Stack::<200>::new();
+        let s = format_in!(&stack, "{}", "b".repeat(200)).unwrap();
+        assert_eq!(s.len(), 200);
+        assert_eq!(stack.used(), 200);
+    }
+
+    #[test]
+    fn format_in_reports_out_of_memory() {
+        let stack = Stack::<8>::new();
+        let error = format_in!(&stack, "{}", 1234567890).unwrap_err();
+        assert_eq!(error.layout(), Some(Layout::new::<[u8; 10]>()));
+        assert_eq!(stack.used(), 0);
+    }
+
+    #[test]
+    #[should_panic = "a formatting trait implementation returned an error"]
+    fn format_in_panics_on_formatting_error() {
+        struct Failing;
+
+        impl fmt::Display for Failing {
+            fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+
+        let stack = Stack::<64>::new();
+        let _ = format_in!(&stack, "{}", Failing);
+    }
+
+    #[test]
+    fn alloc_cstr_terminates_at_end_of_allocation() {
+        let stack = Stack::<64>::new();
+        let name = stack.alloc_cstr("arena").unwrap();
+        assert_eq!(name.to_str(), Ok("arena"));
+        let bytes = name.to_bytes_with_nul();
+        assert_eq!(stack.used(), 6);
+        assert!(stack.contains(NonNull::from(bytes).cast(), Layout::for_value(bytes)));
+        assert_eq!(
+            bytes.as_ptr_range().end as usize,
+            stack.arena_range().unwrap().end
+        );
+        assert_eq!(bytes[5], 0);
+
+        let empty = stack.alloc_cstr_bytes(b"").unwrap();
+        assert_eq!(empty.to_bytes_with_nul(), [0]);
+        let error = stack.alloc_cstr_bytes(b"a\0b").unwrap_err();
+        assert_eq!(error, CStrError::InteriorNul { position: 1 });
+        assert_eq!(stack.used(), 7);
+
+        let error = stack.alloc_cstr(&"x".repeat(57)).unwrap_err();
+        assert_eq!(
+            error,
+            CStrError::Alloc(Error::new(
+                Operation::Allocate,
+                Some(Layout::new::<[u8; 58]>())
+            ))
+        );
+    }
+
+    #[test]
+    fn alloc_cstr_from_iter_joins_segments() {
+        let stack = Stack::<256>::new();
+        let dirs = ["usr", "local", "share"];
+        let path = stack
+            .alloc_cstr_from_iter(dirs.iter().flat_map(|dir| ["/", dir]))
+            .unwrap();
+        assert_eq!(path.to_str(), Ok("/usr/local/share"));
+        let bytes = path.to_bytes_with_nul();
+        assert_eq!(stack.used(), bytes.len());
+        assert_eq!(
+            bytes.as_ptr_range().end as usize,
+            stack.arena_range().unwrap().end
+        );
+        assert_eq!(bytes.last(), Some(&0));
+
+        let error = stack
+            .alloc_cstr_from_iter([&b"ab"[..], b"c\0"])
+            .unwrap_err();
+        assert_eq!(error, CStrError::InteriorNul { position: 3 });
+        assert_eq!(
+            std::format!("{error}"),
+            "found a NUL byte at position 3 of a C string"
+        );
+        assert_eq!(stack.used(), bytes.len());
+        let empty = stack.alloc_cstr_from_iter::<[&str; 0]>([]).unwrap();
+        assert_eq!(empty.to_bytes(), b"");
+    }
+
+    #[test]
+    fn stack_arena_is_local_to_function() {
+        fn squares(n: u32) -> (u32, usize) {
+            stack_arena!(let alloc: 64);
+            let stack = "not the arena";
+            let mut v = allocator_api2::vec::Vec::new_in(alloc);
+            v.extend((0..n).map(|i| i * i));
+            let ptr = NonNull::from(&v[0]).cast();
+            assert!(alloc.contains(ptr, Layout::for_value(&v[..])));
+            assert_eq!(stack, "not the arena");
+            (v.iter().sum(), alloc.used())
+        }
+
+        assert_eq!(squares(4), (14, 16));
+        assert_eq!(squares(16), (1240, 64));
+    }
+
+    #[test]
+    fn stack_arena_falls_back_to_tail() {
+        let outer = Stack::<256>::new();
+        stack_arena!(let alloc: 16, else &outer);
+        let small = allocator_api2::vec![in &alloc; 1u8; 16];
+        let large = allocator_api2::vec![in &alloc; 2u8; 64];
+        assert_eq!(alloc.primary().used(), 16);
+        assert_eq!(outer.used(), 64);
+        assert_eq!((small.len(), large.len()), (16, 64));
+    }
+}
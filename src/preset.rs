@@ -0,0 +1,329 @@
+//! Ready-made compositions of the allocators and combinators of this crate.
+
+use crate::{
+    alloc::Stack,
+    combinator::{Fallback, SpillStats},
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{
+    alloc::Layout,
+    fmt,
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// An allocator serving allocations from an inline [`Stack`] of `N` bytes and spilling to
+/// `secondary` when the stack is full.
+///
+/// It is meant to be created once per thread, e.g. in a `thread_local!`, with `secondary` shared
+/// among the threads, e.g. a reference to a [`SharedFallback`]. Each instance counts its own
+/// spills in [`local_stats`](ScratchWithFallback::local_stats), and a `SharedFallback` counts
+/// the spills of all threads. Deallocations are routed to the allocator that owns the block, like
+/// with [`Fallback`].
+///
+/// The stack isn't `Sync`, so blocks are always deallocated by the thread that allocated them.
+///
+/// # Example
+/// ```
+/// use allocandrescu::preset::{ScratchWithFallback, SharedFallback};
+/// use allocator_api2::vec::Vec;
+/// use std::alloc::System;
+///
+/// static SPILLS: SharedFallback<System> = SharedFallback::new(System);
+///
+/// thread_local! {
+///     static SCRATCH: ScratchWithFallback<4096, &'static SharedFallback<System>> =
+///         const { ScratchWithFallback::new(&SPILLS) };
+/// }
+///
+/// SCRATCH.with(|scratch| {
+///     let small = Vec::<u8, _>::with_capacity_in(1024, scratch);
+///     let large = Vec::<u8, _>::with_capacity_in(8192, scratch);
+///     assert_eq!(scratch.local_stats().secondary_hits(), 1);
+///     drop((small, large));
+/// });
+/// assert_eq!(SPILLS.spills(), 1);
+/// ```
+pub struct ScratchWithFallback<const N: usize, S> {
+    alloc: Fallback<Stack<N>, S, SpillStats>,
+}
+
+impl<const N: usize, S> ScratchWithFallback<N, S> {
+    #[inline]
+    pub const fn new(secondary: S) -> Self {
+        Self {
+            alloc: Fallback::counted(Stack::new(), secondary),
+        }
+    }
+
+    /// Returns the inline stack.
+    #[inline]
+    pub fn stack(&self) -> &Stack<N> {
+        self.alloc.primary()
+    }
+
+    /// Returns the secondary allocator.
+    #[inline]
+    pub fn secondary(&self) -> &S {
+        self.alloc.secondary()
+    }
+
+    /// Returns the statistics of allocations made through this instance.
+    #[inline]
+    pub fn local_stats(&self) -> &SpillStats {
+        self.alloc.stats()
+    }
+}
+
+impl<const N: usize, S> Default for ScratchWithFallback<N, S>
+where
+    S: Default,
+{
+    #[inline]
+    fn default() -> Self {
+        Self::new(S::default())
+    }
+}
+
+impl<const N: usize, S> fmt::Debug for ScratchWithFallback<N, S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScratchWithFallback")
+            .field("stack", self.stack())
+            .field("secondary", self.secondary())
+            .field("local_stats", self.local_stats())
+            .finish()
+    }
+}
+
+unsafe impl<const N: usize, S> Allocator for ScratchWithFallback<N, S>
+where
+    S: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.allocate(layout)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.allocate_zeroed(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.grow(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.grow_zeroed(ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.shrink(ptr, old_layout, new_layout)
+    }
+}
+
+/// An allocator that forwards all operations to `alloc` and counts them with atomics, so that it
+/// can be shared among the threads spilling to it.
+///
+/// Reallocations are counted like allocations of the new layout.
+#[derive(Debug, Default)]
+pub struct SharedFallback<A> {
+    alloc: A,
+    spills: AtomicUsize,
+    spilled_bytes: AtomicUsize,
+    live_bytes: AtomicUsize,
+}
+
+impl<A> SharedFallback<A> {
+    #[inline]
+    pub const fn new(alloc: A) -> Self {
+        Self {
+            alloc,
+            spills: AtomicUsize::new(0),
+            spilled_bytes: AtomicUsize::new(0),
+            live_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn inner(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns the number of allocations served.
+    #[inline]
+    pub fn spills(&self) -> usize {
+        self.spills.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total number of bytes served.
+    #[inline]
+    pub fn spilled_bytes(&self) -> usize {
+        self.spilled_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes currently allocated.
+    #[inline]
+    pub fn live_bytes(&self) -> usize {
+        self.live_bytes.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    fn record(
+        &self,
+        result: Result<NonNull<[u8]>, AllocError>,
+        old_size: usize,
+        new_size: usize,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if result.is_ok() {
+            self.spills.fetch_add(1, Ordering::Relaxed);
+            self.spilled_bytes.fetch_add(new_size, Ordering::Relaxed);
+            self.live_bytes.fetch_add(new_size, Ordering::Relaxed);
+            self.live_bytes.fetch_sub(old_size, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+unsafe impl<A> Allocator for SharedFallback<A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.record(self.alloc.allocate(layout), 0, layout.size())
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.record(self.alloc.allocate_zeroed(layout), 0, layout.size())
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.live_bytes.fetch_sub(layout.size(), Ordering::Relaxed);
+        self.alloc.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.grow(ptr, old_layout, new_layout);
+        self.record(result, old_layout.size(), new_layout.size())
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.grow_zeroed(ptr, old_layout, new_layout);
+        self.record(result, old_layout.size(), new_layout.size())
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.alloc.shrink(ptr, old_layout, new_layout);
+        self.record(result, old_layout.size(), new_layout.size())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use allocator_api2::vec::Vec;
+    use std::alloc::System;
+
+    #[test]
+    fn spills_only_when_local_stack_is_full() {
+        let shared = SharedFallback::new(System);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let scratch = ScratchWithFallback::<256, _>::new(&shared);
+                    let fits = (0..4)
+                        .map(|i| allocator_api2::vec![in &scratch; i as u8; 64])
+                        .collect::<std::vec::Vec<_>>();
+                    assert_eq!(scratch.stack().remaining(), 0);
+                    assert_eq!(scratch.local_stats().secondary_hits(), 0);
+
+                    let mut spilled = Vec::with_capacity_in(16, &scratch);
+                    spilled.extend(0..16u8);
+                    assert_eq!(scratch.local_stats().secondary_hits(), 1);
+                    assert_eq!(scratch.local_stats().secondary_bytes(), 16);
+
+                    // A block freed on the stack makes room for the next allocation again.
+                    drop(spilled);
+                    fits.into_iter().rev().for_each(drop);
+                    let _fits = Vec::<u8, _>::with_capacity_in(128, &scratch);
+                    assert_eq!(scratch.local_stats().secondary_hits(), 1);
+                    assert_eq!(scratch.local_stats().primary_hits(), 5);
+                });
+            }
+        });
+        assert_eq!(shared.spills(), 4);
+        assert_eq!(shared.spilled_bytes(), 4 * 16);
+        assert_eq!(shared.live_bytes(), 0);
+    }
+
+    #[test]
+    fn shared_fallback_counts_reallocations() {
+        let shared = SharedFallback::new(System);
+        let scratch = ScratchWithFallback::<0, _>::new(&shared);
+        let mut v = Vec::new_in(&scratch);
+        (0..100u32).for_each(|i| v.push(i));
+        assert!(shared.spills() > 1);
+        assert_eq!(shared.live_bytes(), v.capacity() * 4);
+        v.shrink_to_fit();
+        assert_eq!(shared.live_bytes(), 400);
+        drop(v);
+        assert_eq!(shared.live_bytes(), 0);
+        assert_eq!(shared.spills(), scratch.local_stats().secondary_hits());
+    }
+
+    #[test]
+    fn default_uses_default_secondary() {
+        let scratch = ScratchWithFallback::<64, System>::default();
+        let v = allocator_api2::vec![in &scratch; 0u8; 100];
+        assert_eq!(v.len(), 100);
+        assert_eq!(scratch.local_stats().secondary_hits(), 1);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::{Error, Operation};
+use allocator_api2::alloc::{AllocError, Allocator};
+use core::{alloc::Layout, fmt, mem::ManuallyDrop, ops::Deref, ptr::NonNull};
+
+/// One of [`Allocator::grow`], [`Allocator::grow_zeroed`] and [`Allocator::shrink`].
+type Resize<A> = unsafe fn(&A, NonNull<u8>, Layout, Layout) -> Result<NonNull<[u8]>, AllocError>;
+
+/// A block of raw memory that is deallocated when dropped.
+///
+/// It remembers the layout that the block was allocated with, so that hand-computed layouts, e.g.
+/// of dynamically sized types or buffers for a C API, don't have to be kept around for every early
+/// return. It dereferences to the allocated block, which may be larger than requested.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, RawAlloc};
+/// use std::alloc::Layout;
+///
+/// let stack = Stack::<256>::new();
+/// let mut block = RawAlloc::new(&stack, Layout::from_size_align(24, 8).unwrap()).unwrap();
+/// unsafe { block.as_non_null_ptr().write_bytes(0, 24) };
+/// block.grow(Layout::from_size_align(64, 8).unwrap()).unwrap();
+/// assert_eq!(block.len(), 64);
+/// drop(block);
+/// assert_eq!(stack.used(), 0);
+/// ```
+pub struct RawAlloc<A: Allocator> {
+    ptr: NonNull<[u8]>,
+    layout: Layout,
+    alloc: A,
+}
+
+impl<A> RawAlloc<A>
+where
+    A: Allocator,
+{
+    /// Allocates a block fitting `layout` in `alloc`.
+    #[inline]
+    pub fn new(alloc: A, layout: Layout) -> Result<Self, Error> {
+        let ptr = alloc
+            .allocate(layout)
+            .map_err(Error::map(Operation::Allocate, layout))?;
+        Ok(Self { ptr, layout, alloc })
+    }
+
+    /// Allocates a zeroed block fitting `layout` in `alloc`.
+    #[inline]
+    pub fn new_zeroed(alloc: A, layout: Layout) -> Result<Self, Error> {
+        let ptr = alloc
+            .allocate_zeroed(layout)
+            .map_err(Error::map(Operation::Allocate, layout))?;
+        Ok(Self { ptr, layout, alloc })
+    }
+
+    /// Takes ownership of a block, which is deallocated when the guard is dropped.
+    ///
+    /// # Safety
+    /// `ptr` must denote a block of memory [currently allocated] by `alloc`, and `layout` must
+    /// [fit] it.
+    ///
+    /// [currently allocated]: allocator_api2::alloc::Allocator#currently-allocated-memory
+    /// [fit]: allocator_api2::alloc::Allocator#memory-fitting
+    #[inline]
+    pub unsafe fn from_raw_parts(ptr: NonNull<[u8]>, layout: Layout, alloc: A) -> Self {
+        Self { ptr, layout, alloc }
+    }
+
+    /// Returns the layout that the block was allocated with.
+    #[inline]
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Returns a reference to the underlying allocator.
+    #[inline]
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a pointer to the start of the block.
+    #[inline]
+    pub fn as_non_null_ptr(&self) -> NonNull<u8> {
+        self.ptr.cast()
+    }
+
+    /// Grows the block to fit `new_layout`, possibly moving it.
+    ///
+    /// The block is left unchanged on failure.
+    ///
+    /// # Panics
+    /// Panics if `new_layout` is smaller than the current layout.
+    #[inline]
+    pub fn grow(&mut self, new_layout: Layout) -> Result<(), Error> {
+        self.resize(new_layout, Operation::Grow, A::grow)
+    }
+
+    /// Grows the block to fit `new_layout`, possibly moving it, and zeroes the new bytes.
+    ///
+    /// The block is left unchanged on failure.
+    ///
+    /// # Panics
+    /// Panics if `new_layout` is smaller than the current layout.
+    #[inline]
+    pub fn grow_zeroed(&mut self, new_layout: Layout) -> Result<(), Error> {
+        self.resize(new_layout, Operation::Grow, A::grow_zeroed)
+    }
+
+    /// Shrinks the block to fit `new_layout`, possibly moving it.
+    ///
+    /// The block is left unchanged on failure.
+    ///
+    /// # Panics
+    /// Panics if `new_layout` is larger than the current layout.
+    #[inline]
+    pub fn shrink(&mut self, new_layout: Layout) -> Result<(), Error> {
+        self.resize(new_layout, Operation::Shrink, A::shrink)
+    }
+
+    fn resize(
+        &mut self,
+        new_layout: Layout,
+        operation: Operation,
+        resize: Resize<A>,
+    ) -> Result<(), Error> {
+        match operation {
+            Operation::Shrink => assert!(
+                new_layout.size() <= self.layout.size(),
+                "new layout must not be larger than the current one"
+            ),
+            _ => assert!(
+                new_layout.size() >= self.layout.size(),
+                "new layout must not be smaller than the current one"
+            ),
+        }
+        let ptr = unsafe { resize(&self.alloc, self.ptr.cast(), self.layout, new_layout) }
+            .map_err(Error::map(operation, new_layout))?;
+        self.ptr = ptr;
+        self.layout = new_layout;
+        Ok(())
+    }
+
+    /// Consumes the guard without deallocating the block, returning it.
+    ///
+    /// The allocator is not dropped either, so that the block stays valid.
+    #[inline]
+    pub fn leak(self) -> NonNull<[u8]> {
+        ManuallyDrop::new(self).ptr
+    }
+
+    /// Consumes the guard without deallocating the block, returning it with its layout and the
+    /// allocator, which can be passed to [`from_raw_parts`](RawAlloc::from_raw_parts).
+    #[inline]
+    pub fn into_raw_parts(self) -> (NonNull<[u8]>, Layout, A) {
+        let this = ManuallyDrop::new(self);
+        // The guard is forgotten, so the allocator is moved out only once.
+        (this.ptr, this.layout, unsafe {
+            core::ptr::read(&this.alloc)
+        })
+    }
+}
+
+impl<A> Deref for RawAlloc<A>
+where
+    A: Allocator,
+{
+    type Target = NonNull<[u8]>;
+
+    #[inline]
+    fn deref(&self) -> &NonNull<[u8]> {
+        &self.ptr
+    }
+}
+
+impl<A> Drop for RawAlloc<A>
+where
+    A: Allocator,
+{
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { self.alloc.deallocate(self.ptr.cast(), self.layout) }
+    }
+}
+
+impl<A> fmt::Debug for RawAlloc<A>
+where
+    A: Allocator + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawAlloc")
+            .field("ptr", &self.ptr)
+            .field("layout", &self.layout)
+            .field("alloc", &self.alloc)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::Stack;
+    use core::cell::Cell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    /// Counts the live blocks of a stack.
+    struct Counting<'a> {
+        stack: &'a Stack<256>,
+        live: Cell<isize>,
+    }
+
+    unsafe impl Allocator for Counting<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            let ptr = self.stack.allocate(layout)?;
+            self.live.set(self.live.get() + 1);
+            Ok(ptr)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.live.set(self.live.get() - 1);
+            self.stack.deallocate(ptr, layout)
+        }
+
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            self.stack.grow(ptr, old_layout, new_layout)
+        }
+
+        unsafe fn grow_zeroed(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            self.stack.grow_zeroed(ptr, old_layout, new_layout)
+        }
+
+        unsafe fn shrink(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            self.stack.shrink(ptr, old_layout, new_layout)
+        }
+    }
+
+    fn counting(stack: &Stack<256>) -> Counting<'_> {
+        Counting {
+            stack,
+            live: Cell::new(0),
+        }
+    }
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(size, 8).unwrap()
+    }
+
+    #[test]
+    fn raw_alloc_deallocates_on_early_return_and_panic() {
+        fn fill<A: Allocator>(alloc: A, bytes: &[u8]) -> Result<NonNull<[u8]>, Error> {
+            let mut block = RawAlloc::new(alloc, layout(16))?;
+            if bytes.len() > 16 {
+                block.grow(layout(bytes.len()))?;
+            }
+            assert!(!bytes.is_empty(), "nothing to fill");
+            unsafe {
+                let ptr = block.as_non_null_ptr().as_ptr();
+                ptr.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+            }
+            Ok(block.leak())
+        }
+
+        let stack = Stack::<256>::new();
+        let alloc = counting(&stack);
+        let error = fill(&alloc, &[1; 512]).unwrap_err();
+        assert_eq!(error.layout(), Some(layout(512)));
+        assert_eq!(alloc.live.get(), 0);
+
+        let result = catch_unwind(AssertUnwindSafe(|| fill(&alloc, &[])));
+        assert!(result.is_err());
+        assert_eq!(alloc.live.get(), 0);
+        assert_eq!(stack.used(), 0);
+
+        let block = fill(&alloc, &[7; 32]).unwrap();
+        assert_eq!(unsafe { block.as_ref() }[31], 7);
+        assert_eq!(alloc.live.get(), 1);
+        unsafe { alloc.deallocate(block.cast(), layout(32)) };
+    }
+
+    #[test]
+    fn raw_alloc_keeps_layout_in_sync() {
+        let stack = Stack::<256>::new();
+        let alloc = counting(&stack);
+        let mut block = RawAlloc::new_zeroed(&alloc, layout(8)).unwrap();
+        block.grow_zeroed(layout(64)).unwrap();
+        assert_eq!(block.layout(), layout(64));
+        assert!(unsafe { block.as_ref() }.iter().all(|&b| b == 0));
+        block.shrink(layout(16)).unwrap();
+        assert_eq!((block.layout(), block.len()), (layout(16), 16));
+        assert_eq!(stack.used(), 16);
+
+        let (ptr, layout, alloc) = block.into_raw_parts();
+        let block = unsafe { RawAlloc::from_raw_parts(ptr, layout, alloc) };
+        drop(block);
+        assert_eq!(stack.used(), 0);
+        assert_eq!(alloc.live.get(), 0);
+    }
+
+    #[test]
+    #[should_panic = "must not be smaller"]
+    fn raw_alloc_rejects_growing_to_smaller_layout() {
+        let stack = Stack::<256>::new();
+        let mut block = RawAlloc::new(&stack, layout(16)).unwrap();
+        let _ = block.grow(layout(8));
+    }
+}
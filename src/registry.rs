@@ -0,0 +1,322 @@
+//! A registry of named arenas, for reporting how much memory each of them uses in one place.
+//!
+//! Arenas are registered through an [`Rc`] with [`register`], which returns a [`Registration`]
+//! that removes the arena from the registry when dropped. The registry only holds weak references,
+//! so an arena dropped while registered is simply left out of reports.
+//!
+//! The allocators of this crate aren't `Sync`, so each thread has a registry of its own.
+//!
+//! # Example
+//! ```
+//! use allocandrescu::{alloc::Stack, combinator::ByDeref, registry};
+//! use allocator_api2::vec::Vec;
+//! use std::rc::Rc;
+//!
+//! let frame = Rc::new(Stack::<1024>::new());
+//! let _registration = registry::register("frame", &frame);
+//!
+//! let mut v = Vec::with_capacity_in(100, ByDeref::new(frame.clone()));
+//! v.extend(0..100u8);
+//!
+//! let reports = registry::report();
+//! assert_eq!(reports[0].name(), "frame");
+//! assert_eq!(reports[0].allocated_bytes(), Some(100));
+//! println!("{}", registry::Table::new(&reports));
+//! ```
+
+use crate::ArenaAllocator;
+use core::{cell::RefCell, fmt, marker::PhantomData};
+use std::{
+    rc::{Rc, Weak},
+    string::String,
+    vec::Vec,
+};
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = const {
+        RefCell::new(Registry {
+            next_id: 0,
+            entries: Vec::new(),
+        })
+    };
+}
+
+struct Registry {
+    next_id: u64,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    id: u64,
+    name: String,
+    alloc: Weak<dyn ArenaAllocator>,
+}
+
+/// Registers `alloc` under `name` in the registry of the current thread until the returned
+/// [`Registration`] is dropped.
+///
+/// Names don't have to be unique. Reports list allocators in the order they were registered.
+pub fn register<A>(name: impl Into<String>, alloc: &Rc<A>) -> Registration
+where
+    A: ArenaAllocator + 'static,
+{
+    let alloc: Weak<A> = Rc::downgrade(alloc);
+    let alloc: Weak<dyn ArenaAllocator> = alloc;
+    let name = name.into();
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.entries.push(Entry { id, name, alloc });
+        Registration {
+            id,
+            marker: PhantomData,
+        }
+    })
+}
+
+/// Returns a report for each allocator registered on the current thread that is still alive.
+pub fn report() -> Vec<AllocatorReport> {
+    // Upgrade first, so that allocators are queried without the registry borrowed.
+    let allocs = REGISTRY.with(|registry| {
+        let registry = registry.borrow();
+        registry
+            .entries
+            .iter()
+            .filter_map(|entry| Some((entry.name.clone(), entry.alloc.upgrade()?)))
+            .collect::<Vec<_>>()
+    });
+    allocs
+        .into_iter()
+        .map(|(name, alloc)| AllocatorReport {
+            name,
+            allocated_bytes: alloc.allocated_bytes(),
+            remaining_capacity: alloc.remaining_capacity(),
+        })
+        .collect()
+}
+
+/// A token that keeps an allocator in the registry, created by [`register`].
+///
+/// Dropping it removes the allocator from the registry of the thread it was registered on.
+#[must_use = "the allocator is removed from the registry when the registration is dropped"]
+pub struct Registration {
+    id: u64,
+    // The registry is thread-local.
+    marker: PhantomData<*const ()>,
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registration")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // The registry may already be gone if the token is dropped during thread exit.
+        let _ = REGISTRY.try_with(|registry| {
+            registry
+                .borrow_mut()
+                .entries
+                .retain(|entry| entry.id != self.id)
+        });
+    }
+}
+
+/// Memory usage of a registered allocator, created by [`report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllocatorReport {
+    name: String,
+    allocated_bytes: Option<usize>,
+    remaining_capacity: Option<usize>,
+}
+
+impl AllocatorReport {
+    /// Returns the name the allocator was registered under.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns [`ArenaAllocator::allocated_bytes`] of the allocator.
+    #[inline]
+    pub fn allocated_bytes(&self) -> Option<usize> {
+        self.allocated_bytes
+    }
+
+    /// Returns [`ArenaAllocator::remaining_capacity`] of the allocator.
+    #[inline]
+    pub fn remaining_capacity(&self) -> Option<usize> {
+        self.remaining_capacity
+    }
+}
+
+impl fmt::Display for AllocatorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} allocated, {} remaining",
+            self.name,
+            Bytes(self.allocated_bytes),
+            Bytes(self.remaining_capacity)
+        )
+    }
+}
+
+/// Renders reports as a table with a row per allocator. Unknown numbers are shown as `-`.
+///
+/// ```
+/// use allocandrescu::{alloc::Stack, registry};
+/// use std::rc::Rc;
+///
+/// let (a, b) = (Rc::new(Stack::<64>::new()), Rc::new(Stack::<256>::new()));
+/// let _registrations = (registry::register("a", &a), registry::register("bb", &b));
+/// let table = registry::Table::new(&registry::report()).to_string();
+/// assert_eq!(
+///     table,
+///     "name  allocated  remaining\n\
+///      a             0         64\n\
+///      bb            0        256\n"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Table<'a> {
+    reports: &'a [AllocatorReport],
+}
+
+impl<'a> Table<'a> {
+    #[inline]
+    pub fn new(reports: &'a [AllocatorReport]) -> Self {
+        Self { reports }
+    }
+}
+
+impl fmt::Display for Table<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .reports
+            .iter()
+            .map(|report| report.name.chars().count())
+            .fold("name".len(), usize::max);
+        writeln!(
+            f,
+            "{:width$}  {:>9}  {:>9}",
+            "name", "allocated", "remaining"
+        )?;
+        for report in self.reports {
+            writeln!(
+                f,
+                "{:width$}  {:>9}  {:>9}",
+                report.name,
+                Bytes(report.allocated_bytes),
+                Bytes(report.remaining_capacity)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A byte count that may be unknown.
+struct Bytes(Option<usize>);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(bytes) => fmt::Display::fmt(&bytes, f),
+            None => f.pad("-"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, combinator::ByDeref};
+    use allocator_api2::{
+        alloc::{AllocError, Allocator, Global},
+        vec::Vec,
+    };
+    use core::{alloc::Layout, ptr::NonNull};
+
+    /// An arena that doesn't know its statistics.
+    struct Opaque;
+
+    unsafe impl Allocator for Opaque {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    impl ArenaAllocator for Opaque {}
+
+    #[test]
+    fn report_reflects_registered_arenas() {
+        let frame = Rc::new(Stack::<1024>::new());
+        let level = Rc::new(Stack::<4096>::new());
+        let _frame = register("frame", &frame);
+        let _level = register(String::from("level"), &level);
+
+        let mut a = Vec::with_capacity_in(100, ByDeref::new(frame.clone()));
+        a.extend(0..100u8);
+        let mut b = Vec::with_capacity_in(250, ByDeref::new(level.clone()));
+        b.extend(0..250u32);
+
+        let reports = report();
+        let summary = reports
+            .iter()
+            .map(|report| {
+                let bytes = (report.allocated_bytes(), report.remaining_capacity());
+                (report.name(), bytes)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("frame", (Some(100), Some(924))),
+                ("level", (Some(1000), Some(3096))),
+            ]
+        );
+        assert_eq!(
+            reports[1].to_string(),
+            "level: 1000 allocated, 3096 remaining"
+        );
+        assert_eq!(
+            Table::new(&reports).to_string(),
+            "name   allocated  remaining\n\
+             frame        100        924\n\
+             level       1000       3096\n"
+        );
+    }
+
+    #[test]
+    fn registrations_are_removed_on_drop() {
+        let arena = Rc::new(Stack::<64>::new());
+        let registration = register("arena", &arena);
+        // Unknown statistics are reported as such.
+        let unknown = Rc::new(Opaque);
+        let _unknown = register("unknown", &unknown);
+        assert_eq!(report().len(), 2);
+        assert_eq!(report()[1].allocated_bytes(), None);
+        assert!(Table::new(&report())
+            .to_string()
+            .contains("unknown          -          -"));
+
+        drop(registration);
+        assert_eq!(report()[0].name(), "unknown");
+
+        // An allocator dropped while registered is left out.
+        let registration = register("dropped", &arena);
+        drop(arena);
+        assert_eq!(report().len(), 1);
+        drop(registration);
+        drop(_unknown);
+        assert!(report().is_empty());
+    }
+}
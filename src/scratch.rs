@@ -0,0 +1,480 @@
+//! A thread-local arena for temporary allocations.
+//!
+//! [`with_scratch`] lends the arena of the current thread to a closure and deallocates everything
+//! allocated in it when the closure returns, keeping the memory for the next call. The arena
+//! grows by allocating chunks from the global allocator, starting at [`DEFAULT_CHUNK_SIZE`]
+//! bytes unless changed with [`set_chunk_size`].
+//!
+//! Calls can be nested. The inner call gets a view of the arena that is rewound to where the
+//! outer call left off, and the outer view fails to allocate until the inner call returns, so that
+//! no block outlives the scope it was allocated in.
+//!
+//! Allocations can't escape the closure:
+//! ```compile_fail
+//! use allocandrescu::scratch::with_scratch;
+//! use allocator_api2::vec::Vec;
+//!
+//! let escaped = with_scratch(|alloc| Vec::<u8, _>::new_in(alloc));
+//! ```
+//!
+//! # Example
+//! ```
+//! use allocandrescu::scratch::with_scratch;
+//! use allocator_api2::vec::Vec;
+//!
+//! fn normalize(words: &[&str]) -> usize {
+//!     with_scratch(|alloc| {
+//!         let mut lower = Vec::new_in(alloc);
+//!         lower.extend(words.iter().map(|word| word.to_ascii_lowercase()));
+//!         lower.sort();
+//!         lower.dedup();
+//!         lower.len()
+//!     })
+//! }
+//!
+//! assert_eq!(normalize(&["a", "B", "b"]), 2);
+//! ```
+
+use crate::{dangling, ranges_contain, util::align_up, ArenaAllocator};
+use allocator_api2::alloc::{AllocError, Allocator, Global};
+use core::{
+    alloc::Layout,
+    cell::{Cell, RefCell},
+    fmt,
+    ptr::NonNull,
+};
+use std::vec::Vec;
+
+/// Size of the first chunk of the arena, unless changed with [`set_chunk_size`].
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Alignment of the chunks, so that common layouts never need padding at their start.
+const CHUNK_ALIGN: usize = 16;
+
+thread_local! {
+    static ARENA: Arena = const { Arena::new() };
+}
+
+/// Runs `f` with the scratch arena of the current thread and deallocates everything allocated in
+/// it afterwards, also when `f` panics.
+///
+/// Does not run any `Drop` implementations on deallocated objects. See the [module
+/// documentation](self) for details.
+pub fn with_scratch<R>(f: impl FnOnce(&ScratchAlloc<'_>) -> R) -> R {
+    ARENA.with(|arena| {
+        let scope = Scope::enter(arena);
+        f(&scope.alloc)
+    })
+}
+
+/// Sets the minimum size of the chunks that the scratch arena of the current thread allocates
+/// from now on.
+///
+/// The first chunk is allocated when the arena is first used, so calling it before that sets the
+/// initial size of the arena. Chunks that are too small for a block are skipped, and later chunks
+/// are at least twice as large as the previous one.
+pub fn set_chunk_size(size: usize) {
+    ARENA.with(|arena| arena.chunk_size.set(size.max(1)));
+}
+
+/// A view of the thread-local scratch arena, lent by [`with_scratch`].
+///
+/// It only allocates while it belongs to the innermost [`with_scratch`] call. Deallocating
+/// reclaims the memory of the topmost block, like [`Stack`](crate::alloc::Stack), and everything
+/// else is reclaimed when the call returns.
+pub struct ScratchAlloc<'a> {
+    arena: &'a Arena,
+    depth: usize,
+}
+
+impl ScratchAlloc<'_> {
+    /// Returns `true` if the view belongs to the innermost [`with_scratch`] call.
+    #[inline]
+    pub fn is_innermost(&self) -> bool {
+        self.arena.depth.get() == self.depth
+    }
+
+    /// Returns the nesting depth of the [`with_scratch`] call that lent the view, starting at 1.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Allocates a new block and moves the contents of the old one to it.
+    unsafe fn relocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.allocate(new_layout)?;
+        let len = old_layout.size().min(new_layout.size());
+        ptr.as_ptr()
+            .copy_to_nonoverlapping(block.cast().as_ptr(), len);
+        self.deallocate(ptr, old_layout);
+        Ok(block)
+    }
+}
+
+impl fmt::Debug for ScratchAlloc<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScratchAlloc")
+            .field("depth", &self.depth)
+            .field("innermost", &self.is_innermost())
+            .finish_non_exhaustive()
+    }
+}
+
+unsafe impl Allocator for ScratchAlloc<'_> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        if !self.is_innermost() {
+            return Err(AllocError);
+        }
+        self.arena.bump(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 && self.is_innermost() {
+            if let Some(start) = self.arena.top_offset(ptr, layout) {
+                self.arena.set_offset(start);
+            }
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() != 0
+            && self.is_innermost()
+            && ptr.as_ptr() as usize % new_layout.align() == 0
+        {
+            if let Some(start) = self.arena.top_offset(ptr, old_layout) {
+                if let Some(end) = start.checked_add(new_layout.size()) {
+                    if end <= self.arena.current_size() {
+                        self.arena.set_offset(end);
+                        return Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()));
+                    }
+                }
+            }
+        }
+        self.relocate(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.grow(ptr, old_layout, new_layout)?;
+        let new = block.cast::<u8>().as_ptr().add(old_layout.size());
+        new.write_bytes(0, new_layout.size() - old_layout.size());
+        Ok(block)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if ptr.as_ptr() as usize % new_layout.align() != 0 {
+            return self.relocate(ptr, old_layout, new_layout);
+        }
+        if new_layout.size() == 0 {
+            self.deallocate(ptr, old_layout);
+            return Ok(dangling(new_layout));
+        }
+        if self.is_innermost() {
+            if let Some(start) = self.arena.top_offset(ptr, old_layout) {
+                self.arena.set_offset(start + new_layout.size());
+            }
+        }
+        Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()))
+    }
+}
+
+impl ArenaAllocator for ScratchAlloc<'_> {
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        let chunks = self.arena.chunks.borrow();
+        let ranges = chunks.iter().map(|chunk| {
+            let start = chunk.ptr.as_ptr() as usize;
+            start..start + chunk.size
+        });
+        ranges_contain(ranges, ptr, layout)
+    }
+}
+
+/// A block of memory allocated from the global allocator.
+struct Chunk {
+    ptr: NonNull<u8>,
+    size: usize,
+}
+
+impl Chunk {
+    fn layout(&self) -> Layout {
+        // The layout was valid when the chunk was allocated.
+        unsafe { Layout::from_size_align_unchecked(self.size, CHUNK_ALIGN) }
+    }
+}
+
+/// A position in the arena. Positions in later chunks compare greater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Position {
+    chunk: usize,
+    offset: usize,
+}
+
+impl Position {
+    const START: Self = Self {
+        chunk: 0,
+        offset: 0,
+    };
+}
+
+struct Arena {
+    /// Chunks in the order they were allocated. They are kept across calls for reuse.
+    chunks: RefCell<Vec<Chunk>>,
+    /// End of the topmost block.
+    top: Cell<Position>,
+    /// Position at which the innermost call started. Blocks below it belong to outer calls.
+    floor: Cell<Position>,
+    /// Number of active `with_scratch` calls.
+    depth: Cell<usize>,
+    chunk_size: Cell<usize>,
+}
+
+impl Arena {
+    const fn new() -> Self {
+        Self {
+            chunks: RefCell::new(Vec::new()),
+            top: Cell::new(Position::START),
+            floor: Cell::new(Position::START),
+            depth: Cell::new(0),
+            chunk_size: Cell::new(DEFAULT_CHUNK_SIZE),
+        }
+    }
+
+    /// Allocates a block at the top, moving on to the next chunk if it doesn't fit.
+    fn bump(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let mut chunks = self.chunks.borrow_mut();
+        let mut top = self.top.get();
+        loop {
+            let Some(chunk) = chunks.get(top.chunk) else {
+                let last = chunks.last().map_or(0, |chunk| chunk.size);
+                let fits = layout
+                    .size()
+                    .checked_add(layout.align())
+                    .ok_or(AllocError)?;
+                let size = self.chunk_size.get().max(last.saturating_mul(2)).max(fits);
+                let layout = Layout::from_size_align(size, CHUNK_ALIGN).map_err(|_| AllocError)?;
+                let ptr = Global.allocate(layout)?.cast();
+                chunks.push(Chunk { ptr, size });
+                continue;
+            };
+            let base = chunk.ptr.as_ptr() as usize;
+            let start = align_up(base + top.offset, layout.align()).map(|start| start - base);
+            let end = start.and_then(|start| start.checked_add(layout.size()));
+            match (start, end) {
+                (Some(start), Some(end)) if end <= chunk.size => {
+                    self.top.set(Position {
+                        chunk: top.chunk,
+                        offset: end,
+                    });
+                    // The block is within the chunk.
+                    let ptr = unsafe { chunk.ptr.add(start) };
+                    return Ok(NonNull::slice_from_raw_parts(ptr, layout.size()));
+                }
+                _ => {
+                    top = Position {
+                        chunk: top.chunk + 1,
+                        offset: 0,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the offset of the block in the current chunk if it is the topmost block and belongs
+    /// to the innermost call.
+    fn top_offset(&self, ptr: NonNull<u8>, layout: Layout) -> Option<usize> {
+        let top = self.top.get();
+        let chunks = self.chunks.borrow();
+        let base = chunks.get(top.chunk)?.ptr.as_ptr() as usize;
+        let start = (ptr.as_ptr() as usize).checked_sub(base)?;
+        let position = Position {
+            chunk: top.chunk,
+            offset: start,
+        };
+        (start.checked_add(layout.size())? == top.offset && position >= self.floor.get())
+            .then_some(start)
+    }
+
+    fn current_size(&self) -> usize {
+        let chunks = self.chunks.borrow();
+        chunks
+            .get(self.top.get().chunk)
+            .map_or(0, |chunk| chunk.size)
+    }
+
+    fn set_offset(&self, offset: usize) {
+        let chunk = self.top.get().chunk;
+        self.top.set(Position { chunk, offset });
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        for chunk in self.chunks.get_mut().drain(..) {
+            unsafe { Global.deallocate(chunk.ptr, chunk.layout()) };
+        }
+    }
+}
+
+/// An active `with_scratch` call, which rewinds the arena when dropped.
+struct Scope<'a> {
+    alloc: ScratchAlloc<'a>,
+    /// Position of the top when the call started.
+    mark: Position,
+    /// Floor of the enclosing call.
+    floor: Position,
+}
+
+impl<'a> Scope<'a> {
+    fn enter(arena: &'a Arena) -> Self {
+        let depth = arena.depth.get() + 1;
+        arena.depth.set(depth);
+        let mark = arena.top.get();
+        Self {
+            alloc: ScratchAlloc { arena, depth },
+            mark,
+            floor: arena.floor.replace(mark),
+        }
+    }
+}
+
+impl Drop for Scope<'_> {
+    fn drop(&mut self) {
+        let arena = self.alloc.arena;
+        // Nothing allocated in the call outlives it, as it had to borrow the view.
+        arena.top.set(self.mark);
+        arena.floor.set(self.floor);
+        arena.depth.set(self.alloc.depth - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use allocator_api2::vec::Vec;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    fn base(alloc: &ScratchAlloc<'_>) -> *const u8 {
+        let block = alloc.allocate(Layout::new::<u64>()).unwrap();
+        unsafe { alloc.deallocate(block.cast(), Layout::new::<u64>()) };
+        block.cast::<u8>().as_ptr()
+    }
+
+    #[test]
+    fn with_scratch_reuses_memory_across_calls() {
+        set_chunk_size(256);
+        let first = with_scratch(|alloc| {
+            let mut v = Vec::new_in(alloc);
+            v.extend(0..16u64);
+            assert!(alloc.contains(NonNull::from(&v[0]).cast(), Layout::for_value(&v[..])));
+            v.as_ptr().cast::<u8>()
+        });
+        for _ in 0..3 {
+            let ptr = with_scratch(|alloc| Vec::<u64, _>::with_capacity_in(16, alloc).as_ptr());
+            assert_eq!(ptr.cast(), first);
+        }
+
+        // Outgrowing the first chunk allocates more, which the next call keeps.
+        let total = with_scratch(|alloc| {
+            let mut v = Vec::new_in(alloc);
+            v.extend(0..1000u32);
+            v.iter().sum::<u32>()
+        });
+        assert_eq!(total, (0..1000).sum::<u32>());
+        with_scratch(|alloc| assert_eq!(base(alloc), first));
+        ARENA.with(|arena| assert!(arena.chunks.borrow().len() > 1));
+    }
+
+    #[test]
+    fn nested_scratch_is_rewound_to_outer_position() {
+        with_scratch(|outer| {
+            let kept = allocator_api2::vec![in outer; 7u32; 4];
+            let inner_base = with_scratch(|inner| {
+                assert_eq!((outer.is_innermost(), inner.depth()), (false, 2));
+                assert!(outer.allocate(Layout::new::<u8>()).is_err());
+                let v = allocator_api2::vec![in inner; 1u8; 32];
+                v.as_ptr()
+            });
+            assert!(outer.is_innermost());
+            assert_eq!(base(outer), inner_base);
+            assert_eq!(kept, [7; 4]);
+        });
+    }
+
+    #[test]
+    fn outer_blocks_do_not_grow_into_inner_scope() {
+        with_scratch(|outer| {
+            let mut v = Vec::new_in(outer);
+            v.push(1u8);
+            with_scratch(|inner| {
+                let marker = allocator_api2::vec![in inner; 2u8; 8];
+                // The outer allocator can't serve the growth while the inner call is active.
+                assert!(v.try_reserve(64).is_err());
+                assert_eq!(marker, [2; 8]);
+            });
+            v.extend([2; 64]);
+            assert_eq!(v.len(), 65);
+        });
+    }
+
+    #[test]
+    fn with_scratch_recovers_from_panic() {
+        let before = with_scratch(base);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            with_scratch(|alloc| {
+                let _v = allocator_api2::vec![in alloc; 0u8; 100];
+                with_scratch(|_| panic!("scratch work failed"));
+            })
+        }));
+        assert!(result.is_err());
+        ARENA.with(|arena| assert_eq!(arena.depth.get(), 0));
+        assert_eq!(with_scratch(base), before);
+        with_scratch(|alloc| assert_eq!(alloc.depth(), 1));
+    }
+
+    #[test]
+    fn scratch_grows_and_shrinks_topmost_block() {
+        with_scratch(|alloc| {
+            let layout = Layout::new::<[u8; 8]>();
+            let block = alloc.allocate(layout).unwrap().cast::<u8>();
+            let grown = unsafe { alloc.grow_zeroed(block, layout, Layout::new::<[u8; 32]>()) };
+            let grown = grown.unwrap();
+            assert_eq!(grown.cast(), block);
+            assert!(unsafe { grown.as_ref() }[8..].iter().all(|&b| b == 0));
+            let shrunk = unsafe { alloc.shrink(block, Layout::new::<[u8; 32]>(), layout) };
+            assert_eq!(shrunk.unwrap().cast(), block);
+            unsafe { alloc.deallocate(block, layout) };
+            assert_eq!(base(alloc), block.as_ptr());
+        });
+    }
+
+    #[test]
+    fn scratch_allocations_are_unique_per_thread() {
+        let here = with_scratch(base) as usize;
+        let there = std::thread::spawn(|| with_scratch(base) as usize)
+            .join()
+            .unwrap();
+        assert_ne!(here, there);
+    }
+}
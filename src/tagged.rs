@@ -0,0 +1,216 @@
+//! Pointers carrying a tag in the low bits that their alignment leaves unused.
+//!
+//! An allocator that guarantees a minimum alignment, like [`MinAlign`](crate::combinator::MinAlign),
+//! hands out [`Aligned`] witnesses. A [`TaggedPtr`] created with a witness checks at compile time
+//! that the alignment leaves enough bits for its tag:
+//! ```compile_fail
+//! use allocandrescu::{alloc::Stack, prelude::*, tagged::TaggedPtr};
+//! use std::alloc::Layout;
+//!
+//! let stack = Stack::<64>::new();
+//! let alloc = stack.by_ref().min_align::<16>();
+//! let ptr = alloc.allocate(Layout::new::<u8>()).unwrap().cast::<u8>();
+//! // 16-byte alignment leaves only 4 bits.
+//! let tagged = TaggedPtr::<u8, 5>::new(ptr, alloc.witness());
+//! ```
+
+use core::{fmt, marker::PhantomData, ptr::NonNull};
+
+/// A witness that the pointers of an allocator are aligned to `ALIGN`, a power of two.
+///
+/// This `struct` is created by [`MinAlign::witness`](crate::combinator::MinAlign::witness).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aligned<const ALIGN: usize> {
+    _private: (),
+}
+
+impl<const ALIGN: usize> Aligned<ALIGN> {
+    /// Creates a witness without checking anything.
+    ///
+    /// # Safety
+    /// `ALIGN` must be a power of two, and the pointers that the witness is used with must be
+    /// aligned to `ALIGN`.
+    #[inline]
+    pub const unsafe fn new_unchecked() -> Self {
+        Self { _private: () }
+    }
+
+    /// Returns the alignment that the witness guarantees.
+    #[inline]
+    pub const fn align(self) -> usize {
+        ALIGN
+    }
+}
+
+/// A non-null pointer to `T` storing a tag of `BITS` bits in its low bits.
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, prelude::*, tagged::TaggedPtr};
+/// use std::alloc::Layout;
+///
+/// let stack = Stack::<64>::new();
+/// let alloc = stack.by_ref().min_align::<8>();
+/// let ptr = alloc.allocate(Layout::new::<u8>()).unwrap().cast::<u8>();
+/// let mut tagged = TaggedPtr::<u8, 3>::new(ptr, alloc.witness());
+/// tagged.set_tag(0b101);
+/// assert_eq!((tagged.untagged(), tagged.tag()), (ptr, 0b101));
+/// ```
+pub struct TaggedPtr<T, const BITS: usize> {
+    ptr: NonNull<u8>,
+    marker: PhantomData<*mut T>,
+}
+
+impl<T, const BITS: usize> TaggedPtr<T, BITS> {
+    /// The largest tag that fits in `BITS` bits.
+    pub const MAX_TAG: usize = (1 << BITS) - 1;
+
+    /// Checks at compile time that pointers aligned to `ALIGN` can store `BITS` bits.
+    #[inline]
+    const fn check<const ALIGN: usize>() {
+        const {
+            assert!(
+                BITS < usize::BITS as usize && 1 << BITS <= ALIGN,
+                "alignment of the witness leaves fewer than `BITS` low bits"
+            )
+        }
+    }
+
+    /// Wraps `ptr`, which must come from the allocator that `witness` was obtained from, with a tag
+    /// of zero.
+    ///
+    /// # Panics
+    /// Panics if `ptr` isn't aligned to `ALIGN`.
+    #[inline]
+    pub fn new<const ALIGN: usize>(ptr: NonNull<T>, witness: Aligned<ALIGN>) -> Self {
+        assert!(
+            ptr.as_ptr() as usize % ALIGN == 0,
+            "pointer is not aligned to the alignment of the witness"
+        );
+        unsafe { Self::new_unchecked(ptr, witness) }
+    }
+
+    /// Wraps `ptr` with a tag of zero, checking its alignment only in debug builds.
+    ///
+    /// # Safety
+    /// `ptr` must be aligned to `ALIGN`.
+    #[inline]
+    pub unsafe fn new_unchecked<const ALIGN: usize>(ptr: NonNull<T>, _: Aligned<ALIGN>) -> Self {
+        Self::check::<ALIGN>();
+        debug_assert!(
+            ptr.as_ptr() as usize % ALIGN == 0,
+            "pointer is not aligned to the alignment of the witness"
+        );
+        Self {
+            ptr: ptr.cast(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the tag.
+    #[inline]
+    pub fn tag(&self) -> usize {
+        self.ptr.as_ptr() as usize & Self::MAX_TAG
+    }
+
+    /// Replaces the tag.
+    ///
+    /// # Panics
+    /// Panics if `tag` doesn't fit in `BITS` bits.
+    #[inline]
+    pub fn set_tag(&mut self, tag: usize) {
+        assert!(tag <= Self::MAX_TAG, "tag doesn't fit in `BITS` bits");
+        // The untagged pointer is aligned to more than `tag`, so adding it doesn't carry.
+        let ptr = self.untagged().cast::<u8>().as_ptr().wrapping_add(tag);
+        self.ptr = unsafe { NonNull::new_unchecked(ptr) };
+    }
+
+    /// Returns the pointer without the tag.
+    #[inline]
+    pub fn untagged(&self) -> NonNull<T> {
+        let ptr = self.ptr.as_ptr().wrapping_sub(self.tag());
+        // The pointer was non-null before it was tagged.
+        unsafe { NonNull::new_unchecked(ptr).cast() }
+    }
+}
+
+impl<T, const BITS: usize> Clone for TaggedPtr<T, BITS> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, const BITS: usize> Copy for TaggedPtr<T, BITS> {}
+
+impl<T, const BITS: usize> PartialEq for TaggedPtr<T, BITS> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl<T, const BITS: usize> Eq for TaggedPtr<T, BITS> {}
+
+impl<T, const BITS: usize> fmt::Debug for TaggedPtr<T, BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaggedPtr")
+            .field("ptr", &self.untagged())
+            .field("tag", &self.tag())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _};
+    use allocator_api2::alloc::Allocator;
+    use core::alloc::Layout;
+
+    #[test]
+    fn tagged_ptr_round_trips_tags() {
+        let (small, big) = (Stack::<64>::new(), Stack::<512>::new());
+        let alloc = small
+            .by_ref()
+            .cond(|layout| layout.size() <= 8)
+            .fallback(big.by_ref())
+            .min_align::<16>();
+        let layouts = [
+            Layout::new::<u8>(),
+            Layout::new::<[u8; 8]>(),
+            Layout::new::<[u8; 24]>(),
+        ];
+        for (i, layout) in layouts.into_iter().cycle().take(12).enumerate() {
+            let ptr = alloc.allocate(layout).unwrap().cast::<u8>();
+            let mut tagged = TaggedPtr::<u8, 4>::new(ptr, alloc.witness());
+            assert_eq!(tagged.tag(), 0);
+            tagged.set_tag(i);
+            tagged.set_tag(15 - i);
+            assert_eq!((tagged.untagged(), tagged.tag()), (ptr, 15 - i));
+            unsafe { tagged.untagged().as_ptr().write(i as u8) };
+            assert_eq!(unsafe { ptr.as_ptr().read() }, i as u8);
+        }
+        assert!(small.used() > 0 && big.used() > 0);
+    }
+
+    #[test]
+    #[should_panic = "not aligned"]
+    fn tagged_ptr_rejects_misaligned_pointer() {
+        let stack = Stack::<64, 16>::new();
+        let block = stack.allocate(Layout::new::<[u8; 2]>()).unwrap();
+        let ptr = unsafe { block.cast::<u8>().add(1) };
+        // Safe only for pointers from the allocator, which this one isn't.
+        let witness = unsafe { Aligned::<2>::new_unchecked() };
+        let _ = TaggedPtr::<u8, 1>::new(ptr, witness);
+    }
+
+    #[test]
+    #[should_panic = "doesn't fit"]
+    fn tagged_ptr_rejects_large_tag() {
+        let stack = Stack::<64, 16>::new();
+        let alloc = stack.by_ref().min_align::<4>();
+        let ptr = alloc.allocate(Layout::new::<u32>()).unwrap().cast::<u32>();
+        TaggedPtr::<u32, 2>::new(ptr, alloc.witness()).set_tag(4);
+    }
+}
@@ -0,0 +1,175 @@
+//! Address arithmetic shared by the allocators of this crate, for implementing your own.
+//!
+//! Each helper returns `None` instead of overflowing, so an allocator built on them rejects
+//! impossible requests instead of handing out wrapped-around blocks.
+//! [`ranges_contain`](crate::ranges_contain) builds on them to implement
+//! [`ArenaAllocator::contains`](crate::ArenaAllocator::contains).
+
+use core::{alloc::Layout, ptr::NonNull};
+
+/// Rounds `addr` up to a multiple of `align`, or returns `None` if the result overflows.
+///
+/// # Panics
+/// Panics in debug builds if `align` is not a power of two.
+///
+/// # Example
+/// ```
+/// use allocandrescu::util::align_up;
+///
+/// assert_eq!(align_up(13, 8), Some(16));
+/// assert_eq!(align_up(16, 8), Some(16));
+/// assert_eq!(align_up(usize::MAX, 2), None);
+/// ```
+#[inline]
+pub const fn align_up(addr: usize, align: usize) -> Option<usize> {
+    debug_assert!(align.is_power_of_two(), "alignment must be a power of two");
+    let mask = align.wrapping_sub(1);
+    match addr.checked_add(mask) {
+        Some(addr) => Some(addr & !mask),
+        None => None,
+    }
+}
+
+/// Returns the start and end addresses of the block specified by `ptr` and `layout`, or `None` if
+/// the block would wrap around the address space.
+///
+/// A zero-sized block starts and ends at its address.
+///
+/// # Example
+/// ```
+/// use allocandrescu::util::checked_range;
+/// use std::{alloc::Layout, ptr::NonNull};
+///
+/// let value = 0u64;
+/// let ptr = NonNull::from(&value).cast::<u8>();
+/// let start = ptr.as_ptr() as usize;
+/// assert_eq!(checked_range(ptr, Layout::new::<u64>()), Some((start, start + 8)));
+/// assert_eq!(checked_range(ptr, Layout::new::<()>()), Some((start, start)));
+/// ```
+#[inline]
+pub fn checked_range(ptr: NonNull<u8>, layout: Layout) -> Option<(usize, usize)> {
+    let start = ptr.as_ptr() as usize;
+    Some((start, start.checked_add(layout.size())?))
+}
+
+/// Returns the layout of a block holding `prefix` followed by `payload`, and the offset of the
+/// payload within it, or `None` if the layout overflows.
+///
+/// The block is aligned to the stricter of the two alignments, and not padded at the end.
+///
+/// # Example
+/// ```
+/// use allocandrescu::util::layout_with_prefix;
+/// use std::alloc::Layout;
+///
+/// let (layout, offset) = layout_with_prefix(Layout::new::<u32>(), Layout::new::<u64>()).unwrap();
+/// assert_eq!((layout.size(), layout.align(), offset), (16, 8, 8));
+/// ```
+#[inline]
+pub fn layout_with_prefix(prefix: Layout, payload: Layout) -> Option<(Layout, usize)> {
+    prefix.extend(payload).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ptr(addr: usize) -> NonNull<u8> {
+        NonNull::new(core::ptr::null_mut::<u8>().wrapping_add(addr)).unwrap()
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn align_up_rounds_to_multiples() {
+        for align in (0..usize::BITS).map(|shift| 1usize << shift) {
+            assert_eq!(align_up(0, align), Some(0));
+            assert_eq!(align_up(align, align), Some(align));
+            let rounded = if align == 1 { 0 } else { align };
+            assert_eq!(align_up(align - 1, align), Some(rounded));
+        }
+        for addr in 0..64 {
+            for align in [1, 2, 4, 8, 16] {
+                let aligned = align_up(addr, align).unwrap();
+                assert!(aligned >= addr && aligned - addr < align && aligned % align == 0);
+            }
+        }
+    }
+
+    #[test]
+    fn align_up_detects_overflow() {
+        assert_eq!(align_up(usize::MAX, 1), Some(usize::MAX));
+        assert_eq!(align_up(usize::MAX, 2), None);
+        assert_eq!(align_up(usize::MAX - 7, 8), Some(usize::MAX - 7));
+        assert_eq!(align_up(usize::MAX - 6, 8), None);
+        let top = 1 << (usize::BITS - 1);
+        assert_eq!(align_up(1, top), Some(top));
+        assert_eq!(align_up(top + 1, top), None);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "power of two"]
+    fn align_up_rejects_non_power_of_two() {
+        let _ = align_up(5, 3);
+    }
+
+    #[test]
+    fn checked_range_handles_zero_sized_and_overflowing_blocks() {
+        assert_eq!(checked_range(ptr(16), layout(8, 8)), Some((16, 24)));
+        assert_eq!(checked_range(ptr(16), layout(0, 8)), Some((16, 16)));
+        assert_eq!(
+            checked_range(ptr(usize::MAX), layout(0, 1)),
+            Some((usize::MAX, usize::MAX))
+        );
+        assert_eq!(checked_range(ptr(usize::MAX), layout(1, 1)), None);
+        assert_eq!(
+            checked_range(ptr(usize::MAX - 4), layout(4, 1)),
+            Some((usize::MAX - 4, usize::MAX))
+        );
+        assert_eq!(checked_range(ptr(usize::MAX - 4), layout(5, 1)), None);
+    }
+
+    #[test]
+    fn layout_with_prefix_places_payload_after_prefix() {
+        assert_eq!(
+            layout_with_prefix(layout(16, 8), layout(4, 4)),
+            Some((layout(20, 8), 16))
+        );
+        assert_eq!(
+            layout_with_prefix(layout(1, 1), layout(8, 8)),
+            Some((layout(16, 8), 8))
+        );
+        assert_eq!(
+            layout_with_prefix(layout(16, 8), layout(32, 32)),
+            Some((layout(64, 32), 32))
+        );
+        assert_eq!(
+            layout_with_prefix(layout(0, 1), layout(0, 1)),
+            Some((layout(0, 1), 0))
+        );
+        assert_eq!(
+            layout_with_prefix(layout(0, 16), layout(3, 1)),
+            Some((layout(3, 16), 0))
+        );
+        assert_eq!(
+            layout_with_prefix(layout(8, 8), layout(0, 1)),
+            Some((layout(8, 8), 8))
+        );
+    }
+
+    #[test]
+    fn layout_with_prefix_detects_overflow() {
+        let max = isize::MAX as usize;
+        let fits = layout(max - 15, 1);
+        assert_eq!(
+            layout_with_prefix(layout(8, 8), fits),
+            Some((layout(max - 7, 8), 8))
+        );
+        assert_eq!(layout_with_prefix(layout(8, 8), layout(max - 14, 1)), None);
+        // The payload offset is rounded up to its alignment before adding its size.
+        assert_eq!(layout_with_prefix(layout(1, 1), layout(max - 15, 16)), None);
+    }
+}
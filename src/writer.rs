@@ -0,0 +1,249 @@
+use crate::{Error, SliceGuard};
+use allocator_api2::alloc::Allocator;
+use core::fmt;
+
+/// A growable byte buffer in an allocator, for writing output of unknown length directly into it.
+///
+/// The buffer grows through [`Allocator::grow`], so an arena that can extend its topmost block
+/// never copies the bytes. If the allocator can't grow the block, the bytes are moved to a new
+/// block of twice the capacity instead. [`finish`](ArenaWriter::finish) shrinks the block to the
+/// written length and returns it.
+///
+/// The writer implements [`fmt::Write`] and, with the `std` feature, [`std::io::Write`].
+///
+/// # Example
+/// ```
+/// use allocandrescu::{alloc::Stack, ArenaWriter};
+/// use core::fmt::Write as _;
+///
+/// let stack = Stack::<256>::new();
+/// let mut writer = ArenaWriter::new(&stack).unwrap();
+/// for i in 0..4 {
+///     write!(writer, "{i},").unwrap();
+/// }
+/// writer.write_bytes(b"done").unwrap();
+/// let bytes = writer.finish();
+/// assert_eq!(bytes, b"0,1,2,3,done");
+/// assert_eq!(stack.used(), bytes.len());
+/// ```
+pub struct ArenaWriter<'a, A: Allocator + ?Sized> {
+    bytes: SliceGuard<'a, u8, A>,
+}
+
+impl<'a, A> ArenaWriter<'a, A>
+where
+    A: Allocator + ?Sized,
+{
+    /// Creates an empty writer. Nothing is allocated until the first write.
+    #[inline]
+    pub fn new(alloc: &'a A) -> Result<Self, Error> {
+        Self::with_capacity(0, alloc)
+    }
+
+    /// Creates a writer with room for `capacity` bytes.
+    #[inline]
+    pub fn with_capacity(capacity: usize, alloc: &'a A) -> Result<Self, Error> {
+        SliceGuard::new(alloc, capacity).map(|bytes| Self { bytes })
+    }
+
+    /// Returns the number of bytes written.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len
+    }
+
+    /// Returns `true` if nothing was written.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bytes.len == 0
+    }
+
+    /// Returns the number of bytes the writer can hold without growing.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.bytes.cap
+    }
+
+    /// Returns the bytes written so far.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.bytes.ptr.as_ptr(), self.bytes.len) }
+    }
+
+    /// Appends `bytes`, growing the block if needed.
+    ///
+    /// Nothing is written on failure.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        while self.bytes.cap - self.bytes.len < bytes.len() {
+            self.bytes.grow_or_move()?;
+        }
+        self.bytes.extend_from_slice(bytes)
+    }
+
+    /// Shrinks the block to the written bytes, if the allocator manages to, and returns them.
+    #[inline]
+    pub fn finish(mut self) -> &'a mut [u8] {
+        self.bytes.shrink_to_fit();
+        self.bytes.finish()
+    }
+}
+
+impl<A> fmt::Write for ArenaWriter<'_, A>
+where
+    A: Allocator + ?Sized,
+{
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> std::io::Write for ArenaWriter<'_, A>
+where
+    A: Allocator + ?Sized,
+{
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_all(buf).map(|()| buf.len())
+    }
+
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.write_bytes(buf)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::OutOfMemory, err))
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<A> fmt::Debug for ArenaWriter<'_, A>
+where
+    A: Allocator + ?Sized,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArenaWriter")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc::Stack, Allocandrescu as _, ArenaAllocator};
+    use allocator_api2::alloc::AllocError;
+    use core::{alloc::Layout, fmt::Write as _, ptr::NonNull};
+
+    /// Forwards to a stack, but fails to grow.
+    struct NoGrow<'a>(&'a Stack<1024>);
+
+    unsafe impl Allocator for NoGrow<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.deallocate(ptr, layout)
+        }
+
+        unsafe fn grow(
+            &self,
+            _: NonNull<u8>,
+            _: Layout,
+            _: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            Err(AllocError)
+        }
+    }
+
+    fn chunk(i: usize) -> impl Iterator<Item = u8> {
+        (0..i % 23).map(move |j| (i * 31 + j) as u8)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too slow under Miri")]
+    fn arena_writer_writes_megabytes_in_small_chunks() {
+        const SIZE: usize = 4 << 20;
+
+        // Unoptimized builds construct the boxed stack on the call stack first.
+        let big = std::thread::Builder::new()
+            .stack_size(4 * SIZE)
+            .spawn(|| std::boxed::Box::new(Stack::<SIZE>::new()))
+            .unwrap()
+            .join()
+            .unwrap();
+        let small = Stack::<4096>::new();
+        let alloc = small
+            .by_ref()
+            .cond(|layout| layout.size() <= 1024)
+            .fallback(&*big);
+        let mut writer = ArenaWriter::new(&alloc).unwrap();
+        let mut i = 0;
+        while writer.len() < 3 << 20 {
+            let bytes: std::vec::Vec<u8> = chunk(i).collect();
+            writer.write_bytes(&bytes).unwrap();
+            i += 1;
+        }
+        write!(writer, "{i}").unwrap();
+        let len = writer.len();
+
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), len);
+        let expected = (0..i)
+            .flat_map(chunk)
+            .chain(std::format!("{i}").into_bytes());
+        assert!(bytes.iter().copied().eq(expected));
+        let layout = Layout::for_value(&*bytes);
+        assert!(big.contains(NonNull::from(&*bytes).cast(), layout));
+        assert_eq!((small.used(), big.used()), (0, len));
+    }
+
+    #[test]
+    fn arena_writer_moves_when_allocator_cannot_grow() {
+        let stack = Stack::<1024>::new();
+        let alloc = NoGrow(&stack);
+        let mut writer = ArenaWriter::with_capacity(2, &alloc).unwrap();
+        for i in 0..40u8 {
+            writer.write_bytes(&[i; 3]).unwrap();
+        }
+        assert_eq!(writer.capacity(), 128);
+        // Every move leaves the old block behind, as the stack only reclaims its top.
+        assert!(stack.used() > 128);
+        let bytes = writer.finish();
+        assert!(bytes.iter().enumerate().all(|(j, &b)| b == (j / 3) as u8));
+        assert!(stack.contains(NonNull::from(&*bytes).cast(), Layout::for_value(&*bytes)));
+    }
+
+    #[test]
+    fn arena_writer_reports_failure_without_writing() {
+        let stack = Stack::<64>::new();
+        let mut writer = ArenaWriter::new(&stack).unwrap();
+        writer.write_bytes(b"kept").unwrap();
+        let error = writer.write_bytes(&[0; 100]).unwrap_err();
+        assert_eq!(error.layout(), Some(Layout::array::<u8>(128).unwrap()));
+        assert!(writer.write_str(&"x".repeat(100)).is_err());
+        assert_eq!(writer.as_bytes(), b"kept");
+        assert_eq!(writer.finish(), b"kept");
+        assert_eq!(stack.used(), 4);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn arena_writer_is_io_write() {
+        use std::io::Write as _;
+
+        let stack = Stack::<64>::new();
+        let mut writer = ArenaWriter::new(&stack).unwrap();
+        writer.write_all(b"io ").unwrap();
+        std::io::copy(&mut &b"copy"[..], &mut writer).unwrap();
+        let error = writer.write_all(&[0; 100]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::OutOfMemory);
+        writer.flush().unwrap();
+        assert_eq!(writer.finish(), b"io copy");
+    }
+}
@@ -0,0 +1,9 @@
+//! Runs the logic of the shipped examples, so that they keep working and not only compiling.
+
+#[path = "../examples/fallback.rs"]
+mod fallback;
+
+#[test]
+fn fallback_example() {
+    assert_eq!(fallback::run(), 3);
+}
@@ -0,0 +1,81 @@
+//! Runs a whole test binary on a composed global allocator.
+//!
+//! With `debug-tracking`, the stack records its allocations on the global heap, which would
+//! call back into itself under the lock.
+#![cfg(not(feature = "debug-tracking"))]
+
+use allocandrescu::{
+    alloc::{Global, Stack},
+    combinator::{Cond, Fallback, SizeAtMost},
+    ArenaAllocator,
+};
+use allocator_api2::alloc::{AllocError, Allocator};
+use std::{
+    alloc::{Layout, System},
+    ptr::NonNull,
+    sync::Mutex,
+};
+
+/// A stack behind a lock, so it can be shared by every thread.
+struct Locked(Mutex<Stack<{ 1 << 20 }>>);
+
+unsafe impl Allocator for Locked {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.lock().unwrap().allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.0.lock().unwrap().deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.lock().unwrap().grow(ptr, old_layout, new_layout)
+    }
+}
+
+impl ArenaAllocator for Locked {
+    fn contains(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.0.lock().unwrap().contains(ptr, layout)
+    }
+}
+
+type Chain = Fallback<Cond<Locked, SizeAtMost<1024>>, System>;
+
+#[global_allocator]
+static GLOBAL: Global<Chain> = Global::new(Fallback::new(
+    Cond::new(Locked(Mutex::new(Stack::new())), SizeAtMost),
+    System,
+));
+
+fn in_stack<T>(values: &[T]) -> bool {
+    let layout = Layout::for_value(values);
+    GLOBAL
+        .inner()
+        .primary()
+        .contains(NonNull::from(values).cast(), layout)
+}
+
+#[test]
+fn std_collections_run_on_composed_global() {
+    let mut small: Vec<u32> = Vec::new();
+    for i in 0..100 {
+        small.push(i);
+    }
+    let text = format!("{small:?}");
+    assert!(text.starts_with("[0, 1, 2"));
+    assert!(in_stack(&small) && in_stack(text.as_bytes()));
+
+    // Too large for the stack, so it goes to the system allocator.
+    let large = vec![1u64; 1024];
+    assert!(!in_stack(&large));
+
+    let mut words: Vec<String> = (0..50).map(|i| i.to_string()).collect();
+    words.retain(|word| word.len() == 1);
+    assert_eq!(words.concat(), "0123456789");
+    assert!(words.iter().all(|word| in_stack(word.as_bytes())));
+}
@@ -0,0 +1,54 @@
+//! Uses the allocators of this crate with the collections of the standard library, which only
+//! accept the unstable `core::alloc::Allocator` trait.
+//!
+//! The rest of the test suite uses allocator-api2's collections, which are replaced by the
+//! standard ones in this configuration, so run only this file:
+//! `cargo +nightly test --features nightly,alloc --test nightly`.
+#![cfg(all(feature = "nightly", feature = "alloc"))]
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
+
+use allocandrescu::{
+    alloc::{Failing, Stack},
+    prelude::*,
+};
+use std::{
+    alloc::{Layout, System},
+    boxed::Box,
+    ptr::NonNull,
+    vec::Vec,
+};
+
+#[test]
+fn std_collections_use_fallback_chain() {
+    let stack = Stack::<256>::new();
+    let alloc = stack.by_ref().fallback(System);
+
+    let boxed = Box::new_in(7u64, &stack);
+    assert!(stack.contains(NonNull::from(&*boxed).cast(), Layout::new::<u64>()));
+
+    let small: Vec<u32, _> = (0..16).collect_in(&alloc);
+    let mut large = Vec::with_capacity_in(16, &alloc);
+    large.extend(0..1000u64);
+    assert!(stack.contains(
+        NonNull::from(&small[..]).cast(),
+        Layout::for_value(&small[..])
+    ));
+    assert!(!stack.contains(
+        NonNull::from(&large[..]).cast(),
+        Layout::for_value(&large[..])
+    ));
+    assert!(large.iter().copied().eq(0..1000));
+    // The stack only reclaims its topmost block, so the last allocation goes first.
+    drop((large, small, boxed));
+    assert_eq!(stack.used(), 0);
+}
+
+#[test]
+fn std_box_reports_failures() {
+    assert!(Box::try_new_in(1u8, Failing).is_err());
+    let stack = Stack::<8>::new();
+    let alloc = stack.by_ref().fallback(Failing);
+    let first = Box::try_new_in(1u64, &alloc).unwrap();
+    assert!(Box::try_new_in(2u64, &alloc).is_err());
+    assert_eq!(*first, 1);
+}